@@ -0,0 +1,657 @@
+//! Import AutoHotkey remaps and hotkeys.
+//!
+//! AutoHotkey sees keys through a keyboard hook, which games reading raw input and remote
+//! desktop sessions bypass; imported here, the same definitions act at the driver level. The
+//! simple, common subset of v1 and v2 scripts is understood:
+//!
+//! ```text
+//! CapsLock::Ctrl              ; a remap, of a key or mouse button
+//! XButton1::^c                ; remaps can hold modifiers
+//! Insert::return              ; block a key
+//! ^!t::Send, {Home}Hello      ; a hotkey; SendInput, SendRaw and v2's Send("...") work too
+//! ~F1::                       ; `~` lets the key through as well
+//!     Send {Esc}
+//!     SendInput Bye`n
+//! return
+//! ```
+//!
+//! Hotkeys may hold the `^`, `!`, `+` and `#` modifiers and carry the `~` and `$` prefixes;
+//! `$` makes no difference at the driver level. Remaps become [`RemapRule`]s and hotkeys
+//! become [`Hotkey`]s for a [`HotkeyTable`](crate::hotkey::HotkeyTable), with `Send` text
+//! typed on a US layout. `#IfWinActive` and the other context-sensitive blocks, since a driver
+//! can't tell which window is active, and everything else — custom combinations such as
+//! `a & b`, `up` hotkeys, hotstrings, other commands — are reported as [`Diagnostic`]s and
+//! left out. Boilerplate such as `#NoEnv`, `#SingleInstance` and `SendMode` is skipped.
+
+use crate::hotkey::Hotkey;
+use crate::rules::{Diagnostic, RemapRule, RuleSet};
+use crate::send_keys::{Modifier, SendKeys, key_from_name};
+use crate::text::{Modifiers, UsQwerty};
+use crate::{Key, KeyStroke, MouseButton, Switch};
+use std::collections::HashMap;
+
+/// The remaps and hotkeys of a script, and what could not be imported
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Import {
+    /// The remaps, as rules for [`Rules`](crate::rules::Rules)
+    pub rules: RuleSet,
+    pub hotkeys: Vec<Hotkey>,
+    /// Problems in file order; whatever they concern is left out
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Import the remaps and hotkeys of a script
+pub fn import(source: &str) -> Import {
+    let mut importer = Importer {
+        source,
+        import: Import::default(),
+        defined: HashMap::new(),
+    };
+    importer.script();
+    let mut import = importer.import;
+    import
+        .diagnostics
+        .sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    import
+}
+
+/// Directives and commands that only matter to AutoHotkey itself
+const IGNORED: [&str; 17] = [
+    "#NoEnv",
+    "#SingleInstance",
+    "#Persistent",
+    "#Warn",
+    "#Requires",
+    "#InstallKeybdHook",
+    "#InstallMouseHook",
+    "#UseHook",
+    "#MaxHotkeysPerInterval",
+    "#NoTrayIcon",
+    "SendMode",
+    "SetWorkingDir",
+    "SetBatchLines",
+    "SetKeyDelay",
+    "SetTitleMatchMode",
+    "ListLines",
+    "Return",
+];
+
+/// Directives that make the hotkeys after them depend on the active window
+const CONTEXT: [&str; 6] = [
+    "#IfWinActive",
+    "#IfWinNotActive",
+    "#IfWinExist",
+    "#IfWinNotExist",
+    "#If",
+    "#HotIf",
+];
+
+/// AutoHotkey's names for mouse buttons
+const BUTTONS: [(&str, MouseButton); 5] = [
+    ("LButton", MouseButton::Left),
+    ("RButton", MouseButton::Right),
+    ("MButton", MouseButton::Middle),
+    ("XButton1", MouseButton::Button4),
+    ("XButton2", MouseButton::Button5),
+];
+
+/// The left-hand side of `::`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Trigger {
+    modifiers: Vec<Modifier>,
+    switch: Switch,
+    pass_through: bool,
+}
+
+/// A hotkey whose action spans several lines, up to `return`
+struct Body {
+    trigger: Option<Trigger>,
+    offset: usize,
+    strokes: Vec<KeyStroke>,
+    /// A line couldn't be imported, so neither can the hotkey
+    failed: bool,
+}
+
+struct Importer<'s> {
+    source: &'s str,
+    import: Import,
+    /// The line each trigger was first defined on, by modifiers and switch
+    defined: HashMap<(u8, Switch), usize>,
+}
+
+impl<'s> Importer<'s> {
+    fn error(&mut self, offset: usize, message: impl Into<String>) {
+        let diagnostic = Diagnostic::at(self.source, offset, message);
+        self.import.diagnostics.push(diagnostic);
+    }
+
+    /// Where `piece`, a slice of the source, starts in it
+    fn offset(&self, piece: &str) -> usize {
+        piece.as_ptr() as usize - self.source.as_ptr() as usize
+    }
+
+    fn script(&mut self) {
+        let source = self.source;
+        let mut in_comment = false;
+        // Whether the hotkeys are in an `#If` block, and left out
+        let mut context = false;
+        let mut body: Option<Body> = None;
+
+        for line in source.lines() {
+            let line = strip_comment(line).trim();
+            if in_comment {
+                in_comment = !line.contains("*/");
+                continue;
+            }
+            if line.starts_with("/*") {
+                in_comment = !line.contains("*/");
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(mut current) = body.take() {
+                if line.eq_ignore_ascii_case("return") || line == "}" {
+                    self.finish(current);
+                } else {
+                    // Without a trigger, the hotkey is left out and so are its lines
+                    if line != "{" && current.trigger.is_some() {
+                        match self.send(line) {
+                            Some(strokes) => current.strokes.extend(strokes),
+                            None => current.failed = true,
+                        }
+                    }
+                    body = Some(current);
+                }
+                continue;
+            }
+
+            let word = first_word(line);
+            if let Some(directive) = CONTEXT.iter().find(|d| d.eq_ignore_ascii_case(word)) {
+                if line[word.len()..].trim().is_empty() {
+                    context = false;
+                } else {
+                    let message = format!(
+                        "`{directive}` is not supported, since the active window is unknown to a driver; the hotkeys up to the next `{directive}` are left out"
+                    );
+                    self.error(self.offset(line), message);
+                    context = true;
+                }
+            } else if IGNORED
+                .iter()
+                .any(|ignored| ignored.eq_ignore_ascii_case(word))
+            {
+                // Settings for AutoHotkey itself, or a `return` ending a one-line hotkey
+            } else if line.starts_with(':') {
+                self.error(self.offset(line), "hotstrings are not supported");
+            } else if let Some(split) = line.find("::").filter(|split| *split > 0) {
+                let (spec, action) = (&line[..split], line[split + 2..].trim());
+                let trigger = if context { None } else { self.trigger(spec) };
+                if action.is_empty() || action == "{" {
+                    let offset = self.offset(line);
+                    body = Some(Body {
+                        trigger,
+                        offset,
+                        strokes: Vec::new(),
+                        failed: false,
+                    });
+                } else if !context {
+                    self.definition(trigger, spec, action);
+                }
+            } else if word.starts_with('#') {
+                self.error(self.offset(line), format!("`{word}` is not supported"));
+            } else {
+                let message = format!("`{word}` outside a hotkey is not supported");
+                self.error(self.offset(line), message);
+            }
+        }
+        if let Some(body) = body {
+            self.finish(body);
+        }
+    }
+
+    /// Add a hotkey or remap defined on one line, as `spec::action`
+    fn definition(&mut self, trigger: Option<Trigger>, spec: &str, action: &str) {
+        let is_send = send_command(action).is_some();
+        if action.eq_ignore_ascii_case("return") || !is_send && switches(action).is_some() {
+            let Some(trigger) = trigger else {
+                return;
+            };
+            let to = if action.eq_ignore_ascii_case("return") {
+                Vec::new()
+            } else {
+                switches(action).unwrap_or_default()
+            };
+            if !trigger.modifiers.is_empty() || trigger.pass_through {
+                let message = "remaps with modifiers or `~` are not supported; use `Send` instead";
+                self.error(self.offset(spec), message);
+                return;
+            }
+            if self.define(&trigger, spec) {
+                self.import.rules.remaps.push(RemapRule {
+                    from: trigger.switch,
+                    to,
+                    devices: Vec::new(),
+                });
+            }
+        } else if is_send {
+            let strokes = self.send(action);
+            if let (Some(trigger), Some(strokes)) = (trigger, strokes) {
+                self.hotkey(trigger, strokes, spec);
+            }
+        } else {
+            let message = format!(
+                "`{}` is not supported; a hotkey can only remap or `Send`",
+                first_word(action)
+            );
+            self.error(self.offset(action), message);
+        }
+    }
+
+    fn finish(&mut self, body: Body) {
+        if let (Some(trigger), false) = (body.trigger, body.failed) {
+            let spec = &self.source[body.offset..];
+            self.hotkey(trigger, body.strokes, spec);
+        }
+    }
+
+    fn hotkey(&mut self, trigger: Trigger, strokes: Vec<KeyStroke>, spec: &str) {
+        let Switch::Key(key) = trigger.switch else {
+            self.error(
+                self.offset(spec),
+                "hotkeys on mouse buttons are not supported",
+            );
+            return;
+        };
+        if self.define(&trigger, spec) {
+            let hotkey = Hotkey::new(trigger.modifiers, key, strokes);
+            let hotkey = hotkey.with_pass_through(trigger.pass_through);
+            self.import.hotkeys.push(hotkey);
+        }
+    }
+
+    /// Record a definition, or report that it is a duplicate
+    fn define(&mut self, trigger: &Trigger, spec: &str) -> bool {
+        let mask = Modifier::ALL
+            .iter()
+            .enumerate()
+            .filter(|(_, modifier)| trigger.modifiers.contains(modifier))
+            .fold(0, |mask, (i, _)| mask | 1 << i);
+        let line = Diagnostic::at(self.source, self.offset(spec), "").line;
+        match self.defined.insert((mask, trigger.switch), line) {
+            Some(first) => {
+                self.defined.insert((mask, trigger.switch), first);
+                let message = format!("duplicate hotkey; it is already defined on line {first}");
+                self.error(self.offset(spec), message);
+                false
+            }
+            None => true,
+        }
+    }
+
+    fn trigger(&mut self, spec: &'s str) -> Option<Trigger> {
+        let mut trigger = Trigger {
+            modifiers: Vec::new(),
+            switch: Switch::Key(Key::ESCAPE),
+            pass_through: false,
+        };
+        let mut name = spec;
+        while name.len() > 1 {
+            let c = name.chars().next().unwrap();
+            match c {
+                '~' => trigger.pass_through = true,
+                '$' => {}
+                '*' | '<' | '>' => {
+                    let message = match c {
+                        '*' => "wildcard hotkeys (`*`) are not supported",
+                        _ => "left- and right-hand modifiers (`<` and `>`) are not supported",
+                    };
+                    self.error(self.offset(name), message);
+                    return None;
+                }
+                _ => match Modifier::from_prefix(c) {
+                    Some(modifier) if !trigger.modifiers.contains(&modifier) => {
+                        trigger.modifiers.push(modifier);
+                    }
+                    Some(_) => {}
+                    None => break,
+                },
+            }
+            name = &name[1..];
+        }
+
+        if name.contains(" & ") {
+            let message = "custom combinations such as `a & b` are not supported";
+            self.error(self.offset(name), message);
+            return None;
+        }
+        let suffix = name.get(name.len().saturating_sub(3)..);
+        if name.len() > 3 && suffix.is_some_and(|suffix| suffix.eq_ignore_ascii_case(" up")) {
+            self.error(self.offset(name), "`up` hotkeys are not supported");
+            return None;
+        }
+        match switch_from_name(name.strip_prefix('`').unwrap_or(name)) {
+            Some(switch) => {
+                trigger.switch = switch;
+                Some(trigger)
+            }
+            None => {
+                self.error(self.offset(name), format!("unknown key `{name}`"));
+                None
+            }
+        }
+    }
+
+    /// The strokes of a `Send` command
+    fn send(&mut self, line: &str) -> Option<Vec<KeyStroke>> {
+        let Some((prefix, text)) = send_command(line) else {
+            let message = format!(
+                "`{}` is not supported; a hotkey can only `Send`",
+                first_word(line)
+            );
+            self.error(self.offset(line), message);
+            return None;
+        };
+        if text.contains('%') && !text.contains("`%") {
+            self.error(self.offset(text), "variables in `Send` are not supported");
+            return None;
+        }
+        let keys = match SendKeys::parse(&format!("{prefix}{}", unescape(text))) {
+            Ok(keys) => keys,
+            Err(e) => {
+                let column = e.column.saturating_sub(prefix.chars().count() + 1);
+                let offset = self.offset(text)
+                    + text.chars().take(column).map(char::len_utf8).sum::<usize>();
+                self.error(offset, e.message);
+                return None;
+            }
+        };
+        match keys.strokes() {
+            Ok(strokes) => Some(strokes),
+            Err(e) => {
+                self.error(self.offset(text), e.to_string());
+                None
+            }
+        }
+    }
+}
+
+/// A line without its comment: a `;` at the start or after whitespace, unless escaped
+fn strip_comment(line: &str) -> &str {
+    let mut previous = None;
+    for (i, c) in line.char_indices() {
+        if c == ';' && previous.is_none_or(|p: char| p.is_whitespace()) {
+            return &line[..i];
+        }
+        previous = Some(c);
+    }
+    line
+}
+
+/// The command or directive a line starts with
+fn first_word(line: &str) -> &str {
+    let end = line
+        .find(|c: char| c.is_whitespace() || c == ',' || c == '(')
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+/// The prefix to give the text of a `Send` command, and the text
+fn send_command(line: &str) -> Option<(&'static str, &str)> {
+    let word = first_word(line);
+    let prefix = match word.to_ascii_lowercase().as_str() {
+        "send" | "sendinput" | "sendevent" | "sendplay" => "",
+        "sendraw" => "{Raw}",
+        "sendtext" => "{Text}",
+        _ => return None,
+    };
+    let mut text = line[word.len()..].trim_start();
+    text = text.strip_prefix(',').unwrap_or(text).trim_start();
+    // v2 passes the text as a string, with or without parentheses
+    if let Some(inner) = text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        text = inner.trim();
+    }
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        text = inner;
+    }
+    Some((prefix, text))
+}
+
+/// Resolve AutoHotkey's backtick escapes
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '`' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => {}
+            Some(c) => out.push(c),
+            None => out.push('`'),
+        }
+    }
+    out
+}
+
+/// A key or mouse button by AutoHotkey's name, a scancode such as `SC03A` or the character
+/// it types
+fn switch_from_name(name: &str) -> Option<Switch> {
+    if let Some((_, button)) = BUTTONS.iter().find(|(b, _)| b.eq_ignore_ascii_case(name)) {
+        return Some(Switch::Button(*button));
+    }
+    if let Some(code) = name
+        .get(..2)
+        .filter(|sc| sc.eq_ignore_ascii_case("sc"))
+        .and_then(|_| u16::from_str_radix(&name[2..], 16).ok())
+    {
+        let prefix = if code > 0xFF { "E0" } else { "" };
+        return Key::from_name(&format!("0x{prefix}{:02X}", code & 0xFF)).map(Switch::Key);
+    }
+    if let Some(key) = key_from_name(name) {
+        return Some(Switch::Key(key));
+    }
+    let mut chars = name.chars();
+    match (chars.next().map(|c| c.to_ascii_lowercase()), chars.next()) {
+        (Some(c), None) => UsQwerty::chord(c)
+            .filter(|chord| chord.modifiers == Modifiers::NONE)
+            .map(|chord| Switch::Key(chord.key)),
+        _ => None,
+    }
+}
+
+/// The switches a remap presses: its modifiers, then the key or button
+fn switches(action: &str) -> Option<Vec<Switch>> {
+    let mut name = action;
+    let mut switches = Vec::new();
+    while name.len() > 1 {
+        let Some(modifier) = name.chars().next().and_then(Modifier::from_prefix) else {
+            break;
+        };
+        switches.push(Switch::Key(modifier.key()));
+        name = &name[1..];
+    }
+    switches.push(switch_from_name(name.strip_prefix('`').unwrap_or(name))?);
+    Some(switches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hotkey::HotkeyTable;
+    use crate::{Event, Processor};
+    use std::time::Instant;
+
+    const SAMPLE: &str = r#"#NoEnv
+#SingleInstance Force
+SendMode Input
+
+/* Remaps
+   for the laptop */
+CapsLock::Ctrl
+XButton1::^c        ; copy
+Insert::return
+`;::Enter
+
+^!t::Send, {Home}Hello
+$F2::SendRaw {not a key}
++#F3::Send("^a{Del}")
+
+~F1::
+    Send {Esc}
+    SendInput Bye`n
+return
+
+#IfWinActive ahk_exe game.exe
+a::b
+F4::
+    Send x
+return
+#IfWinActive
+
+SC03D::Send {Text}ok
+"#;
+
+    fn messages(import: &Import) -> Vec<String> {
+        import.diagnostics.iter().map(ToString::to_string).collect()
+    }
+
+    fn remap(from: impl Into<Switch>, to: &[Switch]) -> RemapRule {
+        RemapRule {
+            from: from.into(),
+            to: to.to_vec(),
+            devices: vec![],
+        }
+    }
+
+    #[test]
+    fn test_import_sample() {
+        let import = import(SAMPLE);
+        assert_eq!(
+            messages(&import),
+            [
+                "21:1: `#IfWinActive` is not supported, since the active window is unknown to a driver; the hotkeys up to the next `#IfWinActive` are left out"
+            ]
+        );
+        assert_eq!(
+            import.rules.remaps,
+            [
+                remap(Key::CAPS_LOCK, &[Key::LEFT_CTRL.into()]),
+                remap(
+                    MouseButton::Button4,
+                    &[Key::LEFT_CTRL.into(), Key::C.into()]
+                ),
+                remap(Key::INSERT, &[]),
+                remap(Key::SEMICOLON, &[Key::ENTER.into()]),
+            ]
+        );
+
+        let hotkeys = &import.hotkeys;
+        assert_eq!(hotkeys.len(), 5);
+        assert_eq!(hotkeys[0].modifiers, [Modifier::Ctrl, Modifier::Alt]);
+        assert_eq!(hotkeys[0].key, Key::T);
+        assert_eq!(
+            hotkeys[0].strokes,
+            SendKeys::parse("{Home}Hello").unwrap().strokes().unwrap()
+        );
+        assert_eq!(
+            hotkeys[1].strokes,
+            SendKeys::parse("{{}not a key{}}")
+                .unwrap()
+                .strokes()
+                .unwrap()
+        );
+        assert_eq!(hotkeys[2].modifiers, [Modifier::Shift, Modifier::Win]);
+        assert_eq!(hotkeys[2].strokes.len(), 6);
+        assert!(hotkeys[3].pass_through);
+        assert_eq!(
+            hotkeys[3].strokes,
+            SendKeys::parse("{Esc}Bye{Enter}")
+                .unwrap()
+                .strokes()
+                .unwrap()
+        );
+        assert_eq!(hotkeys[4].key, Key::F3);
+    }
+
+    #[test]
+    fn test_imported_hotkeys_run() {
+        let mut hotkeys = HotkeyTable::new(import("^!t::Send x").hotkeys);
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for stroke in [
+            Key::LEFT_CTRL.down(),
+            Key::LEFT_ALT.down(),
+            Key::T.down(),
+            Key::T.up(),
+        ] {
+            hotkeys.process(Event::new(0, stroke), now, &mut out);
+        }
+        let strokes: Vec<KeyStroke> = out.iter().map(|event| *event.key().unwrap()).collect();
+        let mut expected = vec![
+            Key::LEFT_CTRL.down(),
+            Key::LEFT_ALT.down(),
+            Key::LEFT_CTRL.up(),
+            Key::LEFT_ALT.up(),
+        ];
+        expected.extend(Key::X.tap());
+        expected.extend([Key::LEFT_CTRL.down(), Key::LEFT_ALT.down()]);
+        assert_eq!(strokes, expected);
+
+        // Modifier keys can be hotkeys on their own
+        let import = import("RAlt::Send x");
+        assert_eq!(import.diagnostics, []);
+        assert_eq!(import.hotkeys[0].key, Key::RIGHT_ALT);
+    }
+
+    #[test]
+    fn test_reports_unsupported() {
+        let source = "\
+a & b::c
+*x::y
+<^q::Send q
+w up::Send w
+^e::f
+::btw::by the way
+F5::Run notepad.exe
+F6::Send %greeting%
+F7::Send {Bogus}
+F8::
+    Send a
+    MsgBox hi
+return
+F9::Send x
+F9::Send y
+Sleep 100
+#Include other.ahk
+LButton::Send x
+éé::Send x
+^éé::b
+";
+        assert_eq!(
+            messages(&import(source)),
+            [
+                "1:1: custom combinations such as `a & b` are not supported",
+                "2:1: wildcard hotkeys (`*`) are not supported",
+                "3:1: left- and right-hand modifiers (`<` and `>`) are not supported",
+                "4:1: `up` hotkeys are not supported",
+                "5:1: remaps with modifiers or `~` are not supported; use `Send` instead",
+                "6:1: hotstrings are not supported",
+                "7:5: `Run` is not supported; a hotkey can only remap or `Send`",
+                "8:10: variables in `Send` are not supported",
+                "9:11: unknown key `Bogus`",
+                "12:5: `MsgBox` is not supported; a hotkey can only `Send`",
+                "15:1: duplicate hotkey; it is already defined on line 14",
+                "16:1: `Sleep` outside a hotkey is not supported",
+                "17:1: `#Include` is not supported",
+                "18:1: hotkeys on mouse buttons are not supported",
+                "19:1: unknown key `éé`",
+                "20:2: unknown key `éé`",
+            ]
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Mouse axis geometry.
+//!
+//! [`Axes`] rotates, swaps and inverts relative mouse movement per device, generalizing the C
+//! `axes` sample, which inverts the Y axis. Rotation corrects trackballs mounted at an angle
+//! or vertical mice held off-axis; rotated movement is fractional, so the fraction is carried
+//! over per device as in [`Sensitivity`](crate::sensitivity::Sensitivity).
+//!
+//! Movement can also be locked to one axis while a key is held, which helps drawing straight
+//! lines.
+
+use crate::{
+    Event, Key, MOUSE_MOVE_ABSOLUTE, MouseStroke, PerDevice, Processor, Remainder, Stroke,
+};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Geometry applied to one device's movement
+///
+/// Rotation comes first, then the swap, then inversion.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AxisSettings {
+    /// Clockwise rotation in degrees, in screen coordinates where Y points down
+    pub rotation: f64,
+    pub swap: bool,
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+impl AxisSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    pub fn with_swap(mut self, swap: bool) -> Self {
+        self.swap = swap;
+        self
+    }
+
+    pub fn with_invert(mut self, invert_x: bool, invert_y: bool) -> Self {
+        self.invert_x = invert_x;
+        self.invert_y = invert_y;
+        self
+    }
+
+    /// Fractional output movement for an input movement
+    pub fn apply(&self, x: i32, y: i32) -> (f64, f64) {
+        let (mut x, mut y) = (f64::from(x), f64::from(y));
+        if self.rotation != 0.0 {
+            let (sin, cos) = self.rotation.to_radians().sin_cos();
+            (x, y) = (x * cos - y * sin, x * sin + y * cos);
+        }
+        if self.swap {
+            (x, y) = (y, x);
+        }
+        if self.invert_x {
+            x = -x;
+        }
+        if self.invert_y {
+            y = -y;
+        }
+        (x, y)
+    }
+}
+
+/// The axis movement is locked to while the lock key is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lock {
+    Horizontal,
+    Vertical,
+    /// Whichever axis the first movement after pressing the key mostly follows
+    Dominant,
+}
+
+/// Rotates, swaps and inverts relative mouse movement
+#[derive(Debug, Clone)]
+pub struct Axes {
+    settings: PerDevice<AxisSettings>,
+    remainders: HashMap<usize, (Remainder, Remainder)>,
+    lock: Option<(Key, Lock)>,
+    /// The lock in effect while the lock key is down; `Dominant` until movement decides
+    locked: Option<Lock>,
+}
+
+impl Axes {
+    pub fn new(settings: AxisSettings) -> Self {
+        Self::per_device(PerDevice::new(settings))
+    }
+
+    /// Use different settings for particular devices
+    pub fn per_device(settings: PerDevice<AxisSettings>) -> Self {
+        Self {
+            settings,
+            remainders: HashMap::new(),
+            lock: None,
+            locked: None,
+        }
+    }
+
+    /// Lock movement to one axis while `key` is held; the key itself still passes through
+    pub fn with_lock(mut self, key: Key, lock: Lock) -> Self {
+        self.lock = Some((key, lock));
+        self
+    }
+
+    /// Transform one stroke from `device`
+    ///
+    /// Absolute movement is left alone. Returns `None` when the stroke only carried movement
+    /// and none of it is left.
+    pub fn transform(&mut self, device: usize, stroke: MouseStroke) -> Option<MouseStroke> {
+        if stroke.flags & MOUSE_MOVE_ABSOLUTE != 0 || (stroke.x == 0 && stroke.y == 0) {
+            return Some(stroke);
+        }
+
+        let (x, y) = self.settings.get(device).apply(stroke.x, stroke.y);
+        let (remainder_x, remainder_y) = self.remainders.entry(device).or_default();
+        let mut transformed = stroke;
+        transformed.x = remainder_x.take(x);
+        transformed.y = remainder_y.take(y);
+
+        if self.locked == Some(Lock::Dominant) && (transformed.x, transformed.y) != (0, 0) {
+            self.locked = Some(if transformed.x.abs() >= transformed.y.abs() {
+                Lock::Horizontal
+            } else {
+                Lock::Vertical
+            });
+        }
+        match self.locked {
+            Some(Lock::Horizontal) => transformed.y = 0,
+            Some(Lock::Vertical) => transformed.x = 0,
+            _ => {}
+        }
+
+        (!transformed.is_empty()).then_some(transformed)
+    }
+}
+
+impl Processor for Axes {
+    fn process(&mut self, event: Event, _now: Instant, out: &mut Vec<Event>) {
+        match event.stroke {
+            Stroke::Mouse(stroke) => {
+                if let Some(stroke) = self.transform(event.device, stroke) {
+                    out.push(Event::new(event.device, stroke));
+                }
+            }
+            Stroke::Key(stroke) => {
+                if let Some((key, lock)) = self.lock
+                    && stroke.key() == key
+                {
+                    if stroke.is_up() {
+                        self.locked = None;
+                    } else if self.locked.is_none() {
+                        self.locked = Some(lock);
+                    }
+                }
+                out.push(event);
+            }
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.settings.attach(device, hardware_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_KEYBOARD, MOUSE_MOVE_RELATIVE};
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    fn moved(axes: &mut Axes, events: &[Event]) -> Vec<(i32, i32)> {
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for event in events {
+            axes.process(*event, now, &mut out);
+        }
+        out.iter()
+            .filter_map(Event::mouse)
+            .map(|stroke| (stroke.x, stroke.y))
+            .collect()
+    }
+
+    fn motion(x: i32, y: i32) -> Event {
+        Event::new(MOUSE, MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, x, y, 0))
+    }
+
+    #[test]
+    fn test_swap_and_invert() {
+        let mut axes = Axes::new(AxisSettings::new().with_swap(true).with_invert(false, true));
+        assert_eq!(moved(&mut axes, &[motion(3, -1)]), vec![(-1, -3)]);
+    }
+
+    #[test]
+    fn test_rotation_carries_fraction() {
+        let mut axes = Axes::new(AxisSettings::new().with_rotation(90.0));
+        assert_eq!(moved(&mut axes, &[motion(5, 0)]), vec![(0, 5)]);
+
+        // Rotated by 45 degrees, each step of 1 moves about 0.707 on both axes
+        let mut axes = Axes::new(AxisSettings::new().with_rotation(45.0));
+        let out = moved(&mut axes, &vec![motion(1, 0); 10]);
+        let total = out.iter().fold((0, 0), |(x, y), (dx, dy)| (x + dx, y + dy));
+        assert_eq!(total, (7, 7));
+    }
+
+    #[test]
+    fn test_dominant_axis_lock() {
+        let mut axes = Axes::new(AxisSettings::new()).with_lock(Key::LEFT_SHIFT, Lock::Dominant);
+        let out = moved(
+            &mut axes,
+            &[
+                motion(2, 2),
+                Event::new(0, Key::LEFT_SHIFT.down()),
+                motion(1, 4),
+                motion(3, 1),
+                Event::new(0, Key::LEFT_SHIFT.down()),
+                motion(2, 1),
+                Event::new(0, Key::LEFT_SHIFT.up()),
+                motion(3, 1),
+            ],
+        );
+        assert_eq!(out, vec![(2, 2), (0, 4), (0, 1), (0, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn test_absolute_untouched() {
+        let mut axes = Axes::new(AxisSettings::new().with_invert(true, true));
+        let absolute = MouseStroke::new(MOUSE_MOVE_ABSOLUTE, 0, 0, 100, 200, 0);
+        assert_eq!(axes.transform(MOUSE, absolute), Some(absolute));
+    }
+}
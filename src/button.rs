@@ -0,0 +1,160 @@
+//! Mouse button identities.
+//!
+//! [`MouseStroke::state`] packs separate down and up bits for each of the five buttons, and a
+//! single stroke may carry transitions for several buttons at once. [`MouseButton`] names a
+//! button and knows both of its bits.
+
+use crate::{
+    MOUSE_BUTTON_4_DOWN, MOUSE_BUTTON_4_UP, MOUSE_BUTTON_5_DOWN, MOUSE_BUTTON_5_UP,
+    MOUSE_LEFT_BUTTON_DOWN, MOUSE_LEFT_BUTTON_UP, MOUSE_MIDDLE_BUTTON_DOWN, MOUSE_MIDDLE_BUTTON_UP,
+    MOUSE_MOVE_ABSOLUTE, MOUSE_MOVE_RELATIVE, MOUSE_RIGHT_BUTTON_DOWN, MOUSE_RIGHT_BUTTON_UP,
+    MouseState, MouseStroke,
+};
+use std::fmt::{Display, Formatter};
+
+/// One of the five buttons the driver reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Button4,
+    Button5,
+}
+
+/// Every bit that reports a button going down
+pub const MOUSE_BUTTONS_DOWN: MouseState = MOUSE_LEFT_BUTTON_DOWN
+    | MOUSE_RIGHT_BUTTON_DOWN
+    | MOUSE_MIDDLE_BUTTON_DOWN
+    | MOUSE_BUTTON_4_DOWN
+    | MOUSE_BUTTON_5_DOWN;
+
+/// Every bit that reports a button going up
+pub const MOUSE_BUTTONS_UP: MouseState = MOUSE_LEFT_BUTTON_UP
+    | MOUSE_RIGHT_BUTTON_UP
+    | MOUSE_MIDDLE_BUTTON_UP
+    | MOUSE_BUTTON_4_UP
+    | MOUSE_BUTTON_5_UP;
+
+impl MouseButton {
+    pub const ALL: [MouseButton; 5] = [
+        MouseButton::Left,
+        MouseButton::Right,
+        MouseButton::Middle,
+        MouseButton::Button4,
+        MouseButton::Button5,
+    ];
+
+    /// The state bit reporting this button going down
+    pub const fn down_flag(self) -> MouseState {
+        match self {
+            MouseButton::Left => MOUSE_LEFT_BUTTON_DOWN,
+            MouseButton::Right => MOUSE_RIGHT_BUTTON_DOWN,
+            MouseButton::Middle => MOUSE_MIDDLE_BUTTON_DOWN,
+            MouseButton::Button4 => MOUSE_BUTTON_4_DOWN,
+            MouseButton::Button5 => MOUSE_BUTTON_5_DOWN,
+        }
+    }
+
+    /// The state bit reporting this button going up
+    pub const fn up_flag(self) -> MouseState {
+        match self {
+            MouseButton::Left => MOUSE_LEFT_BUTTON_UP,
+            MouseButton::Right => MOUSE_RIGHT_BUTTON_UP,
+            MouseButton::Middle => MOUSE_MIDDLE_BUTTON_UP,
+            MouseButton::Button4 => MOUSE_BUTTON_4_UP,
+            MouseButton::Button5 => MOUSE_BUTTON_5_UP,
+        }
+    }
+
+    /// Both state bits of this button
+    pub const fn flags(self) -> MouseState {
+        self.down_flag() | self.up_flag()
+    }
+
+    /// A stroke pressing this button without moving
+    pub fn down(self) -> MouseStroke {
+        MouseStroke::new(MOUSE_MOVE_RELATIVE, self.down_flag(), 0, 0, 0, 0)
+    }
+
+    /// A stroke releasing this button without moving
+    pub fn up(self) -> MouseStroke {
+        MouseStroke::new(MOUSE_MOVE_RELATIVE, self.up_flag(), 0, 0, 0, 0)
+    }
+}
+
+impl MouseButton {
+    /// The button's name: `left`, `right`, `middle`, `x1` or `x2`
+    pub const fn name(self) -> &'static str {
+        match self {
+            MouseButton::Left => "left",
+            MouseButton::Right => "right",
+            MouseButton::Middle => "middle",
+            MouseButton::Button4 => "x1",
+            MouseButton::Button5 => "x2",
+        }
+    }
+
+    /// Look up a button by name, ignoring case; `button4` and `button5` are accepted as well
+    pub fn from_name(name: &str) -> Option<MouseButton> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "button4" => Some(MouseButton::Button4),
+            "button5" => Some(MouseButton::Button5),
+            _ => MouseButton::ALL.into_iter().find(|b| b.name() == name),
+        }
+    }
+}
+
+impl Display for MouseButton {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl MouseStroke {
+    /// Whether this stroke presses the given button
+    pub fn is_down(&self, button: MouseButton) -> bool {
+        self.state & button.down_flag() != 0
+    }
+
+    /// Whether this stroke releases the given button
+    pub fn is_up(&self, button: MouseButton) -> bool {
+        self.state & button.up_flag() != 0
+    }
+
+    /// Whether this stroke presses any button
+    pub fn has_button_down(&self) -> bool {
+        self.state & MOUSE_BUTTONS_DOWN != 0
+    }
+
+    /// Whether this stroke carries nothing: no state bits and no relative movement
+    ///
+    /// Processors that strip bits from a stroke use this to decide whether the rest is
+    /// still worth sending.
+    pub fn is_empty(&self) -> bool {
+        self.state == 0 && self.flags & MOUSE_MOVE_ABSOLUTE == 0 && self.x == 0 && self.y == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_button_flags() {
+        let stroke = MouseStroke::new(
+            MOUSE_MOVE_RELATIVE,
+            MOUSE_LEFT_BUTTON_DOWN | MOUSE_BUTTON_4_UP,
+            0,
+            0,
+            0,
+            0,
+        );
+        assert!(stroke.is_down(MouseButton::Left));
+        assert!(!stroke.is_up(MouseButton::Left));
+        assert!(stroke.is_up(MouseButton::Button4));
+        assert!(stroke.has_button_down());
+        assert_eq!(MouseButton::Right.down().state, MOUSE_RIGHT_BUTTON_DOWN);
+    }
+}
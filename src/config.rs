@@ -0,0 +1,1131 @@
+//! TOML configuration for [`Rules`], with validation and hot reload.
+//!
+//! Requires the `config` feature. A complete file looks like this:
+//!
+//! ```toml
+//! # Which strokes to intercept and where in the chain of Interception clients to sit. These
+//! # apply to every device unless a selector below overrides them.
+//! keyboard_filter = "all"            # "all", "none" or a list of "down", "up", "e0", "e1"
+//! mouse_filter = ["button4-down", "button4-up"]
+//! precedence = 0
+//!
+//! # Device selectors: named groups of devices, matched by hardware ID substrings
+//! [devices]
+//! laptop = "ACPI\\PNP0303"
+//! trackball = { hardware_id = ["VID_047D&PID_2041"], mouse_filter = "all", precedence = 10 }
+//!
+//! # Remaps: press other switches instead
+//! [[remap]]
+//! from = "CapsLock"
+//! to = "LCtrl"                        # one switch, or a list pressed in order
+//! devices = ["laptop"]                # optional; every device by default
+//!
+//! [[remap]]
+//! from = "mouse:x1"
+//! to = ["LCtrl", "C"]
+//!
+//! # Dual-role keys: one thing when tapped, another when held
+//! [[dual_role]]
+//! key = "Space"
+//! tap = "Space"                       # optional; the key itself by default
+//! hold = "LShift"                     # switches to hold, or instead:
+//! # hold_layer = "nav"
+//! timeout = "200ms"                   # optional; milliseconds or "200ms"/"1s"
+//!
+//! # Layers: mappings that apply while a layer is active
+//! [layers.nav]
+//! activate = "RAlt"                   # optional; holding this activates the layer
+//! keys = { H = "Left", J = "Down", K = "Up", L = "Right", U = ["LCtrl", "Left"], Q = [] }
+//!
+//! # Macros: keystrokes in AutoHotkey `Send` syntax, typed on a US layout
+//! [[macro]]
+//! trigger = "F12"
+//! send = "Best regards,{Enter}Jane"
+//! ```
+//!
+//! Keys go by the names of [`key_from_name`] and mouse buttons by those of
+//! [`MouseButton::from_name`] behind `mouse:`, as in `mouse:middle`. See [`crate::rules`] for
+//! which rule wins when several apply.
+//!
+//! [`Config::parse`] checks the whole file and reports every problem it finds with its line
+//! and column. [`ConfigWatcher`] runs the rules and swaps in the new ones whenever the file
+//! changes and is valid, keeping the old ones otherwise.
+
+use crate::rules::{
+    DeviceSelector, Diagnostic, DualRoleRule, HoldAction, Layer, MacroRule, RemapRule, RuleSet,
+    Rules,
+};
+use crate::send_keys::{SendKeys, key_from_name};
+use crate::{
+    Event, FILTER_ALL, FILTER_KEY_ALL, FILTER_KEY_DOWN, FILTER_KEY_E0, FILTER_KEY_E1,
+    FILTER_KEY_UP, FILTER_MOUSE_BUTTON_4_DOWN, FILTER_MOUSE_BUTTON_4_UP,
+    FILTER_MOUSE_BUTTON_5_DOWN, FILTER_MOUSE_BUTTON_5_UP, FILTER_MOUSE_HWHEEL,
+    FILTER_MOUSE_LEFT_BUTTON_DOWN, FILTER_MOUSE_LEFT_BUTTON_UP, FILTER_MOUSE_MIDDLE_BUTTON_DOWN,
+    FILTER_MOUSE_MIDDLE_BUTTON_UP, FILTER_MOUSE_MOVE, FILTER_MOUSE_NONE,
+    FILTER_MOUSE_RIGHT_BUTTON_DOWN, FILTER_MOUSE_RIGHT_BUTTON_UP, FILTER_MOUSE_WHEEL, FILTER_NONE,
+    Filter, KeyFilter, MouseButton, MouseFilter, Precedence, Processor, Switch,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use std::{fs, io};
+use toml::Spanned;
+use toml::de::{DeTable, DeValue};
+
+type Value<'i> = Spanned<DeValue<'i>>;
+
+type ReloadHook = Box<dyn FnMut(Result<&Config, &ConfigError>)>;
+
+/// How long a dual-role key has to be held to count as held, unless configured
+pub const DEFAULT_DUAL_ROLE_TIMEOUT: Duration = Duration::from_millis(200);
+
+const KEY_FILTERS: [(&str, KeyFilter); 4] = [
+    ("down", FILTER_KEY_DOWN),
+    ("up", FILTER_KEY_UP),
+    ("e0", FILTER_KEY_E0),
+    ("e1", FILTER_KEY_E1),
+];
+
+const MOUSE_FILTERS: [(&str, MouseFilter); 13] = [
+    ("left-down", FILTER_MOUSE_LEFT_BUTTON_DOWN),
+    ("left-up", FILTER_MOUSE_LEFT_BUTTON_UP),
+    ("right-down", FILTER_MOUSE_RIGHT_BUTTON_DOWN),
+    ("right-up", FILTER_MOUSE_RIGHT_BUTTON_UP),
+    ("middle-down", FILTER_MOUSE_MIDDLE_BUTTON_DOWN),
+    ("middle-up", FILTER_MOUSE_MIDDLE_BUTTON_UP),
+    ("button4-down", FILTER_MOUSE_BUTTON_4_DOWN),
+    ("button4-up", FILTER_MOUSE_BUTTON_4_UP),
+    ("button5-down", FILTER_MOUSE_BUTTON_5_DOWN),
+    ("button5-up", FILTER_MOUSE_BUTTON_5_UP),
+    ("wheel", FILTER_MOUSE_WHEEL),
+    ("hwheel", FILTER_MOUSE_HWHEEL),
+    ("move", FILTER_MOUSE_MOVE),
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    /// Every problem found, in file order
+    Invalid(Vec<Diagnostic>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{e}"),
+            ConfigError::Invalid(diagnostics) => {
+                for (i, diagnostic) in diagnostics.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{diagnostic}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+/// The filters and precedence set on a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceSettings {
+    pub keyboard_filter: KeyFilter,
+    pub mouse_filter: MouseFilter,
+    pub precedence: Precedence,
+}
+
+impl Default for DeviceSettings {
+    /// Every key stroke, no mouse strokes, precedence 0
+    fn default() -> Self {
+        Self {
+            keyboard_filter: FILTER_KEY_ALL,
+            mouse_filter: FILTER_MOUSE_NONE,
+            precedence: 0,
+        }
+    }
+}
+
+/// Settings a device selector overrides
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceOverride {
+    pub keyboard_filter: Option<KeyFilter>,
+    pub mouse_filter: Option<MouseFilter>,
+    pub precedence: Option<Precedence>,
+}
+
+/// A parsed and validated configuration file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub settings: DeviceSettings,
+    /// Overrides by selector name; a device takes those of the first selector it matches
+    pub overrides: Vec<(String, DeviceOverride)>,
+    pub rules: RuleSet,
+}
+
+impl Config {
+    /// Parse a configuration, reporting every problem in it
+    pub fn parse(source: &str) -> Result<Self, ConfigError> {
+        let (document, errors) = DeTable::parse_recoverable(source);
+        let mut parser = Parser {
+            source,
+            diagnostics: Vec::new(),
+        };
+        for error in errors {
+            parser.error(error.span().unwrap_or(0..0), error.message());
+        }
+        let config = parser.document(document.get_ref());
+        if parser.diagnostics.is_empty() {
+            return Ok(config);
+        }
+        let mut diagnostics = parser.diagnostics;
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+        Err(ConfigError::Invalid(diagnostics))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// The settings for a device, given its hardware ID
+    pub fn settings_for(&self, hardware_id: &str) -> DeviceSettings {
+        let mut settings = self.settings;
+        let matched = self.overrides.iter().find(|(name, _)| {
+            self.rules
+                .devices
+                .iter()
+                .any(|selector| selector.name == *name && selector.matches(hardware_id))
+        });
+        if let Some((_, overrides)) = matched {
+            settings.keyboard_filter = overrides
+                .keyboard_filter
+                .unwrap_or(settings.keyboard_filter);
+            settings.mouse_filter = overrides.mouse_filter.unwrap_or(settings.mouse_filter);
+            settings.precedence = overrides.precedence.unwrap_or(settings.precedence);
+        }
+        settings
+    }
+}
+
+/// Walks a parsed document, collecting diagnostics
+struct Parser<'s> {
+    source: &'s str,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Parser<'_> {
+    fn error(&mut self, span: Range<usize>, message: impl Into<String>) {
+        let diagnostic = Diagnostic::at(self.source, span.start, message);
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// A table, after reporting any keys in it that aren't `allowed`
+    fn table<'v, 'i>(
+        &mut self,
+        value: &'v Value<'i>,
+        what: &str,
+        allowed: &[&str],
+    ) -> Option<&'v DeTable<'i>> {
+        let DeValue::Table(table) = value.get_ref() else {
+            self.error(value.span(), format!("{what} must be a table"));
+            return None;
+        };
+        for (key, _) in table.iter() {
+            if !allowed.contains(&key.get_ref().as_ref()) {
+                let message = format!("unknown key `{}` in {what}", key.get_ref());
+                self.error(key.span(), message);
+            }
+        }
+        Some(table)
+    }
+
+    fn required<'v, 'i>(
+        &mut self,
+        table: &'v DeTable<'i>,
+        span: &Range<usize>,
+        key: &str,
+        what: &str,
+    ) -> Option<&'v Value<'i>> {
+        let value = table.get(key);
+        if value.is_none() {
+            self.error(span.clone(), format!("{what} is missing `{key}`"));
+        }
+        value
+    }
+
+    fn string<'v>(&mut self, value: &'v Value<'_>, key: &str) -> Option<&'v str> {
+        let string = value.get_ref().as_str();
+        if string.is_none() {
+            self.error(value.span(), format!("`{key}` must be a string"));
+        }
+        string
+    }
+
+    /// A string or an array of strings
+    fn strings(&mut self, value: &Value<'_>, key: &str) -> Vec<Spanned<String>> {
+        match value.get_ref() {
+            DeValue::String(string) => vec![Spanned::new(value.span(), string.to_string())],
+            DeValue::Array(array) => array
+                .iter()
+                .filter_map(|item| {
+                    let string = self.string(item, key)?;
+                    Some(Spanned::new(item.span(), string.to_string()))
+                })
+                .collect(),
+            _ => {
+                let message = format!("`{key}` must be a string or an array of strings");
+                self.error(value.span(), message);
+                Vec::new()
+            }
+        }
+    }
+
+    fn precedence(&mut self, value: &Value<'_>) -> Option<Precedence> {
+        let precedence = value
+            .get_ref()
+            .as_integer()
+            .and_then(|integer| i64::from_str_radix(integer.as_str(), integer.radix()).ok())
+            .and_then(|integer| Precedence::try_from(integer).ok());
+        if precedence.is_none() {
+            self.error(value.span(), "`precedence` must be a 32-bit integer");
+        }
+        precedence
+    }
+
+    fn duration(&mut self, value: &Value<'_>, key: &str) -> Option<Duration> {
+        let duration = match value.get_ref() {
+            DeValue::Integer(integer) => u64::from_str_radix(integer.as_str(), integer.radix())
+                .ok()
+                .map(Duration::from_millis),
+            DeValue::String(text) => {
+                if let Some(millis) = text.strip_suffix("ms") {
+                    millis.parse().ok().map(Duration::from_millis)
+                } else if let Some(secs) = text.strip_suffix('s') {
+                    secs.parse().ok().map(Duration::from_secs)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if duration.is_none() {
+            let message = format!("`{key}` must be milliseconds or a duration such as `200ms`");
+            self.error(value.span(), message);
+        }
+        duration
+    }
+
+    /// `"all"`, `"none"` or a list of the given flag names
+    fn filter(&mut self, value: &Value<'_>, key: &str, flags: &[(&str, Filter)]) -> Option<Filter> {
+        match value.get_ref().as_str() {
+            Some("all") => return Some(FILTER_ALL),
+            Some("none") => return Some(FILTER_NONE),
+            _ => {}
+        }
+        if !value.get_ref().is_array() {
+            let message = format!("`{key}` must be \"all\", \"none\" or a list of strokes");
+            self.error(value.span(), message);
+            return None;
+        }
+        let mut filter = 0;
+        let mut valid = true;
+        for name in self.strings(value, key) {
+            match flags.iter().find(|(flag, _)| *flag == name.get_ref()) {
+                Some((_, flag)) => filter |= flag,
+                None => {
+                    let known: Vec<&str> = flags.iter().map(|(flag, _)| *flag).collect();
+                    let message = format!(
+                        "unknown stroke `{}` in `{key}`; expected one of {}",
+                        name.get_ref(),
+                        known.join(", ")
+                    );
+                    self.error(name.span(), message);
+                    valid = false;
+                }
+            }
+        }
+        valid.then_some(filter)
+    }
+
+    fn switch(&mut self, name: &Spanned<String>) -> Option<Switch> {
+        let switch = match name.get_ref().strip_prefix("mouse:") {
+            Some(button) => MouseButton::from_name(button).map(Switch::Button),
+            None => key_from_name(name.get_ref()).map(Switch::Key),
+        };
+        if switch.is_none() {
+            let message = format!(
+                "unknown key or button `{}`; mouse buttons are written like `mouse:left`",
+                name.get_ref()
+            );
+            self.error(name.span(), message);
+        }
+        switch
+    }
+
+    /// A switch, or an array of switches
+    fn switches(&mut self, value: &Value<'_>, key: &str) -> Option<Vec<Switch>> {
+        let names = self.strings(value, key);
+        let switches: Vec<Switch> = names.iter().filter_map(|name| self.switch(name)).collect();
+        (switches.len() == names.len()).then_some(switches)
+    }
+
+    /// The selector names in a rule's `devices`, checked against those defined
+    fn selectors(&mut self, table: &DeTable<'_>, selectors: &[DeviceSelector]) -> Vec<String> {
+        let Some(value) = table.get("devices") else {
+            return Vec::new();
+        };
+        let mut names = Vec::new();
+        for name in self.strings(value, "devices") {
+            if selectors
+                .iter()
+                .any(|selector| selector.name == *name.get_ref())
+            {
+                names.push(name.into_inner());
+            } else {
+                let message = format!("unknown device selector `{}`", name.get_ref());
+                self.error(name.span(), message);
+            }
+        }
+        names
+    }
+
+    /// The tables of an array of tables such as `[[remap]]`
+    fn array_of_tables<'v, 'i>(&mut self, value: &'v Value<'i>, key: &str) -> &'v [Value<'i>] {
+        match value.get_ref() {
+            DeValue::Array(array) => array,
+            _ => {
+                let message = format!("`{key}` must be an array of tables, written `[[{key}]]`");
+                self.error(value.span(), message);
+                &[]
+            }
+        }
+    }
+
+    fn document(&mut self, document: &DeTable<'_>) -> Config {
+        let mut config = Config::default();
+        const TOP_LEVEL: [&str; 8] = [
+            "keyboard_filter",
+            "mouse_filter",
+            "precedence",
+            "devices",
+            "remap",
+            "dual_role",
+            "layers",
+            "macro",
+        ];
+        for (key, _) in document.iter() {
+            if !TOP_LEVEL.contains(&key.get_ref().as_ref()) {
+                self.error(key.span(), format!("unknown key `{}`", key.get_ref()));
+            }
+        }
+
+        if let Some(value) = document.get("keyboard_filter")
+            && let Some(filter) = self.filter(value, "keyboard_filter", &KEY_FILTERS)
+        {
+            config.settings.keyboard_filter = filter;
+        }
+        if let Some(value) = document.get("mouse_filter")
+            && let Some(filter) = self.filter(value, "mouse_filter", &MOUSE_FILTERS)
+        {
+            config.settings.mouse_filter = filter;
+        }
+        if let Some(value) = document.get("precedence")
+            && let Some(precedence) = self.precedence(value)
+        {
+            config.settings.precedence = precedence;
+        }
+
+        if let Some(value) = document.get("devices") {
+            self.devices(value, &mut config);
+        }
+        if let Some(value) = document.get("layers") {
+            self.layers(value, &mut config.rules);
+        }
+        if let Some(value) = document.get("remap") {
+            self.remaps(value, &mut config.rules);
+        }
+        if let Some(value) = document.get("dual_role") {
+            self.dual_roles(value, &mut config.rules);
+        }
+        if let Some(value) = document.get("macro") {
+            self.macros(value, &mut config.rules);
+        }
+        config
+    }
+
+    fn devices(&mut self, value: &Value<'_>, config: &mut Config) {
+        let DeValue::Table(devices) = value.get_ref() else {
+            self.error(
+                value.span(),
+                "`devices` must be a table of device selectors",
+            );
+            return;
+        };
+        for (name, value) in devices.iter() {
+            let what = format!("device selector `{}`", name.get_ref());
+            let (patterns, overrides) = match value.get_ref() {
+                DeValue::String(_) | DeValue::Array(_) => (
+                    self.strings(value, "hardware_id"),
+                    DeviceOverride::default(),
+                ),
+                _ => {
+                    const FIELDS: [&str; 4] = [
+                        "hardware_id",
+                        "keyboard_filter",
+                        "mouse_filter",
+                        "precedence",
+                    ];
+                    let Some(table) = self.table(value, &what, &FIELDS) else {
+                        continue;
+                    };
+                    let patterns = match self.required(table, &value.span(), "hardware_id", &what) {
+                        Some(value) => self.strings(value, "hardware_id"),
+                        None => Vec::new(),
+                    };
+                    let overrides = DeviceOverride {
+                        keyboard_filter: table
+                            .get("keyboard_filter")
+                            .and_then(|value| self.filter(value, "keyboard_filter", &KEY_FILTERS)),
+                        mouse_filter: table
+                            .get("mouse_filter")
+                            .and_then(|value| self.filter(value, "mouse_filter", &MOUSE_FILTERS)),
+                        precedence: table
+                            .get("precedence")
+                            .and_then(|value| self.precedence(value)),
+                    };
+                    (patterns, overrides)
+                }
+            };
+            for pattern in &patterns {
+                if pattern.get_ref().is_empty() {
+                    self.error(
+                        pattern.span(),
+                        "an empty `hardware_id` matches every device",
+                    );
+                }
+            }
+            let name = name.get_ref().to_string();
+            config.overrides.push((name.clone(), overrides));
+            config.rules.devices.push(DeviceSelector {
+                name,
+                patterns: patterns.into_iter().map(Spanned::into_inner).collect(),
+            });
+        }
+    }
+
+    fn layers(&mut self, value: &Value<'_>, rules: &mut RuleSet) {
+        let DeValue::Table(layers) = value.get_ref() else {
+            self.error(value.span(), "`layers` must be a table of layers");
+            return;
+        };
+        for (name, value) in layers.iter() {
+            let what = format!("layer `{}`", name.get_ref());
+            let Some(table) = self.table(value, &what, &["activate", "keys"]) else {
+                continue;
+            };
+            let mut layer = Layer {
+                name: name.get_ref().to_string(),
+                ..Layer::default()
+            };
+            if let Some(value) = table.get("activate")
+                && let Some(name) = self.string(value, "activate")
+            {
+                layer.activate = self.switch(&Spanned::new(value.span(), name.to_string()));
+            }
+            let keys = match table.get("keys") {
+                Some(value) => match value.get_ref() {
+                    DeValue::Table(keys) => keys.iter().collect(),
+                    _ => {
+                        self.error(value.span(), "`keys` must be a table of mappings");
+                        Vec::new()
+                    }
+                },
+                None => Vec::new(),
+            };
+            for (input, outputs) in keys {
+                let input = Spanned::new(input.span(), input.get_ref().to_string());
+                let (Some(input), Some(outputs)) =
+                    (self.switch(&input), self.switches(outputs, "keys"))
+                else {
+                    continue;
+                };
+                layer.mappings.insert(input, outputs);
+            }
+            rules.layers.push(layer);
+        }
+    }
+
+    fn remaps(&mut self, value: &Value<'_>, rules: &mut RuleSet) {
+        let mut seen: HashMap<(Switch, Vec<String>), usize> = HashMap::new();
+        for value in self.array_of_tables(value, "remap") {
+            let what = "`[[remap]]`";
+            let Some(table) = self.table(value, what, &["from", "to", "devices"]) else {
+                continue;
+            };
+            let span = value.span();
+            let from = self.required(table, &span, "from", what);
+            let to = self.required(table, &span, "to", what);
+            let from = from.and_then(|from| {
+                let name = self.string(from, "from")?;
+                let switch = self.switch(&Spanned::new(from.span(), name.to_string()))?;
+                Some((switch, from.span()))
+            });
+            let to = to.and_then(|to| self.switches(to, "to"));
+            let devices = self.selectors(table, &rules.devices);
+            let (Some((from, from_span)), Some(to)) = (from, to) else {
+                continue;
+            };
+
+            let line = self.source[..from_span.start].matches('\n').count() + 1;
+            if let Some(first) = seen.insert((from, devices.clone()), line) {
+                let message = format!("this switch is already remapped on line {first}");
+                self.error(from_span, message);
+            }
+            rules.remaps.push(RemapRule { from, to, devices });
+        }
+    }
+
+    fn dual_roles(&mut self, value: &Value<'_>, rules: &mut RuleSet) {
+        const FIELDS: [&str; 6] = ["key", "tap", "hold", "hold_layer", "timeout", "devices"];
+        for value in self.array_of_tables(value, "dual_role") {
+            let what = "`[[dual_role]]`";
+            let Some(table) = self.table(value, what, &FIELDS) else {
+                continue;
+            };
+            let span = value.span();
+            let switch = self.required(table, &span, "key", what).and_then(|key| {
+                let name = self.string(key, "key")?;
+                self.switch(&Spanned::new(key.span(), name.to_string()))
+            });
+            let tap = match table.get("tap") {
+                Some(tap) => self.switches(tap, "tap"),
+                None => switch.map(|switch| vec![switch]),
+            };
+            let hold = match (table.get("hold"), table.get("hold_layer")) {
+                (Some(hold), None) => self.switches(hold, "hold").map(HoldAction::Press),
+                (None, Some(layer)) => self.string(layer, "hold_layer").and_then(|name| {
+                    if rules.layers.iter().any(|layer| layer.name == name) {
+                        return Some(HoldAction::Layer(name.to_string()));
+                    }
+                    self.error(layer.span(), format!("unknown layer `{name}`"));
+                    None
+                }),
+                (Some(_), Some(layer)) => {
+                    self.error(layer.span(), "give either `hold` or `hold_layer`, not both");
+                    None
+                }
+                (None, None) => {
+                    self.error(span.clone(), format!("{what} needs `hold` or `hold_layer`"));
+                    None
+                }
+            };
+            let timeout = match table.get("timeout") {
+                Some(timeout) => self.duration(timeout, "timeout"),
+                None => Some(DEFAULT_DUAL_ROLE_TIMEOUT),
+            };
+            let devices = self.selectors(table, &rules.devices);
+            if let (Some(switch), Some(tap), Some(hold), Some(timeout)) =
+                (switch, tap, hold, timeout)
+            {
+                rules.dual_roles.push(DualRoleRule {
+                    switch,
+                    tap,
+                    hold,
+                    timeout,
+                    devices,
+                });
+            }
+        }
+    }
+
+    fn macros(&mut self, value: &Value<'_>, rules: &mut RuleSet) {
+        for value in self.array_of_tables(value, "macro") {
+            let what = "`[[macro]]`";
+            let Some(table) = self.table(value, what, &["trigger", "send", "devices"]) else {
+                continue;
+            };
+            let span = value.span();
+            let trigger = self
+                .required(table, &span, "trigger", what)
+                .and_then(|key| {
+                    let name = self.string(key, "trigger")?;
+                    self.switch(&Spanned::new(key.span(), name.to_string()))
+                });
+            let strokes = self.required(table, &span, "send", what).and_then(|send| {
+                let text = self.string(send, "send")?;
+                let keys = match SendKeys::parse(text) {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        // Point into the string, past its opening quote, if it is written out
+                        // as is; escapes and multi-line strings move the text around
+                        let span = send.span();
+                        let raw = &self.source[span.clone()];
+                        let quoted = raw == format!("\"{text}\"") || raw == format!("'{text}'");
+                        let start = if quoted {
+                            let offset: usize =
+                                text.chars().take(e.column - 1).map(char::len_utf8).sum();
+                            span.start + 1 + offset
+                        } else {
+                            span.start
+                        };
+                        self.error(start..start, format!("in `send`: {}", e.message));
+                        return None;
+                    }
+                };
+                match keys.strokes() {
+                    Ok(strokes) => Some(strokes),
+                    Err(e) => {
+                        self.error(send.span(), format!("in `send`: {e}"));
+                        None
+                    }
+                }
+            });
+            let devices = self.selectors(table, &rules.devices);
+            if let (Some(trigger), Some(strokes)) = (trigger, strokes) {
+                rules.macros.push(MacroRule {
+                    trigger,
+                    strokes,
+                    devices,
+                });
+            }
+        }
+    }
+}
+
+/// Runs the rules of a configuration file, reloading it when it changes
+///
+/// The file is checked every [poll interval](ConfigWatcher::with_poll_interval). A changed
+/// file that fails to load is reported to the [reload hook](ConfigWatcher::with_on_reload)
+/// and the rules in use are kept; a missing file is reported once, not again until it has
+/// reappeared. A valid file replaces the rules all at once through [`Rules::replace`], so keys
+/// held across the change are released as they were pressed.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    config: Config,
+    rules: Rules,
+    modified: Option<SystemTime>,
+    /// The file's contents when it was last loaded
+    source: String,
+    poll_interval: Duration,
+    next_poll: Instant,
+    settings_changed: bool,
+    /// Whether the last poll couldn't read the file
+    unreadable: bool,
+    on_reload: Option<ReloadHook>,
+}
+
+impl ConfigWatcher {
+    /// Load the configuration at `path`, checking it for changes every second
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, ConfigError> {
+        let path = path.into();
+        let modified = fs::metadata(&path)?.modified().ok();
+        let source = fs::read_to_string(&path)?;
+        let config = Config::parse(&source)?;
+        let poll_interval = Duration::from_secs(1);
+        Ok(Self {
+            path,
+            rules: Rules::new(config.rules.clone()),
+            config,
+            modified,
+            source,
+            poll_interval,
+            next_poll: Instant::now() + poll_interval,
+            settings_changed: false,
+            unreadable: false,
+            on_reload: None,
+        })
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self.next_poll = Instant::now() + poll_interval;
+        self
+    }
+
+    /// Call `hook` with the new configuration after every reload, or with the reason it failed
+    pub fn with_on_reload(
+        mut self,
+        hook: impl FnMut(Result<&Config, &ConfigError>) + 'static,
+    ) -> Self {
+        self.on_reload = Some(Box::new(hook));
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The configuration in use
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Reload the file if it changed; returns whether the rules were replaced
+    ///
+    /// A file whose modification time is unchanged isn't read; one without a modification time
+    /// is read and compared with what was loaded last.
+    pub fn reload(&mut self) -> Result<bool, ConfigError> {
+        let modified = fs::metadata(&self.path)?.modified().ok();
+        if modified == self.modified && modified.is_some() {
+            return Ok(false);
+        }
+        let source = fs::read_to_string(&self.path)?;
+        self.modified = modified;
+        if source == self.source {
+            return Ok(false);
+        }
+        let config = Config::parse(&source);
+        self.source = source;
+        self.apply(config?);
+        Ok(true)
+    }
+
+    /// Use `config` from now on
+    pub fn apply(&mut self, config: Config) {
+        self.settings_changed |= config.settings != self.config.settings
+            || config.overrides != self.config.overrides
+            || config.rules.devices != self.config.rules.devices;
+        self.rules.replace(config.rules.clone());
+        self.config = config;
+    }
+
+    /// Whether filters or precedence changed since the last call
+    pub fn take_settings_changed(&mut self) -> bool {
+        std::mem::take(&mut self.settings_changed)
+    }
+
+    fn poll(&mut self) {
+        let result = self.reload();
+        // A file that stays missing is reported once, not on every poll
+        let unreadable = matches!(result, Err(ConfigError::Io(_)));
+        let reported = unreadable && self.unreadable;
+        self.unreadable = unreadable;
+        if let Some(hook) = &mut self.on_reload {
+            match result {
+                Ok(false) => {}
+                Ok(true) => hook(Ok(&self.config)),
+                Err(_) if reported => {}
+                Err(e) => hook(Err(&e)),
+            }
+        }
+    }
+}
+
+impl Processor for ConfigWatcher {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        self.rules.process(event, now, out);
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        Some(match self.rules.deadline() {
+            Some(deadline) => deadline.min(self.next_poll),
+            None => self.next_poll,
+        })
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        if self
+            .rules
+            .deadline()
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.rules.tick(now, out);
+        }
+        if self.next_poll <= now {
+            self.next_poll = now + self.poll_interval;
+            self.poll();
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.rules.attach(device, hardware_id);
+    }
+}
+
+#[cfg(windows)]
+impl crate::Interception {
+    /// Set every device's filter and precedence as `config` says
+    pub fn apply_config(&mut self, config: &Config) -> crate::Result<()> {
+        for device in self.devices_mut() {
+            let hardware_id = device.get_hardware_id().unwrap_or_default();
+            let settings = config.settings_for(&hardware_id.to_string_lossy());
+            device.set_precedence(settings.precedence)?;
+            match device {
+                crate::Device::Keyboard(keyboard) => {
+                    keyboard.set_filter(settings.keyboard_filter)?
+                }
+                crate::Device::Mouse(mouse) => mouse.set_filter(settings.mouse_filter)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the rules of a configuration file, reloading it as it changes
+    ///
+    /// Filters and precedence are set from the configuration first and again whenever a reload
+    /// changes them. This only returns on error.
+    pub fn run_config(&mut self, watcher: &mut ConfigWatcher) -> crate::Result<()> {
+        loop {
+            self.apply_config(watcher.config())?;
+            self.run_while(watcher, |watcher| !watcher.settings_changed)?;
+            watcher.settings_changed = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FILTER_MOUSE_ALL, Key};
+
+    const FULL: &str = r#"
+keyboard_filter = "all"
+mouse_filter = ["button4-down", "button4-up"]
+precedence = 5
+
+[devices]
+laptop = "ACPI\\PNP0303"
+trackball = { hardware_id = ["VID_047D&PID_2041"], mouse_filter = "all", precedence = 10 }
+
+[[remap]]
+from = "CapsLock"
+to = "LCtrl"
+devices = ["laptop"]
+
+[[remap]]
+from = "mouse:x1"
+to = ["LCtrl", "C"]
+
+[[dual_role]]
+key = "Space"
+hold = "LShift"
+timeout = "150ms"
+
+[[dual_role]]
+key = "Tab"
+tap = ["Tab"]
+hold_layer = "nav"
+timeout = 300
+
+[layers.nav]
+activate = "RAlt"
+keys = { H = "Left", U = ["LCtrl", "Left"], Q = [] }
+
+[[macro]]
+trigger = "F12"
+send = "^a{Del}"
+"#;
+
+    fn diagnostics(source: &str) -> Vec<String> {
+        match Config::parse(source) {
+            Err(ConfigError::Invalid(diagnostics)) => {
+                diagnostics.iter().map(ToString::to_string).collect()
+            }
+            other => panic!("expected diagnostics, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_full_config() {
+        let config = Config::parse(FULL).unwrap();
+        assert_eq!(config.settings.precedence, 5);
+        assert_eq!(
+            config.settings.mouse_filter,
+            FILTER_MOUSE_BUTTON_4_DOWN | FILTER_MOUSE_BUTTON_4_UP
+        );
+        assert_eq!(
+            config.settings_for("HID\\VID_047D&PID_2041&MI_00"),
+            DeviceSettings {
+                keyboard_filter: FILTER_KEY_ALL,
+                mouse_filter: FILTER_MOUSE_ALL,
+                precedence: 10,
+            }
+        );
+        assert_eq!(config.settings_for("ACPI\\PNP0303"), config.settings);
+
+        let rules = &config.rules;
+        assert_eq!(
+            rules.devices[0],
+            DeviceSelector::new("laptop", ["ACPI\\PNP0303"])
+        );
+        assert_eq!(
+            rules.remaps[0],
+            RemapRule {
+                from: Key::CAPS_LOCK.into(),
+                to: vec![Key::LEFT_CTRL.into()],
+                devices: vec!["laptop".to_string()],
+            }
+        );
+        assert_eq!(rules.remaps[1].from, MouseButton::Button4.into());
+        assert_eq!(
+            rules.dual_roles[0],
+            DualRoleRule {
+                switch: Key::SPACE.into(),
+                tap: vec![Key::SPACE.into()],
+                hold: HoldAction::Press(vec![Key::LEFT_SHIFT.into()]),
+                timeout: Duration::from_millis(150),
+                devices: vec![],
+            }
+        );
+        assert_eq!(
+            rules.dual_roles[1].hold,
+            HoldAction::Layer("nav".to_string())
+        );
+        assert_eq!(rules.dual_roles[1].timeout, Duration::from_millis(300));
+        let nav = &rules.layers[0];
+        assert_eq!(nav.activate, Some(Key::RIGHT_ALT.into()));
+        assert_eq!(nav.mappings[&Key::Q.into()], vec![]);
+        assert_eq!(
+            nav.mappings[&Key::U.into()],
+            vec![Key::LEFT_CTRL.into(), Key::ARROW_LEFT.into()]
+        );
+        assert_eq!(rules.macros[0].trigger, Key::F12.into());
+        assert_eq!(rules.macros[0].strokes.len(), 6);
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let source = r#"
+keyboard_filter = ["down", "sideways"]
+precedence = "high"
+colour = "blue"
+
+[devices]
+desk = { hardware_id = "HID", filter = "all" }
+
+[[remap]]
+from = "CapsLok"
+to = ["LCtrl", "mouse:sixth"]
+devices = ["desk", "couch"]
+
+[[remap]]
+to = "A"
+
+[[dual_role]]
+key = "Space"
+hold = "LShift"
+hold_layer = "nav"
+timeout = "soon"
+
+[[dual_role]]
+key = "Tab"
+
+[[macro]]
+trigger = "F12"
+send = "{Bogus}"
+
+[[macro]]
+trigger = "F11"
+send = "\t{Bogus}"
+
+[layers.nav]
+activate = ["RAlt"]
+"#;
+        assert_eq!(
+            diagnostics(source),
+            [
+                "2:28: unknown stroke `sideways` in `keyboard_filter`; expected one of down, up, e0, e1",
+                "3:14: `precedence` must be a 32-bit integer",
+                "4:1: unknown key `colour`",
+                "7:31: unknown key `filter` in device selector `desk`",
+                "10:8: unknown key or button `CapsLok`; mouse buttons are written like `mouse:left`",
+                "11:16: unknown key or button `mouse:sixth`; mouse buttons are written like `mouse:left`",
+                "12:20: unknown device selector `couch`",
+                "14:1: `[[remap]]` is missing `from`",
+                "20:14: give either `hold` or `hold_layer`, not both",
+                "21:11: `timeout` must be milliseconds or a duration such as `200ms`",
+                "23:1: `[[dual_role]]` needs `hold` or `hold_layer`",
+                "28:10: in `send`: unknown key `Bogus`",
+                // With an escape in the string, the error points at the string
+                "32:8: in `send`: unknown key `Bogus`",
+                "35:12: `activate` must be a string",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors_and_duplicates() {
+        let source = "[[remap]]\nfrom = \"A\"\nto = \"B\"\n\n[[remap]]\nfrom = \"a\"\nto = \"C\"\n";
+        assert_eq!(
+            diagnostics(source),
+            ["6:8: this switch is already remapped on line 2"]
+        );
+        let errors = diagnostics("precedence = \n[[remap]\n");
+        assert!(errors.len() >= 2, "{errors:?}");
+        assert!(errors[0].starts_with("1:"), "{errors:?}");
+    }
+
+    #[test]
+    fn test_watcher_swaps_rules() {
+        let path = std::env::temp_dir().join(format!("interception-{}.toml", std::process::id()));
+        let remap = |to: &str| format!("[[remap]]\nfrom = \"CapsLock\"\nto = \"{to}\"\n");
+        fs::write(&path, remap("LCtrl")).unwrap();
+
+        let reloads = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let seen = reloads.clone();
+        let mut watcher = ConfigWatcher::new(&path)
+            .unwrap()
+            .with_poll_interval(Duration::ZERO)
+            .with_on_reload(move |result| seen.borrow_mut().push(result.is_ok()));
+        let now = Instant::now();
+        let mut out = Vec::new();
+        watcher.process(Event::new(0, Key::CAPS_LOCK.down()), now, &mut out);
+
+        // An invalid file is reported and ignored
+        watcher.modified = None;
+        fs::write(&path, remap("Nope")).unwrap();
+        watcher.tick(now, &mut out);
+        assert_eq!(watcher.config().rules.remaps[0].to, [Key::LEFT_CTRL.into()]);
+
+        watcher.modified = None;
+        fs::write(&path, remap("Esc")).unwrap();
+        watcher.tick(now, &mut out);
+        watcher.process(Event::new(0, Key::CAPS_LOCK.up()), now, &mut out);
+        watcher.process(Event::new(0, Key::CAPS_LOCK.down()), now, &mut out);
+
+        // Without a modification time, an unchanged file isn't reloaded
+        watcher.modified = None;
+        watcher.tick(now, &mut out);
+
+        // A missing file is reported once until it reappears
+        fs::remove_file(&path).unwrap();
+        watcher.tick(now, &mut out);
+        watcher.tick(now, &mut out);
+        fs::write(&path, remap("Esc")).unwrap();
+        watcher.tick(now, &mut out);
+        fs::remove_file(&path).unwrap();
+        watcher.tick(now, &mut out);
+
+        assert_eq!(*reloads.borrow(), [false, true, false, false]);
+        assert!(!watcher.take_settings_changed());
+        assert_eq!(
+            out,
+            [
+                Event::new(0, Key::LEFT_CTRL.down()),
+                Event::new(0, Key::LEFT_CTRL.up()),
+                Event::new(0, Key::ESCAPE.down()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,451 @@
+//! Chatter filtering for keys and mouse buttons.
+//!
+//! Worn switches bounce: a single press can arrive as down-up-down within a few
+//! milliseconds. [`Debounce`] suppresses such bounces per key and per mouse button, in one
+//! of two [`DebounceMode`]s:
+//!
+//! - **Eager** forwards a transition immediately and ignores further transitions of the same
+//!   switch for the threshold. If the switch has settled in a different state by the end of
+//!   the window, that state is forwarded then, so a genuine quick release is delayed but
+//!   never lost.
+//! - **Deferred** holds every transition back until the switch has stayed in the new state
+//!   for the threshold, so bounces never leave the processor at the cost of added latency.
+//!
+//! Each swallowed bounce, a transition followed by its reversal, counts as one chatter event.
+
+use crate::{Event, Key, MouseButton, MouseStroke, PerDevice, Processor, Stroke, Switch};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How bounces are suppressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebounceMode {
+    /// Forward the first transition at once, then ignore the switch for the threshold
+    Eager,
+    /// Forward a transition only once it has been stable for the threshold
+    Deferred,
+}
+
+/// Debounce thresholds for one device
+#[derive(Debug, Clone)]
+pub struct DebounceSettings {
+    mode: DebounceMode,
+    key_threshold: Duration,
+    button_threshold: Duration,
+    overrides: HashMap<Switch, Duration>,
+}
+
+impl Default for DebounceSettings {
+    /// Eager, 5ms for keys and 10ms for mouse buttons
+    fn default() -> Self {
+        Self::new(
+            DebounceMode::Eager,
+            Duration::from_millis(5),
+            Duration::from_millis(10),
+        )
+    }
+}
+
+impl DebounceSettings {
+    /// A zero threshold disables debouncing
+    pub fn new(mode: DebounceMode, key_threshold: Duration, button_threshold: Duration) -> Self {
+        Self {
+            mode,
+            key_threshold,
+            button_threshold,
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Use a different threshold for one key
+    pub fn with_key(mut self, key: Key, threshold: Duration) -> Self {
+        self.overrides.insert(Switch::Key(key), threshold);
+        self
+    }
+
+    /// Use a different threshold for one mouse button
+    pub fn with_button(mut self, button: MouseButton, threshold: Duration) -> Self {
+        self.overrides.insert(Switch::Button(button), threshold);
+        self
+    }
+
+    pub fn mode(&self) -> DebounceMode {
+        self.mode
+    }
+
+    /// The threshold for a switch
+    pub fn threshold(&self, switch: Switch) -> Duration {
+        self.overrides
+            .get(&switch)
+            .copied()
+            .unwrap_or(match switch {
+                Switch::Key(_) => self.key_threshold,
+                Switch::Button(_) => self.button_threshold,
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SwitchState {
+    /// State as forwarded
+    logical: bool,
+    /// State as last reported by the device
+    physical: bool,
+    /// When the eager window closes or the deferred transition becomes stable
+    due: Option<Instant>,
+    /// Stroke to send when the physical state is forwarded late
+    replay: Event,
+}
+
+/// Suppresses key and mouse button chatter
+#[derive(Debug, Clone)]
+pub struct Debounce {
+    settings: PerDevice<DebounceSettings>,
+    switches: HashMap<(usize, Switch), SwitchState>,
+    suppressed: HashMap<(usize, Switch), u64>,
+}
+
+impl Default for Debounce {
+    fn default() -> Self {
+        Self::new(DebounceSettings::default())
+    }
+}
+
+impl Debounce {
+    pub fn new(settings: DebounceSettings) -> Self {
+        Self::per_device(PerDevice::new(settings))
+    }
+
+    /// Use different settings for particular devices
+    pub fn per_device(settings: PerDevice<DebounceSettings>) -> Self {
+        Self {
+            settings,
+            switches: HashMap::new(),
+            suppressed: HashMap::new(),
+        }
+    }
+
+    /// Number of bounces swallowed for one switch on one device
+    pub fn suppressed(&self, device: usize, switch: Switch) -> u64 {
+        self.suppressed.get(&(device, switch)).copied().unwrap_or(0)
+    }
+
+    /// Number of bounces swallowed in total
+    pub fn total_suppressed(&self) -> u64 {
+        self.suppressed.values().sum()
+    }
+
+    /// Per-switch bounce counts, keyed by device index and switch
+    pub fn suppressed_counts(&self) -> &HashMap<(usize, Switch), u64> {
+        &self.suppressed
+    }
+
+    /// Feed one transition; returns whether it should be forwarded now
+    fn transition(
+        &mut self,
+        device: usize,
+        switch: Switch,
+        pressed: bool,
+        replay: Event,
+        now: Instant,
+    ) -> bool {
+        let settings = self.settings.get(device);
+        let threshold = settings.threshold(switch);
+        let mode = settings.mode();
+
+        let state = self
+            .switches
+            .entry((device, switch))
+            .or_insert(SwitchState {
+                logical: !pressed,
+                physical: !pressed,
+                due: None,
+                replay,
+            });
+
+        if pressed == state.physical {
+            // Keyboard autorepeat, or a redundant report; only pass it while settled
+            return state.due.is_none() && state.logical == pressed;
+        }
+        state.physical = pressed;
+        state.replay = replay;
+
+        if threshold.is_zero() {
+            state.logical = pressed;
+            state.due = None;
+            return true;
+        }
+
+        match mode {
+            DebounceMode::Eager => match state.due {
+                Some(_) => {
+                    if pressed == state.logical {
+                        *self.suppressed.entry((device, switch)).or_default() += 1;
+                    }
+                    false
+                }
+                None => {
+                    state.logical = pressed;
+                    state.due = Some(now + threshold);
+                    true
+                }
+            },
+            DebounceMode::Deferred => {
+                if pressed == state.logical {
+                    state.due = None;
+                    *self.suppressed.entry((device, switch)).or_default() += 1;
+                } else {
+                    state.due = Some(now + threshold);
+                }
+                false
+            }
+        }
+    }
+
+    fn process_mouse(
+        &mut self,
+        event: Event,
+        stroke: MouseStroke,
+        now: Instant,
+        out: &mut Vec<Event>,
+    ) {
+        let mut forwarded = stroke;
+        for button in MouseButton::ALL {
+            for pressed in [true, false] {
+                let flag = if pressed {
+                    button.down_flag()
+                } else {
+                    button.up_flag()
+                };
+                if stroke.state & flag == 0 {
+                    continue;
+                }
+                let replay = Event::new(
+                    event.device,
+                    if pressed { button.down() } else { button.up() },
+                );
+                if !self.transition(event.device, Switch::Button(button), pressed, replay, now) {
+                    forwarded.state &= !flag;
+                }
+            }
+        }
+
+        if forwarded == stroke || !forwarded.is_empty() {
+            out.push(Event::new(event.device, forwarded));
+        }
+    }
+}
+
+impl Processor for Debounce {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        match event.stroke {
+            Stroke::Key(stroke) => {
+                let switch = Switch::Key(stroke.key());
+                if self.transition(event.device, switch, stroke.is_down(), event, now) {
+                    out.push(event);
+                }
+            }
+            Stroke::Mouse(stroke) => self.process_mouse(event, stroke, now, out),
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.switches.values().filter_map(|state| state.due).min()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        let mut due: Vec<_> = self
+            .switches
+            .iter_mut()
+            .filter(|(_, state)| state.due.is_some_and(|due| due <= now))
+            .collect();
+        due.sort_by_key(|(_, state)| state.due);
+
+        for ((device, switch), state) in due {
+            state.due = None;
+            if state.physical == state.logical {
+                continue;
+            }
+            state.logical = state.physical;
+            out.push(state.replay);
+            // A late eager transition opens a new window of its own
+            let settings = self.settings.get(*device);
+            if settings.mode() == DebounceMode::Eager {
+                state.due = Some(now + settings.threshold(*switch));
+            }
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.settings.attach(device, hardware_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_KEYBOARD, MOUSE_LEFT_BUTTON_DOWN, MOUSE_MOVE_RELATIVE};
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn run(
+        debounce: &mut Debounce,
+        t0: Instant,
+        events: &[(u64, Event)],
+        until: u64,
+    ) -> Vec<Event> {
+        let mut out = Vec::new();
+        for &(at, event) in events {
+            while let Some(deadline) = debounce.deadline().filter(|d| *d <= t0 + ms(at)) {
+                debounce.tick(deadline, &mut out);
+            }
+            debounce.process(event, t0 + ms(at), &mut out);
+        }
+        while let Some(deadline) = debounce.deadline().filter(|d| *d <= t0 + ms(until)) {
+            debounce.tick(deadline, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_eager_swallows_bounce() {
+        let t0 = Instant::now();
+        let mut debounce =
+            Debounce::new(DebounceSettings::new(DebounceMode::Eager, ms(10), ms(10)));
+        let out = run(
+            &mut debounce,
+            t0,
+            &[
+                (0, Event::new(0, Key::A.down())),
+                (2, Event::new(0, Key::A.up())),
+                (3, Event::new(0, Key::A.down())),
+                (50, Event::new(0, Key::A.up())),
+            ],
+            100,
+        );
+        assert_eq!(
+            out,
+            vec![Event::new(0, Key::A.down()), Event::new(0, Key::A.up())]
+        );
+        assert_eq!(debounce.suppressed(0, Switch::Key(Key::A)), 1);
+    }
+
+    #[test]
+    fn test_eager_forwards_quick_release_late() {
+        let t0 = Instant::now();
+        let mut debounce =
+            Debounce::new(DebounceSettings::new(DebounceMode::Eager, ms(10), ms(10)));
+        let mut out = Vec::new();
+        debounce.process(Event::new(0, Key::A.down()), t0, &mut out);
+        debounce.process(Event::new(0, Key::A.up()), t0 + ms(4), &mut out);
+        assert_eq!(out, vec![Event::new(0, Key::A.down())]);
+
+        debounce.tick(t0 + ms(10), &mut out);
+        assert_eq!(
+            out,
+            vec![Event::new(0, Key::A.down()), Event::new(0, Key::A.up())]
+        );
+        assert_eq!(debounce.total_suppressed(), 0);
+    }
+
+    #[test]
+    fn test_deferred_waits_for_stable_state() {
+        let t0 = Instant::now();
+        let mut debounce = Debounce::new(DebounceSettings::new(
+            DebounceMode::Deferred,
+            ms(10),
+            ms(10),
+        ));
+        let out = run(
+            &mut debounce,
+            t0,
+            &[
+                (0, Event::new(0, Key::A.down())),
+                (2, Event::new(0, Key::A.up())),
+                (20, Event::new(0, Key::A.down())),
+                (22, Event::new(0, Key::A.up())),
+                (23, Event::new(0, Key::A.down())),
+                (40, Event::new(0, Key::A.up())),
+            ],
+            100,
+        );
+        assert_eq!(
+            out,
+            vec![Event::new(0, Key::A.down()), Event::new(0, Key::A.up())]
+        );
+        assert_eq!(debounce.suppressed(0, Switch::Key(Key::A)), 2);
+    }
+
+    #[test]
+    fn test_key_autorepeat_passes() {
+        let t0 = Instant::now();
+        let mut debounce = Debounce::default();
+        let out = run(
+            &mut debounce,
+            t0,
+            &[
+                (0, Event::new(0, Key::A.down())),
+                (500, Event::new(0, Key::A.down())),
+                (530, Event::new(0, Key::A.down())),
+            ],
+            1000,
+        );
+        assert_eq!(out.len(), 3);
+    }
+
+    #[test]
+    fn test_mouse_button_bits_stripped_but_motion_kept() {
+        let t0 = Instant::now();
+        let mut debounce =
+            Debounce::new(DebounceSettings::default().with_button(MouseButton::Right, ms(0)));
+        let moving_down = MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_LEFT_BUTTON_DOWN, 0, 3, 4, 0);
+        let out = run(
+            &mut debounce,
+            t0,
+            &[
+                (0, Event::new(MOUSE, MouseButton::Left.down())),
+                (3, Event::new(MOUSE, MouseButton::Left.up())),
+                (4, Event::new(MOUSE, moving_down)),
+                (5, Event::new(MOUSE, MouseButton::Right.down())),
+                (6, Event::new(MOUSE, MouseButton::Right.up())),
+            ],
+            100,
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseButton::Left.down()),
+                Event::new(MOUSE, MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, 3, 4, 0)),
+                Event::new(MOUSE, MouseButton::Right.down()),
+                Event::new(MOUSE, MouseButton::Right.up()),
+            ]
+        );
+        assert_eq!(
+            debounce.suppressed(MOUSE, Switch::Button(MouseButton::Left)),
+            1
+        );
+    }
+
+    #[test]
+    fn test_settings_per_device() {
+        let t0 = Instant::now();
+        let settings = PerDevice::new(DebounceSettings::new(DebounceMode::Eager, ms(0), ms(0)))
+            .with("VID_046D", DebounceSettings::default());
+        let mut debounce = Debounce::per_device(settings);
+        debounce.attach(MOUSE + 1, r"HID\VID_046D&PID_C52B");
+
+        let clicks = [
+            (0, Event::new(MOUSE, MouseButton::Left.down())),
+            (1, Event::new(MOUSE, MouseButton::Left.up())),
+            (2, Event::new(MOUSE + 1, MouseButton::Left.down())),
+            (3, Event::new(MOUSE + 1, MouseButton::Left.up())),
+            (4, Event::new(MOUSE + 1, MouseButton::Left.down())),
+        ];
+        let out = run(&mut debounce, t0, &clicks, 100);
+        assert_eq!(out, vec![clicks[0].1, clicks[1].1, clicks[2].1]);
+    }
+}
@@ -0,0 +1,545 @@
+//! Turning keystrokes back into the text they type.
+//!
+//! [`TextDecoder`] follows a keyboard's strokes the way Windows does: it tracks Shift, Ctrl,
+//! Alt and AltGr, toggles Caps Lock and Num Lock, looks keys up in a [`Layout`], composes dead
+//! keys with the character after them, and decodes Alt+numpad codes. What comes out is a
+//! stream of [`TextEvent`]s: characters and edits.
+//!
+//! The decoder sees keys, not the screen, so it can't know where the caret is. Keys that move
+//! the caret and shortcuts such as Ctrl+V emit [`TextEvent::Break`], after which text typed
+//! before is no longer known to be in front of the caret. Mouse clicks do the same but never
+//! reach a keyboard decoder; call [`TextDecoder::reset`] for them.
+
+use crate::text::{Layout, Modifiers, Output, UsQwerty, from_windows_1252};
+use crate::{Key, KeyStroke};
+use std::collections::BTreeSet;
+
+/// Something typing did to the text in front of the caret
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextEvent {
+    /// A character was typed; Enter types `'\n'` and Tab `'\t'`
+    Char(char),
+    /// The character before the caret was deleted
+    Backspace,
+    /// The character after the caret was deleted
+    Delete,
+    /// The caret moved or a shortcut ran; the text before the caret is unknown
+    Break,
+}
+
+impl TextEvent {
+    /// Apply this event to the text before the caret
+    pub fn apply(&self, text: &mut String) {
+        match self {
+            TextEvent::Char(c) => text.push(*c),
+            TextEvent::Backspace => {
+                text.pop();
+            }
+            TextEvent::Delete => {}
+            TextEvent::Break => text.clear(),
+        }
+    }
+}
+
+/// The numpad keys, which type digits with Num Lock on and move the caret with it off
+const NUMPAD: [(Key, char); 11] = [
+    (Key::NUMPAD_0, '0'),
+    (Key::NUMPAD_1, '1'),
+    (Key::NUMPAD_2, '2'),
+    (Key::NUMPAD_3, '3'),
+    (Key::NUMPAD_4, '4'),
+    (Key::NUMPAD_5, '5'),
+    (Key::NUMPAD_6, '6'),
+    (Key::NUMPAD_7, '7'),
+    (Key::NUMPAD_8, '8'),
+    (Key::NUMPAD_9, '9'),
+    (Key::NUMPAD_DECIMAL, '.'),
+];
+
+/// Numpad keys that type the same regardless of Num Lock
+const NUMPAD_OPERATORS: [(Key, char); 5] = [
+    (Key::NUMPAD_DIVIDE, '/'),
+    (Key::NUMPAD_MULTIPLY, '*'),
+    (Key::NUMPAD_SUBTRACT, '-'),
+    (Key::NUMPAD_ADD, '+'),
+    (Key::NUMPAD_ENTER, '\n'),
+];
+
+/// Keys that move the caret
+const NAVIGATION: [Key; 9] = [
+    Key::ARROW_LEFT,
+    Key::ARROW_RIGHT,
+    Key::ARROW_UP,
+    Key::ARROW_DOWN,
+    Key::HOME,
+    Key::END,
+    Key::PAGE_UP,
+    Key::PAGE_DOWN,
+    Key::ESCAPE,
+];
+
+/// An Alt+numpad code being typed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct AltCode {
+    hex: bool,
+    digits: String,
+}
+
+/// Decodes a keyboard's strokes into text
+#[derive(Debug, Clone)]
+pub struct TextDecoder<L = UsQwerty> {
+    layout: L,
+    held: BTreeSet<Key>,
+    caps_lock: bool,
+    num_lock: bool,
+    dead: Option<char>,
+    alt_code: Option<AltCode>,
+}
+
+impl Default for TextDecoder {
+    /// US QWERTY, Caps Lock off and Num Lock on
+    fn default() -> Self {
+        Self::new(UsQwerty)
+    }
+}
+
+impl<L: Layout> TextDecoder<L> {
+    /// Decode with `layout`, assuming Caps Lock is off and Num Lock on
+    pub fn new(layout: L) -> Self {
+        Self {
+            layout,
+            held: BTreeSet::new(),
+            caps_lock: false,
+            num_lock: true,
+            dead: None,
+            alt_code: None,
+        }
+    }
+
+    /// Set the initial Caps Lock state
+    pub fn with_caps_lock(mut self, on: bool) -> Self {
+        self.caps_lock = on;
+        self
+    }
+
+    /// Set the initial Num Lock state
+    pub fn with_num_lock(mut self, on: bool) -> Self {
+        self.num_lock = on;
+        self
+    }
+
+    pub fn layout(&self) -> &L {
+        &self.layout
+    }
+
+    pub fn caps_lock(&self) -> bool {
+        self.caps_lock
+    }
+
+    pub fn num_lock(&self) -> bool {
+        self.num_lock
+    }
+
+    /// The dead key waiting for the next character, if any
+    pub fn pending_dead_key(&self) -> Option<char> {
+        self.dead
+    }
+
+    /// Forget a pending dead key or Alt code, as after a mouse click
+    pub fn reset(&mut self) {
+        self.dead = None;
+        self.alt_code = None;
+    }
+
+    /// The modifiers as the layout sees them; Right Alt is AltGr
+    ///
+    /// On a layout without AltGr outputs, Right Alt is just Alt: whatever AltGr doesn't type
+    /// counts as a shortcut.
+    pub fn modifiers(&self) -> Modifiers {
+        let held = |keys: &[Key]| keys.iter().any(|key| self.held.contains(key));
+        Modifiers {
+            shift: held(&[Key::LEFT_SHIFT, Key::RIGHT_SHIFT]),
+            ctrl: held(&[Key::LEFT_CTRL, Key::RIGHT_CTRL, Key::RIGHT_ALT]),
+            alt: held(&[Key::LEFT_ALT, Key::RIGHT_ALT]),
+        }
+    }
+
+    /// Whether `key` is held down
+    pub fn is_held(&self, key: Key) -> bool {
+        self.held.contains(&key)
+    }
+
+    fn windows_key_held(&self) -> bool {
+        self.held.contains(&Key::LEFT_WIN) || self.held.contains(&Key::RIGHT_WIN)
+    }
+
+    /// Decode every stroke in turn
+    pub fn decode(&mut self, strokes: &[KeyStroke]) -> Vec<TextEvent> {
+        let mut out = Vec::new();
+        for stroke in strokes {
+            self.feed(stroke, &mut out);
+        }
+        out
+    }
+
+    /// Decode one stroke, pushing whatever it typed to `out`
+    pub fn feed(&mut self, stroke: &KeyStroke, out: &mut Vec<TextEvent>) {
+        let key = stroke.key();
+        if stroke.is_up() {
+            self.held.remove(&key);
+            if key == Key::LEFT_ALT {
+                self.finish_alt_code(out);
+            }
+            return;
+        }
+
+        let repeat = !self.held.insert(key);
+        if key.is_modifier() {
+            return;
+        }
+        match key {
+            Key::CAPS_LOCK => {
+                self.caps_lock ^= !repeat;
+                return;
+            }
+            Key::NUM_LOCK => {
+                self.num_lock ^= !repeat;
+                return;
+            }
+            _ => {}
+        }
+
+        let modifiers = self.modifiers();
+        if modifiers.alt && !modifiers.ctrl && self.alt_code_digit(key) {
+            return;
+        }
+        if (modifiers.ctrl ^ modifiers.alt) || self.windows_key_held() {
+            // A shortcut, which may do anything to the text
+            self.dead = None;
+            out.push(TextEvent::Break);
+            return;
+        }
+
+        let numpad = NUMPAD.iter().find(|(numpad, _)| *numpad == key);
+        if let Some((_, digit)) = numpad {
+            if self.num_lock && !modifiers.shift {
+                let output = match key {
+                    Key::NUMPAD_DECIMAL => self.layout.output(key, Modifiers::NONE, false),
+                    _ => None,
+                };
+                self.output(output.unwrap_or(Output::Char(*digit)), out);
+            } else if key != Key::NUMPAD_5 {
+                // Navigation, or Delete for the decimal key
+                self.dead = None;
+                out.push(if key == Key::NUMPAD_DECIMAL {
+                    TextEvent::Delete
+                } else {
+                    TextEvent::Break
+                });
+            }
+            return;
+        }
+        if let Some((_, c)) = NUMPAD_OPERATORS.iter().find(|(numpad, _)| *numpad == key) {
+            self.output(Output::Char(*c), out);
+            return;
+        }
+
+        match key {
+            Key::BACKSPACE => {
+                // Backspace cancels a pending dead key instead of deleting
+                if self.dead.take().is_none() {
+                    out.push(TextEvent::Backspace);
+                }
+            }
+            Key::DELETE => out.push(TextEvent::Delete),
+            Key::ENTER => self.output(Output::Char('\n'), out),
+            Key::TAB => self.output(Output::Char('\t'), out),
+            key if NAVIGATION.contains(&key) => {
+                self.dead = None;
+                out.push(TextEvent::Break);
+            }
+            key => match self.layout.output(key, modifiers, self.caps_lock) {
+                Some(output) => self.output(output, out),
+                None if modifiers.ctrl && modifiers.alt => {
+                    // Nothing on AltGr, so a Ctrl+Alt shortcut
+                    self.dead = None;
+                    out.push(TextEvent::Break);
+                }
+                None => {}
+            },
+        }
+    }
+
+    /// Emit a layout output, composing it with a pending dead key
+    fn output(&mut self, output: Output, out: &mut Vec<TextEvent>) {
+        let dead = self.dead.take();
+        match (dead, output) {
+            (None, Output::Dead(c)) => self.dead = Some(c),
+            (Some(dead), Output::Char(c)) => match self.layout.compose(dead, c) {
+                Some(composed) => out.push(TextEvent::Char(composed)),
+                None => out.extend([TextEvent::Char(dead), TextEvent::Char(c)]),
+            },
+            (Some(dead), Output::Dead(c)) => match self.layout.compose(dead, c) {
+                Some(composed) => out.push(TextEvent::Char(composed)),
+                None => out.extend([TextEvent::Char(dead), TextEvent::Char(c)]),
+            },
+            (dead, Output::Text(text)) => {
+                out.extend(dead.map(TextEvent::Char));
+                out.extend(text.chars().map(TextEvent::Char));
+            }
+            (None, Output::Char(c)) => out.push(TextEvent::Char(c)),
+        }
+    }
+
+    /// Collect a digit of an Alt code; returns false, dropping any code begun, if `key` isn't
+    /// part of one
+    fn alt_code_digit(&mut self, key: Key) -> bool {
+        if key == Key::NUMPAD_ADD && self.alt_code.is_none() {
+            self.alt_code = Some(AltCode {
+                hex: true,
+                digits: String::new(),
+            });
+            return true;
+        }
+        let digit = match NUMPAD.iter().find(|(numpad, _)| *numpad == key) {
+            Some((Key::NUMPAD_DECIMAL, _)) => None,
+            Some((_, digit)) => Some(*digit),
+            None if self.alt_code.as_ref().is_some_and(|code| code.hex) => {
+                match self.layout.output(key, Modifiers::NONE, false) {
+                    Some(Output::Char(c)) if c.is_ascii_hexdigit() => Some(c),
+                    _ => None,
+                }
+            }
+            None => None,
+        };
+        let Some(digit) = digit else {
+            self.alt_code = None;
+            return false;
+        };
+        self.alt_code.get_or_insert_default().digits.push(digit);
+        true
+    }
+
+    /// Alt was released: emit the character of the code typed, if any
+    ///
+    /// Codes are taken as Unicode code points, decimal or, after numpad `+`, hexadecimal,
+    /// except that decimal codes up to 255 with a leading `0` are Windows-1252, as in
+    /// [`Fallback::AltNumpad`](crate::text::Fallback::AltNumpad).
+    fn finish_alt_code(&mut self, out: &mut Vec<TextEvent>) {
+        let Some(code) = self.alt_code.take() else {
+            return;
+        };
+        let radix = if code.hex { 16 } else { 10 };
+        let Ok(value) = u32::from_str_radix(&code.digits, radix) else {
+            return;
+        };
+        let c = match u8::try_from(value) {
+            Ok(byte) if !code.hex && code.digits.starts_with('0') => Some(from_windows_1252(byte)),
+            _ => char::from_u32(value),
+        };
+        if let Some(c) = c {
+            self.output(Output::Char(c), out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::klc::KlcLayout;
+    use crate::text::Typist;
+
+    const GERMAN: &[u8] = include_bytes!("../layouts/kbdgr.klc");
+
+    fn taps(keys: &[Key]) -> Vec<KeyStroke> {
+        keys.iter().flat_map(|key| key.tap()).collect()
+    }
+
+    fn held(modifier: Key, keys: &[Key]) -> Vec<KeyStroke> {
+        let mut strokes = vec![modifier.down()];
+        strokes.extend(taps(keys));
+        strokes.push(modifier.up());
+        strokes
+    }
+
+    fn text(events: &[TextEvent]) -> String {
+        let mut text = String::new();
+        for event in events {
+            event.apply(&mut text);
+        }
+        text
+    }
+
+    #[test]
+    fn test_shift_and_caps_lock() {
+        let mut decoder = TextDecoder::default();
+        let mut strokes = held(Key::RIGHT_SHIFT, &[Key::H]);
+        strokes.extend(taps(&[Key::I, Key::CAPS_LOCK, Key::O, Key::DIGIT_1]));
+        strokes.extend(held(Key::LEFT_SHIFT, &[Key::K, Key::DIGIT_1]));
+        assert_eq!(text(&decoder.decode(&strokes)), "HiO1k!");
+        assert!(decoder.caps_lock());
+
+        // Autorepeat neither toggles Caps Lock again nor stops characters repeating
+        let strokes = [
+            Key::CAPS_LOCK.down(),
+            Key::CAPS_LOCK.down(),
+            Key::CAPS_LOCK.up(),
+            Key::X.down(),
+            Key::X.down(),
+            Key::X.up(),
+        ];
+        assert_eq!(text(&decoder.decode(&strokes)), "xx");
+        assert!(!decoder.caps_lock());
+    }
+
+    #[test]
+    fn test_backspace_and_navigation() {
+        let mut decoder = TextDecoder::default();
+        let strokes = taps(&[
+            Key::A,
+            Key::B,
+            Key::BACKSPACE,
+            Key::C,
+            Key::ARROW_LEFT,
+            Key::D,
+            Key::DELETE,
+            Key::ENTER,
+        ]);
+        assert_eq!(
+            decoder.decode(&strokes),
+            vec![
+                TextEvent::Char('a'),
+                TextEvent::Char('b'),
+                TextEvent::Backspace,
+                TextEvent::Char('c'),
+                TextEvent::Break,
+                TextEvent::Char('d'),
+                TextEvent::Delete,
+                TextEvent::Char('\n'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shortcuts_break() {
+        let mut decoder = TextDecoder::default();
+        let mut strokes = taps(&[Key::A]);
+        strokes.extend(held(Key::LEFT_CTRL, &[Key::V]));
+        strokes.extend(held(Key::LEFT_WIN, &[Key::D]));
+        strokes.extend(taps(&[Key::B]));
+        // US QWERTY has nothing on AltGr, so Right Alt is a plain Alt
+        strokes.extend(held(Key::RIGHT_ALT, &[Key::Q]));
+        assert_eq!(
+            decoder.decode(&strokes),
+            vec![
+                TextEvent::Char('a'),
+                TextEvent::Break,
+                TextEvent::Break,
+                TextEvent::Char('b'),
+                TextEvent::Break,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_num_lock() {
+        let mut decoder = TextDecoder::default();
+        let strokes = taps(&[
+            Key::NUMPAD_1,
+            Key::NUMPAD_DECIMAL,
+            Key::NUMPAD_ADD,
+            Key::NUM_LOCK,
+            Key::NUMPAD_4,
+            Key::NUMPAD_5,
+            Key::NUMPAD_DECIMAL,
+            Key::NUMPAD_MULTIPLY,
+        ]);
+        assert_eq!(
+            decoder.decode(&strokes),
+            vec![
+                TextEvent::Char('1'),
+                TextEvent::Char('.'),
+                TextEvent::Char('+'),
+                TextEvent::Break,
+                TextEvent::Delete,
+                TextEvent::Char('*'),
+            ]
+        );
+        assert!(!decoder.num_lock());
+    }
+
+    #[test]
+    fn test_german_altgr_and_dead_keys() {
+        let layout = KlcLayout::from_bytes(GERMAN).unwrap();
+        let mut decoder = TextDecoder::new(&layout);
+
+        let mut strokes = held(Key::RIGHT_ALT, &[Key::Q, Key::E]);
+        // ´ then e, ^ then space, ´ then x, ` then ´
+        strokes.extend(taps(&[Key::EQUAL, Key::E, Key::BACKQUOTE, Key::SPACE]));
+        strokes.extend(taps(&[Key::EQUAL, Key::X]));
+        strokes.extend(held(Key::LEFT_SHIFT, &[Key::EQUAL]));
+        strokes.extend(taps(&[Key::EQUAL]));
+        // Numpad decimal types the layout's comma
+        strokes.extend(taps(&[Key::Y, Key::NUMPAD_DECIMAL]));
+        assert_eq!(text(&decoder.decode(&strokes)), "@€é^´x`´z,");
+
+        // Caps Lock applies to umlauts but not digits
+        let mut strokes = taps(&[Key::CAPS_LOCK, Key::QUOTE, Key::DIGIT_2]);
+        // Backspace cancels a pending dead key
+        strokes.extend(taps(&[Key::EQUAL, Key::BACKSPACE, Key::A]));
+        assert_eq!(text(&decoder.decode(&strokes)), "Ä2A");
+        assert_eq!(decoder.pending_dead_key(), None);
+
+        // AltGr+A types nothing, so it is a shortcut
+        let strokes = held(Key::RIGHT_ALT, &[Key::A]);
+        assert_eq!(decoder.decode(&strokes), vec![TextEvent::Break]);
+    }
+
+    #[test]
+    fn test_alt_codes() {
+        let mut decoder = TextDecoder::default();
+        let mut strokes = held(
+            Key::LEFT_ALT,
+            &[Key::NUMPAD_0, Key::NUMPAD_2, Key::NUMPAD_3, Key::NUMPAD_3],
+        );
+        strokes.extend(held(
+            Key::LEFT_ALT,
+            &[
+                Key::NUMPAD_ADD,
+                Key::NUMPAD_2,
+                Key::NUMPAD_0,
+                Key::A,
+                Key::C,
+            ],
+        ));
+        assert_eq!(text(&decoder.decode(&strokes)), "é€");
+
+        // A leading 0 means Windows-1252, where 128 is the euro sign
+        let mut strokes = held(
+            Key::LEFT_ALT,
+            &[Key::NUMPAD_0, Key::NUMPAD_1, Key::NUMPAD_2, Key::NUMPAD_8],
+        );
+        // A shortcut drops the code begun
+        strokes.extend(held(Key::LEFT_ALT, &[Key::NUMPAD_6, Key::F]));
+        assert_eq!(
+            decoder.decode(&strokes),
+            vec![TextEvent::Char('€'), TextEvent::Break]
+        );
+    }
+
+    #[test]
+    fn test_round_trips_typist() {
+        let layout = KlcLayout::from_bytes(GERMAN).unwrap();
+        let sample = "Grüße, Zoë! (a@b.de) ^2 = ³ € 100 ÀÉÎ\n\tñ";
+        let typed = Typist::new(&layout).type_text(sample).unwrap();
+        let mut decoder = TextDecoder::new(&layout);
+        assert_eq!(text(&decoder.decode(&typed.strokes())), sample);
+
+        let sample = "The quick brown fox jumps over the lazy dog: 0123456789 ~!@#$%^&*()_+{}|<>?";
+        let typed = Typist::default().type_text(sample).unwrap();
+        assert_eq!(
+            text(&TextDecoder::default().decode(&typed.strokes())),
+            sample
+        );
+    }
+}
@@ -0,0 +1,504 @@
+//! Scrolling by moving the mouse while a button is held.
+//!
+//! Trackballs have no wheel, or a poor one. [`DragScroll`] turns movement made while a chosen
+//! button is held into [`MOUSE_WHEEL`](crate::MOUSE_WHEEL) and
+//! [`MOUSE_HWHEEL`](crate::MOUSE_HWHEEL) strokes. The button-down is withheld until it is
+//! clear what the press was: once movement passes a small threshold it becomes a scroll and
+//! the button never reaches the system, and if the button comes back up first the press and
+//! release are sent together as an ordinary click. Movement made in the meantime is held back
+//! with the press and follows it.
+//!
+//! With [`Inertia`], scrolling coasts on after the button is released while still moving,
+//! slowing down until it stops.
+
+use crate::wheel::WheelAxis;
+use crate::{Event, MouseButton, MouseStroke, PerDevice, Processor, Remainder, Stroke};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Which axes scrolling follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snap {
+    /// Scroll both wheels freely
+    Free,
+    /// Scroll only the wheel the gesture started along
+    Dominant,
+    /// Scroll only the vertical wheel
+    Vertical,
+}
+
+/// Coasting after the button is released mid-scroll
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Inertia {
+    /// How often coasting scroll strokes are sent
+    pub interval: Duration,
+    /// Fraction of the speed kept after each interval
+    pub decay: f64,
+    /// Coasting stops below this speed, in wheel units per second
+    pub min_speed: f64,
+    /// The button must be released within this long of the last movement to coast
+    pub release_window: Duration,
+}
+
+impl Default for Inertia {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(16),
+            decay: 0.9,
+            min_speed: 120.0,
+            release_window: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Drag-to-scroll configuration for one device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DragScrollSettings {
+    pub button: MouseButton,
+    /// Wheel units per count of movement
+    pub speed: f64,
+    /// Movement, in counts, a press may make and still count as a click
+    pub threshold: f64,
+    pub snap: Snap,
+    /// Move the content with the pointer, as when dragging a page, instead of scrolling toward
+    /// the movement
+    pub natural: bool,
+    pub inertia: Option<Inertia>,
+}
+
+impl DragScrollSettings {
+    /// Scroll while `button` is held, at 10 wheel units per count, snapping to the dominant
+    /// axis and without inertia
+    pub fn new(button: MouseButton) -> Self {
+        Self {
+            button,
+            speed: 10.0,
+            threshold: 3.0,
+            snap: Snap::Dominant,
+            natural: false,
+            inertia: None,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_snap(mut self, snap: Snap) -> Self {
+        self.snap = snap;
+        self
+    }
+
+    pub fn with_natural(mut self, natural: bool) -> Self {
+        self.natural = natural;
+        self
+    }
+
+    pub fn with_inertia(mut self, inertia: Inertia) -> Self {
+        self.inertia = Some(inertia);
+        self
+    }
+
+    /// Fractional wheel deltas, vertical and horizontal, for a movement
+    fn wheel(&self, x: i32, y: i32) -> (f64, f64) {
+        // Moving up scrolls up, which is a positive wheel delta but a negative Y
+        let sign = if self.natural { -1.0 } else { 1.0 };
+        (
+            sign * -f64::from(y) * self.speed,
+            sign * f64::from(x) * self.speed,
+        )
+    }
+}
+
+/// Wheel output of one scroll, with fractions carried over
+#[derive(Debug, Clone, Copy, Default)]
+struct Scroll {
+    axis: Option<WheelAxis>,
+    vertical: Remainder,
+    horizontal: Remainder,
+    /// Smoothed speed in wheel units per second, vertical and horizontal
+    velocity: (f64, f64),
+    last: Option<Instant>,
+}
+
+impl Scroll {
+    fn emit(&mut self, device: usize, (vertical, horizontal): (f64, f64), out: &mut Vec<Event>) {
+        let vertical = if self.axis == Some(WheelAxis::Horizontal) {
+            0.0
+        } else {
+            vertical
+        };
+        let horizontal = if self.axis == Some(WheelAxis::Vertical) {
+            0.0
+        } else {
+            horizontal
+        };
+
+        for (delta, remainder, stroke) in [
+            (
+                vertical,
+                &mut self.vertical,
+                MouseStroke::wheel as fn(i16) -> MouseStroke,
+            ),
+            (horizontal, &mut self.horizontal, MouseStroke::hwheel),
+        ] {
+            let delta = remainder.take(delta);
+            if delta != 0 {
+                let delta = delta.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+                out.push(Event::new(device, stroke(delta)));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Drag {
+    /// The button is down but withheld; movement so far
+    Pending {
+        x: i32,
+        y: i32,
+    },
+    Scrolling(Scroll),
+    /// Scrolling on after release; the next step is due at `next`
+    Coasting {
+        scroll: Scroll,
+        next: Instant,
+    },
+}
+
+/// Turns movement while a button is held into wheel scrolling
+#[derive(Debug, Clone)]
+pub struct DragScroll {
+    settings: PerDevice<DragScrollSettings>,
+    drags: HashMap<usize, Drag>,
+    /// Movement held back while a press is pending, sent after it if it is a click
+    withheld: HashMap<usize, Vec<MouseStroke>>,
+}
+
+impl DragScroll {
+    pub fn new(settings: DragScrollSettings) -> Self {
+        Self::per_device(PerDevice::new(settings))
+    }
+
+    /// Use different settings for particular devices
+    pub fn per_device(settings: PerDevice<DragScrollSettings>) -> Self {
+        Self {
+            settings,
+            drags: HashMap::new(),
+            withheld: HashMap::new(),
+        }
+    }
+
+    /// Whether a scroll gesture is in progress on `device`, including coasting
+    pub fn is_scrolling(&self, device: usize) -> bool {
+        matches!(
+            self.drags.get(&device),
+            Some(Drag::Scrolling(_) | Drag::Coasting { .. })
+        )
+    }
+
+    fn movement(
+        &mut self,
+        device: usize,
+        stroke: MouseStroke,
+        now: Instant,
+        out: &mut Vec<Event>,
+    ) -> bool {
+        let (x, y) = (stroke.x, stroke.y);
+        let settings = *self.settings.get(device);
+        let Some(drag) = self.drags.get_mut(&device) else {
+            return false;
+        };
+
+        match drag {
+            Drag::Pending { x: px, y: py } => {
+                *px += x;
+                *py += y;
+                let (x, y) = (*px, *py);
+                let motion = MouseStroke::new(stroke.flags, 0, 0, stroke.x, stroke.y, 0);
+                let withheld = self.withheld.entry(device).or_default();
+                withheld.push(motion);
+                if f64::from(x).hypot(f64::from(y)) > settings.threshold {
+                    withheld.clear();
+                    let axis = match settings.snap {
+                        Snap::Free => None,
+                        Snap::Vertical => Some(WheelAxis::Vertical),
+                        Snap::Dominant if y.abs() >= x.abs() => Some(WheelAxis::Vertical),
+                        Snap::Dominant => Some(WheelAxis::Horizontal),
+                    };
+                    let mut scroll = Scroll {
+                        axis,
+                        last: Some(now),
+                        ..Scroll::default()
+                    };
+                    scroll.emit(device, settings.wheel(x, y), out);
+                    *drag = Drag::Scrolling(scroll);
+                }
+            }
+            Drag::Scrolling(scroll) => {
+                let wheel = settings.wheel(x, y);
+                if let Some(last) = scroll.last {
+                    let elapsed = (now - last).max(Duration::from_millis(1)).as_secs_f64();
+                    let (v, h) = scroll.velocity;
+                    scroll.velocity =
+                        ((v + wheel.0 / elapsed) / 2.0, (h + wheel.1 / elapsed) / 2.0);
+                }
+                scroll.last = Some(now);
+                scroll.emit(device, wheel, out);
+            }
+            // Movement after release is ordinary movement
+            Drag::Coasting { .. } => return false,
+        }
+        true
+    }
+
+    fn release(&mut self, device: usize, now: Instant, out: &mut Vec<Event>) -> bool {
+        let settings = *self.settings.get(device);
+        match self.drags.remove(&device) {
+            Some(Drag::Pending { .. }) => {
+                out.push(Event::new(device, settings.button.down()));
+                let strokes = self.withheld.remove(&device).unwrap_or_default();
+                out.extend(strokes.into_iter().map(|s| Event::new(device, s)));
+                false
+            }
+            Some(Drag::Scrolling(scroll)) => {
+                if let Some(inertia) = settings.inertia
+                    && scroll
+                        .last
+                        .is_some_and(|last| now - last <= inertia.release_window)
+                {
+                    self.drags.insert(
+                        device,
+                        Drag::Coasting {
+                            scroll,
+                            next: now + inertia.interval,
+                        },
+                    );
+                }
+                true
+            }
+            Some(coasting @ Drag::Coasting { .. }) => {
+                self.drags.insert(device, coasting);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+impl Processor for DragScroll {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        let Stroke::Mouse(stroke) = event.stroke else {
+            out.push(event);
+            return;
+        };
+        let device = event.device;
+        let button = self.settings.get(device).button;
+        let mut remaining = stroke;
+
+        if stroke.is_down(button) {
+            // A new press stops coasting and is withheld until it proves to be a click
+            self.drags.insert(device, Drag::Pending { x: 0, y: 0 });
+            self.withheld.remove(&device);
+            remaining.state &= !button.down_flag();
+        }
+        if (stroke.x != 0 || stroke.y != 0) && self.movement(device, stroke, now, out) {
+            remaining.x = 0;
+            remaining.y = 0;
+        }
+        if stroke.is_up(button) && self.release(device, now, out) {
+            remaining.state &= !button.up_flag();
+        }
+
+        if remaining == stroke || !remaining.is_empty() {
+            out.push(Event::new(device, remaining));
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.drags
+            .values()
+            .filter_map(|drag| match drag {
+                Drag::Coasting { next, .. } => Some(*next),
+                _ => None,
+            })
+            .min()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        let mut stopped = Vec::new();
+        for (&device, drag) in &mut self.drags {
+            let Drag::Coasting { scroll, next } = drag else {
+                continue;
+            };
+            let Some(inertia) = self.settings.get(device).inertia else {
+                stopped.push(device);
+                continue;
+            };
+            while *next <= now {
+                let (v, h) = scroll.velocity;
+                let (v, h) = (v * inertia.decay, h * inertia.decay);
+                scroll.velocity = (v, h);
+                if v.hypot(h) < inertia.min_speed {
+                    stopped.push(device);
+                    break;
+                }
+                let seconds = inertia.interval.as_secs_f64();
+                scroll.emit(device, (v * seconds, h * seconds), out);
+                *next += inertia.interval;
+            }
+        }
+        for device in stopped {
+            self.drags.remove(&device);
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.settings.attach(device, hardware_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_KEYBOARD, MOUSE_MOVE_RELATIVE};
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn motion(x: i32, y: i32) -> MouseStroke {
+        MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, x, y, 0)
+    }
+
+    fn feed(drag: &mut DragScroll, t0: Instant, strokes: &[(u64, MouseStroke)]) -> Vec<Event> {
+        let mut out = Vec::new();
+        for &(at, stroke) in strokes {
+            drag.process(Event::new(MOUSE, stroke), t0 + ms(at), &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_click_without_movement_passes() {
+        let mut drag = DragScroll::new(DragScrollSettings::new(MouseButton::Button4));
+        let t0 = Instant::now();
+        let out = feed(
+            &mut drag,
+            t0,
+            &[
+                (0, MouseButton::Button4.down()),
+                (5, motion(1, 1)),
+                (10, MouseButton::Button4.up()),
+                (20, motion(4, 0)),
+            ],
+        );
+        // The small movement during the click follows the press
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseButton::Button4.down()),
+                Event::new(MOUSE, motion(1, 1)),
+                Event::new(MOUSE, MouseButton::Button4.up()),
+                Event::new(MOUSE, motion(4, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drag_scrolls_dominant_axis() {
+        let mut drag = DragScroll::new(DragScrollSettings::new(MouseButton::Button4));
+        let t0 = Instant::now();
+        let out = feed(
+            &mut drag,
+            t0,
+            &[
+                (0, MouseButton::Button4.down()),
+                (5, motion(1, -2)),
+                (10, motion(1, -2)),
+                (15, motion(5, 3)),
+                (20, MouseButton::Button4.up()),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseStroke::wheel(40)),
+                Event::new(MOUSE, MouseStroke::wheel(-30)),
+            ]
+        );
+        assert!(!drag.is_scrolling(MOUSE));
+    }
+
+    #[test]
+    fn test_free_scroll_and_natural() {
+        let settings = DragScrollSettings::new(MouseButton::Middle)
+            .with_snap(Snap::Free)
+            .with_natural(true)
+            .with_speed(2.5)
+            .with_threshold(0.0);
+        let mut drag = DragScroll::new(settings);
+        let out = feed(
+            &mut drag,
+            Instant::now(),
+            &[(0, MouseButton::Middle.down()), (1, motion(3, -1))],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseStroke::wheel(-2)),
+                Event::new(MOUSE, MouseStroke::hwheel(-7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inertia_coasts_and_stops() {
+        let inertia = Inertia {
+            interval: ms(10),
+            decay: 0.5,
+            min_speed: 1000.0,
+            release_window: ms(50),
+        };
+        let settings = DragScrollSettings::new(MouseButton::Button4).with_inertia(inertia);
+        let mut drag = DragScroll::new(settings);
+        let t0 = Instant::now();
+        let mut out = feed(
+            &mut drag,
+            t0,
+            &[
+                (0, MouseButton::Button4.down()),
+                (10, motion(0, -10)),
+                (20, motion(0, -10)),
+                (30, motion(0, -10)),
+                (35, MouseButton::Button4.up()),
+            ],
+        );
+        assert!(drag.is_scrolling(MOUSE));
+        out.clear();
+
+        // Speed is 7500 units/s at release, halving every 10ms until below 1000
+        let mut at = t0 + ms(35);
+        while let Some(deadline) = drag.deadline() {
+            at = deadline;
+            drag.tick(at, &mut out);
+        }
+        assert_eq!(at, t0 + ms(65));
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseStroke::wheel(37)),
+                Event::new(MOUSE, MouseStroke::wheel(19)),
+            ]
+        );
+        assert!(!drag.is_scrolling(MOUSE));
+    }
+}
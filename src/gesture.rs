@@ -0,0 +1,509 @@
+//! Mouse gestures drawn with a held button.
+//!
+//! [`Gestures`] records the path the mouse takes while the gesture button, by default the
+//! right button, is held, in the style of browser mouse gestures. The path is quantized into
+//! a sequence of [`Direction`]s by a [`Quantizer`]: every time the pointer has travelled a
+//! segment length from the last anchor, the direction of that segment is noted, and repeats
+//! of the same direction collapse into one. On release the sequence is looked up in a
+//! [`GestureLibrary`] and the matching [`Mapping`] is tapped.
+//!
+//! The button press is withheld while the gesture is drawn. If no direction was recorded the
+//! press and release are sent as an ordinary click; a path that doesn't match any gesture is
+//! dropped. A button held for the hold timeout without a direction being recorded isn't a
+//! gesture either: the press is sent then, so the button can still drag. Movement passes
+//! through unchanged, and absolute movement, as from a tablet, doesn't draw.
+
+use crate::remap::{ActiveDevices, Mapping};
+use crate::{Event, MOUSE_MOVE_ABSOLUTE, MouseButton, Processor, Stroke};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The direction of one segment of a gesture, in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+impl Direction {
+    /// The direction of a movement, with or without diagonals
+    pub fn of(x: f64, y: f64, diagonals: bool) -> Self {
+        if !diagonals {
+            return match (x.abs() >= y.abs(), x >= 0.0, y >= 0.0) {
+                (true, true, _) => Direction::Right,
+                (true, false, _) => Direction::Left,
+                (false, _, true) => Direction::Down,
+                (false, _, false) => Direction::Up,
+            };
+        }
+
+        // Eight sectors of 45 degrees, starting with Right centered on zero; Y points down
+        let sector = (y.atan2(x).to_degrees() / 45.0).round().rem_euclid(8.0) as u8;
+        match sector {
+            0 => Direction::Right,
+            1 => Direction::DownRight,
+            2 => Direction::Down,
+            3 => Direction::DownLeft,
+            4 => Direction::Left,
+            5 => Direction::UpLeft,
+            6 => Direction::Up,
+            _ => Direction::UpRight,
+        }
+    }
+
+    /// The direction a letter stands for: `U`, `D`, `L` and `R`, or `7`, `9`, `1` and `3` for
+    /// the diagonals as laid out on a numpad
+    pub fn from_char(c: char) -> Option<Self> {
+        Some(match c.to_ascii_uppercase() {
+            'U' | '8' => Direction::Up,
+            'D' | '2' => Direction::Down,
+            'L' | '4' => Direction::Left,
+            'R' | '6' => Direction::Right,
+            '7' => Direction::UpLeft,
+            '9' => Direction::UpRight,
+            '1' => Direction::DownLeft,
+            '3' => Direction::DownRight,
+            _ => return None,
+        })
+    }
+
+    /// Parse a sequence of direction letters such as `"DR"`
+    pub fn parse_sequence(s: &str) -> Option<Vec<Self>> {
+        s.chars().map(Self::from_char).collect()
+    }
+}
+
+/// Turns relative movement into a sequence of directions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantizer {
+    segment_length: f64,
+    diagonals: bool,
+    position: (i64, i64),
+    anchor: (i64, i64),
+    directions: Vec<Direction>,
+}
+
+impl Quantizer {
+    pub fn new(segment_length: f64, diagonals: bool) -> Self {
+        Self {
+            segment_length,
+            diagonals,
+            position: (0, 0),
+            anchor: (0, 0),
+            directions: Vec::new(),
+        }
+    }
+
+    /// Add one relative movement
+    pub fn push(&mut self, x: i32, y: i32) {
+        self.position.0 += i64::from(x);
+        self.position.1 += i64::from(y);
+        let dx = (self.position.0 - self.anchor.0) as f64;
+        let dy = (self.position.1 - self.anchor.1) as f64;
+        if dx.hypot(dy) < self.segment_length {
+            return;
+        }
+
+        let direction = Direction::of(dx, dy, self.diagonals);
+        if self.directions.last() != Some(&direction) {
+            self.directions.push(direction);
+        }
+        self.anchor = self.position;
+    }
+
+    /// The directions recorded so far
+    pub fn directions(&self) -> &[Direction] {
+        &self.directions
+    }
+
+    /// Quantize a whole recorded path
+    pub fn quantize(
+        moves: impl IntoIterator<Item = (i32, i32)>,
+        segment_length: f64,
+        diagonals: bool,
+    ) -> Vec<Direction> {
+        let mut quantizer = Self::new(segment_length, diagonals);
+        for (x, y) in moves {
+            quantizer.push(x, y);
+        }
+        quantizer.directions
+    }
+}
+
+/// Looks up what a drawn gesture does
+pub trait GestureLibrary {
+    /// The mapping to tap for a direction sequence, if any
+    fn recognize(&self, directions: &[Direction]) -> Option<&Mapping>;
+}
+
+/// Gestures matched against direction templates
+///
+/// A drawn sequence matches the template with the smallest edit distance to it, as long as
+/// that distance is within the tolerance; ties go to the template added first.
+#[derive(Debug, Clone, Default)]
+pub struct Templates {
+    templates: Vec<(Vec<Direction>, Mapping)>,
+    tolerance: usize,
+}
+
+impl Templates {
+    /// Templates that must be matched exactly
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept sequences up to `tolerance` insertions, deletions or substitutions away
+    pub fn with_tolerance(mut self, tolerance: usize) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with(
+        mut self,
+        directions: impl IntoIterator<Item = Direction>,
+        mapping: Mapping,
+    ) -> Self {
+        self.insert(directions, mapping);
+        self
+    }
+
+    pub fn insert(&mut self, directions: impl IntoIterator<Item = Direction>, mapping: Mapping) {
+        self.templates
+            .push((directions.into_iter().collect(), mapping));
+    }
+}
+
+impl GestureLibrary for Templates {
+    fn recognize(&self, directions: &[Direction]) -> Option<&Mapping> {
+        self.templates
+            .iter()
+            .map(|(template, mapping)| (edit_distance(template, directions), mapping))
+            .filter(|(distance, _)| *distance <= self.tolerance)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, mapping)| mapping)
+    }
+}
+
+/// Levenshtein distance between two direction sequences
+pub fn edit_distance(a: &[Direction], b: &[Direction]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x != y);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// A gesture being drawn on one device
+#[derive(Debug, Clone)]
+struct Drawing {
+    quantizer: Quantizer,
+    /// When the press is sent if no direction has been recorded by then
+    deadline: Instant,
+}
+
+/// Recognizes gestures drawn while a mouse button is held
+#[derive(Debug, Clone)]
+pub struct Gestures<L = Templates> {
+    library: L,
+    button: MouseButton,
+    segment_length: f64,
+    diagonals: bool,
+    hold_timeout: Duration,
+    drawing: HashMap<usize, Drawing>,
+    active: ActiveDevices,
+}
+
+impl<L: GestureLibrary> Gestures<L> {
+    /// Draw with the right button, in four directions with 20-count segments, and send the
+    /// press after 500ms without a direction
+    pub fn new(library: L) -> Self {
+        Self {
+            library,
+            button: MouseButton::Right,
+            segment_length: 20.0,
+            diagonals: false,
+            hold_timeout: Duration::from_millis(500),
+            drawing: HashMap::new(),
+            active: ActiveDevices::default(),
+        }
+    }
+
+    pub fn with_button(mut self, button: MouseButton) -> Self {
+        self.button = button;
+        self
+    }
+
+    /// Movement, in counts, that makes up one segment of a gesture
+    pub fn with_segment_length(mut self, segment_length: f64) -> Self {
+        self.segment_length = segment_length;
+        self
+    }
+
+    /// Recognize diagonal directions as well
+    pub fn with_diagonals(mut self, diagonals: bool) -> Self {
+        self.diagonals = diagonals;
+        self
+    }
+
+    /// How long the button can be held before the first direction, after which it is pressed
+    /// as usual
+    pub fn with_hold_timeout(mut self, timeout: Duration) -> Self {
+        self.hold_timeout = timeout;
+        self
+    }
+
+    pub fn library(&self) -> &L {
+        &self.library
+    }
+
+    pub fn library_mut(&mut self) -> &mut L {
+        &mut self.library
+    }
+
+    /// Tap the mapping a gesture is recognized as, if any
+    fn tap(&self, device: usize, directions: &[Direction], out: &mut Vec<Event>) {
+        let Some(mapping) = self.library.recognize(directions) else {
+            return;
+        };
+        let targets: Vec<_> = mapping
+            .outputs
+            .iter()
+            .map(|&output| {
+                let target = if output.is_key() {
+                    mapping.keyboard
+                } else {
+                    mapping.mouse
+                };
+                (output, self.active.resolve(output, device, target))
+            })
+            .collect();
+        for &(output, target) in &targets {
+            out.push(Event::new(target, output.press()));
+        }
+        for &(output, target) in targets.iter().rev() {
+            out.push(Event::new(target, output.release()));
+        }
+    }
+}
+
+impl<L: GestureLibrary> Processor for Gestures<L> {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        self.active.observe(event.device);
+        let Stroke::Mouse(stroke) = event.stroke else {
+            out.push(event);
+            return;
+        };
+        let device = event.device;
+        let mut remaining = stroke;
+
+        if stroke.is_down(self.button) {
+            remaining.state &= !self.button.down_flag();
+            let drawing = Drawing {
+                quantizer: Quantizer::new(self.segment_length, self.diagonals),
+                deadline: now + self.hold_timeout,
+            };
+            self.drawing.insert(device, drawing);
+        }
+        if let Some(drawing) = self.drawing.get_mut(&device)
+            && stroke.flags & MOUSE_MOVE_ABSOLUTE == 0
+        {
+            drawing.quantizer.push(stroke.x, stroke.y);
+        }
+        let mut taps = Vec::new();
+        if stroke.is_up(self.button)
+            && let Some(Drawing { quantizer, .. }) = self.drawing.remove(&device)
+        {
+            if quantizer.directions().is_empty() {
+                // Not a gesture; the press goes out ahead of the stroke releasing it
+                out.push(Event::new(device, self.button.down()));
+            } else {
+                remaining.state &= !self.button.up_flag();
+                self.tap(device, quantizer.directions(), &mut taps);
+            }
+        }
+
+        if remaining == stroke || !remaining.is_empty() {
+            out.push(Event::new(device, remaining));
+        }
+        out.extend(taps);
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.drawing
+            .values()
+            .filter(|drawing| drawing.quantizer.directions().is_empty())
+            .map(|drawing| drawing.deadline)
+            .min()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        let button = self.button;
+        self.drawing.retain(|&device, drawing| {
+            let held = drawing.deadline <= now && drawing.quantizer.directions().is_empty();
+            if held {
+                out.push(Event::new(device, button.down()));
+            }
+            !held
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, MAX_KEYBOARD, MOUSE_MOVE_RELATIVE, MouseStroke};
+    use Direction::*;
+    use std::time::Duration;
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    fn motion(x: i32, y: i32) -> Event {
+        Event::new(MOUSE, MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, x, y, 0))
+    }
+
+    fn feed<L: GestureLibrary>(gestures: &mut Gestures<L>, events: &[Event]) -> Vec<Event> {
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for event in events {
+            gestures.process(*event, now, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_quantize_recorded_paths() {
+        // An "L": down, then right, with jitter along the way
+        let l = [
+            (1, 6),
+            (-1, 7),
+            (0, 8),
+            (1, 5),
+            (6, 1),
+            (7, -1),
+            (8, 0),
+            (5, 0),
+        ];
+        assert_eq!(Quantizer::quantize(l, 20.0, false), vec![Down, Right]);
+
+        let zigzag = [(10, -10), (10, -10), (-10, 10), (-10, 10)];
+        assert_eq!(Quantizer::quantize(zigzag, 20.0, false), vec![Right, Left]);
+        assert_eq!(
+            Quantizer::quantize(zigzag, 20.0, true),
+            vec![UpRight, DownLeft]
+        );
+
+        // Short wobbles never reach a segment
+        assert!(Quantizer::quantize([(3, 2), (-2, -3), (4, 1)], 20.0, false).is_empty());
+    }
+
+    #[test]
+    fn test_template_tolerance() {
+        let back = Mapping::new([Key::LEFT_ALT, Key::ARROW_LEFT]);
+        let templates = Templates::new()
+            .with(Direction::parse_sequence("LUR").unwrap(), back.clone())
+            .with_tolerance(1);
+        assert_eq!(templates.recognize(&[Left, Up, Right]), Some(&back));
+        assert_eq!(templates.recognize(&[Left, Right]), Some(&back));
+        assert_eq!(templates.recognize(&[Right]), None);
+        assert_eq!(edit_distance(&[Up, Down], &[Down, Up, Down]), 1);
+    }
+
+    #[test]
+    fn test_gesture_taps_mapping() {
+        let templates =
+            Templates::new().with([Left], Mapping::new([Key::LEFT_ALT, Key::ARROW_LEFT]));
+        let mut gestures = Gestures::new(templates);
+        let out = feed(
+            &mut gestures,
+            &[
+                Event::new(1, Key::A.up()),
+                Event::new(MOUSE, MouseButton::Right.down()),
+                motion(-15, 1),
+                motion(-15, -1),
+                Event::new(MOUSE, MouseButton::Right.up()),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(1, Key::A.up()),
+                motion(-15, 1),
+                motion(-15, -1),
+                Event::new(1, Key::LEFT_ALT.down()),
+                Event::new(1, Key::ARROW_LEFT.down()),
+                Event::new(1, Key::ARROW_LEFT.up()),
+                Event::new(1, Key::LEFT_ALT.up()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plain_click_and_unknown_gesture() {
+        let mut gestures = Gestures::new(Templates::new());
+        let out = feed(
+            &mut gestures,
+            &[
+                Event::new(MOUSE, MouseButton::Right.down()),
+                motion(2, 3),
+                Event::new(MOUSE, MouseButton::Right.up()),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                motion(2, 3),
+                Event::new(MOUSE, MouseButton::Right.down()),
+                Event::new(MOUSE, MouseButton::Right.up()),
+            ]
+        );
+
+        let out = feed(
+            &mut gestures,
+            &[
+                Event::new(MOUSE, MouseButton::Right.down()),
+                motion(0, 50),
+                Event::new(MOUSE, MouseButton::Right.up()),
+            ],
+        );
+        assert_eq!(out, vec![motion(0, 50)]);
+    }
+
+    #[test]
+    fn test_hold_timeout_presses_button() {
+        let templates = Templates::new().with([Down], Mapping::new([Key::ESCAPE]));
+        let mut gestures = Gestures::new(templates).with_hold_timeout(Duration::from_millis(300));
+        let t0 = Instant::now();
+        let mut out = Vec::new();
+        gestures.process(Event::new(MOUSE, MouseButton::Right.down()), t0, &mut out);
+        // Absolute movement doesn't draw, so nothing has been recorded yet
+        let absolute = MouseStroke::new(MOUSE_MOVE_ABSOLUTE, 0, 0, 0, 40000, 0);
+        gestures.process(Event::new(MOUSE, absolute), t0, &mut out);
+        assert_eq!(gestures.deadline(), Some(t0 + Duration::from_millis(300)));
+        gestures.tick(t0 + Duration::from_millis(300), &mut out);
+        assert_eq!(gestures.deadline(), None);
+
+        // The button drags as usual from then on
+        let later = [motion(0, 50), Event::new(MOUSE, MouseButton::Right.up())];
+        out.extend(feed(&mut gestures, &later));
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, absolute),
+                Event::new(MOUSE, MouseButton::Right.down()),
+                motion(0, 50),
+                Event::new(MOUSE, MouseButton::Right.up()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Hotkeys: keystrokes sent when a key is pressed with certain modifiers held.
+//!
+//! A [`Hotkey`] such as Ctrl+Alt+T, written `^!t` in AutoHotkey, fires when its key goes down
+//! while exactly its modifiers are held, on either side of the keyboard. [`HotkeyTable`] sends
+//! its strokes with the held modifiers released around them, and withholds the key's press and
+//! release unless the hotkey [passes it through](Hotkey::with_pass_through). Autorepeat fires
+//! a hotkey again, as it does in AutoHotkey.
+//!
+//! A modifier key can be a hotkey too, such as Right Alt alone; it fires when it goes down, and
+//! a withheld modifier doesn't count as held for other hotkeys.
+
+use crate::send_keys::Modifier;
+use crate::{Event, Key, KeyStroke, Processor, Stroke};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+/// Strokes sent when a key is pressed with modifiers held
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkey {
+    pub modifiers: Vec<Modifier>,
+    pub key: Key,
+    pub strokes: Vec<KeyStroke>,
+    /// Let the key through as well, like AutoHotkey's `~`
+    pub pass_through: bool,
+}
+
+impl Hotkey {
+    pub fn new(
+        modifiers: impl IntoIterator<Item = Modifier>,
+        key: Key,
+        strokes: Vec<KeyStroke>,
+    ) -> Self {
+        Self {
+            modifiers: modifiers.into_iter().collect(),
+            key,
+            strokes,
+            pass_through: false,
+        }
+    }
+
+    pub fn with_pass_through(mut self, pass_through: bool) -> Self {
+        self.pass_through = pass_through;
+        self
+    }
+
+    /// Whether pressing `key` with the modifier keys `held` fires this hotkey
+    ///
+    /// A modifier key doesn't count as holding its own modifier.
+    fn matches(&self, key: Key, held: &[Key]) -> bool {
+        key == self.key
+            && Modifier::ALL.into_iter().all(|modifier| {
+                let is_held = held
+                    .iter()
+                    .any(|held| *held != key && Modifier::from_key(*held) == Some(modifier));
+                self.modifiers.contains(&modifier) == is_held
+            })
+    }
+}
+
+/// Fires hotkeys pressed on any keyboard
+///
+/// When several hotkeys match, the first listed wins.
+#[derive(Debug, Clone, Default)]
+pub struct HotkeyTable {
+    hotkeys: Vec<Hotkey>,
+    /// Modifier keys held on each device, in the order they went down
+    held: HashMap<usize, Vec<Key>>,
+    /// Keys whose press fired a hotkey and was withheld; their release is dropped too
+    swallowed: HashSet<(usize, Key)>,
+}
+
+impl HotkeyTable {
+    pub fn new(hotkeys: impl IntoIterator<Item = Hotkey>) -> Self {
+        Self {
+            hotkeys: hotkeys.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn hotkeys(&self) -> &[Hotkey] {
+        &self.hotkeys
+    }
+}
+
+impl Processor for HotkeyTable {
+    fn process(&mut self, event: Event, _now: Instant, out: &mut Vec<Event>) {
+        let Stroke::Key(stroke) = event.stroke else {
+            out.push(event);
+            return;
+        };
+        let key = stroke.key();
+        let is_modifier = Modifier::from_key(key).is_some();
+        let held = self.held.entry(event.device).or_default();
+        if stroke.is_up() {
+            held.retain(|held| *held != key);
+            if !self.swallowed.remove(&(event.device, key)) {
+                out.push(event);
+            }
+            return;
+        }
+
+        let Some(hotkey) = self.hotkeys.iter().find(|hotkey| hotkey.matches(key, held)) else {
+            if is_modifier && !held.contains(&key) {
+                held.push(key);
+            }
+            out.push(event);
+            return;
+        };
+        let released = held.clone();
+        if hotkey.pass_through {
+            out.push(event);
+            if is_modifier && !held.contains(&key) {
+                held.push(key);
+            }
+        } else {
+            self.swallowed.insert((event.device, key));
+        }
+        let strokes = released
+            .iter()
+            .map(|key| key.up())
+            .chain(hotkey.strokes.iter().copied())
+            .chain(released.iter().map(|key| key.down()));
+        out.extend(strokes.map(|stroke| Event::new(event.device, stroke)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(hotkeys: &mut HotkeyTable, strokes: &[KeyStroke]) -> Vec<KeyStroke> {
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for stroke in strokes {
+            hotkeys.process(Event::new(0, *stroke), now, &mut out);
+        }
+        out.iter().map(|event| *event.key().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_fires_with_exact_modifiers() {
+        let mut hotkeys = HotkeyTable::new([Hotkey::new(
+            [Modifier::Ctrl, Modifier::Alt],
+            Key::T,
+            Key::F5.tap().to_vec(),
+        )]);
+        let out = process(
+            &mut hotkeys,
+            &[
+                Key::RIGHT_CTRL.down(),
+                Key::LEFT_ALT.down(),
+                Key::T.down(),
+                Key::T.up(),
+                Key::LEFT_SHIFT.down(),
+                Key::T.down(),
+                Key::T.up(),
+            ],
+        );
+        let mut expected = vec![
+            Key::RIGHT_CTRL.down(),
+            Key::LEFT_ALT.down(),
+            Key::RIGHT_CTRL.up(),
+            Key::LEFT_ALT.up(),
+        ];
+        expected.extend(Key::F5.tap());
+        expected.extend([Key::RIGHT_CTRL.down(), Key::LEFT_ALT.down()]);
+        // With Shift held too, it is a different combination
+        expected.extend([Key::LEFT_SHIFT.down(), Key::T.down(), Key::T.up()]);
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_modifier_as_hotkey() {
+        let mut hotkeys = HotkeyTable::new([
+            Hotkey::new([], Key::RIGHT_ALT, Key::X.tap().to_vec()),
+            Hotkey::new([Modifier::Alt], Key::T, Key::Y.tap().to_vec()),
+        ]);
+        let out = process(
+            &mut hotkeys,
+            &[
+                Key::RIGHT_ALT.down(),
+                // Right Alt was withheld, so this isn't Alt+T
+                Key::T.down(),
+                Key::T.up(),
+                Key::RIGHT_ALT.up(),
+                Key::LEFT_CTRL.down(),
+                Key::RIGHT_ALT.down(),
+            ],
+        );
+        let mut expected = Key::X.tap().to_vec();
+        expected.extend([Key::T.down(), Key::T.up(), Key::LEFT_CTRL.down()]);
+        // With Ctrl held, Right Alt alone isn't pressed
+        expected.push(Key::RIGHT_ALT.down());
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_pass_through() {
+        let mut hotkeys = HotkeyTable::new([
+            Hotkey::new([], Key::F1, Key::A.tap().to_vec()).with_pass_through(true),
+            Hotkey::new([], Key::F1, Key::B.tap().to_vec()),
+        ]);
+        let out = process(&mut hotkeys, &Key::F1.tap());
+        let mut expected = vec![Key::F1.down()];
+        expected.extend(Key::A.tap());
+        expected.push(Key::F1.up());
+        assert_eq!(out, expected);
+    }
+}
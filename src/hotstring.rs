@@ -0,0 +1,601 @@
+//! Text expansion: typed abbreviations replaced by longer text.
+//!
+//! A [`Hotstring`] pairs a trigger such as `;sig` with its expansion. [`HotstringMatcher`] is
+//! the driver-free core: fed the [`TextEvent`]s of one keyboard, it keeps the last few
+//! characters typed and looks them up in a trie of reversed triggers. [`Hotstrings`] wraps it
+//! as a [`Processor`] that decodes every keyboard separately, erases the trigger with
+//! Backspace and types the expansion in its place.
+//!
+//! As in AutoHotkey, a trigger only fires at the start of a word and once an ending character
+//! such as a space or punctuation follows it, unless it is [immediate](Hotstring::with_immediate)
+//! or allowed [inside words](Hotstring::with_inside_word). The expansion is sent in place of
+//! the stroke that completed the trigger and never goes back through the decoder, so an
+//! expansion containing a trigger does not fire again.
+
+use crate::decoder::{TextDecoder, TextEvent};
+use crate::text::{Layout, Typist, UsQwerty};
+use crate::{Event, Key, KeyStroke, MODIFIERS, Processor, Stroke};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::Instant;
+
+/// How the case of the typed trigger matters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Case {
+    /// Match any case and carry it over: a trigger typed in capitals expands in capitals, one
+    /// typed with a leading capital expands with a leading capital
+    #[default]
+    Propagate,
+    /// Match any case and expand exactly as written
+    Insensitive,
+    /// Only match the trigger exactly as written
+    Sensitive,
+}
+
+/// A trigger and the text it expands to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotstring {
+    pub trigger: String,
+    pub expansion: String,
+    pub case: Case,
+    /// Fire as soon as the last character of the trigger is typed
+    pub immediate: bool,
+    /// Fire even when the trigger follows a letter or digit
+    pub inside_word: bool,
+    /// Drop the ending character that completed the trigger instead of typing it
+    pub omit_ending: bool,
+}
+
+impl Hotstring {
+    /// Expand `trigger` to `expansion` once followed by an ending character, propagating case
+    pub fn new(trigger: impl Into<String>, expansion: impl Into<String>) -> Self {
+        Self {
+            trigger: trigger.into(),
+            expansion: expansion.into(),
+            case: Case::default(),
+            immediate: false,
+            inside_word: false,
+            omit_ending: false,
+        }
+    }
+
+    pub fn with_case(mut self, case: Case) -> Self {
+        self.case = case;
+        self
+    }
+
+    pub fn with_immediate(mut self, immediate: bool) -> Self {
+        self.immediate = immediate;
+        self
+    }
+
+    pub fn with_inside_word(mut self, inside_word: bool) -> Self {
+        self.inside_word = inside_word;
+        self
+    }
+
+    pub fn with_omit_ending(mut self, omit_ending: bool) -> Self {
+        self.omit_ending = omit_ending;
+        self
+    }
+
+    /// The expansion, with the case of `typed` carried over if the hotstring propagates case
+    fn expand(&self, typed: impl Iterator<Item = char>) -> String {
+        if self.case != Case::Propagate {
+            return self.expansion.clone();
+        }
+        let letters: Vec<char> = typed.filter(|c| c.is_alphabetic()).collect();
+        if letters.len() > 1 && letters.iter().all(|c| c.is_uppercase()) {
+            self.expansion.to_uppercase()
+        } else if letters.first().is_some_and(|c| c.is_uppercase())
+            && let Some(at) = self.expansion.find(char::is_alphabetic)
+        {
+            let (head, tail) = self.expansion.split_at(at);
+            let mut tail = tail.chars();
+            let first = tail.next().into_iter().flat_map(char::to_uppercase);
+            head.chars().chain(first).chain(tail).collect()
+        } else {
+            self.expansion.clone()
+        }
+    }
+}
+
+/// The characters that end a word and complete a trigger, as in AutoHotkey
+pub const DEFAULT_END_CHARS: &str = "-()[]{}':;\"/\\,.?!\n \t";
+
+/// What to do once a trigger has been typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    /// Index of the hotstring that fired
+    pub hotstring: usize,
+    /// Characters in front of the caret to delete; the character that completed the trigger
+    /// is not counted, as it should be withheld rather than typed
+    pub erase: usize,
+    /// The text to type in their place
+    pub text: String,
+    /// The ending character to type after the expansion, if kept
+    pub ending: Option<char>,
+}
+
+/// A node of the trie of reversed, case-folded triggers
+#[derive(Debug, Clone, Default)]
+struct Node {
+    children: BTreeMap<char, usize>,
+    /// Hotstrings whose trigger ends here
+    hotstrings: Vec<usize>,
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Matches the text typed on one keyboard against a set of hotstrings
+///
+/// When several triggers match, the hotstring listed first wins.
+#[derive(Debug, Clone)]
+pub struct HotstringMatcher {
+    hotstrings: Vec<Hotstring>,
+    trie: Vec<Node>,
+    end_chars: Vec<char>,
+    /// The last characters typed, enough for the longest trigger, its ending and the
+    /// character before it
+    typed: VecDeque<char>,
+    capacity: usize,
+}
+
+impl HotstringMatcher {
+    /// Match `hotstrings`, ended by [`DEFAULT_END_CHARS`]; empty triggers never fire
+    pub fn new(hotstrings: impl IntoIterator<Item = Hotstring>) -> Self {
+        let hotstrings: Vec<Hotstring> = hotstrings.into_iter().collect();
+        let mut trie = vec![Node::default()];
+        for (index, hotstring) in hotstrings.iter().enumerate() {
+            if hotstring.trigger.is_empty() {
+                continue;
+            }
+            let mut node = 0;
+            for c in hotstring.trigger.chars().rev().map(fold) {
+                node = match trie[node].children.get(&c) {
+                    Some(&child) => child,
+                    None => {
+                        trie.push(Node::default());
+                        let child = trie.len() - 1;
+                        trie[node].children.insert(c, child);
+                        child
+                    }
+                };
+            }
+            trie[node].hotstrings.push(index);
+        }
+        let longest = hotstrings
+            .iter()
+            .map(|hotstring| hotstring.trigger.chars().count())
+            .max()
+            .unwrap_or(0);
+        Self {
+            hotstrings,
+            trie,
+            end_chars: DEFAULT_END_CHARS.chars().collect(),
+            typed: VecDeque::new(),
+            capacity: longest + 2,
+        }
+    }
+
+    /// The characters that complete a trigger that isn't immediate
+    pub fn with_end_chars(mut self, end_chars: &str) -> Self {
+        self.end_chars = end_chars.chars().collect();
+        self
+    }
+
+    pub fn hotstrings(&self) -> &[Hotstring] {
+        &self.hotstrings
+    }
+
+    /// Forget what was typed
+    pub fn reset(&mut self) {
+        self.typed.clear();
+    }
+
+    /// Follow one edit, returning the expansion to perform if it completed a trigger
+    pub fn push(&mut self, event: &TextEvent) -> Option<Expansion> {
+        let c = self.follow(event)?;
+        let end = self.typed.len();
+        let (index, erase, ending) = match self.find(end, true) {
+            Some((index, len)) => (index, len - 1, None),
+            None if self.end_chars.contains(&c) => {
+                let (index, len) = self.find(end - 1, false)?;
+                let ending = (!self.hotstrings[index].omit_ending).then_some(c);
+                (index, len, ending)
+            }
+            None => return None,
+        };
+
+        let hotstring = &self.hotstrings[index];
+        let typed_trigger = self.typed.range(end - erase - 1..end);
+        let typed_trigger = typed_trigger.take(hotstring.trigger.chars().count());
+        let text = hotstring.expand(typed_trigger.copied());
+        self.typed.clear();
+        self.typed.extend(ending);
+        Some(Expansion {
+            hotstring: index,
+            erase,
+            text,
+            ending,
+        })
+    }
+
+    /// Follow one edit without completing a trigger, returning the character it typed
+    fn follow(&mut self, event: &TextEvent) -> Option<char> {
+        let c = match event {
+            TextEvent::Char(c) => *c,
+            TextEvent::Backspace => {
+                self.typed.pop_back();
+                return None;
+            }
+            TextEvent::Delete => return None,
+            TextEvent::Break => {
+                self.reset();
+                return None;
+            }
+        };
+        self.typed.push_back(c);
+        if self.typed.len() > self.capacity {
+            self.typed.pop_front();
+        }
+        Some(c)
+    }
+
+    /// The first hotstring whose trigger ends at `end`, with the trigger's length
+    fn find(&self, end: usize, immediate: bool) -> Option<(usize, usize)> {
+        let mut node = 0;
+        let mut found: Option<(usize, usize)> = None;
+        for (depth, at) in (0..end).rev().enumerate() {
+            match self.trie[node].children.get(&fold(self.typed[at])) {
+                Some(&child) => node = child,
+                None => break,
+            }
+            let len = depth + 1;
+            for &index in &self.trie[node].hotstrings {
+                let hotstring = &self.hotstrings[index];
+                if hotstring.immediate != immediate {
+                    continue;
+                }
+                if !hotstring.inside_word && at > 0 && self.typed[at - 1].is_alphanumeric() {
+                    continue;
+                }
+                if hotstring.case == Case::Sensitive
+                    && !self
+                        .typed
+                        .range(at..end)
+                        .copied()
+                        .eq(hotstring.trigger.chars())
+                {
+                    continue;
+                }
+                if found.is_none_or(|(first, _)| index < first) {
+                    found = Some((index, len));
+                }
+            }
+        }
+        found
+    }
+}
+
+/// The state kept for each keyboard
+#[derive(Debug, Clone)]
+struct Keyboard<L> {
+    decoder: TextDecoder<L>,
+    matcher: HotstringMatcher,
+    /// Keys whose down completed a trigger and was withheld; their up is dropped too
+    swallowed: HashSet<Key>,
+}
+
+/// Expands hotstrings typed on any keyboard
+///
+/// Only keys that type a single character complete a trigger; a trigger completed by a dead
+/// key sequence or an Alt code is not expanded, though its characters still count towards
+/// later triggers. The expansion is typed with a [`Typist`] over
+/// the same layout, with held modifiers released and Caps Lock turned off around it.
+#[derive(Debug, Clone)]
+pub struct Hotstrings<L = UsQwerty> {
+    matcher: HotstringMatcher,
+    typist: Typist<L>,
+    keyboards: HashMap<usize, Keyboard<L>>,
+}
+
+impl Hotstrings {
+    /// Expand `matcher`'s hotstrings as typed on a US QWERTY layout
+    pub fn new(matcher: HotstringMatcher) -> Self {
+        Self::with_layout(matcher, UsQwerty)
+    }
+}
+
+impl<L: Layout + Clone> Hotstrings<L> {
+    /// Expand `matcher`'s hotstrings, decoding and typing with `layout`
+    pub fn with_layout(matcher: HotstringMatcher, layout: L) -> Self {
+        Self {
+            matcher,
+            typist: Typist::new(layout),
+            keyboards: HashMap::new(),
+        }
+    }
+
+    /// Type expansions with `typist` instead, e.g. to change its fallback
+    pub fn with_typist(mut self, typist: Typist<L>) -> Self {
+        self.typist = typist;
+        self
+    }
+
+    /// Forget what was typed on every keyboard, as after a mouse click
+    pub fn reset(&mut self) {
+        for keyboard in self.keyboards.values_mut() {
+            keyboard.decoder.reset();
+            keyboard.matcher.reset();
+        }
+    }
+
+    /// The strokes performing `expansion`, or None if the text can't be typed
+    fn strokes(&self, keyboard: &Keyboard<L>, expansion: &Expansion) -> Option<Vec<KeyStroke>> {
+        let typed = self.typist.type_text(&expansion.text).ok()?;
+        let held: Vec<Key> = MODIFIERS
+            .into_iter()
+            .filter(|key| keyboard.decoder.is_held(*key))
+            .collect();
+        let caps_lock = keyboard.decoder.caps_lock();
+
+        let mut strokes = Vec::new();
+        strokes.extend(held.iter().map(|key| key.up()));
+        if caps_lock {
+            strokes.extend(Key::CAPS_LOCK.tap());
+        }
+        for _ in 0..expansion.erase {
+            strokes.extend(Key::BACKSPACE.tap());
+        }
+        strokes.extend(typed.strokes());
+        if caps_lock {
+            strokes.extend(Key::CAPS_LOCK.tap());
+        }
+        strokes.extend(held.iter().map(|key| key.down()));
+        Some(strokes)
+    }
+}
+
+impl<L: Layout + Clone> Processor for Hotstrings<L> {
+    fn process(&mut self, event: Event, _now: Instant, out: &mut Vec<Event>) {
+        let Stroke::Key(stroke) = event.stroke else {
+            out.push(event);
+            return;
+        };
+        let keyboard = self
+            .keyboards
+            .entry(event.device)
+            .or_insert_with(|| Keyboard {
+                decoder: TextDecoder::new(self.typist.layout().clone()),
+                matcher: self.matcher.clone(),
+                swallowed: HashSet::new(),
+            });
+        let key = stroke.key();
+        if stroke.is_up() && keyboard.swallowed.remove(&key) {
+            keyboard.decoder.feed(&stroke, &mut Vec::new());
+            return;
+        }
+
+        let mut edits = Vec::new();
+        keyboard.decoder.feed(&stroke, &mut edits);
+        let expansion = match edits.as_slice() {
+            [edit @ TextEvent::Char(_)] if stroke.is_down() => keyboard.matcher.push(edit),
+            _ => {
+                for edit in &edits {
+                    keyboard.matcher.follow(edit);
+                }
+                None
+            }
+        };
+        let Some(expansion) = expansion else {
+            out.push(event);
+            return;
+        };
+
+        let keyboard = &self.keyboards[&event.device];
+        let Some(strokes) = self.strokes(keyboard, &expansion) else {
+            out.push(event);
+            return;
+        };
+        out.extend(
+            strokes
+                .into_iter()
+                .map(|stroke| Event::new(event.device, stroke)),
+        );
+        if expansion.ending.is_some() {
+            out.push(event);
+        } else if let Some(keyboard) = self.keyboards.get_mut(&event.device) {
+            keyboard.swallowed.insert(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_text(matcher: &mut HotstringMatcher, text: &str) -> Vec<Expansion> {
+        text.chars()
+            .filter_map(|c| match c {
+                '\x08' => matcher.push(&TextEvent::Backspace),
+                '|' => matcher.push(&TextEvent::Break),
+                c => matcher.push(&TextEvent::Char(c)),
+            })
+            .collect()
+    }
+
+    fn expansion(hotstring: usize, erase: usize, text: &str, ending: Option<char>) -> Expansion {
+        Expansion {
+            hotstring,
+            erase,
+            text: text.to_string(),
+            ending,
+        }
+    }
+
+    #[test]
+    fn test_word_boundaries() {
+        let mut matcher = HotstringMatcher::new([
+            Hotstring::new("btw", "by the way"),
+            Hotstring::new("ing", "ING").with_inside_word(true),
+        ]);
+        assert_eq!(
+            type_text(&mut matcher, "btw "),
+            vec![expansion(0, 3, "by the way", Some(' '))]
+        );
+        // Not at the start of a word, not yet ended, and ended after a Break
+        assert!(type_text(&mut matcher, "abtw. btwx btw").is_empty());
+        assert_eq!(
+            type_text(&mut matcher, "|btw,"),
+            vec![expansion(0, 3, "by the way", Some(','))]
+        );
+        assert_eq!(
+            type_text(&mut matcher, "sing!"),
+            vec![expansion(1, 3, "ING", Some('!'))]
+        );
+        // Backspace edits what the trigger is matched against
+        assert_eq!(
+            type_text(&mut matcher, "btx\x08w\n"),
+            vec![expansion(0, 3, "by the way", Some('\n'))]
+        );
+    }
+
+    #[test]
+    fn test_immediate_and_omit_ending() {
+        let signature = "Best regards,\nJane";
+        let mut matcher = HotstringMatcher::new([
+            Hotstring::new(";sig", signature).with_immediate(true),
+            Hotstring::new("afaik", "as far as I know").with_omit_ending(true),
+        ]);
+        assert_eq!(
+            type_text(&mut matcher, ";sig"),
+            vec![expansion(0, 3, signature, None)]
+        );
+        assert_eq!(
+            type_text(&mut matcher, " afaik "),
+            vec![expansion(1, 5, "as far as I know", None)]
+        );
+    }
+
+    #[test]
+    fn test_case() {
+        let mut matcher = HotstringMatcher::new([
+            Hotstring::new("teh", "the"),
+            Hotstring::new("nyc", "New York").with_case(Case::Insensitive),
+            Hotstring::new("OK", "okay").with_case(Case::Sensitive),
+        ]);
+        let expansions = type_text(&mut matcher, "teh Teh TEH NYC ok OK ");
+        let texts: Vec<&str> = expansions.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["the", "The", "THE", "New York", "okay"]);
+    }
+
+    #[test]
+    fn test_first_listed_wins() {
+        let mut matcher = HotstringMatcher::new([
+            Hotstring::new("ab", "first").with_inside_word(true),
+            Hotstring::new("cab", "second"),
+            Hotstring::new("", "never").with_immediate(true),
+        ]);
+        assert_eq!(
+            type_text(&mut matcher, "cab "),
+            vec![expansion(0, 2, "first", Some(' '))]
+        );
+    }
+
+    fn process(hotstrings: &mut Hotstrings, strokes: &[KeyStroke]) -> Vec<KeyStroke> {
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for stroke in strokes {
+            hotstrings.process(Event::new(0, *stroke), now, &mut out);
+        }
+        out.iter().map(|event| *event.key().unwrap()).collect()
+    }
+
+    fn taps(keys: &[Key]) -> Vec<KeyStroke> {
+        keys.iter().flat_map(|key| key.tap()).collect()
+    }
+
+    #[test]
+    fn test_processor_replaces_trigger() {
+        let matcher = HotstringMatcher::new([Hotstring::new("btw", "btw!")]);
+        let mut hotstrings = Hotstrings::new(matcher);
+        let out = process(
+            &mut hotstrings,
+            &taps(&[Key::B, Key::T, Key::W, Key::SPACE]),
+        );
+
+        let mut expected = taps(&[Key::B, Key::T, Key::W]);
+        expected.extend(taps(&[Key::BACKSPACE; 3]));
+        expected.extend(taps(&[Key::B, Key::T, Key::W]));
+        expected.extend([Key::LEFT_SHIFT.down()]);
+        expected.extend(Key::DIGIT_1.tap());
+        expected.extend([Key::LEFT_SHIFT.up()]);
+        expected.extend(taps(&[Key::SPACE]));
+        assert_eq!(out, expected);
+
+        // The expansion contained the trigger, but isn't read back
+        let out = process(&mut hotstrings, &taps(&[Key::A, Key::SPACE]));
+        assert_eq!(out, taps(&[Key::A, Key::SPACE]));
+    }
+
+    #[test]
+    fn test_processor_swallows_immediate_key() {
+        let matcher = HotstringMatcher::new([Hotstring::new(";s", "x").with_immediate(true)]);
+        let mut hotstrings = Hotstrings::new(matcher);
+        let strokes = [
+            Key::CAPS_LOCK.down(),
+            Key::CAPS_LOCK.up(),
+            Key::SEMICOLON.down(),
+            Key::SEMICOLON.up(),
+            Key::LEFT_SHIFT.down(),
+            Key::S.down(),
+            Key::LEFT_SHIFT.up(),
+            Key::S.up(),
+        ];
+        let out = process(&mut hotstrings, &strokes);
+
+        let mut expected = vec![
+            Key::CAPS_LOCK.down(),
+            Key::CAPS_LOCK.up(),
+            Key::SEMICOLON.down(),
+            Key::SEMICOLON.up(),
+            Key::LEFT_SHIFT.down(),
+            // Shift and Caps Lock are undone while the expansion is typed
+            Key::LEFT_SHIFT.up(),
+            Key::CAPS_LOCK.down(),
+            Key::CAPS_LOCK.up(),
+        ];
+        expected.extend(Key::BACKSPACE.tap());
+        expected.extend(Key::X.tap());
+        expected.extend([
+            Key::CAPS_LOCK.down(),
+            Key::CAPS_LOCK.up(),
+            Key::LEFT_SHIFT.down(),
+            Key::LEFT_SHIFT.up(),
+        ]);
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_processor_follows_alt_codes_without_expanding() {
+        let matcher = HotstringMatcher::new([
+            Hotstring::new("ab", "x").with_immediate(true),
+            Hotstring::new("abc", "y").with_immediate(true),
+        ]);
+        let mut hotstrings = Hotstrings::new(matcher);
+        let mut strokes = taps(&[Key::A]);
+        strokes.push(Key::LEFT_ALT.down());
+        strokes.extend(taps(&[Key::NUMPAD_9, Key::NUMPAD_8]));
+        strokes.push(Key::LEFT_ALT.up());
+        strokes.push(Key::C.down());
+        let out = process(&mut hotstrings, &strokes);
+
+        // The Alt code completes `ab` unexpanded, yet still counts towards `abc`
+        let mut expected = strokes[..strokes.len() - 1].to_vec();
+        expected.extend(taps(&[Key::BACKSPACE; 2]));
+        expected.extend(taps(&[Key::Y]));
+        assert_eq!(out, expected);
+    }
+}
@@ -0,0 +1,423 @@
+//! Scancode-level key identities.
+//!
+//! The driver reports keys as set-1 make codes plus the `KEY_E0`/`KEY_E1` prefix bits in
+//! [`KeyStroke::state`]. A [`Key`] bundles both so that e.g. the left and right Ctrl keys,
+//! which share make code `0x1D`, compare as different keys.
+
+use crate::{KEY_E0, KEY_E1, KEY_UP, KeyState, KeyStroke};
+use std::fmt::{Display, Formatter};
+
+/// A physical key: a set-1 make code together with its `E0`/`E1` prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Key {
+    code: u16,
+    prefix: KeyState,
+}
+
+impl Key {
+    /// Create a key without a prefix
+    pub const fn new(code: u16) -> Self {
+        Self { code, prefix: 0 }
+    }
+
+    /// Create an extended key (`E0` prefix)
+    pub const fn extended(code: u16) -> Self {
+        Self {
+            code,
+            prefix: KEY_E0,
+        }
+    }
+
+    /// Create a key with explicit prefix bits; anything other than `KEY_E0`/`KEY_E1` is ignored
+    pub const fn with_prefix(code: u16, prefix: KeyState) -> Self {
+        Self {
+            code,
+            prefix: prefix & (KEY_E0 | KEY_E1),
+        }
+    }
+
+    /// The key a stroke refers to
+    pub const fn of(stroke: &KeyStroke) -> Self {
+        Self::with_prefix(stroke.code, stroke.state)
+    }
+
+    /// Set-1 make code
+    pub const fn code(self) -> u16 {
+        self.code
+    }
+
+    /// `KEY_E0`/`KEY_E1` prefix bits
+    pub const fn prefix(self) -> KeyState {
+        self.prefix
+    }
+
+    /// Whether the key carries the `E0` prefix
+    pub const fn is_extended(self) -> bool {
+        self.prefix & KEY_E0 != 0
+    }
+
+    /// Key down stroke for this key
+    pub fn down(self) -> KeyStroke {
+        KeyStroke::new(self.code, self.prefix)
+    }
+
+    /// Key up stroke for this key
+    pub fn up(self) -> KeyStroke {
+        KeyStroke::new(self.code, self.prefix | KEY_UP)
+    }
+
+    /// Down followed by up
+    pub fn tap(self) -> [KeyStroke; 2] {
+        [self.down(), self.up()]
+    }
+
+    /// Whether this is one of the Shift, Ctrl, Alt or Windows keys
+    pub fn is_modifier(self) -> bool {
+        MODIFIERS.contains(&self)
+    }
+
+    pub const ESCAPE: Key = Key::new(0x01);
+    pub const DIGIT_1: Key = Key::new(0x02);
+    pub const DIGIT_2: Key = Key::new(0x03);
+    pub const DIGIT_3: Key = Key::new(0x04);
+    pub const DIGIT_4: Key = Key::new(0x05);
+    pub const DIGIT_5: Key = Key::new(0x06);
+    pub const DIGIT_6: Key = Key::new(0x07);
+    pub const DIGIT_7: Key = Key::new(0x08);
+    pub const DIGIT_8: Key = Key::new(0x09);
+    pub const DIGIT_9: Key = Key::new(0x0A);
+    pub const DIGIT_0: Key = Key::new(0x0B);
+    pub const MINUS: Key = Key::new(0x0C);
+    pub const EQUAL: Key = Key::new(0x0D);
+    pub const BACKSPACE: Key = Key::new(0x0E);
+    pub const TAB: Key = Key::new(0x0F);
+    pub const Q: Key = Key::new(0x10);
+    pub const W: Key = Key::new(0x11);
+    pub const E: Key = Key::new(0x12);
+    pub const R: Key = Key::new(0x13);
+    pub const T: Key = Key::new(0x14);
+    pub const Y: Key = Key::new(0x15);
+    pub const U: Key = Key::new(0x16);
+    pub const I: Key = Key::new(0x17);
+    pub const O: Key = Key::new(0x18);
+    pub const P: Key = Key::new(0x19);
+    pub const BRACKET_LEFT: Key = Key::new(0x1A);
+    pub const BRACKET_RIGHT: Key = Key::new(0x1B);
+    pub const ENTER: Key = Key::new(0x1C);
+    pub const LEFT_CTRL: Key = Key::new(0x1D);
+    pub const A: Key = Key::new(0x1E);
+    pub const S: Key = Key::new(0x1F);
+    pub const D: Key = Key::new(0x20);
+    pub const F: Key = Key::new(0x21);
+    pub const G: Key = Key::new(0x22);
+    pub const H: Key = Key::new(0x23);
+    pub const J: Key = Key::new(0x24);
+    pub const K: Key = Key::new(0x25);
+    pub const L: Key = Key::new(0x26);
+    pub const SEMICOLON: Key = Key::new(0x27);
+    pub const QUOTE: Key = Key::new(0x28);
+    pub const BACKQUOTE: Key = Key::new(0x29);
+    pub const LEFT_SHIFT: Key = Key::new(0x2A);
+    pub const BACKSLASH: Key = Key::new(0x2B);
+    pub const Z: Key = Key::new(0x2C);
+    pub const X: Key = Key::new(0x2D);
+    pub const C: Key = Key::new(0x2E);
+    pub const V: Key = Key::new(0x2F);
+    pub const B: Key = Key::new(0x30);
+    pub const N: Key = Key::new(0x31);
+    pub const M: Key = Key::new(0x32);
+    pub const COMMA: Key = Key::new(0x33);
+    pub const PERIOD: Key = Key::new(0x34);
+    pub const SLASH: Key = Key::new(0x35);
+    pub const RIGHT_SHIFT: Key = Key::new(0x36);
+    pub const NUMPAD_MULTIPLY: Key = Key::new(0x37);
+    pub const LEFT_ALT: Key = Key::new(0x38);
+    pub const SPACE: Key = Key::new(0x39);
+    pub const CAPS_LOCK: Key = Key::new(0x3A);
+    pub const F1: Key = Key::new(0x3B);
+    pub const F2: Key = Key::new(0x3C);
+    pub const F3: Key = Key::new(0x3D);
+    pub const F4: Key = Key::new(0x3E);
+    pub const F5: Key = Key::new(0x3F);
+    pub const F6: Key = Key::new(0x40);
+    pub const F7: Key = Key::new(0x41);
+    pub const F8: Key = Key::new(0x42);
+    pub const F9: Key = Key::new(0x43);
+    pub const F10: Key = Key::new(0x44);
+    pub const NUM_LOCK: Key = Key::new(0x45);
+    pub const SCROLL_LOCK: Key = Key::new(0x46);
+    pub const NUMPAD_7: Key = Key::new(0x47);
+    pub const NUMPAD_8: Key = Key::new(0x48);
+    pub const NUMPAD_9: Key = Key::new(0x49);
+    pub const NUMPAD_SUBTRACT: Key = Key::new(0x4A);
+    pub const NUMPAD_4: Key = Key::new(0x4B);
+    pub const NUMPAD_5: Key = Key::new(0x4C);
+    pub const NUMPAD_6: Key = Key::new(0x4D);
+    pub const NUMPAD_ADD: Key = Key::new(0x4E);
+    pub const NUMPAD_1: Key = Key::new(0x4F);
+    pub const NUMPAD_2: Key = Key::new(0x50);
+    pub const NUMPAD_3: Key = Key::new(0x51);
+    pub const NUMPAD_0: Key = Key::new(0x52);
+    pub const NUMPAD_DECIMAL: Key = Key::new(0x53);
+    pub const INTL_BACKSLASH: Key = Key::new(0x56);
+    pub const F11: Key = Key::new(0x57);
+    pub const F12: Key = Key::new(0x58);
+
+    pub const NUMPAD_ENTER: Key = Key::extended(0x1C);
+    pub const RIGHT_CTRL: Key = Key::extended(0x1D);
+    pub const VOLUME_MUTE: Key = Key::extended(0x20);
+    pub const MEDIA_PLAY_PAUSE: Key = Key::extended(0x22);
+    pub const MEDIA_STOP: Key = Key::extended(0x24);
+    pub const VOLUME_DOWN: Key = Key::extended(0x2E);
+    pub const VOLUME_UP: Key = Key::extended(0x30);
+    pub const MEDIA_PREVIOUS: Key = Key::extended(0x10);
+    pub const MEDIA_NEXT: Key = Key::extended(0x19);
+    pub const NUMPAD_DIVIDE: Key = Key::extended(0x35);
+    pub const PRINT_SCREEN: Key = Key::extended(0x37);
+    pub const RIGHT_ALT: Key = Key::extended(0x38);
+    pub const HOME: Key = Key::extended(0x47);
+    pub const ARROW_UP: Key = Key::extended(0x48);
+    pub const PAGE_UP: Key = Key::extended(0x49);
+    pub const ARROW_LEFT: Key = Key::extended(0x4B);
+    pub const ARROW_RIGHT: Key = Key::extended(0x4D);
+    pub const END: Key = Key::extended(0x4F);
+    pub const ARROW_DOWN: Key = Key::extended(0x50);
+    pub const PAGE_DOWN: Key = Key::extended(0x51);
+    pub const INSERT: Key = Key::extended(0x52);
+    pub const DELETE: Key = Key::extended(0x53);
+    pub const LEFT_WIN: Key = Key::extended(0x5B);
+    pub const RIGHT_WIN: Key = Key::extended(0x5C);
+    pub const CONTEXT_MENU: Key = Key::extended(0x5D);
+}
+
+/// Canonical key names, as used by [`Key::name`] and the [`Display`] implementation
+const NAMES: [(&str, Key); 111] = [
+    ("Esc", Key::ESCAPE),
+    ("1", Key::DIGIT_1),
+    ("2", Key::DIGIT_2),
+    ("3", Key::DIGIT_3),
+    ("4", Key::DIGIT_4),
+    ("5", Key::DIGIT_5),
+    ("6", Key::DIGIT_6),
+    ("7", Key::DIGIT_7),
+    ("8", Key::DIGIT_8),
+    ("9", Key::DIGIT_9),
+    ("0", Key::DIGIT_0),
+    ("Minus", Key::MINUS),
+    ("Equal", Key::EQUAL),
+    ("Backspace", Key::BACKSPACE),
+    ("Tab", Key::TAB),
+    ("Q", Key::Q),
+    ("W", Key::W),
+    ("E", Key::E),
+    ("R", Key::R),
+    ("T", Key::T),
+    ("Y", Key::Y),
+    ("U", Key::U),
+    ("I", Key::I),
+    ("O", Key::O),
+    ("P", Key::P),
+    ("LBracket", Key::BRACKET_LEFT),
+    ("RBracket", Key::BRACKET_RIGHT),
+    ("Enter", Key::ENTER),
+    ("LCtrl", Key::LEFT_CTRL),
+    ("A", Key::A),
+    ("S", Key::S),
+    ("D", Key::D),
+    ("F", Key::F),
+    ("G", Key::G),
+    ("H", Key::H),
+    ("J", Key::J),
+    ("K", Key::K),
+    ("L", Key::L),
+    ("Semicolon", Key::SEMICOLON),
+    ("Quote", Key::QUOTE),
+    ("Backquote", Key::BACKQUOTE),
+    ("LShift", Key::LEFT_SHIFT),
+    ("Backslash", Key::BACKSLASH),
+    ("Z", Key::Z),
+    ("X", Key::X),
+    ("C", Key::C),
+    ("V", Key::V),
+    ("B", Key::B),
+    ("N", Key::N),
+    ("M", Key::M),
+    ("Comma", Key::COMMA),
+    ("Period", Key::PERIOD),
+    ("Slash", Key::SLASH),
+    ("RShift", Key::RIGHT_SHIFT),
+    ("NumMultiply", Key::NUMPAD_MULTIPLY),
+    ("LAlt", Key::LEFT_ALT),
+    ("Space", Key::SPACE),
+    ("CapsLock", Key::CAPS_LOCK),
+    ("F1", Key::F1),
+    ("F2", Key::F2),
+    ("F3", Key::F3),
+    ("F4", Key::F4),
+    ("F5", Key::F5),
+    ("F6", Key::F6),
+    ("F7", Key::F7),
+    ("F8", Key::F8),
+    ("F9", Key::F9),
+    ("F10", Key::F10),
+    ("NumLock", Key::NUM_LOCK),
+    ("ScrollLock", Key::SCROLL_LOCK),
+    ("Num7", Key::NUMPAD_7),
+    ("Num8", Key::NUMPAD_8),
+    ("Num9", Key::NUMPAD_9),
+    ("NumSubtract", Key::NUMPAD_SUBTRACT),
+    ("Num4", Key::NUMPAD_4),
+    ("Num5", Key::NUMPAD_5),
+    ("Num6", Key::NUMPAD_6),
+    ("NumAdd", Key::NUMPAD_ADD),
+    ("Num1", Key::NUMPAD_1),
+    ("Num2", Key::NUMPAD_2),
+    ("Num3", Key::NUMPAD_3),
+    ("Num0", Key::NUMPAD_0),
+    ("NumDecimal", Key::NUMPAD_DECIMAL),
+    ("IntlBackslash", Key::INTL_BACKSLASH),
+    ("F11", Key::F11),
+    ("F12", Key::F12),
+    ("NumEnter", Key::NUMPAD_ENTER),
+    ("RCtrl", Key::RIGHT_CTRL),
+    ("Mute", Key::VOLUME_MUTE),
+    ("PlayPause", Key::MEDIA_PLAY_PAUSE),
+    ("MediaStop", Key::MEDIA_STOP),
+    ("VolumeDown", Key::VOLUME_DOWN),
+    ("VolumeUp", Key::VOLUME_UP),
+    ("MediaPrev", Key::MEDIA_PREVIOUS),
+    ("MediaNext", Key::MEDIA_NEXT),
+    ("NumDivide", Key::NUMPAD_DIVIDE),
+    ("PrintScreen", Key::PRINT_SCREEN),
+    ("RAlt", Key::RIGHT_ALT),
+    ("Home", Key::HOME),
+    ("Up", Key::ARROW_UP),
+    ("PgUp", Key::PAGE_UP),
+    ("Left", Key::ARROW_LEFT),
+    ("Right", Key::ARROW_RIGHT),
+    ("End", Key::END),
+    ("Down", Key::ARROW_DOWN),
+    ("PgDn", Key::PAGE_DOWN),
+    ("Insert", Key::INSERT),
+    ("Delete", Key::DELETE),
+    ("LWin", Key::LEFT_WIN),
+    ("RWin", Key::RIGHT_WIN),
+    ("Menu", Key::CONTEXT_MENU),
+];
+
+/// Alternative names accepted by [`Key::from_name`]
+const ALIASES: [(&str, Key); 15] = [
+    ("Escape", Key::ESCAPE),
+    ("Return", Key::ENTER),
+    ("Ctrl", Key::LEFT_CTRL),
+    ("Control", Key::LEFT_CTRL),
+    ("Shift", Key::LEFT_SHIFT),
+    ("Alt", Key::LEFT_ALT),
+    ("AltGr", Key::RIGHT_ALT),
+    ("Win", Key::LEFT_WIN),
+    ("PageUp", Key::PAGE_UP),
+    ("PageDown", Key::PAGE_DOWN),
+    ("Del", Key::DELETE),
+    ("Ins", Key::INSERT),
+    ("Apps", Key::CONTEXT_MENU),
+    ("Grave", Key::BACKQUOTE),
+    ("PrtSc", Key::PRINT_SCREEN),
+];
+
+impl Key {
+    /// The canonical name of this key, such as `LCtrl`, `A`, `F5` or `PgUp`
+    pub fn name(self) -> Option<&'static str> {
+        NAMES
+            .iter()
+            .find(|(_, key)| *key == self)
+            .map(|(name, _)| *name)
+    }
+
+    /// The scancode in the hex form [`Key::from_name`] accepts, e.g. `0xE05E`
+    pub fn hex_name(self) -> String {
+        let prefix = match self.prefix {
+            KEY_E0 => 0xE000,
+            KEY_E1 => 0xE100,
+            _ => 0,
+        };
+        format!("0x{:02X}", prefix | self.code)
+    }
+
+    /// Look up a key by name, ignoring case
+    ///
+    /// Accepts the canonical names, common aliases such as `Ctrl`, `Escape` or `PageUp`, and
+    /// raw scancodes in hex, with `E0`/`E1` prefixes as the high byte: `0x54`, `0xE05E`.
+    pub fn from_name(name: &str) -> Option<Key> {
+        if let Some(hex) = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")) {
+            let code = u16::from_str_radix(hex, 16).ok()?;
+            return match code >> 8 {
+                0 => Some(Key::new(code)),
+                0xE0 => Some(Key::with_prefix(code & 0xFF, KEY_E0)),
+                0xE1 => Some(Key::with_prefix(code & 0xFF, KEY_E1)),
+                _ => None,
+            };
+        }
+        NAMES
+            .iter()
+            .chain(&ALIASES)
+            .find(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+            .map(|(_, key)| *key)
+    }
+}
+
+impl Display for Key {
+    /// The canonical name, or the scancode in the hex form [`Key::from_name`] accepts
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => f.write_str(&self.hex_name()),
+        }
+    }
+}
+
+/// The Shift, Ctrl, Alt and Windows keys on both sides
+pub const MODIFIERS: [Key; 8] = [
+    Key::LEFT_SHIFT,
+    Key::RIGHT_SHIFT,
+    Key::LEFT_CTRL,
+    Key::RIGHT_CTRL,
+    Key::LEFT_ALT,
+    Key::RIGHT_ALT,
+    Key::LEFT_WIN,
+    Key::RIGHT_WIN,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_distinguishes_keys() {
+        assert_ne!(Key::LEFT_CTRL, Key::RIGHT_CTRL);
+        assert_eq!(Key::LEFT_CTRL.code(), Key::RIGHT_CTRL.code());
+
+        let stroke = KeyStroke::new(0x1D, KEY_E0 | KEY_UP);
+        assert_eq!(stroke.key(), Key::RIGHT_CTRL);
+        assert!(stroke.is_up());
+        assert_eq!(Key::RIGHT_CTRL.up(), stroke);
+        assert_eq!(Key::RIGHT_CTRL.down().state, KEY_E0);
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for (name, key) in NAMES {
+            assert_eq!(Key::from_name(name), Some(key));
+            assert_eq!(key.to_string(), name);
+        }
+        assert_eq!(Key::from_name("lctrl"), Some(Key::LEFT_CTRL));
+        assert_eq!(Key::from_name("Escape"), Some(Key::ESCAPE));
+        assert_eq!(Key::from_name("0x1D"), Some(Key::LEFT_CTRL));
+        assert_eq!(Key::from_name("0xE01D"), Some(Key::RIGHT_CTRL));
+        assert_eq!(Key::from_name("Nope"), None);
+
+        let unnamed = Key::extended(0x5E);
+        assert_eq!(unnamed.to_string(), "0xE05E");
+        assert_eq!(Key::from_name(&unnamed.to_string()), Some(unnamed));
+        assert_eq!(Key::new(0x54).to_string(), "0x54");
+    }
+}
@@ -0,0 +1,646 @@
+//! Keyboard layouts from Microsoft Keyboard Layout Creator `.klc` files.
+//!
+//! A `.klc` file is UTF-16 text, as saved by MSKLC; UTF-8 is accepted too. Its `SHIFTSTATE`
+//! section lists the modifier combinations the layout defines, `LAYOUT` gives each scancode's
+//! output in every one of them, and each `DEADKEY` section lists what a dead key combines
+//! with. [`KlcLayout`] keeps all of it and implements [`Layout`], so it works both ways: from a
+//! key to what it types, and from text to the chords typing it.
+//!
+//! The `layouts` directory of the repository has German, French and US Dvorak samples.
+
+use crate::text::{Chord, Layout, Modifiers, Output};
+use crate::{KEY_E0, KEY_E1, Key};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+/// Why a `.klc` file couldn't be loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KlcError {
+    /// The file is neither UTF-16 with a byte order mark nor UTF-8
+    Encoding,
+    /// A line doesn't parse; `line` is 1-based
+    Syntax { line: usize, message: String },
+}
+
+impl Display for KlcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Encoding => write!(f, "Not UTF-16 with a byte order mark, nor UTF-8"),
+            Self::Syntax { line, message } => write!(f, "Line {line}: {message}"),
+        }
+    }
+}
+
+impl Error for KlcError {}
+
+/// One key of the `LAYOUT` section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDefinition {
+    /// The virtual key name, such as `Q` or `OEM_4`
+    pub virtual_key: String,
+    /// Caps Lock acts as Shift for the states without AltGr
+    pub caps_lock: bool,
+    /// Caps Lock acts as Shift for the AltGr states
+    pub altgr_caps_lock: bool,
+    /// Output per shift state, in the order of [`KlcLayout::shift_states`]
+    pub outputs: Vec<Option<Output>>,
+    /// Separate outputs with Caps Lock on, for `SGCAPS` keys: without and with Shift
+    pub caps_outputs: Option<[Option<Output>; 2]>,
+}
+
+/// A keyboard layout loaded from a `.klc` file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KlcLayout {
+    /// The layout's DLL name from the `KBD` line, such as `KBDGR`
+    pub name: String,
+    pub description: String,
+    /// The `LOCALENAME`, such as `de-DE`
+    pub locale: String,
+    shift_states: Vec<Modifiers>,
+    keys: BTreeMap<Key, KeyDefinition>,
+    dead_keys: BTreeMap<char, BTreeMap<char, char>>,
+    reverse: HashMap<char, Vec<Chord>>,
+}
+
+/// Section keywords; any of them at the start of a line ends the previous section
+const KEYWORDS: [&str; 17] = [
+    "KBD",
+    "COPYRIGHT",
+    "COMPANY",
+    "LOCALENAME",
+    "LOCALEID",
+    "VERSION",
+    "ATTRIBUTES",
+    "SHIFTSTATE",
+    "LAYOUT",
+    "DEADKEY",
+    "LIGATURE",
+    "KEYNAME",
+    "KEYNAME_EXT",
+    "KEYNAME_DEAD",
+    "DESCRIPTIONS",
+    "LANGUAGENAMES",
+    "ENDKBD",
+];
+
+/// Caps column bits
+const CAPLOK: u8 = 1;
+const CAPLOKALTGR: u8 = 4;
+const SGCAPS: u8 = 2;
+
+fn decode(bytes: &[u8]) -> Result<String, KlcError> {
+    let utf16 = |units: Vec<u16>| String::from_utf16(&units).map_err(|_| KlcError::Encoding);
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => utf16(
+            rest.chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect(),
+        ),
+        [0xFE, 0xFF, rest @ ..] => utf16(
+            rest.chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect(),
+        ),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => {
+            String::from_utf8(rest.to_vec()).map_err(|_| KlcError::Encoding)
+        }
+        _ => String::from_utf8(bytes.to_vec()).map_err(|_| KlcError::Encoding),
+    }
+}
+
+/// Split a line into words and quoted strings, dropping `//` and `;` comments
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() && !rest.starts_with("//") && !rest.starts_with(';') {
+        let end = if let Some(quoted) = rest.strip_prefix('"') {
+            quoted.find('"').map_or(rest.len(), |end| end + 2)
+        } else {
+            rest.find(char::is_whitespace).unwrap_or(rest.len())
+        };
+        tokens.push(rest[..end].trim_matches('"'));
+        rest = rest[end..].trim_start();
+    }
+    tokens
+}
+
+fn code_point(text: &str) -> Option<char> {
+    u32::from_str_radix(text, 16).ok().and_then(char::from_u32)
+}
+
+/// A scancode as written in the `SC` column, with `e0`/`e1` prefixes as the high byte
+fn scancode(text: &str) -> Option<Key> {
+    let code = u16::from_str_radix(text, 16).ok()?;
+    match code >> 8 {
+        0 => Some(Key::new(code)),
+        0xE0 => Some(Key::with_prefix(code & 0xFF, KEY_E0)),
+        0xE1 => Some(Key::with_prefix(code & 0xFF, KEY_E1)),
+        _ => None,
+    }
+}
+
+/// The modifiers of a `SHIFTSTATE` number: 1 is Shift, 2 Ctrl and 4 Alt
+fn modifiers(state: u8) -> Modifiers {
+    Modifiers {
+        shift: state & 1 != 0,
+        ctrl: state & 2 != 0,
+        alt: state & 4 != 0,
+    }
+}
+
+/// A cell of the `LAYOUT` section, before ligatures are resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Cell {
+    None,
+    Output(Output),
+    Ligature,
+}
+
+struct Parser {
+    layout: KlcLayout,
+    line: usize,
+    ligatures: HashMap<(String, usize), String>,
+    /// Keys with `%%` cells, and the cells' columns
+    pending: Vec<(Key, usize)>,
+    /// The key before an `SGCAPS` continuation line
+    last_key: Option<Key>,
+}
+
+impl Parser {
+    fn error(&self, message: impl Into<String>) -> KlcError {
+        KlcError::Syntax {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn cell(&self, text: &str) -> Result<Cell, KlcError> {
+        if text == "-1" {
+            return Ok(Cell::None);
+        }
+        if text == "%%" {
+            return Ok(Cell::Ligature);
+        }
+        let (text, dead) = match text.strip_suffix('@') {
+            Some(text) if !text.is_empty() => (text, true),
+            _ => (text, false),
+        };
+        let mut chars = text.chars();
+        let c = match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => code_point(text),
+        }
+        .ok_or_else(|| self.error(format!("invalid character `{text}`")))?;
+        Ok(Cell::Output(if dead {
+            Output::Dead(c)
+        } else {
+            Output::Char(c)
+        }))
+    }
+
+    fn shift_state(&mut self, tokens: &[&str]) -> Result<(), KlcError> {
+        let state = tokens[0]
+            .parse::<u8>()
+            .ok()
+            .filter(|state| *state < 8)
+            .ok_or_else(|| self.error(format!("invalid shift state `{}`", tokens[0])))?;
+        self.layout.shift_states.push(modifiers(state));
+        Ok(())
+    }
+
+    fn layout_line(&mut self, tokens: &[&str]) -> Result<(), KlcError> {
+        let columns = self.layout.shift_states.len();
+        if tokens.len() < 3 + columns {
+            return Err(self.error(format!(
+                "expected a scancode, a virtual key, caps and {columns} outputs"
+            )));
+        }
+        let mut cells = Vec::with_capacity(columns);
+        for text in &tokens[3..3 + columns] {
+            cells.push(self.cell(text)?);
+        }
+
+        if tokens[0] == "-1" {
+            // The Caps Lock outputs of the previous SGCAPS key
+            let definition = self
+                .last_key
+                .and_then(|key| self.layout.keys.get_mut(&key))
+                .ok_or_else(|| KlcError::Syntax {
+                    line: self.line,
+                    message: "caps line without an SGCAPS key before it".into(),
+                })?;
+            let output = |cell: &Cell| match cell {
+                Cell::Output(output) => Some(output.clone()),
+                _ => None,
+            };
+            definition.caps_outputs = Some([
+                cells.first().and_then(output),
+                cells.get(1).and_then(output),
+            ]);
+            self.last_key = None;
+            return Ok(());
+        }
+
+        let key = scancode(tokens[0])
+            .ok_or_else(|| self.error(format!("invalid scancode `{}`", tokens[0])))?;
+        let caps = match tokens[2] {
+            "SGCap" => SGCAPS,
+            caps => caps
+                .parse::<u8>()
+                .map_err(|_| self.error(format!("invalid caps value `{caps}`")))?,
+        };
+        for (column, cell) in cells.iter().enumerate() {
+            if *cell == Cell::Ligature {
+                self.pending.push((key, column));
+            }
+        }
+        let outputs = cells
+            .into_iter()
+            .map(|cell| match cell {
+                Cell::Output(output) => Some(output),
+                Cell::None | Cell::Ligature => None,
+            })
+            .collect();
+        self.layout.keys.insert(
+            key,
+            KeyDefinition {
+                virtual_key: tokens[1].to_string(),
+                caps_lock: caps & CAPLOK != 0,
+                altgr_caps_lock: caps & CAPLOKALTGR != 0,
+                outputs,
+                caps_outputs: None,
+            },
+        );
+        self.last_key = (caps & SGCAPS != 0).then_some(key);
+        Ok(())
+    }
+
+    fn ligature(&mut self, tokens: &[&str]) -> Result<(), KlcError> {
+        let column = tokens
+            .get(1)
+            .and_then(|column| column.parse().ok())
+            .ok_or_else(|| self.error("expected a virtual key, a column and characters"))?;
+        let mut text = String::new();
+        for token in &tokens[2..] {
+            text.push(
+                code_point(token)
+                    .ok_or_else(|| self.error(format!("invalid character `{token}`")))?,
+            );
+        }
+        self.ligatures.insert((tokens[0].to_string(), column), text);
+        Ok(())
+    }
+
+    fn dead_key_line(&mut self, dead: char, tokens: &[&str]) -> Result<(), KlcError> {
+        let [base, composed, ..] = tokens else {
+            return Err(self.error("expected a base and a composed character"));
+        };
+        let (Some(base), Some(composed)) = (code_point(base), code_point(composed)) else {
+            return Err(self.error("invalid character"));
+        };
+        self.layout
+            .dead_keys
+            .entry(dead)
+            .or_default()
+            .insert(base, composed);
+        Ok(())
+    }
+
+    fn finish(mut self) -> KlcLayout {
+        for (key, column) in self.pending {
+            let definition = self.layout.keys.get_mut(&key).expect("pending keys exist");
+            let text = self
+                .ligatures
+                .get(&(definition.virtual_key.clone(), column));
+            definition.outputs[column] = text.map(|text| Output::Text(text.clone()));
+        }
+        self.layout.build_reverse();
+        self.layout
+    }
+}
+
+/// Where the parser is in the file
+#[derive(Debug, Clone, Copy)]
+enum Section {
+    Other,
+    ShiftState,
+    Layout,
+    DeadKey(char),
+    Ligature,
+}
+
+impl KlcLayout {
+    /// Load a layout from the bytes of a `.klc` file
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KlcError> {
+        Self::parse(&decode(bytes)?)
+    }
+
+    /// Load a layout from the text of a `.klc` file
+    pub fn parse(source: &str) -> Result<Self, KlcError> {
+        let mut parser = Parser {
+            layout: KlcLayout::default(),
+            line: 0,
+            ligatures: HashMap::new(),
+            pending: Vec::new(),
+            last_key: None,
+        };
+        let mut section = Section::Other;
+
+        for (index, line) in source.lines().enumerate() {
+            parser.line = index + 1;
+            let tokens = tokenize(line);
+            let Some(&first) = tokens.first() else {
+                continue;
+            };
+
+            if KEYWORDS.contains(&first) {
+                let argument = tokens.get(1).copied().unwrap_or_default();
+                section = match first {
+                    "KBD" => {
+                        parser.layout.name = argument.to_string();
+                        parser.layout.description =
+                            tokens.get(2).copied().unwrap_or_default().to_string();
+                        Section::Other
+                    }
+                    "LOCALENAME" => {
+                        parser.layout.locale = argument.to_string();
+                        Section::Other
+                    }
+                    "SHIFTSTATE" => Section::ShiftState,
+                    "LAYOUT" => Section::Layout,
+                    "LIGATURE" => Section::Ligature,
+                    "DEADKEY" => Section::DeadKey(
+                        code_point(argument)
+                            .ok_or_else(|| parser.error("expected the dead key's character"))?,
+                    ),
+                    "ENDKBD" => break,
+                    _ => Section::Other,
+                };
+                continue;
+            }
+
+            match section {
+                Section::Other => {}
+                Section::ShiftState => parser.shift_state(&tokens)?,
+                Section::Layout => parser.layout_line(&tokens)?,
+                Section::DeadKey(dead) => parser.dead_key_line(dead, &tokens)?,
+                Section::Ligature => parser.ligature(&tokens)?,
+            }
+        }
+        Ok(parser.finish())
+    }
+
+    /// The modifier combinations the layout defines, in column order
+    pub fn shift_states(&self) -> &[Modifiers] {
+        &self.shift_states
+    }
+
+    /// The definition of a key, if the layout has one
+    pub fn key(&self, key: Key) -> Option<&KeyDefinition> {
+        self.keys.get(&key)
+    }
+
+    /// Every defined key, by scancode
+    pub fn keys(&self) -> impl Iterator<Item = (Key, &KeyDefinition)> {
+        self.keys.iter().map(|(key, definition)| (*key, definition))
+    }
+
+    /// The dead keys and what each combines with
+    pub fn dead_keys(&self) -> &BTreeMap<char, BTreeMap<char, char>> {
+        &self.dead_keys
+    }
+
+    /// Index characters to the chords typing them, preferring fewer modifiers, then lower
+    /// scancodes, and plain keys to dead key sequences
+    fn build_reverse(&mut self) {
+        let mut reverse = HashMap::new();
+        let mut dead_chords = HashMap::new();
+        for (column, modifiers) in self.shift_states.iter().enumerate() {
+            for (key, definition) in &self.keys {
+                let chord = Chord::new(*key, *modifiers);
+                match definition.outputs.get(column) {
+                    Some(Some(Output::Char(c))) => {
+                        reverse.entry(*c).or_insert_with(|| vec![chord]);
+                    }
+                    Some(Some(Output::Dead(c))) => {
+                        dead_chords.entry(*c).or_insert(chord);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut composed_chords = HashMap::new();
+        for (dead, table) in &self.dead_keys {
+            let Some(dead_chord) = dead_chords.get(dead) else {
+                continue;
+            };
+            for (base, composed) in table {
+                if reverse.contains_key(composed) || composed_chords.contains_key(composed) {
+                    continue;
+                }
+                if let Some([base_chord]) = reverse.get(base).map(Vec::as_slice) {
+                    composed_chords.insert(*composed, vec![*dead_chord, *base_chord]);
+                }
+            }
+        }
+        reverse.extend(composed_chords);
+        self.reverse = reverse;
+    }
+}
+
+impl Layout for KlcLayout {
+    fn chords(&self, c: char) -> Option<Vec<Chord>> {
+        self.reverse.get(&c).cloned()
+    }
+
+    fn output(&self, key: Key, modifiers: Modifiers, caps_lock: bool) -> Option<Output> {
+        let definition = self.keys.get(&key)?;
+        let altgr = modifiers.is_altgr();
+        let mut modifiers = modifiers;
+        if caps_lock {
+            if let Some(caps_outputs) = &definition.caps_outputs
+                && !modifiers.ctrl
+                && !modifiers.alt
+            {
+                return caps_outputs[usize::from(modifiers.shift)].clone();
+            }
+            if (altgr && definition.altgr_caps_lock) || (!altgr && definition.caps_lock) {
+                modifiers.shift = !modifiers.shift;
+            }
+        }
+        let column = self.shift_states.iter().position(|m| *m == modifiers)?;
+        definition.outputs.get(column)?.clone()
+    }
+
+    fn compose(&self, dead: char, base: char) -> Option<char> {
+        self.dead_keys.get(&dead)?.get(&base).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::Typist;
+
+    const GERMAN: &[u8] = include_bytes!("../layouts/kbdgr.klc");
+    const FRENCH: &[u8] = include_bytes!("../layouts/kbdfr.klc");
+    const DVORAK: &[u8] = include_bytes!("../layouts/kbddv.klc");
+
+    fn char(c: char) -> Option<Output> {
+        Some(Output::Char(c))
+    }
+
+    #[test]
+    fn test_german() {
+        let layout = KlcLayout::from_bytes(GERMAN).unwrap();
+        assert_eq!(layout.name, "KBDGR");
+        assert_eq!(layout.locale, "de-DE");
+        assert_eq!(layout.shift_states().len(), 5);
+
+        assert_eq!(layout.output(Key::Y, Modifiers::NONE, false), char('z'));
+        assert_eq!(layout.output(Key::Q, Modifiers::ALTGR, false), char('@'));
+        assert_eq!(layout.output(Key::E, Modifiers::ALTGR, false), char('€'));
+        assert_eq!(layout.output(Key::QUOTE, Modifiers::NONE, true), char('Ä'));
+        assert_eq!(
+            layout.output(Key::DIGIT_2, Modifiers::SHIFT, true),
+            char('"')
+        );
+        assert_eq!(
+            layout.output(Key::BACKQUOTE, Modifiers::NONE, false),
+            Some(Output::Dead('^'))
+        );
+        assert_eq!(layout.compose('´', 'e'), Some('é'));
+
+        assert_eq!(
+            layout.chords('@'),
+            Some(vec![Chord::new(Key::Q, Modifiers::ALTGR)])
+        );
+        assert_eq!(
+            layout.chords('Z'),
+            Some(vec![Chord::new(Key::Y, Modifiers::SHIFT)])
+        );
+        assert_eq!(
+            layout.chords('È'),
+            Some(vec![
+                Chord::new(Key::EQUAL, Modifiers::SHIFT),
+                Chord::new(Key::E, Modifiers::SHIFT),
+            ])
+        );
+        assert_eq!(
+            layout.chords('^'),
+            Some(vec![
+                Chord::new(Key::BACKQUOTE, Modifiers::NONE),
+                Chord::new(Key::SPACE, Modifiers::NONE),
+            ])
+        );
+        assert_eq!(layout.chords('ñ'), None);
+    }
+
+    #[test]
+    fn test_french() {
+        let layout = KlcLayout::from_bytes(FRENCH).unwrap();
+        assert_eq!(layout.output(Key::Q, Modifiers::NONE, false), char('a'));
+        assert_eq!(
+            layout.output(Key::DIGIT_1, Modifiers::NONE, false),
+            char('&')
+        );
+        assert_eq!(
+            layout.output(Key::DIGIT_1, Modifiers::SHIFT, false),
+            char('1')
+        );
+        assert_eq!(
+            layout.output(Key::DIGIT_0, Modifiers::ALTGR, false),
+            char('@')
+        );
+        assert_eq!(layout.compose('¨', 'i'), Some('ï'));
+
+        let typed = Typist::new(&layout).type_text("aé1ñ").unwrap();
+        assert_eq!(typed.chunks[0], Key::Q.tap());
+        assert_eq!(typed.chunks[1], Key::DIGIT_2.tap());
+        assert_eq!(typed.chunks[2][1], Key::DIGIT_1.down());
+        // Dead tilde on AltGr+2, then N
+        assert_eq!(typed.chunks[3][2], Key::DIGIT_2.down());
+        assert_eq!(&typed.chunks[3][6..], &Key::N.tap());
+    }
+
+    #[test]
+    fn test_dvorak_round_trip() {
+        let layout = KlcLayout::from_bytes(DVORAK).unwrap();
+        assert_eq!(layout.dead_keys().len(), 0);
+        for (key, definition) in layout.keys() {
+            for (column, output) in definition.outputs.iter().enumerate() {
+                let modifiers = layout.shift_states()[column];
+                if let Some(Output::Char(c)) = output {
+                    let chords = layout.chords(*c).unwrap();
+                    let [chord] = chords.as_slice() else {
+                        panic!("{c:?} takes one chord");
+                    };
+                    assert_eq!(layout.output(chord.key, chord.modifiers, false), char(*c));
+                    if *c != ' ' && *c != '.' {
+                        assert_eq!((chord.key, chord.modifiers), (key, modifiers));
+                    }
+                }
+            }
+        }
+        assert_eq!(layout.output(Key::S, Modifiers::NONE, false), char('o'));
+    }
+
+    #[test]
+    fn test_sgcaps_and_ligatures() {
+        let source = "\
+KBD\tTEST\t\"Test; with semicolon\"
+SHIFTSTATE
+0
+1
+LAYOUT
+1a\tOEM_1\tSGCap\t00fc\t00e8\t// ü è
+-1\t-1\t\t0\t00dc\t00c8\t// Ü È
+10\tQ\t1\t%%\tQ
+LIGATURE
+Q\t0\t0071\t0075
+ENDKBD
+";
+        let layout = KlcLayout::parse(source).unwrap();
+        assert_eq!(layout.description, "Test; with semicolon");
+        let key = Key::BRACKET_LEFT;
+        assert_eq!(layout.output(key, Modifiers::NONE, false), char('ü'));
+        assert_eq!(layout.output(key, Modifiers::NONE, true), char('Ü'));
+        assert_eq!(layout.output(key, Modifiers::SHIFT, true), char('È'));
+        assert_eq!(
+            layout.output(Key::Q, Modifiers::NONE, false),
+            Some(Output::Text("qu".into()))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source: &str| KlcLayout::parse(source).unwrap_err();
+        assert_eq!(
+            error("SHIFTSTATE\n0\nLAYOUT\nzz\tQ\t1\tq"),
+            KlcError::Syntax {
+                line: 4,
+                message: "invalid scancode `zz`".into()
+            }
+        );
+        assert_eq!(
+            error("SHIFTSTATE\n0\n1\nLAYOUT\n10\tQ\t1\tq"),
+            KlcError::Syntax {
+                line: 5,
+                message: "expected a scancode, a virtual key, caps and 2 outputs".into()
+            }
+        );
+        assert_eq!(
+            error("SHIFTSTATE\n0\nLAYOUT\n10\tQ\t1\tnope").to_string(),
+            "Line 4: invalid character `nope`"
+        );
+        assert_eq!(
+            KlcLayout::from_bytes(&[0xFF, 0xFE, 0x00, 0xD8]),
+            Err(KlcError::Encoding)
+        );
+        // A caps line with no shift states declared has nothing to read, but is no error
+        let layout = KlcLayout::parse("LAYOUT\n10\tQ\tSGCap\n-1\t-1\t0\n").unwrap();
+        assert_eq!(layout.output(Key::Q, Modifiers::NONE, true), None);
+    }
+}
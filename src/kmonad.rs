@@ -0,0 +1,793 @@
+//! Import KMonad `.kbd` configurations into a [`RuleSet`].
+//!
+//! The core of the format is understood:
+//!
+//! - `defsrc` lists the physical keys, and each `deflayer` gives a button for every one of
+//!   them. The first layer is the base layer and becomes remaps and dual-role rules; the
+//!   others become [`Layer`]s.
+//! - `defalias` names buttons for use as `@name`.
+//! - Buttons are key names such as `a`, `lsft` or `kp7`, shortcuts such as `C-S-t` and
+//!   shifted symbols such as `!` or `\_`, `_` (transparent), `XX` (blocked),
+//!   `(around lctl c)`, `(layer-toggle name)`, and `(tap-hold ms tap hold)` or
+//!   `(tap-hold-next ms tap hold)`, where `hold` can toggle a layer. A backslash makes the
+//!   next character part of a key name, as in `\(`, `\"` or `\_`.
+//!
+//! Both `tap-hold` flavours count as held on the timeout or when another key goes down,
+//! which is what `tap-hold-next` does in KMonad. `layer-toggle` and `tap-hold` only work in
+//! the base layer, since a layer only maps keys to keys. `defcfg` describes Linux input and
+//! output devices and is ignored; everything else is reported as a [`Diagnostic`] and left
+//! out of the rules.
+
+use crate::rules::{Diagnostic, DualRoleRule, HoldAction, Layer, RemapRule, RuleSet};
+use crate::{Key, Switch};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The rules translated from a configuration, and what could not be translated
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Import {
+    pub rules: RuleSet,
+    /// Problems in file order; the rules leave out whatever they concern
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Translate a `.kbd` configuration
+pub fn import(source: &str) -> Import {
+    let mut importer = Importer {
+        source,
+        diagnostics: Vec::new(),
+        aliases: HashMap::new(),
+        layer_names: Vec::new(),
+    };
+    let forms = importer.parse();
+    let rules = importer.translate(&forms);
+    let mut diagnostics = importer.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    Import { rules, diagnostics }
+}
+
+/// KMonad's names for keys, where they differ from [`Key::from_name`]'s
+const NAMES: [(&str, Key); 61] = [
+    ("grv", Key::BACKQUOTE),
+    ("-", Key::MINUS),
+    ("min", Key::MINUS),
+    ("=", Key::EQUAL),
+    ("eql", Key::EQUAL),
+    ("bspc", Key::BACKSPACE),
+    ("bks", Key::BACKSPACE),
+    ("[", Key::BRACKET_LEFT),
+    ("lbrc", Key::BRACKET_LEFT),
+    ("]", Key::BRACKET_RIGHT),
+    ("rbrc", Key::BRACKET_RIGHT),
+    ("\\", Key::BACKSLASH),
+    ("bksl", Key::BACKSLASH),
+    ("caps", Key::CAPS_LOCK),
+    (";", Key::SEMICOLON),
+    ("scln", Key::SEMICOLON),
+    ("'", Key::QUOTE),
+    ("apos", Key::QUOTE),
+    ("ret", Key::ENTER),
+    ("ent", Key::ENTER),
+    (",", Key::COMMA),
+    ("comm", Key::COMMA),
+    (".", Key::PERIOD),
+    ("dot", Key::PERIOD),
+    ("/", Key::SLASH),
+    ("slsh", Key::SLASH),
+    ("lsft", Key::LEFT_SHIFT),
+    ("rsft", Key::RIGHT_SHIFT),
+    ("lctl", Key::LEFT_CTRL),
+    ("rctl", Key::RIGHT_CTRL),
+    ("lmet", Key::LEFT_WIN),
+    ("lgui", Key::LEFT_WIN),
+    ("rmet", Key::RIGHT_WIN),
+    ("rgui", Key::RIGHT_WIN),
+    ("cmp", Key::CONTEXT_MENU),
+    ("cmps", Key::CONTEXT_MENU),
+    ("spc", Key::SPACE),
+    ("pgup", Key::PAGE_UP),
+    ("pgdn", Key::PAGE_DOWN),
+    ("rght", Key::ARROW_RIGHT),
+    ("nlck", Key::NUM_LOCK),
+    ("slck", Key::SCROLL_LOCK),
+    ("ssrq", Key::PRINT_SCREEN),
+    ("sys", Key::PRINT_SCREEN),
+    ("prnt", Key::PRINT_SCREEN),
+    ("kp0", Key::NUMPAD_0),
+    ("kp1", Key::NUMPAD_1),
+    ("kp2", Key::NUMPAD_2),
+    ("kp3", Key::NUMPAD_3),
+    ("kp4", Key::NUMPAD_4),
+    ("kp5", Key::NUMPAD_5),
+    ("kp6", Key::NUMPAD_6),
+    ("kp7", Key::NUMPAD_7),
+    ("kp8", Key::NUMPAD_8),
+    ("kp9", Key::NUMPAD_9),
+    ("kp/", Key::NUMPAD_DIVIDE),
+    ("kp*", Key::NUMPAD_MULTIPLY),
+    ("kp-", Key::NUMPAD_SUBTRACT),
+    ("kp+", Key::NUMPAD_ADD),
+    ("kp.", Key::NUMPAD_DECIMAL),
+    ("kprt", Key::NUMPAD_ENTER),
+];
+
+/// Shifted symbols of a US layout, typed with Shift and the key
+const SHIFTED: [(&str, Key); 20] = [
+    ("~", Key::BACKQUOTE),
+    ("!", Key::DIGIT_1),
+    ("#", Key::DIGIT_3),
+    ("$", Key::DIGIT_4),
+    ("%", Key::DIGIT_5),
+    ("^", Key::DIGIT_6),
+    ("&", Key::DIGIT_7),
+    ("*", Key::DIGIT_8),
+    ("(", Key::DIGIT_9),
+    (")", Key::DIGIT_0),
+    ("+", Key::EQUAL),
+    ("{", Key::BRACKET_LEFT),
+    ("}", Key::BRACKET_RIGHT),
+    ("_", Key::MINUS),
+    ("|", Key::BACKSLASH),
+    ("\"", Key::QUOTE),
+    (":", Key::SEMICOLON),
+    ("<", Key::COMMA),
+    (">", Key::PERIOD),
+    ("?", Key::SLASH),
+];
+
+/// Shortcut prefixes, as in `C-S-t`
+const MODIFIERS: [(&str, Key); 8] = [
+    ("C-", Key::LEFT_CTRL),
+    ("S-", Key::LEFT_SHIFT),
+    ("A-", Key::LEFT_ALT),
+    ("M-", Key::LEFT_WIN),
+    ("RC-", Key::RIGHT_CTRL),
+    ("RS-", Key::RIGHT_SHIFT),
+    ("RA-", Key::RIGHT_ALT),
+    ("RM-", Key::RIGHT_WIN),
+];
+
+/// How deeply aliases may refer to each other, to catch cycles
+const MAX_ALIAS_DEPTH: usize = 16;
+
+/// An s-expression, with the byte offset where it starts
+///
+/// Unquoted atoms keep their escapes, so that `\_` stays apart from `_`; see [`unescape`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Sexp {
+    Atom(String, usize),
+    List(Vec<Sexp>, usize),
+}
+
+impl Sexp {
+    fn offset(&self) -> usize {
+        match self {
+            Sexp::Atom(_, offset) | Sexp::List(_, offset) => *offset,
+        }
+    }
+
+    fn atom(&self) -> Option<&str> {
+        match self {
+            Sexp::Atom(atom, _) => Some(atom),
+            Sexp::List(..) => None,
+        }
+    }
+}
+
+/// What a button in a layer does
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Button {
+    Transparent,
+    /// Presses these, in order; none blocks the key
+    Press(Vec<Switch>),
+    LayerToggle(String),
+    DualRole {
+        timeout: Duration,
+        tap: Vec<Switch>,
+        hold: HoldAction,
+    },
+}
+
+struct Importer<'s> {
+    source: &'s str,
+    diagnostics: Vec<Diagnostic>,
+    aliases: HashMap<String, Sexp>,
+    layer_names: Vec<String>,
+}
+
+impl Importer<'_> {
+    fn error(&mut self, offset: usize, message: impl Into<String>) {
+        let diagnostic = Diagnostic::at(self.source, offset, message);
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// The top-level forms
+    fn parse(&mut self) -> Vec<Sexp> {
+        let source = self.source;
+        let mut chars = source.char_indices().peekable();
+        // Lists being read, with where they start
+        let mut stack: Vec<(Vec<Sexp>, usize)> = vec![(Vec::new(), 0)];
+
+        while let Some((offset, c)) = chars.next() {
+            let rest = &source[offset..];
+            if c.is_whitespace() {
+                continue;
+            } else if rest.starts_with(";;") {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            } else if rest.starts_with("#|") {
+                match rest.find("|#") {
+                    Some(end) => while chars.next_if(|&(i, _)| i < offset + end + 2).is_some() {},
+                    None => {
+                        self.error(offset, "unterminated `#|` comment");
+                        break;
+                    }
+                }
+            } else if c == '(' {
+                stack.push((Vec::new(), offset));
+            } else if c == ')' {
+                if stack.len() == 1 {
+                    self.error(offset, "unmatched `)`");
+                    continue;
+                }
+                let (list, start) = stack.pop().unwrap();
+                stack.last_mut().unwrap().0.push(Sexp::List(list, start));
+            } else if c == '"' {
+                let mut string = String::new();
+                let mut closed = false;
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '"' => {
+                            closed = true;
+                            break;
+                        }
+                        '\\' => string.extend(chars.next().map(|(_, c)| c)),
+                        c => string.push(c),
+                    }
+                }
+                if !closed {
+                    self.error(offset, "unterminated string");
+                }
+                stack.last_mut().unwrap().0.push(Sexp::Atom(string, offset));
+            } else {
+                let mut atom = String::new();
+                let mut c = Some(c);
+                while let Some(next) = c {
+                    atom.push(next);
+                    if next == '\\' {
+                        atom.extend(chars.next().map(|(_, c)| c));
+                    }
+                    c = chars
+                        .next_if(|&(_, c)| !c.is_whitespace() && c != '(' && c != ')')
+                        .map(|(_, c)| c);
+                }
+                stack.last_mut().unwrap().0.push(Sexp::Atom(atom, offset));
+            }
+        }
+
+        while stack.len() > 1 {
+            let (list, start) = stack.pop().unwrap();
+            self.error(start, "unclosed `(`");
+            stack.last_mut().unwrap().0.push(Sexp::List(list, start));
+        }
+        stack.pop().unwrap().0
+    }
+
+    fn translate(&mut self, forms: &[Sexp]) -> RuleSet {
+        // Unknown keys stay as `None`, keeping the later keys in their positions
+        let mut source: Option<Vec<Option<Key>>> = None;
+        let mut layers: Vec<(&str, usize, &[Sexp])> = Vec::new();
+
+        for form in forms {
+            let Sexp::List(items, offset) = form else {
+                self.error(form.offset(), "expected a form such as `(defsrc ...)`");
+                continue;
+            };
+            let Some((head, args)) = items.split_first() else {
+                self.error(*offset, "empty form");
+                continue;
+            };
+            match head.atom() {
+                Some("defcfg") => {}
+                Some("defsrc") if source.is_some() => {
+                    self.error(*offset, "`defsrc` is given more than once");
+                }
+                Some("defsrc") => {
+                    source = Some(args.iter().map(|arg| self.source_key(arg)).collect());
+                }
+                Some("defalias") => {
+                    for pair in args.chunks(2) {
+                        match pair {
+                            [Sexp::Atom(name, _), button] => {
+                                self.aliases.insert(name.clone(), button.clone());
+                            }
+                            [name, _] => self.error(name.offset(), "alias names must be atoms"),
+                            [name] => {
+                                self.error(name.offset(), "alias has no button");
+                            }
+                            _ => unreachable!(),
+                        }
+                    }
+                }
+                Some("deflayer") => match args.split_first() {
+                    Some((Sexp::Atom(name, _), buttons)) => {
+                        if self.layer_names.contains(name) {
+                            self.error(*offset, format!("layer `{name}` is defined twice"));
+                            continue;
+                        }
+                        self.layer_names.push(name.clone());
+                        layers.push((name, *offset, buttons));
+                    }
+                    _ => self.error(*offset, "`deflayer` needs a name"),
+                },
+                Some(other) => {
+                    let message = format!("`{other}` is not supported");
+                    self.error(head.offset(), message);
+                }
+                None => self.error(head.offset(), "expected a form name"),
+            }
+        }
+
+        let mut rules = RuleSet::default();
+        let Some(source) = source else {
+            self.error(0, "no `defsrc`");
+            return rules;
+        };
+        // Layers other than the base one, by name
+        let mut extra: Vec<Layer> = layers
+            .iter()
+            .skip(1)
+            .map(|(name, ..)| Layer {
+                name: name.to_string(),
+                ..Layer::default()
+            })
+            .collect();
+
+        for (index, &(name, offset, buttons)) in layers.iter().enumerate() {
+            if buttons.len() != source.len() {
+                let message = format!(
+                    "layer `{name}` has {} buttons but `defsrc` has {} keys",
+                    buttons.len(),
+                    source.len()
+                );
+                self.error(offset, message);
+            }
+            for (&from, sexp) in source.iter().zip(buttons) {
+                let (Some(from), Some(button)) = (from, self.button(sexp, 0)) else {
+                    continue;
+                };
+                let from = Switch::Key(from);
+                if index == 0 {
+                    self.base(from, button, sexp.offset(), &mut rules, &mut extra);
+                    continue;
+                }
+                match button {
+                    Button::Transparent => {}
+                    Button::Press(to) => {
+                        extra[index - 1].mappings.insert(from, to);
+                    }
+                    Button::LayerToggle(_) | Button::DualRole { .. } => {
+                        let message = "`layer-toggle` and `tap-hold` only work in the first layer";
+                        self.error(sexp.offset(), message);
+                    }
+                }
+            }
+        }
+        rules.layers = extra;
+        rules
+    }
+
+    /// Add a base layer button on the key `from`
+    fn base(
+        &mut self,
+        from: Switch,
+        button: Button,
+        offset: usize,
+        rules: &mut RuleSet,
+        layers: &mut [Layer],
+    ) {
+        match button {
+            Button::Transparent => {}
+            Button::Press(to) if to == [from] => {}
+            Button::Press(to) => rules.remaps.push(RemapRule {
+                from,
+                to,
+                devices: Vec::new(),
+            }),
+            Button::LayerToggle(name) => {
+                let Some(layer) = layers.iter_mut().find(|layer| layer.name == name) else {
+                    self.error(offset, "the first layer is always active");
+                    return;
+                };
+                match layer.activate {
+                    Some(_) => {
+                        let message = format!(
+                            "layer `{name}` is already toggled by another key; only one key can toggle a layer"
+                        );
+                        self.error(offset, message);
+                    }
+                    None => layer.activate = Some(from),
+                }
+            }
+            Button::DualRole { timeout, tap, hold } => rules.dual_roles.push(DualRoleRule {
+                switch: from,
+                tap,
+                hold,
+                timeout,
+                devices: Vec::new(),
+            }),
+        }
+    }
+
+    fn source_key(&mut self, sexp: &Sexp) -> Option<Key> {
+        let Some(name) = sexp.atom() else {
+            self.error(sexp.offset(), "`defsrc` lists key names");
+            return None;
+        };
+        let key = key_from_name(&unescape(name));
+        if key.is_none() {
+            self.error(sexp.offset(), format!("unknown key `{name}`"));
+        }
+        key
+    }
+
+    fn button(&mut self, sexp: &Sexp, depth: usize) -> Option<Button> {
+        let offset = sexp.offset();
+        let items = match sexp {
+            Sexp::Atom(atom, _) => return self.atom_button(atom, offset, depth),
+            Sexp::List(items, _) => items,
+        };
+        let Some((head, args)) = items.split_first() else {
+            self.error(offset, "empty button");
+            return None;
+        };
+        match (head.atom(), args) {
+            (Some("around"), [outer, inner]) => {
+                let outer = self.keys(outer, depth)?;
+                let inner = self.keys(inner, depth)?;
+                Some(Button::Press([outer, inner].concat()))
+            }
+            (Some("layer-toggle"), [Sexp::Atom(name, _)]) => {
+                if !self.layer_names.contains(name) {
+                    self.error(args[0].offset(), format!("unknown layer `{name}`"));
+                    return None;
+                }
+                Some(Button::LayerToggle(name.clone()))
+            }
+            (Some("tap-hold" | "tap-hold-next"), [timeout, tap, hold]) => {
+                let timeout = match timeout.atom().map(str::parse) {
+                    Some(Ok(millis)) => Duration::from_millis(millis),
+                    _ => {
+                        self.error(timeout.offset(), "expected a timeout in milliseconds");
+                        return None;
+                    }
+                };
+                let tap = self.keys(tap, depth);
+                let hold = match self.button(hold, depth)? {
+                    Button::Press(keys) => HoldAction::Press(keys),
+                    Button::LayerToggle(name) => HoldAction::Layer(name),
+                    _ => {
+                        let message = "a hold can only press keys or toggle a layer";
+                        self.error(hold.offset(), message);
+                        return None;
+                    }
+                };
+                Some(Button::DualRole {
+                    timeout,
+                    tap: tap?,
+                    hold,
+                })
+            }
+            (Some(name @ ("around" | "layer-toggle" | "tap-hold" | "tap-hold-next")), _) => {
+                let expected = match name {
+                    "around" => "two buttons",
+                    "layer-toggle" => "a layer name",
+                    _ => "a timeout, a tap button and a hold button",
+                };
+                self.error(offset, format!("`{name}` takes {expected}"));
+                None
+            }
+            (Some(name), _) => {
+                self.error(offset, format!("`{name}` buttons are not supported"));
+                None
+            }
+            (None, _) => {
+                self.error(offset, "expected a button name");
+                None
+            }
+        }
+    }
+
+    fn atom_button(&mut self, atom: &str, offset: usize, depth: usize) -> Option<Button> {
+        if atom == "_" {
+            return Some(Button::Transparent);
+        }
+        if atom == "XX" {
+            return Some(Button::Press(Vec::new()));
+        }
+        if let Some(alias) = atom.strip_prefix('@') {
+            let Some(button) = self.aliases.get(alias).cloned() else {
+                self.error(offset, format!("unknown alias `{alias}`"));
+                return None;
+            };
+            if depth == MAX_ALIAS_DEPTH {
+                self.error(offset, format!("alias `{alias}` refers to itself"));
+                return None;
+            }
+            let errors = self.diagnostics.len();
+            let button = self.button(&button, depth + 1);
+            if depth == 0 {
+                // Report problems inside aliases where they are used, not where they are defined
+                let at = Diagnostic::at(self.source, offset, "");
+                for diagnostic in &mut self.diagnostics[errors..] {
+                    diagnostic.message = format!("in `@{alias}`: {}", diagnostic.message);
+                    (diagnostic.line, diagnostic.column) = (at.line, at.column);
+                }
+            }
+            return button;
+        }
+        match shortcut(atom) {
+            Some(keys) => Some(Button::Press(keys)),
+            None => {
+                self.error(offset, format!("unknown key `{atom}`"));
+                None
+            }
+        }
+    }
+
+    /// A button that only presses keys
+    fn keys(&mut self, sexp: &Sexp, depth: usize) -> Option<Vec<Switch>> {
+        match self.button(sexp, depth)? {
+            Button::Press(keys) => Some(keys),
+            _ => {
+                self.error(sexp.offset(), "expected keys");
+                None
+            }
+        }
+    }
+}
+
+fn key_from_name(name: &str) -> Option<Key> {
+    NAMES
+        .iter()
+        .find(|(candidate, _)| *candidate == name)
+        .map(|(_, key)| *key)
+        .or_else(|| Key::from_name(name))
+}
+
+/// The keys pressed by a key name, a shifted symbol or a shortcut such as `C-S-t`
+fn shortcut(mut name: &str) -> Option<Vec<Switch>> {
+    let mut keys = Vec::new();
+    'prefixes: while name.len() > 2 {
+        for (prefix, modifier) in MODIFIERS {
+            if let Some(rest) = name.strip_prefix(prefix) {
+                keys.push(Switch::Key(modifier));
+                name = rest;
+                continue 'prefixes;
+            }
+        }
+        break;
+    }
+    let name = unescape(name);
+    if let Some((_, key)) = SHIFTED.iter().find(|(symbol, _)| *symbol == name) {
+        keys.extend([Switch::Key(Key::LEFT_SHIFT), Switch::Key(*key)]);
+    } else {
+        keys.push(Switch::Key(key_from_name(&name)?));
+    }
+    Some(keys)
+}
+
+/// An atom without the backslashes that escape its characters
+fn unescape(atom: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = atom.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(chars.next().unwrap_or('\\')),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::Rules;
+    use crate::{Event, Processor};
+    use std::time::Instant;
+
+    const SAMPLE: &str = r#"
+;; A home-row layout with a navigation layer
+(defcfg
+  input  (device-file "/dev/input/by-id/usb-keyboard-event-kbd")
+  output (uinput-sink "KMonad output")
+  fallthrough true)
+
+(defsrc
+  esc  1    2    3
+  caps a    s    h    j    k    l    ;
+  lsft z    x    c    v
+  lctl lmet lalt spc  ralt)
+
+(defalias
+  cesc (tap-hold-next 180 esc lctl)   ;; Caps is Esc, or Ctrl when held
+  nav  (layer-toggle nav)
+  sa   (tap-hold 200 a lsft)
+  cpy  C-c
+  #| Block comments
+     are skipped |#
+  ul   (around lctl \())
+
+(deflayer base
+  grv  _    _    _
+  @cesc @sa s    h    j    k    l    ;
+  lsft z    x    c    v
+  lctl lmet lalt (tap-hold 250 spc (layer-toggle nav)) @nav)
+
+(deflayer nav
+  _    f1   f2   f3
+  _    _    XX   left down up   rght @cpy
+  _    _    _    _    !
+  _    _    _    _    _)
+"#;
+
+    fn key(key: Key) -> Switch {
+        Switch::Key(key)
+    }
+
+    #[test]
+    fn test_import_sample() {
+        let Import { rules, diagnostics } = import(SAMPLE);
+        assert_eq!(diagnostics, []);
+        assert_eq!(
+            rules.remaps,
+            [RemapRule {
+                from: key(Key::ESCAPE),
+                to: vec![key(Key::BACKQUOTE)],
+                devices: vec![],
+            }]
+        );
+        assert_eq!(
+            rules.dual_roles,
+            [
+                DualRoleRule {
+                    switch: key(Key::CAPS_LOCK),
+                    tap: vec![key(Key::ESCAPE)],
+                    hold: HoldAction::Press(vec![key(Key::LEFT_CTRL)]),
+                    timeout: Duration::from_millis(180),
+                    devices: vec![],
+                },
+                DualRoleRule {
+                    switch: key(Key::A),
+                    tap: vec![key(Key::A)],
+                    hold: HoldAction::Press(vec![key(Key::LEFT_SHIFT)]),
+                    timeout: Duration::from_millis(200),
+                    devices: vec![],
+                },
+                DualRoleRule {
+                    switch: key(Key::SPACE),
+                    tap: vec![key(Key::SPACE)],
+                    hold: HoldAction::Layer("nav".to_string()),
+                    timeout: Duration::from_millis(250),
+                    devices: vec![],
+                },
+            ]
+        );
+
+        let [nav] = &rules.layers[..] else {
+            panic!("expected one layer: {:?}", rules.layers);
+        };
+        assert_eq!(nav.name, "nav");
+        assert_eq!(nav.activate, Some(key(Key::RIGHT_ALT)));
+        assert_eq!(nav.mappings.len(), 10);
+        assert_eq!(nav.mappings[&key(Key::DIGIT_1)], [key(Key::F1)]);
+        assert_eq!(nav.mappings[&key(Key::S)], []);
+        assert_eq!(nav.mappings[&key(Key::L)], [key(Key::ARROW_RIGHT)]);
+        assert_eq!(
+            nav.mappings[&key(Key::SEMICOLON)],
+            [key(Key::LEFT_CTRL), key(Key::C)]
+        );
+        assert_eq!(
+            nav.mappings[&key(Key::V)],
+            [key(Key::LEFT_SHIFT), key(Key::DIGIT_1)]
+        );
+    }
+
+    #[test]
+    fn test_imported_rules_run() {
+        let mut rules = Rules::new(import(SAMPLE).rules);
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for stroke in [
+            Key::RIGHT_ALT.down(),
+            Key::H.down(),
+            Key::H.up(),
+            Key::RIGHT_ALT.up(),
+            Key::H.down(),
+            Key::H.up(),
+        ] {
+            rules.process(Event::new(0, stroke), now, &mut out);
+        }
+        assert_eq!(
+            out,
+            [
+                Event::new(0, Key::ARROW_LEFT.down()),
+                Event::new(0, Key::ARROW_LEFT.up()),
+                Event::new(0, Key::H.down()),
+                Event::new(0, Key::H.up()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_unsupported() {
+        let source = "\
+(defsrc a b c d)
+(defalias mac (tap-macro a b) loop @loop)
+(deflayer base
+  @mac (layer-switch other) foo @loop)
+(deflayer other
+  _ (tap-hold 200 a lsft) _)
+(defchords x)
+(deflayer other _ _ _ _)
+";
+        let messages: Vec<String> = import(source)
+            .diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "4:3: in `@mac`: `tap-macro` buttons are not supported",
+                "4:8: `layer-switch` buttons are not supported",
+                "4:29: unknown key `foo`",
+                "4:33: in `@loop`: alias `loop` refers to itself",
+                "5:1: layer `other` has 3 buttons but `defsrc` has 4 keys",
+                "6:5: `layer-toggle` and `tap-hold` only work in the first layer",
+                "7:2: `defchords` is not supported",
+                "8:1: layer `other` is defined twice",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let messages: Vec<String> = import("(defsrc a))\n(deflayer base\n  a")
+            .diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(messages, ["1:11: unmatched `)`", "2:1: unclosed `(`"]);
+        assert_eq!(
+            shortcut("C--"),
+            Some(vec![key(Key::LEFT_CTRL), key(Key::MINUS)])
+        );
+        assert_eq!(shortcut("RA-S-e").map(|keys| keys.len()), Some(3));
+    }
+
+    #[test]
+    fn test_escaped_symbols() {
+        let Import { rules, diagnostics } =
+            import(r#"(defsrc a b c d e) (deflayer base \_ _ \" C-\( \\)"#);
+        assert_eq!(diagnostics, []);
+        let remaps: Vec<_> = rules
+            .remaps
+            .iter()
+            .map(|remap| (remap.from, remap.to.clone()))
+            .collect();
+        let shift = key(Key::LEFT_SHIFT);
+        assert_eq!(
+            remaps,
+            [
+                (key(Key::A), vec![shift, key(Key::MINUS)]),
+                (key(Key::C), vec![shift, key(Key::QUOTE)]),
+                (
+                    key(Key::D),
+                    vec![key(Key::LEFT_CTRL), shift, key(Key::DIGIT_9)]
+                ),
+                (key(Key::E), vec![key(Key::BACKSLASH)]),
+            ]
+        );
+    }
+}
@@ -11,6 +11,7 @@
 //! The library provides type-safe device structures that prevent misuse:
 //!
 //! ```rust,no_run
+//! # #[cfg(windows)] {
 //! use interception::{KeyboardDevice, MouseDevice, KeyStroke, MouseStroke, FILTER_KEY_ALL, FILTER_MOUSE_ALL};
 //!
 //! // Create type-safe keyboard device
@@ -35,6 +36,7 @@
 //!     MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_LEFT_BUTTON_DOWN, 0, 0, 0, 0),  // Left button down
 //! ];
 //! mouse.send(&mouse_strokes).expect("Failed to send mouse strokes");
+//! # }
 //! ```
 //!
 //! ## Precedence and Event Processing Order
@@ -53,6 +55,7 @@
 //! ### Setting Precedence:
 //!
 //! ```rust,no_run
+//! # #[cfg(windows)] {
 //! use interception::{Interception, KeyboardDevice};
 //!
 //! // Set precedence for entire context (all devices)
@@ -62,19 +65,24 @@
 //! // Or set precedence for individual devices
 //! let mut keyboard = KeyboardDevice::new(0)?;
 //! keyboard.set_precedence(50)?; // Medium priority
+//! # }
 //! # Ok::<(), interception::InterceptionError>(())
 //! ```
 //!
 
-#![cfg(windows)]
-
 use std::error::Error;
-use std::ffi::{OsString, c_int, c_long, c_short, c_uint, c_ulong, c_ushort, c_void};
+#[cfg(windows)]
+use std::ffi::{OsString, c_void};
+use std::ffi::{c_int, c_short, c_uint, c_ushort};
 use std::fmt::{Display, Formatter};
 use std::mem;
+#[cfg(windows)]
 use std::os::windows::ffi::OsStringExt;
+#[cfg(windows)]
 use std::ptr;
-use std::time::Duration;
+#[cfg(windows)]
+use std::time::{Duration, Instant};
+#[cfg(windows)]
 use windows_sys::Win32::{
     Foundation::{
         CloseHandle, FALSE, GENERIC_READ, GetLastError, HANDLE, INVALID_HANDLE_VALUE, TRUE,
@@ -88,11 +96,56 @@ use windows_sys::Win32::{
     },
 };
 
+pub mod ahk;
+pub mod axes;
+mod button;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod debounce;
+pub mod decoder;
+pub mod drag_scroll;
+pub mod gesture;
+pub mod hotkey;
+pub mod hotstring;
+mod key;
+pub mod klc;
+pub mod kmonad;
+pub mod middle_click;
+mod motion;
+pub mod mouse_keys;
+pub mod path;
+mod processor;
+pub mod record;
+pub mod remap;
+pub mod repeat;
+pub mod rules;
+pub mod screen;
+pub mod script;
+pub mod send_keys;
+pub mod sensitivity;
+pub mod smoothing;
+pub mod sticky;
+pub mod tablet;
+pub mod text;
+pub mod wheel;
+
+pub use button::{MOUSE_BUTTONS_DOWN, MOUSE_BUTTONS_UP, MouseButton};
+pub use key::{Key, MODIFIERS};
+pub use motion::Remainder;
+pub use processor::{
+    Chain, Event, Passthrough, PerDevice, Processor, Stroke, Switch, is_keyboard, is_mouse,
+};
+#[cfg(windows)]
+use remap::ActiveDevices;
+
+#[cfg(windows)]
 pub struct Interception {
     devices: [Device; MAX_DEVICES],
     wait_handles: [WaitHandle; MAX_DEVICES],
+    active: ActiveDevices,
 }
 
+#[cfg(windows)]
 impl Interception {
     /// Will fail with `CreateFile(ERROR_FILE_NOT_FOUND)` error if the Interception driver is not installed.
     pub fn new() -> Result<Self> {
@@ -120,6 +173,7 @@ impl Interception {
         Ok(Interception {
             devices,
             wait_handles,
+            active: ActiveDevices::default(),
         })
     }
 
@@ -169,8 +223,124 @@ impl Interception {
 
     pub fn wait_index(&mut self, timeout: Option<Duration>) -> Result<usize> {
         let index = wait(&self.wait_handles, timeout)?;
+        self.active.observe(index);
         Ok(index)
     }
+
+    /// The most recently active keyboard and mouse, as seen by [`wait_index`](Self::wait_index)
+    pub fn active_devices(&self) -> ActiveDevices {
+        self.active
+    }
+
+    /// Run a processor over every stroke received from any device
+    ///
+    /// Each received stroke is handed to `processor`, and whatever it emits is sent to the
+    /// device named by the event. Waiting is bounded by [`Processor::deadline`] so that timers
+    /// fire on time. Only strokes matching each device's filter are received, so filters must
+    /// be set beforehand. Every device that is present is first passed to
+    /// [`Processor::attach`] with its hardware ID.
+    ///
+    /// This only returns on error.
+    pub fn run<P: Processor + ?Sized>(&mut self, processor: &mut P) -> Result<()> {
+        self.run_while(processor, |_| true)
+    }
+
+    /// Like [`Interception::run`], but returns once `running` is false after a round of
+    /// sending
+    pub(crate) fn run_while<P: Processor + ?Sized>(
+        &mut self,
+        processor: &mut P,
+        running: impl Fn(&P) -> bool,
+    ) -> Result<()> {
+        for (index, device) in self.devices.iter_mut().enumerate() {
+            if let Ok(hardware_id) = device.get_hardware_id() {
+                processor.attach(index, &hardware_id.to_string_lossy());
+            }
+        }
+
+        let mut key_strokes = [KeyStroke::default(); 32];
+        let mut mouse_strokes = [MouseStroke::default(); 32];
+        let mut out = Vec::new();
+
+        loop {
+            let timeout = processor
+                .deadline()
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let ready = match self.wait_index(timeout) {
+                Ok(index) => Some(index),
+                Err(InterceptionError::Wait(WaitError::WaitTimeout)) => None,
+                Err(e) => return Err(e),
+            };
+
+            // Timers that expired while waiting fire before the strokes that ended the wait
+            let now = Instant::now();
+            if processor.deadline().is_some_and(|deadline| deadline <= now) {
+                processor.tick(now, &mut out);
+            }
+
+            match ready.map(|index| (index, &mut self.devices[index])) {
+                Some((index, Device::Keyboard(keyboard))) => {
+                    for stroke in keyboard.receive(&mut key_strokes)? {
+                        processor.process(Event::new(index, *stroke), now, &mut out);
+                    }
+                }
+                Some((index, Device::Mouse(mouse))) => {
+                    for stroke in mouse.receive(&mut mouse_strokes)? {
+                        processor.process(Event::new(index, *stroke), now, &mut out);
+                    }
+                }
+                None => {}
+            }
+
+            self.send_events(&out)?;
+            out.clear();
+            if !running(processor) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Send events to the devices they name
+    ///
+    /// A key stroke addressed to a mouse is sent to the most recently active keyboard instead,
+    /// and a mouse stroke addressed to a keyboard to the most recently active mouse.
+    /// Consecutive strokes for the same device are sent in one batch.
+    pub fn send_events(&mut self, events: &[Event]) -> Result<()> {
+        let mut key_strokes = Vec::new();
+        let mut mouse_strokes = Vec::new();
+
+        let routed: Vec<Event> = events
+            .iter()
+            .map(|event| match event.stroke {
+                Stroke::Key(_) if !is_keyboard(event.device) => Event {
+                    device: self.active.keyboard,
+                    ..*event
+                },
+                Stroke::Mouse(_) if !is_mouse(event.device) => Event {
+                    device: self.active.mouse,
+                    ..*event
+                },
+                _ => *event,
+            })
+            .collect();
+
+        for batch in routed.chunk_by(|a, b| a.device == b.device) {
+            match self.devices.get_mut(batch[0].device) {
+                Some(Device::Keyboard(keyboard)) => {
+                    key_strokes.clear();
+                    key_strokes.extend(batch.iter().filter_map(|event| event.key().copied()));
+                    keyboard.send(&key_strokes)?;
+                }
+                Some(Device::Mouse(mouse)) => {
+                    mouse_strokes.clear();
+                    mouse_strokes.extend(batch.iter().filter_map(|event| event.mouse().copied()));
+                    mouse.send(&mouse_strokes)?;
+                }
+                None => return Err(InterceptionError::InvalidDevice),
+            }
+        }
+        Ok(())
+    }
 }
 
 // Constants from the original C header
@@ -314,7 +484,7 @@ pub const FILTER_MOUSE_MOVE: MouseFilter = 0x1000;
 
 /// `KEYBOARD_INPUT_DATA` structure
 /// <https://learn.microsoft.com/en-us/windows/win32/api/ntddkbd/ns-ntddkbd-keyboard_input_data>
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct KeyStroke {
     /// Device unit ID (internal use only)
@@ -377,11 +547,26 @@ impl KeyStroke {
     pub fn up(code: u16) -> Self {
         Self::new(code, KEY_UP)
     }
+
+    /// The key this stroke refers to, including its `E0`/`E1` prefix
+    pub fn key(&self) -> Key {
+        Key::of(self)
+    }
+
+    /// Whether this is a key up stroke
+    pub fn is_up(&self) -> bool {
+        self.state & KEY_UP != 0
+    }
+
+    /// Whether this is a key down stroke
+    pub fn is_down(&self) -> bool {
+        !self.is_up()
+    }
 }
 
 /// `MOUSE_INPUT_DATA` structure
 /// <https://learn.microsoft.com/en-us/windows/win32/api/ntddmou/ns-ntddmou-mouse_input_data>
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct MouseStroke {
     /// Device unit ID (unused)
@@ -393,13 +578,13 @@ pub struct MouseStroke {
     /// Mouse wheel delta (`button_data` in Windows API)
     pub rolling: c_short,
     /// Raw buttons state (unused)
-    _raw_buttons: c_ulong,
+    _raw_buttons: u32,
     /// X coordinate (`last_x` in Windows API)
-    pub x: c_long,
+    pub x: i32,
     /// Y coordinate (`last_y` in Windows API)
-    pub y: c_long,
+    pub y: i32,
     /// Additional information (`extra_information` in Windows API)
-    information: c_ulong,
+    information: u32,
 }
 #[allow(clippy::unnecessary_operation, clippy::identity_op)]
 const _: () = {
@@ -429,9 +614,9 @@ impl MouseStroke {
         flags: MouseFlag,
         state: MouseState,
         rolling: c_short,
-        x: c_long,
-        y: c_long,
-        information: c_ulong,
+        x: i32,
+        y: i32,
+        information: u32,
     ) -> Self {
         Self {
             _unit_id: 0,
@@ -447,14 +632,18 @@ impl MouseStroke {
 }
 
 /// Trait for stroke types that can be sent/received through device I/O operations
-trait Stroke: Default + Clone + Sized {
+#[cfg(windows)]
+trait RawStroke: Default + Clone + Sized {
     // This trait provides a unified interface for both KeyStroke and MouseStroke types
 }
 
-// Implement the Stroke trait for both KeyStroke and MouseStroke
-impl Stroke for KeyStroke {}
-impl Stroke for MouseStroke {}
+// Implement the RawStroke trait for both KeyStroke and MouseStroke
+#[cfg(windows)]
+impl RawStroke for KeyStroke {}
+#[cfg(windows)]
+impl RawStroke for MouseStroke {}
 
+#[cfg(windows)]
 #[derive(Debug)]
 pub enum Device {
     /// Keyboard device
@@ -463,6 +652,7 @@ pub enum Device {
     Mouse(MouseDevice),
 }
 
+#[cfg(windows)]
 impl Device {
     pub fn new(index: usize) -> Result<Self> {
         if index < MAX_KEYBOARD {
@@ -517,10 +707,12 @@ impl Device {
     }
 }
 
+#[cfg(windows)]
 /// A keyboard input device for intercepting and injecting keyboard events
 #[derive(Debug)]
 pub struct KeyboardDevice(RawDevice);
 
+#[cfg(windows)]
 impl KeyboardDevice {
     /// Create a new keyboard device
     ///
@@ -591,10 +783,12 @@ impl KeyboardDevice {
     }
 }
 
+#[cfg(windows)]
 /// A mouse input device for intercepting and injecting mouse events
 #[derive(Debug)]
 pub struct MouseDevice(RawDevice);
 
+#[cfg(windows)]
 impl MouseDevice {
     /// Create a new mouse device
     ///
@@ -666,9 +860,11 @@ impl MouseDevice {
     }
 }
 
+#[cfg(windows)]
 #[derive(Debug)]
 pub struct RawDevice(RawDeviceHandle);
 
+#[cfg(windows)]
 impl RawDevice {
     fn new(index: usize) -> Result<Self> {
         let path = format!("\\\\.\\interception{index:02}");
@@ -739,7 +935,7 @@ impl RawDevice {
     }
 
     /// Generic function to send strokes to a device
-    fn send_strokes<T: Stroke>(&mut self, strokes: &[T]) -> Result<usize> {
+    fn send_strokes<T: RawStroke>(&mut self, strokes: &[T]) -> Result<usize> {
         if strokes.is_empty() {
             return Ok(0);
         }
@@ -752,7 +948,7 @@ impl RawDevice {
     ///
     /// Strokes buffer will be filled with received strokes, and the returned slice
     /// will be a subslice of the input buffer containing only the received strokes.
-    fn receive_strokes<'a, T: Stroke>(&mut self, strokes: &'a mut [T]) -> Result<&'a mut [T]> {
+    fn receive_strokes<'a, T: RawStroke>(&mut self, strokes: &'a mut [T]) -> Result<&'a mut [T]> {
         if strokes.is_empty() {
             return Ok(strokes);
         }
@@ -765,9 +961,11 @@ impl RawDevice {
     }
 }
 
+#[cfg(windows)]
 #[derive(Debug)]
 struct RawDeviceHandle(HANDLE);
 
+#[cfg(windows)]
 impl RawDeviceHandle {
     fn new(path: &str) -> Result<Self> {
         let path_w: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
@@ -839,25 +1037,35 @@ impl RawDeviceHandle {
 }
 
 // IOCTL codes from the original C implementation
+#[cfg(windows)]
 const IOCTL_SET_PRECEDENCE: u32 =
     ctl_code(FILE_DEVICE_UNKNOWN, 0x801, METHOD_BUFFERED, FILE_ANY_ACCESS);
+#[cfg(windows)]
 const IOCTL_GET_PRECEDENCE: u32 =
     ctl_code(FILE_DEVICE_UNKNOWN, 0x802, METHOD_BUFFERED, FILE_ANY_ACCESS);
+#[cfg(windows)]
 const IOCTL_SET_FILTER: u32 =
     ctl_code(FILE_DEVICE_UNKNOWN, 0x804, METHOD_BUFFERED, FILE_ANY_ACCESS);
+#[cfg(windows)]
 const IOCTL_GET_FILTER: u32 =
     ctl_code(FILE_DEVICE_UNKNOWN, 0x808, METHOD_BUFFERED, FILE_ANY_ACCESS);
+#[cfg(windows)]
 const IOCTL_SET_EVENT: u32 = ctl_code(FILE_DEVICE_UNKNOWN, 0x810, METHOD_BUFFERED, FILE_ANY_ACCESS);
+#[cfg(windows)]
 const IOCTL_WRITE: u32 = ctl_code(FILE_DEVICE_UNKNOWN, 0x820, METHOD_BUFFERED, FILE_ANY_ACCESS);
+#[cfg(windows)]
 const IOCTL_READ: u32 = ctl_code(FILE_DEVICE_UNKNOWN, 0x840, METHOD_BUFFERED, FILE_ANY_ACCESS);
+#[cfg(windows)]
 const IOCTL_GET_HARDWARE_ID: u32 =
     ctl_code(FILE_DEVICE_UNKNOWN, 0x880, METHOD_BUFFERED, FILE_ANY_ACCESS);
 
+#[cfg(windows)]
 /// `CTL_CODE` macro in `winioctl.h`
 const fn ctl_code(device_type: u32, function: u32, method: u32, access: u32) -> u32 {
     (device_type << 16) | (access << 14) | (function << 2) | method
 }
 
+#[cfg(windows)]
 impl Drop for RawDeviceHandle {
     fn drop(&mut self) {
         unsafe {
@@ -866,10 +1074,12 @@ impl Drop for RawDeviceHandle {
     }
 }
 
+#[cfg(windows)]
 #[derive(Debug)]
 #[repr(transparent)]
 pub struct WaitHandle(HANDLE);
 
+#[cfg(windows)]
 impl WaitHandle {
     fn new() -> Result<Self> {
         unsafe {
@@ -889,6 +1099,7 @@ impl WaitHandle {
     }
 }
 
+#[cfg(windows)]
 impl Drop for WaitHandle {
     fn drop(&mut self) {
         unsafe {
@@ -897,6 +1108,7 @@ impl Drop for WaitHandle {
     }
 }
 
+#[cfg(windows)]
 /// Waits for any of the provided handles to be signaled.
 /// Returns the index of the signaled handle or an error if none were signaled within the timeout.
 pub fn wait(handles: &[WaitHandle], timeout: Option<Duration>) -> Result<usize, WaitError> {
@@ -957,6 +1169,7 @@ impl From<WaitError> for InterceptionError {
     }
 }
 
+#[cfg(windows)]
 type Result<T, E = InterceptionError> = std::result::Result<T, E>;
 
 /// Error types for Interception operations
@@ -990,6 +1203,7 @@ impl Display for InterceptionError {
 
 impl Error for InterceptionError {}
 
+#[cfg(windows)]
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,305 @@
+//! Middle-click emulation.
+//!
+//! [`MiddleClick`] turns a left and right button press arriving within a short window of each
+//! other into a middle button press, for laptops and touchpads without a middle button.
+//!
+//! The first of the two presses is withheld for the window. If the other button goes down in
+//! time, both are swallowed and the middle button goes down instead; it comes back up when
+//! either of the two is released, and the later release is swallowed too. Otherwise the
+//! withheld press is replayed, ahead of anything else the device reports afterwards, so
+//! ordinary clicks and drags are only delayed and never reordered. Motion reported during
+//! the window is held back with the press and follows it.
+
+use crate::{
+    Event, MOUSE_BUTTONS_DOWN, MOUSE_BUTTONS_UP, MOUSE_MIDDLE_BUTTON_DOWN, MOUSE_MIDDLE_BUTTON_UP,
+    MouseButton, MouseStroke, Processor, Stroke,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Chord {
+    /// One of left and right is down but withheld until `deadline`
+    Pending {
+        button: MouseButton,
+        deadline: Instant,
+    },
+    /// The middle button is down in place of left and right; which of them are still held
+    Middle { left: bool, right: bool },
+}
+
+/// Emulates the middle button with a left and right press
+#[derive(Debug, Clone)]
+pub struct MiddleClick {
+    window: Duration,
+    chords: HashMap<usize, Chord>,
+    /// Strokes that arrived while a press was withheld, to follow it in order
+    withheld: HashMap<usize, Vec<MouseStroke>>,
+}
+
+impl Default for MiddleClick {
+    /// A 50ms window
+    fn default() -> Self {
+        Self::new(Duration::from_millis(50))
+    }
+}
+
+impl MiddleClick {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            chords: HashMap::new(),
+            withheld: HashMap::new(),
+        }
+    }
+
+    /// Whether the emulated middle button is down on `device`
+    pub fn is_middle_down(&self, device: usize) -> bool {
+        matches!(self.chords.get(&device), Some(Chord::Middle { .. }))
+    }
+}
+
+const fn other(button: MouseButton) -> MouseButton {
+    match button {
+        MouseButton::Left => MouseButton::Right,
+        _ => MouseButton::Left,
+    }
+}
+
+impl Processor for MiddleClick {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        let Stroke::Mouse(stroke) = event.stroke else {
+            out.push(event);
+            return;
+        };
+        let device = event.device;
+        let (left, right) = (MouseButton::Left, MouseButton::Right);
+        let mut remaining = stroke;
+
+        match self.chords.get(&device).copied() {
+            None => {
+                if stroke.is_down(left) && stroke.is_down(right) {
+                    remaining.state &= !(left.down_flag() | right.down_flag());
+                    remaining.state |= MOUSE_MIDDLE_BUTTON_DOWN;
+                    self.chords.insert(
+                        device,
+                        Chord::Middle {
+                            left: true,
+                            right: true,
+                        },
+                    );
+                } else if let Some(button) = [left, right].into_iter().find(|b| stroke.is_down(*b))
+                {
+                    remaining.state &= !button.down_flag();
+                    self.chords.insert(
+                        device,
+                        Chord::Pending {
+                            button,
+                            deadline: now + self.window,
+                        },
+                    );
+                }
+            }
+            Some(Chord::Pending { button, deadline }) => {
+                let chorded = other(button);
+                let other_bits = (MOUSE_BUTTONS_DOWN | MOUSE_BUTTONS_UP)
+                    & !chorded.down_flag()
+                    & !button.flags();
+                if now < deadline && stroke.is_down(chorded) && !stroke.is_up(button) {
+                    let withheld = self.withheld.remove(&device).unwrap_or_default();
+                    out.extend(withheld.into_iter().map(|s| Event::new(device, s)));
+                    remaining.state &= !chorded.down_flag();
+                    remaining.state |= MOUSE_MIDDLE_BUTTON_DOWN;
+                    self.chords.insert(
+                        device,
+                        Chord::Middle {
+                            left: true,
+                            right: true,
+                        },
+                    );
+                } else if now >= deadline
+                    || stroke.is_up(button)
+                    || stroke.is_down(chorded)
+                    || stroke.state & other_bits != 0
+                {
+                    // Not a chord; let the press through ahead of whatever comes next
+                    out.push(Event::new(device, button.down()));
+                    let withheld = self.withheld.remove(&device).unwrap_or_default();
+                    out.extend(withheld.into_iter().map(|s| Event::new(device, s)));
+                    self.chords.remove(&device);
+                } else {
+                    self.withheld.entry(device).or_default().push(stroke);
+                    return;
+                }
+            }
+            Some(Chord::Middle {
+                left: left_held,
+                right: right_held,
+            }) => {
+                let released_left = left_held && stroke.is_up(left);
+                let released_right = right_held && stroke.is_up(right);
+                if released_left {
+                    remaining.state &= !left.up_flag();
+                }
+                if released_right {
+                    remaining.state &= !right.up_flag();
+                }
+                if (released_left || released_right) && left_held && right_held {
+                    remaining.state |= MOUSE_MIDDLE_BUTTON_UP;
+                }
+
+                let (left_held, right_held) =
+                    (left_held && !released_left, right_held && !released_right);
+                if left_held || right_held {
+                    self.chords.insert(
+                        device,
+                        Chord::Middle {
+                            left: left_held,
+                            right: right_held,
+                        },
+                    );
+                } else {
+                    self.chords.remove(&device);
+                }
+            }
+        }
+
+        if remaining == stroke || !remaining.is_empty() {
+            out.push(Event::new(device, remaining));
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.chords
+            .values()
+            .filter_map(|chord| match chord {
+                Chord::Pending { deadline, .. } => Some(*deadline),
+                Chord::Middle { .. } => None,
+            })
+            .min()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        let withheld = &mut self.withheld;
+        self.chords.retain(|&device, chord| match *chord {
+            Chord::Pending { button, deadline } if deadline <= now => {
+                out.push(Event::new(device, button.down()));
+                let strokes = withheld.remove(&device).unwrap_or_default();
+                out.extend(strokes.into_iter().map(|s| Event::new(device, s)));
+                false
+            }
+            _ => true,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_KEYBOARD, MOUSE_MOVE_RELATIVE};
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Feed timed strokes, ticking at every deadline that falls before the next stroke
+    fn run(middle: &mut MiddleClick, t0: Instant, strokes: &[(u64, MouseStroke)]) -> Vec<Event> {
+        let mut out = Vec::new();
+        for &(at, stroke) in strokes {
+            let now = t0 + ms(at);
+            while let Some(deadline) = middle.deadline().filter(|d| *d <= now) {
+                middle.tick(deadline, &mut out);
+            }
+            middle.process(Event::new(MOUSE, stroke), now, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_chord_within_window_is_middle_click() {
+        let mut middle = MiddleClick::default();
+        let t0 = Instant::now();
+        let motion = MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, 1, 1, 0);
+        let out = run(
+            &mut middle,
+            t0,
+            &[
+                (0, MouseButton::Right.down()),
+                (10, motion),
+                (20, MouseButton::Left.down()),
+                (100, MouseButton::Left.up()),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, motion),
+                Event::new(MOUSE, MouseButton::Middle.down()),
+                Event::new(MOUSE, MouseButton::Middle.up()),
+            ]
+        );
+        assert!(middle.is_middle_down(MOUSE));
+
+        let out = run(&mut middle, t0, &[(120, MouseButton::Right.up())]);
+        assert!(out.is_empty());
+        assert!(!middle.is_middle_down(MOUSE));
+    }
+
+    #[test]
+    fn test_expired_window_replays_press() {
+        let mut middle = MiddleClick::default();
+        let t0 = Instant::now();
+        let out = run(
+            &mut middle,
+            t0,
+            &[
+                (0, MouseButton::Left.down()),
+                (80, MouseButton::Right.down()),
+                (90, MouseButton::Right.up()),
+                (100, MouseButton::Left.up()),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseButton::Left.down()),
+                Event::new(MOUSE, MouseButton::Right.down()),
+                Event::new(MOUSE, MouseButton::Right.up()),
+                Event::new(MOUSE, MouseButton::Left.up()),
+            ]
+        );
+        assert_eq!(middle.deadline(), None);
+    }
+
+    #[test]
+    fn test_quick_click_keeps_order() {
+        let mut middle = MiddleClick::default();
+        let wheel = MouseStroke::wheel(120);
+        let motion = MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, 2, 0, 0);
+        let out = run(
+            &mut middle,
+            Instant::now(),
+            &[
+                (0, MouseButton::Left.down()),
+                (5, motion),
+                (10, MouseButton::Left.up()),
+                (20, MouseButton::Right.down()),
+                (25, MouseButton::Button4.down()),
+                (30, wheel),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseButton::Left.down()),
+                Event::new(MOUSE, motion),
+                Event::new(MOUSE, MouseButton::Left.up()),
+                Event::new(MOUSE, MouseButton::Right.down()),
+                Event::new(MOUSE, MouseButton::Button4.down()),
+                Event::new(MOUSE, wheel),
+            ]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Fractional movement bookkeeping.
+//!
+//! Mouse strokes carry whole counts, but scaled or generated movement is fractional. Rounding
+//! each stroke on its own loses slow movement entirely, so [`Remainder`] carries the
+//! fractional part over to the next stroke.
+
+/// Carries the fractional part of a movement over to the next one
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Remainder(f64);
+
+impl Remainder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a fractional delta and take out the whole counts, rounding toward zero
+    pub fn take(&mut self, delta: f64) -> i32 {
+        let total = self.0 + delta;
+        let whole = total.trunc();
+        self.0 = total - whole;
+        whole as i32
+    }
+
+    /// The fraction carried over so far
+    pub fn get(&self) -> f64 {
+        self.0
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remainder_keeps_slow_movement() {
+        let mut remainder = Remainder::new();
+        let moved: i32 = (0..10).map(|_| remainder.take(0.25)).sum();
+        assert_eq!(moved, 2);
+        assert!((remainder.get() - 0.5).abs() < 1e-9);
+
+        assert_eq!(remainder.take(-1.75), -1);
+        assert!((remainder.get() + 0.25).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,553 @@
+//! Keyboard-driven pointer control.
+//!
+//! [`MouseKeys`] turns held keys into relative pointer movement, button presses and wheel
+//! steps. Movement starts at a configurable speed and accelerates along an
+//! [`Acceleration`] curve towards a maximum while direction keys stay held; it is emitted on
+//! every [`tick`](Processor::tick) and sub-pixel remainders are carried between ticks, so even
+//! very slow speeds move the pointer smoothly.
+
+use crate::remap::ActiveDevices;
+use crate::{
+    Event, Key, MOUSE_HWHEEL, MOUSE_MOVE_RELATIVE, MOUSE_WHEEL, MouseButton, MouseStroke,
+    Processor, Remainder, Stroke,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Pointer speed as a function of how long movement keys have been held
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Acceleration {
+    /// Speed when movement starts, in counts per second
+    pub start_speed: f64,
+    /// Speed reached after `ramp`, in counts per second
+    pub max_speed: f64,
+    /// Time taken to reach `max_speed`
+    pub ramp: Duration,
+    /// Shape of the ramp: 1 is linear, larger values start slower and finish faster
+    pub exponent: f64,
+}
+
+impl Default for Acceleration {
+    fn default() -> Self {
+        Self::linear(100.0, 1200.0, Duration::from_millis(1500))
+    }
+}
+
+impl Acceleration {
+    pub fn linear(start_speed: f64, max_speed: f64, ramp: Duration) -> Self {
+        Self {
+            start_speed,
+            max_speed,
+            ramp,
+            exponent: 1.0,
+        }
+    }
+
+    pub fn with_exponent(mut self, exponent: f64) -> Self {
+        self.exponent = exponent;
+        self
+    }
+
+    /// Speed in counts per second after movement has been held for `held`
+    pub fn speed(&self, held: Duration) -> f64 {
+        let progress = if self.ramp.is_zero() {
+            1.0
+        } else {
+            (held.as_secs_f64() / self.ramp.as_secs_f64()).min(1.0)
+        };
+        self.start_speed + (self.max_speed - self.start_speed) * progress.powf(self.exponent)
+    }
+}
+
+/// A wheel step produced while a key is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WheelStep {
+    pub horizontal: bool,
+    /// Wheel delta per step; 120 is one notch, positive is up or right
+    pub delta: i16,
+}
+
+/// What each key does while mouse keys are active
+#[derive(Debug, Clone)]
+pub struct MouseKeysSettings {
+    /// Unit direction per key; held directions are summed
+    pub directions: HashMap<Key, (i8, i8)>,
+    pub buttons: HashMap<Key, MouseButton>,
+    pub wheel: HashMap<Key, WheelStep>,
+    pub acceleration: Acceleration,
+    /// How often movement is emitted
+    pub interval: Duration,
+    /// How often wheel steps repeat while held
+    pub wheel_interval: Duration,
+}
+
+impl MouseKeysSettings {
+    fn with_layout(
+        directions: impl IntoIterator<Item = (Key, (i8, i8))>,
+        buttons: impl IntoIterator<Item = (Key, MouseButton)>,
+        wheel: impl IntoIterator<Item = (Key, WheelStep)>,
+    ) -> Self {
+        Self {
+            directions: directions.into_iter().collect(),
+            buttons: buttons.into_iter().collect(),
+            wheel: wheel.into_iter().collect(),
+            acceleration: Acceleration::default(),
+            interval: Duration::from_millis(10),
+            wheel_interval: Duration::from_millis(80),
+        }
+    }
+
+    /// Numpad digits move (diagonals on the corners), 5 clicks, 0 right clicks,
+    /// `/` middle clicks and `-`/`+` scroll
+    pub fn numpad() -> Self {
+        Self::with_layout(
+            [
+                (Key::NUMPAD_8, (0, -1)),
+                (Key::NUMPAD_2, (0, 1)),
+                (Key::NUMPAD_4, (-1, 0)),
+                (Key::NUMPAD_6, (1, 0)),
+                (Key::NUMPAD_7, (-1, -1)),
+                (Key::NUMPAD_9, (1, -1)),
+                (Key::NUMPAD_1, (-1, 1)),
+                (Key::NUMPAD_3, (1, 1)),
+            ],
+            [
+                (Key::NUMPAD_5, MouseButton::Left),
+                (Key::NUMPAD_0, MouseButton::Right),
+                (Key::NUMPAD_DIVIDE, MouseButton::Middle),
+            ],
+            [
+                (
+                    Key::NUMPAD_SUBTRACT,
+                    WheelStep {
+                        horizontal: false,
+                        delta: 120,
+                    },
+                ),
+                (
+                    Key::NUMPAD_ADD,
+                    WheelStep {
+                        horizontal: false,
+                        delta: -120,
+                    },
+                ),
+            ],
+        )
+    }
+
+    /// `H`/`J`/`K`/`L` move, `I` clicks, `O` right clicks and `U`/`D` scroll
+    pub fn hjkl() -> Self {
+        Self::with_layout(
+            [
+                (Key::H, (-1, 0)),
+                (Key::J, (0, 1)),
+                (Key::K, (0, -1)),
+                (Key::L, (1, 0)),
+            ],
+            [(Key::I, MouseButton::Left), (Key::O, MouseButton::Right)],
+            [
+                (
+                    Key::U,
+                    WheelStep {
+                        horizontal: false,
+                        delta: 120,
+                    },
+                ),
+                (
+                    Key::D,
+                    WheelStep {
+                        horizontal: false,
+                        delta: -120,
+                    },
+                ),
+            ],
+        )
+    }
+
+    fn handles(&self, key: Key) -> bool {
+        self.directions.contains_key(&key)
+            || self.buttons.contains_key(&key)
+            || self.wheel.contains_key(&key)
+    }
+}
+
+/// Held-key movement state, independent of any device
+///
+/// This is the movement model used by [`MouseKeys`], exposed so it can be driven directly.
+#[derive(Debug, Clone, Default)]
+pub struct Motion {
+    since: Option<Instant>,
+    last: Option<Instant>,
+    x: Remainder,
+    y: Remainder,
+}
+
+impl Motion {
+    /// Start moving, unless already moving
+    pub fn start(&mut self, now: Instant) {
+        if self.since.is_none() {
+            self.since = Some(now);
+            self.last = Some(now);
+        }
+    }
+
+    /// Stop moving and forget any remainder
+    pub fn stop(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.since.is_some()
+    }
+
+    /// When movement was last advanced
+    pub fn last(&self) -> Option<Instant> {
+        self.last
+    }
+
+    /// Advance to `now` in the given direction, returning the whole counts moved
+    ///
+    /// The direction is normalized so diagonals are not faster than straight movement.
+    pub fn advance(
+        &mut self,
+        now: Instant,
+        direction: (f64, f64),
+        acceleration: &Acceleration,
+    ) -> (i32, i32) {
+        let (Some(since), Some(last)) = (self.since, self.last) else {
+            return (0, 0);
+        };
+        self.last = Some(now);
+
+        let length = direction.0.hypot(direction.1);
+        if length == 0.0 {
+            return (0, 0);
+        }
+        let distance = acceleration.speed(now.saturating_duration_since(since))
+            * now.saturating_duration_since(last).as_secs_f64();
+        let scale = distance / length.max(1.0);
+        (
+            self.x.take(direction.0 * scale),
+            self.y.take(direction.1 * scale),
+        )
+    }
+}
+
+/// Moves the pointer, clicks and scrolls from the keyboard
+#[derive(Debug, Clone)]
+pub struct MouseKeys {
+    settings: MouseKeysSettings,
+    toggle: Option<Key>,
+    active: bool,
+    /// Keys consumed by this processor that are still held
+    held: Vec<Key>,
+    /// Buttons pressed by held keys, with the mouse they were pressed on
+    buttons: HashMap<Key, (usize, MouseButton)>,
+    motion: Motion,
+    next_wheel: Option<Instant>,
+    devices: ActiveDevices,
+}
+
+impl MouseKeys {
+    /// Always active
+    pub fn new(settings: MouseKeysSettings) -> Self {
+        Self {
+            settings,
+            toggle: None,
+            active: true,
+            held: Vec::new(),
+            buttons: HashMap::new(),
+            motion: Motion::default(),
+            next_wheel: None,
+            devices: ActiveDevices::default(),
+        }
+    }
+
+    /// Start inactive and switch on and off with `key`
+    pub fn with_toggle(mut self, key: Key) -> Self {
+        self.toggle = Some(key);
+        self.active = false;
+        self
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Switch on or off; switching off releases everything held
+    pub fn set_active(&mut self, active: bool, out: &mut Vec<Event>) {
+        self.active = active;
+        if !active {
+            for (device, button) in self.buttons.drain().map(|(_, pressed)| pressed) {
+                out.push(Event::new(device, button.up()));
+            }
+            self.motion.stop();
+            self.next_wheel = None;
+        }
+    }
+
+    fn direction(&self) -> (f64, f64) {
+        self.held
+            .iter()
+            .filter_map(|key| self.settings.directions.get(key))
+            .fold((0.0, 0.0), |(x, y), &(dx, dy)| {
+                (x + f64::from(dx), y + f64::from(dy))
+            })
+    }
+
+    fn wheel_strokes(&self, out: &mut Vec<Event>) {
+        for key in &self.held {
+            if let Some(step) = self.settings.wheel.get(key) {
+                let flag = if step.horizontal {
+                    MOUSE_HWHEEL
+                } else {
+                    MOUSE_WHEEL
+                };
+                let stroke = MouseStroke::new(MOUSE_MOVE_RELATIVE, flag, step.delta, 0, 0, 0);
+                out.push(Event::new(self.devices.mouse, stroke));
+            }
+        }
+    }
+
+    fn press(&mut self, key: Key, now: Instant, out: &mut Vec<Event>) {
+        if self.held.contains(&key) {
+            // Autorepeat; movement and wheel steps have their own timing
+            return;
+        }
+        self.held.push(key);
+
+        if self.settings.directions.contains_key(&key) {
+            self.motion.start(now);
+        }
+        if let Some(&button) = self.settings.buttons.get(&key) {
+            let device = self.devices.mouse;
+            self.buttons.insert(key, (device, button));
+            out.push(Event::new(device, button.down()));
+        }
+        if let Some(step) = self.settings.wheel.get(&key) {
+            let step = *step;
+            let flag = if step.horizontal {
+                MOUSE_HWHEEL
+            } else {
+                MOUSE_WHEEL
+            };
+            let stroke = MouseStroke::new(MOUSE_MOVE_RELATIVE, flag, step.delta, 0, 0, 0);
+            out.push(Event::new(self.devices.mouse, stroke));
+            self.next_wheel
+                .get_or_insert(now + self.settings.wheel_interval);
+        }
+    }
+
+    fn release(&mut self, key: Key, out: &mut Vec<Event>) {
+        self.held.retain(|&held| held != key);
+        if let Some((device, button)) = self.buttons.remove(&key) {
+            out.push(Event::new(device, button.up()));
+        }
+        if !self
+            .held
+            .iter()
+            .any(|key| self.settings.directions.contains_key(key))
+        {
+            self.motion.stop();
+        }
+        if !self
+            .held
+            .iter()
+            .any(|key| self.settings.wheel.contains_key(key))
+        {
+            self.next_wheel = None;
+        }
+    }
+}
+
+impl Processor for MouseKeys {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        self.devices.observe(event.device);
+        let Stroke::Key(stroke) = event.stroke else {
+            out.push(event);
+            return;
+        };
+        let key = stroke.key();
+
+        if Some(key) == self.toggle {
+            if stroke.is_down() && !self.held.contains(&key) {
+                self.held.push(key);
+                let active = !self.active;
+                self.set_active(active, out);
+            } else if stroke.is_up() {
+                self.held.retain(|&held| held != key);
+            }
+            return;
+        }
+
+        // Keys pressed while active stay consumed until released, even if switched off since
+        let consumed = self.held.contains(&key) || (self.active && self.settings.handles(key));
+        if !consumed {
+            out.push(event);
+        } else if stroke.is_down() {
+            self.press(key, now, out);
+        } else {
+            self.release(key, out);
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        let motion = self.motion.last().map(|last| last + self.settings.interval);
+        [motion, self.next_wheel].into_iter().flatten().min()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        if self.motion.is_moving() {
+            let direction = self.direction();
+            let (x, y) = self
+                .motion
+                .advance(now, direction, &self.settings.acceleration);
+            if x != 0 || y != 0 {
+                let stroke = MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, x, y, 0);
+                out.push(Event::new(self.devices.mouse, stroke));
+            }
+        }
+        if let Some(next) = self.next_wheel
+            && next <= now
+        {
+            self.wheel_strokes(out);
+            self.next_wheel = Some(next + self.settings.wheel_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_KEYBOARD;
+
+    const MOUSE: usize = MAX_KEYBOARD + 1;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Tick at every deadline up to `until`, collecting relative movement
+    fn run_until(mouse_keys: &mut MouseKeys, until: Instant) -> Vec<Event> {
+        let mut out = Vec::new();
+        while let Some(deadline) = mouse_keys.deadline().filter(|d| *d <= until) {
+            mouse_keys.tick(deadline, &mut out);
+        }
+        out
+    }
+
+    fn total_motion(events: &[Event]) -> (i32, i32) {
+        events
+            .iter()
+            .filter_map(Event::mouse)
+            .fold((0, 0), |(x, y), stroke| (x + stroke.x, y + stroke.y))
+    }
+
+    #[test]
+    fn test_acceleration_curve() {
+        let linear = Acceleration::linear(100.0, 1100.0, ms(1000));
+        assert_eq!(linear.speed(ms(0)), 100.0);
+        assert_eq!(linear.speed(ms(500)), 600.0);
+        assert_eq!(linear.speed(ms(5000)), 1100.0);
+
+        let quadratic = linear.with_exponent(2.0);
+        assert_eq!(quadratic.speed(ms(500)), 350.0);
+    }
+
+    #[test]
+    fn test_motion_accumulates_sub_pixel_steps() {
+        let t0 = Instant::now();
+        let constant = Acceleration::linear(50.0, 50.0, ms(0));
+        let mut motion = Motion::default();
+        motion.start(t0);
+
+        // 50 counts/s at 10ms ticks is half a count per tick
+        let moved: Vec<_> = (1..=4)
+            .map(|i| motion.advance(t0 + ms(10 * i), (1.0, 0.0), &constant))
+            .collect();
+        assert_eq!(moved, vec![(0, 0), (1, 0), (0, 0), (1, 0)]);
+
+        // Diagonals are normalized
+        let mut motion = Motion::default();
+        motion.start(t0);
+        let (x, y) = motion.advance(t0 + ms(1000), (1.0, 1.0), &constant);
+        assert_eq!((x, y), (35, 35));
+    }
+
+    #[test]
+    fn test_held_key_moves_pointer() {
+        let t0 = Instant::now();
+        let mut settings = MouseKeysSettings::numpad();
+        settings.acceleration = Acceleration::linear(100.0, 100.0, ms(0));
+        let mut mouse_keys = MouseKeys::new(settings);
+
+        let mut out = Vec::new();
+        mouse_keys.process(Event::new(MOUSE, MouseStroke::default()), t0, &mut out);
+        mouse_keys.process(Event::new(0, Key::NUMPAD_6.down()), t0, &mut out);
+        mouse_keys.process(Event::new(0, Key::NUMPAD_6.down()), t0 + ms(300), &mut out);
+        assert_eq!(out.len(), 1);
+
+        let moved = run_until(&mut mouse_keys, t0 + ms(500));
+        assert_eq!(total_motion(&moved), (50, 0));
+        assert!(moved.iter().all(|event| event.device == MOUSE));
+
+        mouse_keys.process(Event::new(0, Key::NUMPAD_6.up()), t0 + ms(500), &mut out);
+        assert_eq!(mouse_keys.deadline(), None);
+    }
+
+    #[test]
+    fn test_click_and_wheel_keys() {
+        let t0 = Instant::now();
+        let mut mouse_keys = MouseKeys::new(MouseKeysSettings::numpad());
+        let mut out = Vec::new();
+        mouse_keys.process(Event::new(0, Key::NUMPAD_5.down()), t0, &mut out);
+        mouse_keys.process(Event::new(0, Key::NUMPAD_5.up()), t0, &mut out);
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MAX_KEYBOARD, MouseButton::Left.down()),
+                Event::new(MAX_KEYBOARD, MouseButton::Left.up()),
+            ]
+        );
+
+        out.clear();
+        mouse_keys.process(Event::new(0, Key::NUMPAD_SUBTRACT.down()), t0, &mut out);
+        out.extend(run_until(&mut mouse_keys, t0 + ms(200)));
+        assert_eq!(out.len(), 3);
+        assert!(out.iter().all(|event| {
+            event
+                .mouse()
+                .is_some_and(|stroke| stroke.state == MOUSE_WHEEL && stroke.rolling == 120)
+        }));
+    }
+
+    #[test]
+    fn test_toggle_passes_keys_through_when_off() {
+        let t0 = Instant::now();
+        let mut mouse_keys =
+            MouseKeys::new(MouseKeysSettings::hjkl()).with_toggle(Key::SCROLL_LOCK);
+        let mut out = Vec::new();
+
+        mouse_keys.process(Event::new(0, Key::I.down()), t0, &mut out);
+        assert_eq!(out, vec![Event::new(0, Key::I.down())]);
+        mouse_keys.process(Event::new(0, Key::I.up()), t0, &mut out);
+
+        out.clear();
+        for stroke in Key::SCROLL_LOCK.tap() {
+            mouse_keys.process(Event::new(0, stroke), t0, &mut out);
+        }
+        mouse_keys.process(Event::new(0, Key::I.down()), t0, &mut out);
+        // Switching off releases the button, and the key's release stays consumed
+        for stroke in Key::SCROLL_LOCK.tap() {
+            mouse_keys.process(Event::new(0, stroke), t0, &mut out);
+        }
+        mouse_keys.process(Event::new(0, Key::I.up()), t0, &mut out);
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MAX_KEYBOARD, MouseButton::Left.down()),
+                Event::new(MAX_KEYBOARD, MouseButton::Left.up()),
+            ]
+        );
+    }
+}
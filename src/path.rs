@@ -0,0 +1,408 @@
+//! Generated pointer paths.
+//!
+//! A [`Path`] is a sequence of timed points, produced from a parametric curve, a Bézier curve
+//! or a [`HumanMotion`] between two points, and turned into relative or absolute
+//! [`MouseStroke`]s with [`Path::relative`] and [`Path::absolute`]. [`play`] replays the
+//! strokes at their times, sleeping until each one is due; times are measured from the start
+//! so pacing doesn't drift even when a send is late.
+//!
+//! Points are in screen coordinates, Y pointing down. The curves in this module, the ones the
+//! C `mathpointer` sample draws, are centered on the origin, so move them into place with
+//! [`Path::translate`].
+
+use crate::{MOUSE_MOVE_RELATIVE, MouseFlag, MouseStroke};
+use std::f64::consts::PI;
+use std::ops::Range;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A point on a path
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    pub const fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    fn lerp(self, other: Point, t: f64) -> Point {
+        Point::new(
+            self.x + (other.x - self.x) * t,
+            self.y + (other.y - self.y) * t,
+        )
+    }
+}
+
+/// A stroke and when to send it, relative to the start of playback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedStroke {
+    pub at: Duration,
+    pub stroke: MouseStroke,
+}
+
+/// Points to move the pointer through, each with the time to reach it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Path {
+    points: Vec<(Duration, Point)>,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a point; times should not go backwards
+    pub fn push(&mut self, at: Duration, point: Point) {
+        self.points.push((at, point));
+    }
+
+    pub fn points(&self) -> &[(Duration, Point)] {
+        &self.points
+    }
+
+    /// The time of the last point
+    pub fn duration(&self) -> Duration {
+        self.points.last().map_or(Duration::ZERO, |(at, _)| *at)
+    }
+
+    /// Sample `curve` at `steps + 1` evenly spaced parameters across `range`, spread evenly
+    /// over `duration`
+    pub fn parametric(
+        curve: impl Fn(f64) -> Point,
+        range: Range<f64>,
+        steps: u32,
+        duration: Duration,
+    ) -> Self {
+        let steps = steps.max(1);
+        let mut path = Self::new();
+        for i in 0..=steps {
+            let fraction = f64::from(i) / f64::from(steps);
+            let t = range.start + (range.end - range.start) * fraction;
+            path.push(duration.mul_f64(fraction), curve(t));
+        }
+        path
+    }
+
+    /// A Bézier curve of any degree through its control points
+    pub fn bezier(controls: &[Point], steps: u32, duration: Duration) -> Self {
+        if controls.is_empty() {
+            return Self::new();
+        }
+        Self::parametric(|t| de_casteljau(controls, t), 0.0..1.0, steps, duration)
+    }
+
+    /// Move every point by an offset
+    pub fn translate(mut self, x: f64, y: f64) -> Self {
+        for (_, point) in &mut self.points {
+            point.x += x;
+            point.y += y;
+        }
+        self
+    }
+
+    /// Relative strokes moving from the first point along the path
+    ///
+    /// The pointer is assumed to start at the first point. Each stroke moves to the nearest
+    /// whole count of its point's offset from the start, so rounding never accumulates and the
+    /// strokes add up to the rounded displacement of the whole path.
+    pub fn relative(&self) -> Vec<TimedStroke> {
+        let Some(&(_, origin)) = self.points.first() else {
+            return Vec::new();
+        };
+        let mut last = (0, 0);
+        let mut strokes = Vec::new();
+        for &(at, point) in &self.points[1..] {
+            let offset = (
+                (point.x - origin.x).round() as i32,
+                (point.y - origin.y).round() as i32,
+            );
+            let (x, y) = (offset.0 - last.0, offset.1 - last.1);
+            last = offset;
+            if x != 0 || y != 0 {
+                let stroke = MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, x, y, 0);
+                strokes.push(TimedStroke { at, stroke });
+            }
+        }
+        strokes
+    }
+
+    /// Absolute strokes placing the pointer on every point
+    ///
+    /// `to_absolute` maps a point to normalized coordinates and `flags` should include
+    /// [`MOUSE_MOVE_ABSOLUTE`](crate::MOUSE_MOVE_ABSOLUTE), and
+    /// [`MOUSE_VIRTUAL_DESKTOP`](crate::MOUSE_VIRTUAL_DESKTOP) if the coordinates span all
+    /// monitors.
+    pub fn absolute(
+        &self,
+        flags: MouseFlag,
+        to_absolute: impl Fn(Point) -> (i32, i32),
+    ) -> Vec<TimedStroke> {
+        self.points
+            .iter()
+            .map(|&(at, point)| {
+                let (x, y) = to_absolute(point);
+                let stroke = MouseStroke::new(flags, 0, 0, x, y, 0);
+                TimedStroke { at, stroke }
+            })
+            .collect()
+    }
+}
+
+fn de_casteljau(controls: &[Point], t: f64) -> Point {
+    let mut points = controls.to_vec();
+    for len in (1..points.len()).rev() {
+        for i in 0..len {
+            points[i] = points[i].lerp(points[i + 1], t);
+        }
+    }
+    points[0]
+}
+
+/// A circle of the given radius
+pub fn circle(radius: f64) -> impl Fn(f64) -> Point {
+    move |t| Point::new(radius * t.cos(), radius * t.sin())
+}
+
+/// A circle of radius `small` rolling around the outside of one of radius `big`, traced by a
+/// point `distance` from its center; a full figure takes `t` over `0..2π·small/gcd`
+pub fn epitrochoid(big: f64, small: f64, distance: f64) -> impl Fn(f64) -> Point {
+    let sum = big + small;
+    move |t| {
+        Point::new(
+            sum * t.cos() - distance * (sum * t / small).cos(),
+            sum * t.sin() - distance * (sum * t / small).sin(),
+        )
+    }
+}
+
+/// A circle of radius `small` rolling around the inside of one of radius `big`, traced by a
+/// point `distance` from its center
+pub fn hypotrochoid(big: f64, small: f64, distance: f64) -> impl Fn(f64) -> Point {
+    let difference = big - small;
+    move |t| {
+        Point::new(
+            difference * t.cos() + distance * (difference * t / small).cos(),
+            difference * t.sin() - distance * (difference * t / small).sin(),
+        )
+    }
+}
+
+/// A Lissajous figure with frequencies `a` and `b`
+pub fn lissajous(a: f64, b: f64, radius: f64) -> impl Fn(f64) -> Point {
+    move |t| Point::new(radius * (a * t).sin(), radius * (b * t).sin())
+}
+
+/// A rose with petal ratio `k`
+pub fn rose(k: f64, radius: f64) -> impl Fn(f64) -> Point {
+    move |t| {
+        Point::new(
+            radius * (k * t).cos() * t.cos(),
+            radius * (k * t).cos() * t.sin(),
+        )
+    }
+}
+
+/// Point-to-point movement that looks like a hand moved it
+///
+/// Progress along the way follows a minimum-jerk profile, accelerating smoothly and easing
+/// into the target. The path bows slightly to one side and carries a little jitter; both fade
+/// in and out with the progress, so the path starts and ends exactly on its endpoints.
+/// Randomness comes from `seed`, so the same settings always give the same path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HumanMotion {
+    /// Time between points
+    pub interval: Duration,
+    /// Greatest sideways bow, as a fraction of the distance
+    pub curvature: f64,
+    /// Greatest random displacement of a point, in pixels
+    pub jitter: f64,
+    pub seed: u64,
+}
+
+impl Default for HumanMotion {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(8),
+            curvature: 0.1,
+            jitter: 0.5,
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+impl HumanMotion {
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// A path from `from` to `to` taking `duration`
+    pub fn path(&self, from: Point, to: Point, duration: Duration) -> Path {
+        let steps = (duration.as_secs_f64() / self.interval.as_secs_f64().max(1e-6)).ceil();
+        let steps = (steps as u32).max(1);
+        let (dx, dy) = (to.x - from.x, to.y - from.y);
+        let mut rng = XorShift(self.seed | 1);
+        let bow = self.curvature * if rng.next_f64() < 0.5 { -1.0 } else { 1.0 };
+
+        let mut path = Path::new();
+        for i in 0..=steps {
+            let tau = f64::from(i) / f64::from(steps);
+            let progress = tau * tau * tau * (10.0 - 15.0 * tau + 6.0 * tau * tau);
+            let fade = (PI * progress).sin();
+            let side = bow * fade;
+            let jitter_x = self.jitter * fade * (rng.next_f64() * 2.0 - 1.0);
+            let jitter_y = self.jitter * fade * (rng.next_f64() * 2.0 - 1.0);
+            path.push(
+                duration.mul_f64(tau),
+                Point::new(
+                    from.x + dx * progress - dy * side + jitter_x,
+                    from.y + dy * progress + dx * side + jitter_y,
+                ),
+            );
+        }
+        path
+    }
+}
+
+/// Small deterministic generator for jitter
+struct XorShift(u64);
+
+impl XorShift {
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Send timed strokes, sleeping until each is due
+///
+/// Stops at the first error from `send`.
+pub fn play<E>(
+    strokes: &[TimedStroke],
+    mut send: impl FnMut(MouseStroke) -> Result<(), E>,
+) -> Result<(), E> {
+    let start = Instant::now();
+    for timed in strokes {
+        let due = start + timed.at;
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        }
+        send(timed.stroke)?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+impl crate::MouseDevice {
+    /// Send timed strokes to this device, sleeping until each is due
+    pub fn play(&mut self, strokes: &[TimedStroke]) -> crate::Result<()> {
+        play(strokes, |stroke| self.send(&[stroke]).map(drop))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MOUSE_MOVE_ABSOLUTE;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn close(a: Point, b: Point) -> bool {
+        (a.x - b.x).abs() < 1e-9 && (a.y - b.y).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_parametric_circle_closes() {
+        let path = Path::parametric(circle(100.0), 0.0..2.0 * PI, 64, ms(640)).translate(10.0, 0.0);
+        let points = path.points();
+        assert_eq!(points.len(), 65);
+        assert_eq!(points[1].0, ms(10));
+        assert_eq!(path.duration(), ms(640));
+        assert!(close(points[0].1, Point::new(110.0, 0.0)));
+        assert!(close(points[16].1, Point::new(10.0, 100.0)));
+
+        let strokes = path.relative();
+        let x: i32 = strokes.iter().map(|s| s.stroke.x).sum();
+        let y: i32 = strokes.iter().map(|s| s.stroke.y).sum();
+        assert_eq!((x, y), (0, 0));
+    }
+
+    #[test]
+    fn test_bezier() {
+        let controls = [
+            Point::new(0.0, 0.0),
+            Point::new(0.0, 100.0),
+            Point::new(100.0, 100.0),
+        ];
+        let path = Path::bezier(&controls, 4, ms(100));
+        let points = path.points();
+        assert!(close(points[0].1, controls[0]));
+        assert!(close(points[2].1, Point::new(25.0, 75.0)));
+        assert!(close(points[4].1, controls[2]));
+    }
+
+    #[test]
+    fn test_human_motion_reaches_target() {
+        let motion = HumanMotion::default();
+        let (from, to) = (Point::new(10.0, 10.0), Point::new(410.0, 310.0));
+        let path = motion.path(from, to, ms(400));
+        assert_eq!(path, motion.path(from, to, ms(400)));
+        assert_ne!(path, motion.with_seed(7).path(from, to, ms(400)));
+
+        let points = path.points();
+        assert_eq!(points.len(), 51);
+        assert!(close(points[0].1, from));
+        assert!(close(points[50].1, to));
+
+        // Minimum jerk: slow at both ends, fastest in the middle
+        let speed = |i: usize| {
+            let (a, b) = (points[i].1, points[i + 1].1);
+            (b.x - a.x).hypot(b.y - a.y)
+        };
+        assert!(speed(0) < speed(24) / 10.0);
+        assert!(speed(49) < speed(24) / 10.0);
+
+        let strokes = path.relative();
+        let x: i32 = strokes.iter().map(|s| s.stroke.x).sum();
+        let y: i32 = strokes.iter().map(|s| s.stroke.y).sum();
+        assert_eq!((x, y), (400, 300));
+    }
+
+    #[test]
+    fn test_absolute_strokes() {
+        let path = Path::parametric(|t| Point::new(t, 2.0 * t), 0.0..1.0, 2, ms(20));
+        let strokes = path.absolute(MOUSE_MOVE_ABSOLUTE, |p| {
+            ((p.x * 1000.0) as i32, (p.y * 1000.0) as i32)
+        });
+        assert_eq!(
+            strokes[1],
+            TimedStroke {
+                at: ms(10),
+                stroke: MouseStroke::new(MOUSE_MOVE_ABSOLUTE, 0, 0, 500, 1000, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn test_play_paces_strokes() {
+        let path = Path::parametric(|t| Point::new(t, 0.0), 0.0..4.0, 4, ms(20));
+        let start = Instant::now();
+        let mut sent = Vec::new();
+        play::<()>(&path.relative(), |stroke| {
+            sent.push((start.elapsed(), stroke.x));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(sent.len(), 4);
+        assert!(sent.iter().all(|(_, x)| *x == 1));
+        assert!(sent[3].0 >= ms(20));
+    }
+}
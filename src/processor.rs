@@ -0,0 +1,374 @@
+//! Stroke-stream processing.
+//!
+//! A [`Processor`] sits between [`Interception::run`](crate::Interception::run) and the
+//! devices: every received stroke is handed to it, and whatever it pushes to the output
+//! buffer is sent back in place of the original. A processor can therefore pass strokes
+//! through, drop them, rewrite them, withhold them for later, or inject new ones.
+//!
+//! Time is always passed in explicitly, so processors with timers can be driven
+//! deterministically in tests by offsetting a fixed [`Instant`].
+
+use crate::{Key, KeyStroke, MAX_DEVICES, MAX_KEYBOARD, MouseButton, MouseStroke};
+use std::time::Instant;
+
+/// A stroke from either kind of device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stroke {
+    Key(KeyStroke),
+    Mouse(MouseStroke),
+}
+
+impl From<KeyStroke> for Stroke {
+    fn from(stroke: KeyStroke) -> Self {
+        Stroke::Key(stroke)
+    }
+}
+
+impl From<MouseStroke> for Stroke {
+    fn from(stroke: MouseStroke) -> Self {
+        Stroke::Mouse(stroke)
+    }
+}
+
+/// Anything that can be pressed and released: a key or a mouse button
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Switch {
+    Key(Key),
+    Button(MouseButton),
+}
+
+impl From<Key> for Switch {
+    fn from(key: Key) -> Self {
+        Switch::Key(key)
+    }
+}
+
+impl From<MouseButton> for Switch {
+    fn from(button: MouseButton) -> Self {
+        Switch::Button(button)
+    }
+}
+
+impl Switch {
+    /// A stroke pressing this switch
+    pub fn press(self) -> Stroke {
+        match self {
+            Switch::Key(key) => Stroke::Key(key.down()),
+            Switch::Button(button) => Stroke::Mouse(button.down()),
+        }
+    }
+
+    /// A stroke releasing this switch
+    pub fn release(self) -> Stroke {
+        match self {
+            Switch::Key(key) => Stroke::Key(key.up()),
+            Switch::Button(button) => Stroke::Mouse(button.up()),
+        }
+    }
+
+    /// Whether this switch lives on a keyboard
+    pub fn is_key(self) -> bool {
+        matches!(self, Switch::Key(_))
+    }
+}
+
+/// A stroke together with the index of the device it came from or is going to
+///
+/// Device indices follow [`Interception::wait_index`](crate::Interception::wait_index):
+/// `0..MAX_KEYBOARD` are keyboards and `MAX_KEYBOARD..MAX_DEVICES` are mice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub device: usize,
+    pub stroke: Stroke,
+}
+
+impl Event {
+    pub fn new(device: usize, stroke: impl Into<Stroke>) -> Self {
+        Self {
+            device,
+            stroke: stroke.into(),
+        }
+    }
+
+    /// The key stroke carried by this event, if any
+    pub fn key(&self) -> Option<&KeyStroke> {
+        match &self.stroke {
+            Stroke::Key(stroke) => Some(stroke),
+            Stroke::Mouse(_) => None,
+        }
+    }
+
+    /// The mouse stroke carried by this event, if any
+    pub fn mouse(&self) -> Option<&MouseStroke> {
+        match &self.stroke {
+            Stroke::Key(_) => None,
+            Stroke::Mouse(stroke) => Some(stroke),
+        }
+    }
+}
+
+/// Whether a device index refers to a keyboard
+pub const fn is_keyboard(device: usize) -> bool {
+    device < MAX_KEYBOARD
+}
+
+/// Whether a device index refers to a mouse
+pub const fn is_mouse(device: usize) -> bool {
+    device >= MAX_KEYBOARD && device < MAX_DEVICES
+}
+
+/// A stateful transformation over the stroke stream
+pub trait Processor {
+    /// Handle one incoming event, pushing whatever should be sent in its place to `out`
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>);
+
+    /// The next instant at which [`tick`](Processor::tick) should be called, if any
+    fn deadline(&self) -> Option<Instant> {
+        None
+    }
+
+    /// Called once [`deadline`](Processor::deadline) has passed
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        let _ = (now, out);
+    }
+
+    /// Called before processing starts for every device whose hardware ID could be read
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        let _ = (device, hardware_id);
+    }
+}
+
+impl<P: Processor + ?Sized> Processor for Box<P> {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        (**self).process(event, now, out)
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        (**self).deadline()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        (**self).tick(now, out)
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        (**self).attach(device, hardware_id)
+    }
+}
+
+/// Passes every event through unchanged
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Passthrough;
+
+impl Processor for Passthrough {
+    fn process(&mut self, event: Event, _now: Instant, out: &mut Vec<Event>) {
+        out.push(event);
+    }
+}
+
+/// Runs processors in sequence, feeding the output of each into the next
+#[derive(Default)]
+pub struct Chain {
+    stages: Vec<Box<dyn Processor>>,
+}
+
+impl Chain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a processor to the end of the chain
+    pub fn push(&mut self, processor: impl Processor + 'static) {
+        self.stages.push(Box::new(processor));
+    }
+
+    /// Append a processor to the end of the chain, builder style
+    pub fn with(mut self, processor: impl Processor + 'static) -> Self {
+        self.push(processor);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Feed events through the stages starting at `first`
+    fn run_from(&mut self, first: usize, mut events: Vec<Event>, now: Instant) -> Vec<Event> {
+        for stage in &mut self.stages[first..] {
+            let mut next = Vec::with_capacity(events.len());
+            for event in events {
+                stage.process(event, now, &mut next);
+            }
+            events = next;
+        }
+        events
+    }
+}
+
+impl Processor for Chain {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        out.extend(self.run_from(0, vec![event], now));
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.stages
+            .iter()
+            .filter_map(|stage| stage.deadline())
+            .min()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        for i in 0..self.stages.len() {
+            if self.stages[i]
+                .deadline()
+                .is_some_and(|deadline| deadline <= now)
+            {
+                let mut emitted = Vec::new();
+                self.stages[i].tick(now, &mut emitted);
+                out.extend(self.run_from(i + 1, emitted, now));
+            }
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        for stage in &mut self.stages {
+            stage.attach(device, hardware_id);
+        }
+    }
+}
+
+/// Settings with overrides for particular devices
+///
+/// Overrides are selected by hardware ID: a device uses the first override whose pattern
+/// occurs anywhere in its hardware ID, compared case-insensitively, so a pattern such as
+/// `VID_046D&PID_C52B` matches every interface of that product. Devices are bound to their
+/// settings through [`attach`](PerDevice::attach), normally forwarded from
+/// [`Processor::attach`].
+#[derive(Debug, Clone)]
+pub struct PerDevice<T> {
+    default: T,
+    overrides: Vec<(String, T)>,
+    bound: [Option<usize>; MAX_DEVICES],
+}
+
+impl<T: Default> Default for PerDevice<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> PerDevice<T> {
+    pub fn new(default: T) -> Self {
+        Self {
+            default,
+            overrides: Vec::new(),
+            bound: [None; MAX_DEVICES],
+        }
+    }
+
+    /// Use `settings` for devices whose hardware ID contains `pattern`
+    pub fn with(mut self, pattern: &str, settings: T) -> Self {
+        self.overrides.push((pattern.to_uppercase(), settings));
+        self
+    }
+
+    /// Bind a device to the override matching its hardware ID, if any
+    pub fn attach(&mut self, device: usize, hardware_id: &str) {
+        let Some(slot) = self.bound.get_mut(device) else {
+            return;
+        };
+        let hardware_id = hardware_id.to_uppercase();
+        *slot = self
+            .overrides
+            .iter()
+            .position(|(pattern, _)| hardware_id.contains(pattern.as_str()));
+    }
+
+    /// The settings that apply to a device
+    pub fn get(&self, device: usize) -> &T {
+        match self.bound.get(device).copied().flatten() {
+            Some(index) => &self.overrides[index].1,
+            None => &self.default,
+        }
+    }
+
+    pub fn default_settings(&self) -> &T {
+        &self.default
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Key;
+    use std::time::Duration;
+
+    /// Delays every event by a fixed amount
+    struct Delay {
+        delay: Duration,
+        pending: Vec<(Instant, Event)>,
+    }
+
+    impl Processor for Delay {
+        fn process(&mut self, event: Event, now: Instant, _out: &mut Vec<Event>) {
+            self.pending.push((now + self.delay, event));
+        }
+
+        fn deadline(&self) -> Option<Instant> {
+            self.pending.first().map(|(at, _)| *at)
+        }
+
+        fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+            while self.pending.first().is_some_and(|(at, _)| *at <= now) {
+                out.push(self.pending.remove(0).1);
+            }
+        }
+    }
+
+    /// Turns every key into `B`
+    struct ToB;
+
+    impl Processor for ToB {
+        fn process(&mut self, mut event: Event, _now: Instant, out: &mut Vec<Event>) {
+            if let Stroke::Key(stroke) = &mut event.stroke {
+                stroke.code = Key::B.code();
+            }
+            out.push(event);
+        }
+    }
+
+    #[test]
+    fn test_chain_ticks_feed_later_stages() {
+        let t0 = Instant::now();
+        let mut chain = Chain::new()
+            .with(Delay {
+                delay: Duration::from_millis(10),
+                pending: Vec::new(),
+            })
+            .with(ToB);
+
+        let mut out = Vec::new();
+        chain.process(Event::new(0, Key::A.down()), t0, &mut out);
+        assert!(out.is_empty());
+        assert_eq!(chain.deadline(), Some(t0 + Duration::from_millis(10)));
+
+        chain.tick(t0 + Duration::from_millis(10), &mut out);
+        assert_eq!(out, vec![Event::new(0, Key::B.down())]);
+        assert_eq!(chain.deadline(), None);
+    }
+
+    #[test]
+    fn test_per_device_matches_hardware_id_substring() {
+        let mut settings = PerDevice::new(1).with("vid_046d&pid_c52b", 2);
+        settings.attach(3, r"HID\VID_046D&PID_C52B&REV_1211&MI_00");
+        settings.attach(4, r"HID\VID_1234&PID_0001");
+
+        assert_eq!(*settings.get(3), 2);
+        assert_eq!(*settings.get(4), 1);
+        assert_eq!(*settings.get(5), 1);
+    }
+}
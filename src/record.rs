@@ -0,0 +1,625 @@
+//! Recording strokes and playing them back with their original timing.
+//!
+//! [`Recorder`] is a pass-through [`Processor`] that, between [`Recorder::start`] and
+//! [`Recorder::stop`], keeps every stroke from the selected devices with its time since the
+//! start and its device index. [`Player`] is a processor that injects a [`Recording`] back into
+//! the stream, faster or slower, any number of times, until it is done or the abort key is
+//! pressed. Both are usually driven by [`Interception::run`](crate::Interception::run), which
+//! sends each played event to the device it was recorded from.
+//!
+//! A recording rarely begins and ends with nothing held: the hotkey that started it is usually
+//! released during the recording, and the one that stopped it pressed. The recorder notes what
+//! was held at either end, and the player only releases what it pressed itself, releasing
+//! anything still held when a loop ends or playback is aborted, so keys are never left stuck.
+
+use crate::{Event, Key, MouseButton, PerDevice, Processor, Stroke, Switch};
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+/// The slowest and fastest playback speeds
+pub const SPEED_RANGE: (f64, f64) = (0.01, 100.0);
+
+/// The shortest a loop can take, so that a recording of simultaneous events can't loop
+/// endlessly without time passing
+const MIN_LOOP: Duration = Duration::from_millis(1);
+
+/// An event and when it happened, relative to the start of the recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recorded {
+    pub at: Duration,
+    pub event: Event,
+}
+
+/// A recorded session
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub events: Vec<Recorded>,
+    /// Keys and buttons that were down when recording started, by device
+    pub held_at_start: Vec<(usize, Switch)>,
+    /// Keys and buttons that were down when recording stopped, by device
+    pub held_at_stop: Vec<(usize, Switch)>,
+    /// Time from start to stop
+    pub duration: Duration,
+}
+
+impl Recording {
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// Every key or button transition in a stroke
+fn transitions(stroke: &Stroke) -> Vec<(Switch, bool)> {
+    match stroke {
+        Stroke::Key(stroke) => vec![(Switch::Key(stroke.key()), stroke.is_down())],
+        Stroke::Mouse(stroke) => MouseButton::ALL
+            .into_iter()
+            .flat_map(|button| {
+                [
+                    (stroke.is_down(button), (Switch::Button(button), true)),
+                    (stroke.is_up(button), (Switch::Button(button), false)),
+                ]
+            })
+            .filter_map(|(present, transition)| present.then_some(transition))
+            .collect(),
+    }
+}
+
+/// Records strokes from the selected devices while passing everything through
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    devices: PerDevice<bool>,
+    held: BTreeSet<(usize, Switch)>,
+    started: Option<Instant>,
+    recording: Recording,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder {
+    /// Record from every device
+    pub fn new() -> Self {
+        Self::per_device(PerDevice::new(true))
+    }
+
+    /// Record only from devices selected with `true`
+    pub fn per_device(devices: PerDevice<bool>) -> Self {
+        Self {
+            devices,
+            held: BTreeSet::new(),
+            started: None,
+            recording: Recording::default(),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Start a new recording, discarding one in progress
+    pub fn start(&mut self, now: Instant) {
+        self.started = Some(now);
+        self.recording = Recording {
+            held_at_start: self.held.iter().copied().collect(),
+            ..Recording::default()
+        };
+    }
+
+    /// Stop recording and return what was recorded; empty if it wasn't started
+    pub fn stop(&mut self, now: Instant) -> Recording {
+        let Some(started) = self.started.take() else {
+            return Recording::default();
+        };
+        let mut recording = std::mem::take(&mut self.recording);
+        recording.held_at_stop = self.held.iter().copied().collect();
+        recording.duration = now.saturating_duration_since(started);
+        recording
+    }
+}
+
+impl Processor for Recorder {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        out.push(event);
+        if !*self.devices.get(event.device) {
+            return;
+        }
+
+        for (switch, down) in transitions(&event.stroke) {
+            if down {
+                self.held.insert((event.device, switch));
+            } else {
+                self.held.remove(&(event.device, switch));
+            }
+        }
+        if let Some(started) = self.started {
+            self.recording.events.push(Recorded {
+                at: now.saturating_duration_since(started),
+                event,
+            });
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.devices.attach(device, hardware_id);
+    }
+}
+
+/// How a recording is played
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaybackSettings {
+    /// Playback speed relative to the recording; 2 plays twice as fast
+    ///
+    /// Speeds outside [`SPEED_RANGE`] are clamped to it, and NaN plays at the original speed.
+    pub speed: f64,
+    /// How many times to play the recording; `None` repeats until aborted
+    pub loops: Option<u32>,
+    /// A key that stops playback when pressed on any keyboard
+    pub abort: Option<Key>,
+}
+
+impl Default for PlaybackSettings {
+    /// Once at the original speed, aborted by Escape
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            loops: Some(1),
+            abort: Some(Key::ESCAPE),
+        }
+    }
+}
+
+fn clamp_speed(speed: f64) -> f64 {
+    if speed.is_nan() {
+        1.0
+    } else {
+        speed.clamp(SPEED_RANGE.0, SPEED_RANGE.1)
+    }
+}
+
+impl PlaybackSettings {
+    /// Play at `speed`, clamped to [`SPEED_RANGE`]
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        self.speed = clamp_speed(speed);
+        self
+    }
+
+    pub fn with_loops(mut self, loops: u32) -> Self {
+        self.loops = Some(loops);
+        self
+    }
+
+    /// Repeat until aborted
+    pub fn with_endless_loop(mut self) -> Self {
+        self.loops = None;
+        self
+    }
+
+    pub fn with_abort(mut self, abort: Option<Key>) -> Self {
+        self.abort = abort;
+        self
+    }
+}
+
+/// Where playback is
+#[derive(Debug, Clone)]
+struct Cursor {
+    recording: Recording,
+    /// When the current loop started
+    loop_start: Instant,
+    index: usize,
+    loops_done: u32,
+}
+
+/// Plays recordings back into the stroke stream, passing live input through
+#[derive(Debug, Clone)]
+pub struct Player {
+    settings: PlaybackSettings,
+    cursor: Option<Cursor>,
+    /// Keys and buttons pressed by playback and not yet released
+    pressed: BTreeSet<(usize, Switch)>,
+    /// Abort keys whose release should be swallowed, by device
+    swallow: HashMap<usize, Key>,
+}
+
+impl Player {
+    pub fn new(settings: PlaybackSettings) -> Self {
+        Self {
+            settings,
+            cursor: None,
+            pressed: BTreeSet::new(),
+            swallow: HashMap::new(),
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// Start playing `recording`, replacing any playback in progress
+    ///
+    /// Anything the previous playback held is released to `out`.
+    /// A loop takes at least a millisecond, even if every event happened at once.
+    pub fn play(&mut self, mut recording: Recording, now: Instant, out: &mut Vec<Event>) {
+        self.release_all(out);
+        let nothing_to_play = recording.is_empty() && recording.duration.is_zero();
+        recording.duration = recording.duration.max(MIN_LOOP);
+        self.cursor = (self.settings.loops != Some(0) && !nothing_to_play).then_some(Cursor {
+            recording,
+            loop_start: now,
+            index: 0,
+            loops_done: 0,
+        });
+    }
+
+    /// Stop playback, releasing anything it holds to `out`
+    pub fn abort(&mut self, out: &mut Vec<Event>) {
+        self.cursor = None;
+        self.release_all(out);
+    }
+
+    fn release_all(&mut self, out: &mut Vec<Event>) {
+        while let Some((device, switch)) = self.pressed.pop_last() {
+            out.push(Event::new(device, switch.release()));
+        }
+    }
+
+    fn scaled(&self, at: Duration) -> Duration {
+        at.div_f64(clamp_speed(self.settings.speed))
+    }
+
+    /// Send a recorded event, dropping releases of anything playback didn't press
+    fn emit(&mut self, event: Event, out: &mut Vec<Event>) {
+        let mut stroke = event.stroke;
+        for (switch, down) in transitions(&event.stroke) {
+            let id = (event.device, switch);
+            if down {
+                self.pressed.insert(id);
+            } else if !self.pressed.remove(&id) {
+                match (&mut stroke, switch) {
+                    (Stroke::Mouse(mouse), Switch::Button(button)) => {
+                        mouse.state &= !button.up_flag();
+                    }
+                    _ => return,
+                }
+            }
+        }
+        match stroke {
+            Stroke::Mouse(mouse) if mouse.is_empty() && event.stroke != stroke => {}
+            _ => out.push(Event::new(event.device, stroke)),
+        }
+    }
+}
+
+impl Processor for Player {
+    fn process(&mut self, event: Event, _now: Instant, out: &mut Vec<Event>) {
+        if let Some(stroke) = event.key() {
+            let key = stroke.key();
+            if stroke.is_up() && self.swallow.get(&event.device) == Some(&key) {
+                self.swallow.remove(&event.device);
+                return;
+            }
+            if self.is_playing() && stroke.is_down() && self.settings.abort == Some(key) {
+                self.swallow.insert(event.device, key);
+                self.abort(out);
+                return;
+            }
+        }
+        out.push(event);
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        let cursor = self.cursor.as_ref()?;
+        let at = match cursor.recording.events.get(cursor.index) {
+            Some(recorded) => recorded.at,
+            None => cursor.recording.duration,
+        };
+        Some(cursor.loop_start + self.scaled(at))
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        while let Some(deadline) = self.deadline().filter(|deadline| *deadline <= now) {
+            let Some(cursor) = self.cursor.as_mut() else {
+                break;
+            };
+            if let Some(recorded) = cursor.recording.events.get(cursor.index) {
+                let event = recorded.event;
+                cursor.index += 1;
+                self.emit(event, out);
+                continue;
+            }
+
+            // End of a loop: start the next one from a clean slate
+            cursor.index = 0;
+            cursor.loops_done += 1;
+            cursor.loop_start = deadline;
+            let finished = self
+                .settings
+                .loops
+                .is_some_and(|loops| cursor.loops_done >= loops);
+            self.release_all(out);
+            if finished {
+                self.cursor = None;
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+impl crate::Interception {
+    /// Play a recording to the devices it was recorded from, passing live input through,
+    /// until it is done or aborted
+    pub fn play(&mut self, recording: Recording, settings: PlaybackSettings) -> crate::Result<()> {
+        let mut player = Player::new(settings);
+        let mut out = Vec::new();
+        player.play(recording, Instant::now(), &mut out);
+        self.send_events(&out)?;
+        if !player.is_playing() {
+            return Ok(());
+        }
+        self.run_while(&mut player, Player::is_playing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KeyStroke, MAX_KEYBOARD, MOUSE_MOVE_RELATIVE, MouseStroke};
+
+    const KEYBOARD: usize = 0;
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn key(stroke: KeyStroke) -> Event {
+        Event::new(KEYBOARD, stroke)
+    }
+
+    /// Start `player` at `t0` and tick at every deadline until it is done
+    fn play_all(player: &mut Player, recording: Recording, t0: Instant) -> Vec<(Duration, Event)> {
+        let mut out = Vec::new();
+        player.play(recording, t0, &mut out);
+        let mut timed = Vec::new();
+        while let Some(deadline) = player.deadline() {
+            player.tick(deadline, &mut out);
+            timed.extend(out.drain(..).map(|event| (deadline - t0, event)));
+        }
+        timed
+    }
+
+    #[test]
+    fn test_records_selected_devices_with_held_state() {
+        let mut recorder = Recorder::per_device(PerDevice::new(true).with("PID_0002", false));
+        recorder.attach(MOUSE + 1, r"HID\VID_0001&PID_0002");
+        let t0 = Instant::now();
+        let mut out = Vec::new();
+
+        // Ctrl is held when recording starts, A when it stops
+        recorder.process(key(Key::LEFT_CTRL.down()), t0, &mut out);
+        recorder.start(t0 + ms(10));
+        recorder.process(key(Key::LEFT_CTRL.up()), t0 + ms(20), &mut out);
+        let motion = MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, 5, 0, 0);
+        recorder.process(Event::new(MOUSE + 1, motion), t0 + ms(25), &mut out);
+        recorder.process(
+            Event::new(MOUSE, MouseButton::Left.down()),
+            t0 + ms(30),
+            &mut out,
+        );
+        recorder.process(key(Key::A.down()), t0 + ms(40), &mut out);
+        let recording = recorder.stop(t0 + ms(50));
+
+        assert_eq!(out.len(), 5);
+        assert!(!recorder.is_recording());
+        assert_eq!(
+            recording.held_at_start,
+            vec![(KEYBOARD, Key::LEFT_CTRL.into())]
+        );
+        assert_eq!(
+            recording.held_at_stop,
+            vec![(KEYBOARD, Key::A.into()), (MOUSE, MouseButton::Left.into())]
+        );
+        assert_eq!(recording.duration, ms(40));
+        let times: Vec<_> = recording.events.iter().map(|r| r.at).collect();
+        assert_eq!(times, vec![ms(10), ms(20), ms(30)]);
+    }
+
+    #[test]
+    fn test_playback_timing_and_speed() {
+        let recording = Recording {
+            events: vec![
+                Recorded {
+                    at: ms(0),
+                    event: key(Key::A.down()),
+                },
+                Recorded {
+                    at: ms(100),
+                    event: key(Key::A.up()),
+                },
+            ],
+            duration: ms(200),
+            ..Recording::default()
+        };
+        let settings = PlaybackSettings::default().with_speed(2.0).with_loops(2);
+        let mut player = Player::new(settings);
+        let timed = play_all(&mut player, recording, Instant::now());
+        assert_eq!(
+            timed,
+            vec![
+                (ms(0), key(Key::A.down())),
+                (ms(50), key(Key::A.up())),
+                (ms(100), key(Key::A.down())),
+                (ms(150), key(Key::A.up())),
+            ]
+        );
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn test_simultaneous_events_loop_with_time() {
+        let recording = Recording {
+            events: vec![
+                Recorded {
+                    at: ms(0),
+                    event: key(Key::A.down()),
+                },
+                Recorded {
+                    at: ms(0),
+                    event: key(Key::A.up()),
+                },
+            ],
+            ..Recording::default()
+        };
+        let mut player = Player::new(PlaybackSettings::default().with_endless_loop());
+        let t0 = Instant::now();
+        let mut out = Vec::new();
+        player.play(recording, t0, &mut out);
+        player.tick(t0 + ms(5), &mut out);
+
+        // One loop per millisecond, from 0 to 5ms
+        assert_eq!(out.len(), 12);
+        assert!(player.is_playing());
+        assert_eq!(player.deadline(), Some(t0 + ms(6)));
+    }
+
+    #[test]
+    fn test_invalid_speeds_are_clamped() {
+        for (speed, clamped) in [
+            (0.0, 0.01),
+            (-2.0, 0.01),
+            (f64::INFINITY, 100.0),
+            (f64::NAN, 1.0),
+        ] {
+            let settings = PlaybackSettings::default().with_speed(speed);
+            assert_eq!(settings.speed, clamped);
+        }
+        let mut player = Player::new(PlaybackSettings {
+            speed: 0.0,
+            ..PlaybackSettings::default()
+        });
+        let recording = Recording {
+            events: vec![Recorded {
+                at: ms(1),
+                event: key(Key::A.down()),
+            }],
+            duration: ms(1),
+            ..Recording::default()
+        };
+        let t0 = Instant::now();
+        player.play(recording, t0, &mut Vec::new());
+        assert_eq!(player.deadline(), Some(t0 + ms(100)));
+    }
+
+    #[test]
+    fn test_playback_never_leaves_keys_stuck() {
+        let recording = Recording {
+            events: vec![
+                // Released hotkey from before the recording started
+                Recorded {
+                    at: ms(0),
+                    event: key(Key::LEFT_CTRL.up()),
+                },
+                Recorded {
+                    at: ms(10),
+                    event: key(Key::LEFT_SHIFT.down()),
+                },
+                Recorded {
+                    at: ms(20),
+                    event: Event::new(MOUSE, MouseButton::Right.down()),
+                },
+            ],
+            held_at_start: vec![(KEYBOARD, Key::LEFT_CTRL.into())],
+            held_at_stop: vec![
+                (KEYBOARD, Key::LEFT_SHIFT.into()),
+                (MOUSE, MouseButton::Right.into()),
+            ],
+            duration: ms(30),
+        };
+        let mut player = Player::new(PlaybackSettings::default());
+        let events: Vec<_> = play_all(&mut player, recording, Instant::now())
+            .into_iter()
+            .map(|(_, event)| event)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                key(Key::LEFT_SHIFT.down()),
+                Event::new(MOUSE, MouseButton::Right.down()),
+                Event::new(MOUSE, MouseButton::Right.up()),
+                key(Key::LEFT_SHIFT.up()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_abort_key_releases_and_is_swallowed() {
+        let recording = Recording {
+            events: vec![
+                Recorded {
+                    at: ms(0),
+                    event: key(Key::B.down()),
+                },
+                Recorded {
+                    at: ms(500),
+                    event: key(Key::B.up()),
+                },
+            ],
+            duration: ms(500),
+            ..Recording::default()
+        };
+        let mut player = Player::new(PlaybackSettings::default().with_endless_loop());
+        let t0 = Instant::now();
+        let mut out = Vec::new();
+        player.play(recording, t0, &mut out);
+        player.tick(t0, &mut out);
+        player.process(key(Key::C.down()), t0 + ms(5), &mut out);
+        player.process(key(Key::ESCAPE.down()), t0 + ms(10), &mut out);
+        player.process(key(Key::ESCAPE.up()), t0 + ms(20), &mut out);
+
+        assert_eq!(
+            out,
+            vec![key(Key::B.down()), key(Key::C.down()), key(Key::B.up())]
+        );
+        assert!(!player.is_playing());
+        assert_eq!(player.deadline(), None);
+
+        // Not playing: the abort key is an ordinary key
+        out.clear();
+        player.process(key(Key::ESCAPE.down()), t0 + ms(30), &mut out);
+        assert_eq!(out, vec![key(Key::ESCAPE.down())]);
+    }
+
+    #[test]
+    fn test_record_then_play_round_trip() {
+        let mut recorder = Recorder::new();
+        let t0 = Instant::now();
+        let mut out = Vec::new();
+        recorder.start(t0);
+        let strokes = [
+            Event::new(MOUSE, MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, 3, -2, 0)),
+            key(Key::X.down()),
+            key(Key::X.up()),
+        ];
+        for (i, event) in strokes.iter().enumerate() {
+            recorder.process(*event, t0 + ms(7 * i as u64), &mut out);
+        }
+        let recording = recorder.stop(t0 + ms(30));
+
+        let mut player = Player::new(PlaybackSettings::default());
+        let timed = play_all(&mut player, recording, t0);
+        let expected: Vec<_> = strokes
+            .iter()
+            .enumerate()
+            .map(|(i, event)| (ms(7 * i as u64), *event))
+            .collect();
+        assert_eq!(timed, expected);
+    }
+}
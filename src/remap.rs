@@ -0,0 +1,344 @@
+//! Switch remapping across device kinds.
+//!
+//! [`Remap`] replaces presses of a key or mouse button with presses of other switches, which
+//! may live on the other kind of device: mouse button 4 can become Ctrl+C, and a key can
+//! become a middle click. Outputs of the other kind are sent to an explicitly chosen device or,
+//! by default, to the most recently active device of that kind.
+//!
+//! The devices an output was pressed on are remembered until the input is released, so the
+//! release always reaches the same devices as the press even if another device has become
+//! active in between.
+
+use crate::{Event, MAX_KEYBOARD, MouseButton, Processor, Stroke, Switch, is_keyboard, is_mouse};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Where the output of a mapping is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    /// The input's own device if it is of the right kind, otherwise the most recently active
+    /// device of that kind
+    #[default]
+    Auto,
+    /// Always this device index; used for outputs of the matching kind only
+    Device(usize),
+}
+
+/// What a switch is remapped to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    /// Pressed in order on input press, released in reverse on input release
+    pub outputs: Vec<Switch>,
+    pub keyboard: Target,
+    pub mouse: Target,
+}
+
+impl Mapping {
+    pub fn new(outputs: impl IntoIterator<Item = impl Into<Switch>>) -> Self {
+        Self {
+            outputs: outputs.into_iter().map(Into::into).collect(),
+            keyboard: Target::Auto,
+            mouse: Target::Auto,
+        }
+    }
+
+    /// Send key outputs to a particular keyboard
+    pub fn on_keyboard(mut self, device: usize) -> Self {
+        self.keyboard = Target::Device(device);
+        self
+    }
+
+    /// Send button outputs to a particular mouse
+    pub fn on_mouse(mut self, device: usize) -> Self {
+        self.mouse = Target::Device(device);
+        self
+    }
+}
+
+/// Tracks the most recently active keyboard and mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveDevices {
+    pub keyboard: usize,
+    pub mouse: usize,
+}
+
+impl Default for ActiveDevices {
+    fn default() -> Self {
+        Self {
+            keyboard: 0,
+            mouse: MAX_KEYBOARD,
+        }
+    }
+}
+
+impl ActiveDevices {
+    /// Note that a device produced input
+    pub fn observe(&mut self, device: usize) {
+        if is_keyboard(device) {
+            self.keyboard = device;
+        } else if is_mouse(device) {
+            self.mouse = device;
+        }
+    }
+
+    /// The device a switch should be sent to, given the device that caused it
+    pub fn resolve(&self, switch: Switch, source: usize, target: Target) -> usize {
+        match (target, switch.is_key()) {
+            (Target::Device(device), true) if is_keyboard(device) => device,
+            (Target::Device(device), false) if is_mouse(device) => device,
+            (_, true) if is_keyboard(source) => source,
+            (_, false) if is_mouse(source) => source,
+            (_, true) => self.keyboard,
+            (_, false) => self.mouse,
+        }
+    }
+}
+
+/// Remaps keys and mouse buttons to switches on either kind of device
+#[derive(Debug, Clone, Default)]
+pub struct Remap {
+    mappings: HashMap<Switch, Mapping>,
+    /// Outputs currently pressed, per input, as release events
+    pressed: HashMap<(usize, Switch), Vec<Event>>,
+    active: ActiveDevices,
+}
+
+impl Remap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remap `input`, replacing any earlier mapping for it
+    pub fn with(mut self, input: impl Into<Switch>, mapping: Mapping) -> Self {
+        self.insert(input, mapping);
+        self
+    }
+
+    pub fn insert(&mut self, input: impl Into<Switch>, mapping: Mapping) {
+        self.mappings.insert(input.into(), mapping);
+    }
+
+    pub fn remove(&mut self, input: impl Into<Switch>) -> Option<Mapping> {
+        self.mappings.remove(&input.into())
+    }
+
+    /// The most recently active keyboard and mouse
+    pub fn active_devices(&self) -> ActiveDevices {
+        self.active
+    }
+
+    /// Handle one transition of a switch; returns false if it isn't remapped
+    fn transition(
+        &mut self,
+        device: usize,
+        input: Switch,
+        down: bool,
+        out: &mut Vec<Event>,
+    ) -> bool {
+        let id = (device, input);
+        if !down {
+            return match self.pressed.remove(&id) {
+                Some(releases) => {
+                    out.extend(releases);
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let Some(mapping) = self.mappings.get(&input) else {
+            return false;
+        };
+
+        if let Some(releases) = self.pressed.get(&id) {
+            // Autorepeat: repeat the last key output on its device, drop anything else
+            if let Some(Event {
+                device,
+                stroke: Stroke::Key(release),
+            }) = releases.first()
+            {
+                out.push(Event::new(*device, release.key().down()));
+            }
+            return true;
+        }
+
+        let mut releases = Vec::with_capacity(mapping.outputs.len());
+        for &output in &mapping.outputs {
+            let target = if output.is_key() {
+                mapping.keyboard
+            } else {
+                mapping.mouse
+            };
+            let target = self.active.resolve(output, device, target);
+            out.push(Event::new(target, output.press()));
+            releases.push(Event::new(target, output.release()));
+        }
+        releases.reverse();
+        self.pressed.insert(id, releases);
+        true
+    }
+}
+
+impl Processor for Remap {
+    fn process(&mut self, event: Event, _now: Instant, out: &mut Vec<Event>) {
+        self.active.observe(event.device);
+
+        match event.stroke {
+            Stroke::Key(stroke) => {
+                if !self.transition(
+                    event.device,
+                    Switch::Key(stroke.key()),
+                    stroke.is_down(),
+                    out,
+                ) {
+                    out.push(event);
+                }
+            }
+            Stroke::Mouse(stroke) => {
+                let mut remaining = stroke;
+                let mut remapped = Vec::new();
+                for button in MouseButton::ALL {
+                    for (flag, down) in [(button.down_flag(), true), (button.up_flag(), false)] {
+                        if stroke.state & flag != 0
+                            && self.transition(
+                                event.device,
+                                Switch::Button(button),
+                                down,
+                                &mut remapped,
+                            )
+                        {
+                            remaining.state &= !flag;
+                        }
+                    }
+                }
+
+                if remaining == stroke || !remaining.is_empty() {
+                    out.push(Event::new(event.device, remaining));
+                }
+                out.extend(remapped);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, MOUSE_BUTTON_4_DOWN, MOUSE_MOVE_RELATIVE, MouseStroke};
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    fn feed(remap: &mut Remap, events: &[Event]) -> Vec<Event> {
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for event in events {
+            remap.process(*event, now, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_mouse_button_to_chord_on_active_keyboard() {
+        let mut remap =
+            Remap::new().with(MouseButton::Button4, Mapping::new([Key::LEFT_CTRL, Key::C]));
+        let out = feed(
+            &mut remap,
+            &[
+                Event::new(2, Key::A.down()),
+                Event::new(2, Key::A.up()),
+                Event::new(MOUSE, MouseButton::Button4.down()),
+                Event::new(MOUSE, MouseButton::Button4.up()),
+            ],
+        );
+        assert_eq!(
+            out[2..],
+            [
+                Event::new(2, Key::LEFT_CTRL.down()),
+                Event::new(2, Key::C.down()),
+                Event::new(2, Key::C.up()),
+                Event::new(2, Key::LEFT_CTRL.up()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_release_follows_press_device() {
+        let mut remap = Remap::new().with(MouseButton::Button4, Mapping::new([Key::ESCAPE]));
+        let out = feed(
+            &mut remap,
+            &[
+                Event::new(1, Key::A.up()),
+                Event::new(MOUSE, MouseButton::Button4.down()),
+                Event::new(3, Key::B.down()),
+                Event::new(MOUSE, MouseButton::Button4.up()),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(1, Key::A.up()),
+                Event::new(1, Key::ESCAPE.down()),
+                Event::new(3, Key::B.down()),
+                Event::new(1, Key::ESCAPE.up()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_to_click_on_chosen_mouse() {
+        let mut remap = Remap::new().with(
+            Key::RIGHT_CTRL,
+            Mapping::new([MouseButton::Middle]).on_mouse(MOUSE + 2),
+        );
+        let out = feed(
+            &mut remap,
+            &[
+                Event::new(0, Key::RIGHT_CTRL.down()),
+                Event::new(0, Key::RIGHT_CTRL.down()),
+                Event::new(0, Key::RIGHT_CTRL.up()),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE + 2, MouseButton::Middle.down()),
+                Event::new(MOUSE + 2, MouseButton::Middle.up()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_motion_kept_when_button_remapped() {
+        let mut remap = Remap::new().with(MouseButton::Button4, Mapping::new([Key::ENTER]));
+        let stroke = MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_BUTTON_4_DOWN, 0, 5, -2, 0);
+        let out = feed(&mut remap, &[Event::new(MOUSE, stroke)]);
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, 5, -2, 0)),
+                Event::new(0, Key::ENTER.down()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_key_remap_repeats_output() {
+        let mut remap = Remap::new().with(Key::CAPS_LOCK, Mapping::new([Key::ESCAPE]));
+        let out = feed(
+            &mut remap,
+            &[
+                Event::new(0, Key::CAPS_LOCK.down()),
+                Event::new(0, Key::CAPS_LOCK.down()),
+                Event::new(0, Key::CAPS_LOCK.up()),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(0, Key::ESCAPE.down()),
+                Event::new(0, Key::ESCAPE.down()),
+                Event::new(0, Key::ESCAPE.up()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,419 @@
+//! Software key repeat.
+//!
+//! The keyboard reports autorepeat as further key downs for a held key without an
+//! intervening key up. [`KeyRepeat`] recognizes those repeats and, depending on the
+//! [`Repeat`] policy for the key and device, passes them on, drops them, or drops them and
+//! generates its own at a custom delay and rate.
+//!
+//! [`RepeatThread`] moves a [`KeyRepeat`] onto a timer thread that sends the synthetic repeats
+//! itself, through a keyboard's `send` or any other callback, so they keep coming on time no
+//! matter how the receiving loop waits. A bare [`KeyRepeat`] instead produces them from
+//! [`Processor::tick`], which suits [`Interception::run`](crate::Interception::run) and its
+//! deadline-bounded wait.
+
+use crate::{Event, Key, PerDevice, Processor, Stroke};
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How a held key repeats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    /// Pass the keyboard's own repeats through
+    Hardware,
+    /// Never repeat
+    Disabled,
+    /// Repeat after `delay`, then every `interval`
+    Custom { delay: Duration, interval: Duration },
+}
+
+/// Repeat policies for one device
+#[derive(Debug, Clone)]
+pub struct RepeatSettings {
+    default: Repeat,
+    keys: HashMap<Key, Repeat>,
+}
+
+impl Default for RepeatSettings {
+    /// Every key uses hardware repeat
+    fn default() -> Self {
+        Self::new(Repeat::Hardware)
+    }
+}
+
+impl RepeatSettings {
+    /// Use `default` for every key without its own policy
+    pub fn new(default: Repeat) -> Self {
+        Self {
+            default,
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Override the policy of a single key
+    pub fn with_key(mut self, key: Key, repeat: Repeat) -> Self {
+        self.keys.insert(key, repeat);
+        self
+    }
+
+    /// Override the policy of several keys, e.g. `with_keys(MODIFIERS, Repeat::Disabled)`
+    pub fn with_keys(mut self, keys: impl IntoIterator<Item = Key>, repeat: Repeat) -> Self {
+        for key in keys {
+            self.keys.insert(key, repeat);
+        }
+        self
+    }
+
+    /// The policy for a key
+    pub fn get(&self, key: Key) -> Repeat {
+        self.keys.get(&key).copied().unwrap_or(self.default)
+    }
+}
+
+/// A synthetic repeat in progress
+#[derive(Debug, Clone, Copy)]
+struct Repeating {
+    event: Event,
+    key: Key,
+    interval: Duration,
+    next: Instant,
+}
+
+/// Replaces keyboard autorepeat with per-key, per-device policies
+#[derive(Debug, Clone)]
+pub struct KeyRepeat {
+    settings: PerDevice<RepeatSettings>,
+    /// Keys currently held, per device
+    held: HashMap<(usize, Key), Repeat>,
+    /// Like the keyboard itself, only the most recently pressed key repeats
+    repeating: Option<Repeating>,
+}
+
+impl KeyRepeat {
+    pub fn new(settings: RepeatSettings) -> Self {
+        Self::per_device(PerDevice::new(settings))
+    }
+
+    /// Use different settings for particular devices
+    pub fn per_device(settings: PerDevice<RepeatSettings>) -> Self {
+        Self {
+            settings,
+            held: HashMap::new(),
+            repeating: None,
+        }
+    }
+}
+
+impl Processor for KeyRepeat {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        let Stroke::Key(stroke) = event.stroke else {
+            out.push(event);
+            return;
+        };
+        let key = stroke.key();
+        let id = (event.device, key);
+
+        if stroke.is_up() {
+            self.held.remove(&id);
+            if self
+                .repeating
+                .is_some_and(|r| r.event.device == event.device && r.key == key)
+            {
+                self.repeating = None;
+            }
+            out.push(event);
+            return;
+        }
+
+        if let Some(repeat) = self.held.get(&id) {
+            if *repeat == Repeat::Hardware {
+                out.push(event);
+            }
+            return;
+        }
+
+        let repeat = self.settings.get(event.device).get(key);
+        self.held.insert(id, repeat);
+        self.repeating = match repeat {
+            Repeat::Custom { delay, interval } => Some(Repeating {
+                event,
+                key,
+                interval,
+                next: now + delay,
+            }),
+            Repeat::Hardware | Repeat::Disabled => None,
+        };
+        out.push(event);
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.repeating.map(|r| r.next)
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        let Some(repeating) = &mut self.repeating else {
+            return;
+        };
+        if repeating.next > now {
+            return;
+        }
+        out.push(repeating.event);
+        repeating.next += repeating.interval;
+        // Don't burst to catch up after a stall
+        if repeating.next <= now {
+            repeating.next = now + repeating.interval;
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.settings.attach(device, hardware_id);
+    }
+}
+
+struct RepeatState {
+    repeat: KeyRepeat,
+    stopped: bool,
+}
+
+struct Shared {
+    state: Mutex<RepeatState>,
+    changed: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, RepeatState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A [`KeyRepeat`] whose synthetic repeats are sent from a timer thread
+///
+/// As a processor it passes, drops and tracks strokes exactly like the wrapped [`KeyRepeat`],
+/// but never has a deadline: the thread sleeps until the next repeat is due and sends it
+/// straight to the device, bypassing any processors after this one in a [`Chain`](crate::Chain).
+/// Repeats are sent while the processor is locked, so one can never overtake the key up that
+/// ends it. The thread stops once this is dropped.
+pub struct RepeatThread {
+    shared: Arc<Shared>,
+}
+
+impl RepeatThread {
+    /// Start a thread sending `repeat`'s synthetic repeats with `send`
+    ///
+    /// The thread ends with the first error from `send`, which the handle returns.
+    pub fn spawn<E: Send + 'static>(
+        repeat: KeyRepeat,
+        send: impl FnMut(Event) -> Result<(), E> + Send + 'static,
+    ) -> (Self, JoinHandle<Result<(), E>>) {
+        let this = Self::new(repeat);
+        let shared = this.shared.clone();
+        (this, thread::spawn(move || run(&shared, send)))
+    }
+
+    /// Start a thread sending `repeat`'s synthetic repeats through keyboard devices of its own
+    #[cfg(windows)]
+    pub fn spawn_on_devices(repeat: KeyRepeat) -> (Self, JoinHandle<crate::Result<()>>) {
+        let this = Self::new(repeat);
+        let shared = this.shared.clone();
+        let handle = thread::spawn(move || {
+            use std::collections::hash_map::Entry;
+
+            let mut devices = HashMap::new();
+            run(&shared, |event| {
+                let Stroke::Key(stroke) = event.stroke else {
+                    return Ok(());
+                };
+                let device = match devices.entry(event.device) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(crate::KeyboardDevice::new(event.device)?),
+                };
+                device.send(&[stroke]).map(drop)
+            })
+        });
+        (this, handle)
+    }
+
+    fn new(repeat: KeyRepeat) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(RepeatState {
+                    repeat,
+                    stopped: false,
+                }),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+}
+
+/// Send repeats as they fall due until stopped or `send` fails
+fn run<E>(shared: &Shared, mut send: impl FnMut(Event) -> Result<(), E>) -> Result<(), E> {
+    let mut out = Vec::new();
+    let mut state = shared.lock();
+    while !state.stopped {
+        let now = Instant::now();
+        state = match state.repeat.deadline() {
+            Some(deadline) if deadline <= now => {
+                state.repeat.tick(now, &mut out);
+                for event in out.drain(..) {
+                    send(event)?;
+                }
+                state
+            }
+            Some(deadline) => {
+                let wait = shared.changed.wait_timeout(state, deadline - now);
+                wait.unwrap_or_else(|e| e.into_inner()).0
+            }
+            None => {
+                let wait = shared.changed.wait(state);
+                wait.unwrap_or_else(|e| e.into_inner())
+            }
+        };
+    }
+    Ok(())
+}
+
+impl Processor for RepeatThread {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        self.shared.lock().repeat.process(event, now, out);
+        self.shared.changed.notify_one();
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.shared.lock().repeat.attach(device, hardware_id);
+    }
+}
+
+impl Drop for RepeatThread {
+    fn drop(&mut self) {
+        self.shared.lock().stopped = true;
+        self.shared.changed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MODIFIERS;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn custom(delay: u64, interval: u64) -> Repeat {
+        Repeat::Custom {
+            delay: ms(delay),
+            interval: ms(interval),
+        }
+    }
+
+    #[test]
+    fn test_hardware_and_disabled_repeat() {
+        let now = Instant::now();
+        let mut repeat =
+            KeyRepeat::new(RepeatSettings::default().with_keys(MODIFIERS, Repeat::Disabled));
+        let mut out = Vec::new();
+        for stroke in [
+            Key::A.down(),
+            Key::A.down(),
+            Key::LEFT_SHIFT.down(),
+            Key::LEFT_SHIFT.down(),
+        ] {
+            repeat.process(Event::new(0, stroke), now, &mut out);
+        }
+        assert_eq!(
+            out,
+            vec![
+                Event::new(0, Key::A.down()),
+                Event::new(0, Key::A.down()),
+                Event::new(0, Key::LEFT_SHIFT.down()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_repeat_replaces_hardware_repeat() {
+        let t0 = Instant::now();
+        let mut repeat = KeyRepeat::new(RepeatSettings::new(custom(300, 50)));
+        let mut out = Vec::new();
+
+        repeat.process(Event::new(0, Key::A.down()), t0, &mut out);
+        repeat.process(Event::new(0, Key::A.down()), t0 + ms(250), &mut out);
+        assert_eq!(out, vec![Event::new(0, Key::A.down())]);
+        assert_eq!(repeat.deadline(), Some(t0 + ms(300)));
+
+        repeat.tick(t0 + ms(300), &mut out);
+        repeat.tick(t0 + ms(350), &mut out);
+        assert_eq!(out.len(), 3);
+        assert_eq!(repeat.deadline(), Some(t0 + ms(400)));
+
+        repeat.process(Event::new(0, Key::A.up()), t0 + ms(360), &mut out);
+        assert_eq!(out.last(), Some(&Event::new(0, Key::A.up())));
+        assert_eq!(repeat.deadline(), None);
+    }
+
+    #[test]
+    fn test_only_latest_key_repeats() {
+        let t0 = Instant::now();
+        let mut repeat = KeyRepeat::new(RepeatSettings::new(custom(100, 10)));
+        let mut out = Vec::new();
+
+        repeat.process(Event::new(0, Key::A.down()), t0, &mut out);
+        repeat.process(Event::new(0, Key::B.down()), t0 + ms(50), &mut out);
+        out.clear();
+        repeat.tick(t0 + ms(150), &mut out);
+        assert_eq!(out, vec![Event::new(0, Key::B.down())]);
+
+        // Releasing the earlier key doesn't stop the later one
+        repeat.process(Event::new(0, Key::A.up()), t0 + ms(155), &mut out);
+        assert_eq!(repeat.deadline(), Some(t0 + ms(160)));
+    }
+
+    #[test]
+    fn test_settings_per_device() {
+        let t0 = Instant::now();
+        let settings = PerDevice::new(RepeatSettings::default())
+            .with("VID_1234", RepeatSettings::new(Repeat::Disabled));
+        let mut repeat = KeyRepeat::per_device(settings);
+        repeat.attach(1, r"HID\VID_1234&PID_0001");
+
+        let mut out = Vec::new();
+        for device in [0, 1] {
+            repeat.process(Event::new(device, Key::A.down()), t0, &mut out);
+            repeat.process(Event::new(device, Key::A.down()), t0, &mut out);
+        }
+        assert_eq!(
+            out,
+            vec![
+                Event::new(0, Key::A.down()),
+                Event::new(0, Key::A.down()),
+                Event::new(1, Key::A.down()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeat_thread_sends_until_key_up() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let (mut repeat, handle) = RepeatThread::spawn(
+            KeyRepeat::new(RepeatSettings::new(custom(20, 10))),
+            move |event| sender.send(event),
+        );
+        let mut out = Vec::new();
+
+        repeat.process(Event::new(0, Key::A.down()), Instant::now(), &mut out);
+        assert_eq!(out, vec![Event::new(0, Key::A.down())]);
+        for _ in 0..3 {
+            let sent = receiver.recv_timeout(Duration::from_secs(5));
+            assert_eq!(sent, Ok(Event::new(0, Key::A.down())));
+        }
+
+        repeat.process(Event::new(0, Key::A.up()), Instant::now(), &mut out);
+        receiver.try_iter().for_each(drop);
+        assert!(receiver.recv_timeout(ms(100)).is_err());
+
+        drop(repeat);
+        assert_eq!(handle.join().unwrap(), Ok(()));
+    }
+}
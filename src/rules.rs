@@ -0,0 +1,655 @@
+//! Declarative remapping rules: remaps, dual-role keys, layers and macros.
+//!
+//! A [`RuleSet`] is plain data, normally loaded from a configuration file, and [`Rules`] is the
+//! processor that applies it. Each rule can be limited to the devices picked out by named
+//! [`DeviceSelector`]s. When a switch goes down, the first of these that applies decides what
+//! happens:
+//!
+//! 1. its mapping in the active layers, the most recently activated first;
+//! 2. a layer it activates while held;
+//! 3. a dual-role rule: a tap sends one thing, a hold another or activates a layer;
+//! 4. a macro, sent when it is pressed;
+//! 5. a remap;
+//!
+//! and otherwise the stroke passes through. What a press did is remembered until the
+//! release, so a release always undoes its own press, even after
+//! [`replace`](Rules::replace) has swapped in different rules.
+
+use crate::remap::{ActiveDevices, Target};
+use crate::{Event, KeyStroke, MouseButton, Processor, Stroke, Switch, is_keyboard};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+/// A named group of devices, picked by hardware ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceSelector {
+    pub name: String,
+    /// A device belongs to the group if any pattern occurs in its hardware ID, ignoring case
+    pub patterns: Vec<String>,
+}
+
+impl DeviceSelector {
+    pub fn new(
+        name: impl Into<String>,
+        patterns: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            patterns: patterns.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn matches(&self, hardware_id: &str) -> bool {
+        let hardware_id = hardware_id.to_uppercase();
+        self.patterns
+            .iter()
+            .any(|pattern| hardware_id.contains(&pattern.to_uppercase()))
+    }
+}
+
+/// Replaces a switch with others, pressed in order and released in reverse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemapRule {
+    pub from: Switch,
+    pub to: Vec<Switch>,
+    /// Names of the selectors this applies to; empty for every device
+    pub devices: Vec<String>,
+}
+
+/// What holding a dual-role switch does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HoldAction {
+    Press(Vec<Switch>),
+    Layer(String),
+}
+
+/// A switch that does one thing when tapped and another when held
+///
+/// It counts as held once `timeout` passes or another switch goes down before its release.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DualRoleRule {
+    pub switch: Switch,
+    pub tap: Vec<Switch>,
+    pub hold: HoldAction,
+    pub timeout: Duration,
+    pub devices: Vec<String>,
+}
+
+/// Mappings that apply while the layer is active
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layer {
+    pub name: String,
+    /// Holding this switch activates the layer
+    pub activate: Option<Switch>,
+    /// An empty mapping blocks the switch
+    pub mappings: HashMap<Switch, Vec<Switch>>,
+}
+
+/// Strokes sent when a switch is pressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacroRule {
+    pub trigger: Switch,
+    pub strokes: Vec<KeyStroke>,
+    pub devices: Vec<String>,
+}
+
+/// Everything [`Rules`] applies
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RuleSet {
+    pub devices: Vec<DeviceSelector>,
+    pub remaps: Vec<RemapRule>,
+    pub dual_roles: Vec<DualRoleRule>,
+    pub layers: Vec<Layer>,
+    pub macros: Vec<MacroRule>,
+}
+
+/// A problem found while reading rules from a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column, in characters
+    pub column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// A problem at byte `offset` of `source`
+    pub fn at(source: &str, offset: usize, message: impl Into<String>) -> Self {
+        let before = &source[..offset.min(source.len())];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// What a press did, to be undone by its release
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Held {
+    /// Outputs were pressed; these events release them
+    Outputs(Vec<Event>),
+    Layer(String),
+    /// The press was consumed, as by a macro
+    Swallowed,
+}
+
+/// A dual-role switch that is down but not yet known to be a tap or a hold
+#[derive(Debug, Clone)]
+struct Pending {
+    device: usize,
+    rule: DualRoleRule,
+    deadline: Instant,
+}
+
+/// Applies a [`RuleSet`]
+#[derive(Debug, Clone, Default)]
+pub struct Rules {
+    rules: RuleSet,
+    hardware_ids: HashMap<usize, String>,
+    held: HashMap<(usize, Switch), Held>,
+    /// Active layers, most recently activated last
+    layers: Vec<String>,
+    pending: Option<Pending>,
+    active: ActiveDevices,
+}
+
+impl Rules {
+    pub fn new(rules: RuleSet) -> Self {
+        Self {
+            rules,
+            ..Self::default()
+        }
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    /// Apply different rules from now on
+    ///
+    /// Switches that are down stay as they are: releasing one undoes what its press did under
+    /// the old rules, including deactivating its layer.
+    pub fn replace(&mut self, rules: RuleSet) {
+        self.rules = rules;
+    }
+
+    /// Names of the active layers, most recently activated last
+    pub fn active_layers(&self) -> &[String] {
+        &self.layers
+    }
+
+    /// Whether a rule limited to `devices` applies to `device`
+    fn applies(&self, devices: &[String], device: usize) -> bool {
+        if devices.is_empty() {
+            return true;
+        }
+        let Some(hardware_id) = self.hardware_ids.get(&device) else {
+            return false;
+        };
+        self.rules
+            .devices
+            .iter()
+            .filter(|selector| devices.contains(&selector.name))
+            .any(|selector| selector.matches(hardware_id))
+    }
+
+    /// Press `outputs` for an input on `device`, returning the events releasing them
+    fn press(&self, device: usize, outputs: &[Switch], out: &mut Vec<Event>) -> Vec<Event> {
+        let mut releases = Vec::with_capacity(outputs.len());
+        for &output in outputs {
+            let target = self.active.resolve(output, device, Target::Auto);
+            out.push(Event::new(target, output.press()));
+            releases.push(Event::new(target, output.release()));
+        }
+        releases.reverse();
+        releases
+    }
+
+    /// Decide that the pending dual-role switch is held
+    fn resolve_hold(&mut self, out: &mut Vec<Event>) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let held = match pending.rule.hold {
+            HoldAction::Press(outputs) => Held::Outputs(self.press(pending.device, &outputs, out)),
+            HoldAction::Layer(layer) => {
+                self.layers.push(layer.clone());
+                Held::Layer(layer)
+            }
+        };
+        self.held
+            .insert((pending.device, pending.rule.switch), held);
+    }
+
+    /// Handle one transition of a switch; returns false if it passes through
+    fn transition(
+        &mut self,
+        device: usize,
+        switch: Switch,
+        down: bool,
+        now: Instant,
+        out: &mut Vec<Event>,
+    ) -> bool {
+        let is_pending =
+            |pending: &Pending| pending.device == device && pending.rule.switch == switch;
+        if !down {
+            if let Some(pending) = self.pending.take_if(|pending| is_pending(pending)) {
+                let releases = self.press(device, &pending.rule.tap, out);
+                out.extend(releases);
+                return true;
+            }
+            return match self.held.remove(&(device, switch)) {
+                Some(Held::Outputs(releases)) => {
+                    out.extend(releases);
+                    true
+                }
+                Some(Held::Layer(layer)) => {
+                    if let Some(index) = self.layers.iter().rposition(|active| *active == layer) {
+                        self.layers.remove(index);
+                    }
+                    true
+                }
+                Some(Held::Swallowed) => true,
+                None => false,
+            };
+        }
+
+        if self.pending.as_ref().is_some_and(is_pending) {
+            return true;
+        }
+        self.resolve_hold(out);
+
+        if let Some(held) = self.held.get(&(device, switch)) {
+            // Autorepeat: repeat the last key output, drop anything else
+            if let Held::Outputs(releases) = held
+                && let Some(Event {
+                    device,
+                    stroke: Stroke::Key(release),
+                }) = releases.first()
+            {
+                out.push(Event::new(*device, release.key().down()));
+            }
+            return true;
+        }
+
+        let held = if let Some(outputs) = self
+            .layers
+            .iter()
+            .rev()
+            .filter_map(|name| self.rules.layers.iter().find(|layer| layer.name == *name))
+            .find_map(|layer| layer.mappings.get(&switch))
+        {
+            Held::Outputs(self.press(device, outputs, out))
+        } else if let Some(layer) = self
+            .rules
+            .layers
+            .iter()
+            .find(|layer| layer.activate == Some(switch))
+        {
+            self.layers.push(layer.name.clone());
+            Held::Layer(layer.name.clone())
+        } else if let Some(rule) = self
+            .rules
+            .dual_roles
+            .iter()
+            .find(|rule| rule.switch == switch && self.applies(&rule.devices, device))
+        {
+            self.pending = Some(Pending {
+                device,
+                rule: rule.clone(),
+                deadline: now + rule.timeout,
+            });
+            return true;
+        } else if let Some(rule) = self
+            .rules
+            .macros
+            .iter()
+            .find(|rule| rule.trigger == switch && self.applies(&rule.devices, device))
+        {
+            let keyboard = if is_keyboard(device) {
+                device
+            } else {
+                self.active.keyboard
+            };
+            out.extend(
+                rule.strokes
+                    .iter()
+                    .map(|stroke| Event::new(keyboard, *stroke)),
+            );
+            Held::Swallowed
+        } else if let Some(rule) = self
+            .rules
+            .remaps
+            .iter()
+            .find(|rule| rule.from == switch && self.applies(&rule.devices, device))
+        {
+            Held::Outputs(self.press(device, &rule.to, out))
+        } else {
+            return false;
+        };
+        self.held.insert((device, switch), held);
+        true
+    }
+}
+
+impl Processor for Rules {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        self.active.observe(event.device);
+
+        match event.stroke {
+            Stroke::Key(stroke) => {
+                let switch = Switch::Key(stroke.key());
+                if !self.transition(event.device, switch, stroke.is_down(), now, out) {
+                    out.push(event);
+                }
+            }
+            Stroke::Mouse(stroke) => {
+                let mut remaining = stroke;
+                let mut handled = Vec::new();
+                for button in MouseButton::ALL {
+                    for (flag, down) in [(button.down_flag(), true), (button.up_flag(), false)] {
+                        if stroke.state & flag != 0
+                            && self.transition(
+                                event.device,
+                                Switch::Button(button),
+                                down,
+                                now,
+                                &mut handled,
+                            )
+                        {
+                            remaining.state &= !flag;
+                        }
+                    }
+                }
+
+                if remaining == stroke || !remaining.is_empty() {
+                    out.push(Event::new(event.device, remaining));
+                }
+                out.extend(handled);
+            }
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|pending| pending.deadline)
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        if self.deadline().is_some_and(|deadline| deadline <= now) {
+            self.resolve_hold(out);
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.hardware_ids.insert(device, hardware_id.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Key, MAX_KEYBOARD};
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn key(device: usize, stroke: KeyStroke) -> Event {
+        Event::new(device, stroke)
+    }
+
+    fn feed(rules: &mut Rules, start: Instant, events: &[(u64, Event)]) -> Vec<Event> {
+        let mut out = Vec::new();
+        for &(at, event) in events {
+            let now = start + ms(at);
+            if rules.deadline().is_some_and(|deadline| deadline <= now) {
+                rules.tick(now, &mut out);
+            }
+            rules.process(event, now, &mut out);
+        }
+        out
+    }
+
+    fn nav_layer() -> Layer {
+        Layer {
+            name: "nav".to_string(),
+            activate: Some(Key::RIGHT_ALT.into()),
+            mappings: HashMap::from([
+                (Key::H.into(), vec![Key::ARROW_LEFT.into()]),
+                (Key::Q.into(), vec![]),
+            ]),
+        }
+    }
+
+    fn dual_role(hold: HoldAction) -> DualRoleRule {
+        DualRoleRule {
+            switch: Key::CAPS_LOCK.into(),
+            tap: vec![Key::ESCAPE.into()],
+            hold,
+            timeout: ms(200),
+            devices: vec![],
+        }
+    }
+
+    #[test]
+    fn test_remap_scoped_to_devices() {
+        let mut rules = Rules::new(RuleSet {
+            devices: vec![DeviceSelector::new("laptop", ["acpi\\pnp0303"])],
+            remaps: vec![RemapRule {
+                from: Key::CAPS_LOCK.into(),
+                to: vec![Key::LEFT_CTRL.into()],
+                devices: vec!["laptop".to_string()],
+            }],
+            ..RuleSet::default()
+        });
+        rules.attach(0, "ACPI\\PNP0303");
+        rules.attach(1, "HID\\VID_046D&PID_C52B");
+
+        let out = feed(
+            &mut rules,
+            Instant::now(),
+            &[
+                (0, key(0, Key::CAPS_LOCK.down())),
+                (0, key(0, Key::CAPS_LOCK.down())),
+                (0, key(0, Key::CAPS_LOCK.up())),
+                (0, key(1, Key::CAPS_LOCK.down())),
+            ],
+        );
+        assert_eq!(
+            out,
+            [
+                key(0, Key::LEFT_CTRL.down()),
+                key(0, Key::LEFT_CTRL.down()),
+                key(0, Key::LEFT_CTRL.up()),
+                key(1, Key::CAPS_LOCK.down()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dual_role_tap_and_hold() {
+        let hold = HoldAction::Press(vec![Key::LEFT_CTRL.into()]);
+        let mut rules = Rules::new(RuleSet {
+            dual_roles: vec![dual_role(hold)],
+            ..RuleSet::default()
+        });
+        let start = Instant::now();
+
+        // Tapped
+        let out = feed(
+            &mut rules,
+            start,
+            &[
+                (0, key(0, Key::CAPS_LOCK.down())),
+                (50, key(0, Key::CAPS_LOCK.down())),
+                (100, key(0, Key::CAPS_LOCK.up())),
+            ],
+        );
+        assert_eq!(out, [key(0, Key::ESCAPE.down()), key(0, Key::ESCAPE.up())]);
+
+        // Held past the timeout
+        let out = feed(
+            &mut rules,
+            start,
+            &[
+                (200, key(0, Key::CAPS_LOCK.down())),
+                (450, key(0, Key::C.down())),
+                (460, key(0, Key::C.up())),
+                (470, key(0, Key::CAPS_LOCK.up())),
+            ],
+        );
+        assert_eq!(
+            out,
+            [
+                key(0, Key::LEFT_CTRL.down()),
+                key(0, Key::C.down()),
+                key(0, Key::C.up()),
+                key(0, Key::LEFT_CTRL.up()),
+            ]
+        );
+
+        // Interrupted by another key before the timeout
+        let out = feed(
+            &mut rules,
+            start,
+            &[
+                (500, key(0, Key::CAPS_LOCK.down())),
+                (510, key(0, Key::C.down())),
+                (520, key(0, Key::CAPS_LOCK.up())),
+                (530, key(0, Key::C.up())),
+            ],
+        );
+        assert_eq!(
+            out,
+            [
+                key(0, Key::LEFT_CTRL.down()),
+                key(0, Key::C.down()),
+                key(0, Key::LEFT_CTRL.up()),
+                key(0, Key::C.up()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut rules = Rules::new(RuleSet {
+            dual_roles: vec![dual_role(HoldAction::Layer("nav".to_string()))],
+            layers: vec![nav_layer()],
+            ..RuleSet::default()
+        });
+        let start = Instant::now();
+
+        let out = feed(
+            &mut rules,
+            start,
+            &[
+                (0, key(0, Key::RIGHT_ALT.down())),
+                (10, key(0, Key::H.down())),
+                (20, key(0, Key::Q.down())),
+                (30, key(0, Key::RIGHT_ALT.up())),
+                // Released after the layer is gone, still as the layer's output
+                (40, key(0, Key::H.up())),
+                (50, key(0, Key::H.down())),
+            ],
+        );
+        assert_eq!(
+            out,
+            [
+                key(0, Key::ARROW_LEFT.down()),
+                key(0, Key::ARROW_LEFT.up()),
+                key(0, Key::H.down()),
+            ]
+        );
+        assert!(rules.active_layers().is_empty());
+
+        // The dual-role key activates the layer once held
+        let out = feed(
+            &mut rules,
+            start,
+            &[
+                (100, key(0, Key::CAPS_LOCK.down())),
+                (110, key(0, Key::H.down())),
+                (120, key(0, Key::H.up())),
+            ],
+        );
+        assert_eq!(
+            out,
+            [key(0, Key::ARROW_LEFT.down()), key(0, Key::ARROW_LEFT.up())]
+        );
+        assert_eq!(rules.active_layers(), ["nav"]);
+    }
+
+    #[test]
+    fn test_macro_on_mouse_button() {
+        let mut rules = Rules::new(RuleSet {
+            macros: vec![MacroRule {
+                trigger: MouseButton::Button4.into(),
+                strokes: Key::A.tap().to_vec(),
+                devices: vec![],
+            }],
+            ..RuleSet::default()
+        });
+        let out = feed(
+            &mut rules,
+            Instant::now(),
+            &[
+                (0, key(3, Key::B.down())),
+                (0, Event::new(MOUSE, MouseButton::Button4.down())),
+                (0, Event::new(MOUSE, MouseButton::Button4.up())),
+            ],
+        );
+        assert_eq!(
+            out,
+            [
+                key(3, Key::B.down()),
+                key(3, Key::A.down()),
+                key(3, Key::A.up()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replace_keeps_pressed_switches() {
+        let remap = |to: Key| RuleSet {
+            remaps: vec![RemapRule {
+                from: Key::CAPS_LOCK.into(),
+                to: vec![to.into()],
+                devices: vec![],
+            }],
+            ..RuleSet::default()
+        };
+        let mut rules = Rules::new(remap(Key::LEFT_CTRL));
+        let start = Instant::now();
+
+        let mut out = feed(&mut rules, start, &[(0, key(0, Key::CAPS_LOCK.down()))]);
+        rules.replace(remap(Key::ESCAPE));
+        out.extend(feed(
+            &mut rules,
+            start,
+            &[
+                (10, key(0, Key::CAPS_LOCK.up())),
+                (20, key(0, Key::CAPS_LOCK.down())),
+            ],
+        ));
+        assert_eq!(
+            out,
+            [
+                key(0, Key::LEFT_CTRL.down()),
+                key(0, Key::LEFT_CTRL.up()),
+                key(0, Key::ESCAPE.down()),
+            ]
+        );
+    }
+}
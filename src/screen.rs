@@ -0,0 +1,255 @@
+//! Screen geometry for absolute pointer movement.
+//!
+//! Absolute mouse strokes don't carry pixels. With [`MOUSE_MOVE_ABSOLUTE`] alone, X and Y are
+//! normalized to `0..=65535` across the primary monitor; with [`MOUSE_VIRTUAL_DESKTOP`] as well
+//! they span the bounding box of all monitors instead. [`Screen`] holds the monitor layout,
+//! supplied by the caller, and converts between the two.
+//!
+//! Pixel coordinates follow Windows: the primary monitor's top-left corner is the origin and
+//! monitors to its left or above have negative coordinates. Windows maps a normalized
+//! coordinate `n` on an axis `size` pixels long to pixel `n * size / 65536`, rounding down;
+//! [`Screen::to_normalized`] picks the smallest `n` that lands on the requested pixel, so a
+//! round trip always gives the pixel back.
+
+use crate::{MOUSE_MOVE_ABSOLUTE, MOUSE_VIRTUAL_DESKTOP, MouseStroke};
+
+/// The largest normalized coordinate
+pub const ABSOLUTE_MAX: i32 = 0xFFFF;
+
+/// A rectangle in pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub const fn new(left: i32, top: i32, width: i32, height: i32) -> Self {
+        Self {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+
+    /// One past the rightmost column
+    pub const fn right(&self) -> i32 {
+        self.left + self.width
+    }
+
+    /// One past the bottom row
+    pub const fn bottom(&self) -> i32 {
+        self.top + self.height
+    }
+
+    pub const fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.left && x < self.right() && y >= self.top && y < self.bottom()
+    }
+
+    /// The smallest rectangle containing both
+    pub fn union(&self, other: &Rect) -> Rect {
+        let left = self.left.min(other.left);
+        let top = self.top.min(other.top);
+        Rect::new(
+            left,
+            top,
+            self.right().max(other.right()) - left,
+            self.bottom().max(other.bottom()) - top,
+        )
+    }
+
+    /// The nearest pixel inside the rectangle
+    pub fn clamp(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            x.clamp(self.left, self.right() - 1),
+            y.clamp(self.top, self.bottom() - 1),
+        )
+    }
+
+    /// Pixel to normalized coordinates relative to this rectangle, clamping to its edges
+    pub fn to_normalized(&self, x: i32, y: i32) -> (i32, i32) {
+        let (x, y) = self.clamp(x, y);
+        (
+            normalize(x - self.left, self.width),
+            normalize(y - self.top, self.height),
+        )
+    }
+
+    /// Normalized coordinates relative to this rectangle to a pixel
+    pub fn to_pixel(&self, x: i32, y: i32) -> (i32, i32) {
+        (
+            self.left + denormalize(x, self.width),
+            self.top + denormalize(y, self.height),
+        )
+    }
+}
+
+fn normalize(offset: i32, size: i32) -> i32 {
+    let (offset, size) = (i64::from(offset), i64::from(size.max(1)));
+    ((offset * 65536 + size - 1) / size).min(ABSOLUTE_MAX.into()) as i32
+}
+
+fn denormalize(normalized: i32, size: i32) -> i32 {
+    let normalized = i64::from(normalized.clamp(0, ABSOLUTE_MAX));
+    ((normalized * i64::from(size)) >> 16) as i32
+}
+
+/// The monitor layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    monitors: Vec<Rect>,
+    primary: usize,
+}
+
+impl Screen {
+    /// A layout of one or more monitors; the one at the origin is the primary, or the first
+    /// if none is
+    ///
+    /// # Panics
+    ///
+    /// If `monitors` is empty.
+    pub fn new(monitors: impl IntoIterator<Item = Rect>) -> Self {
+        let monitors: Vec<_> = monitors.into_iter().collect();
+        assert!(!monitors.is_empty(), "a screen needs at least one monitor");
+        let primary = monitors
+            .iter()
+            .position(|m| m.left == 0 && m.top == 0)
+            .unwrap_or(0);
+        Self { monitors, primary }
+    }
+
+    /// A single monitor of the given size
+    pub fn single(width: i32, height: i32) -> Self {
+        Self::new([Rect::new(0, 0, width, height)])
+    }
+
+    pub fn monitors(&self) -> &[Rect] {
+        &self.monitors
+    }
+
+    pub fn primary(&self) -> Rect {
+        self.monitors[self.primary]
+    }
+
+    /// The bounding box of all monitors
+    pub fn virtual_desktop(&self) -> Rect {
+        self.monitors
+            .iter()
+            .skip(1)
+            .fold(self.monitors[0], |bounds, monitor| bounds.union(monitor))
+    }
+
+    /// The monitor showing a pixel
+    pub fn monitor_at(&self, x: i32, y: i32) -> Option<Rect> {
+        self.monitors.iter().copied().find(|m| m.contains(x, y))
+    }
+
+    /// Pixel to normalized coordinates, across all monitors if `virtual_desktop` is set and
+    /// across the primary monitor otherwise
+    pub fn to_normalized(&self, x: i32, y: i32, virtual_desktop: bool) -> (i32, i32) {
+        self.area(virtual_desktop).to_normalized(x, y)
+    }
+
+    /// Normalized coordinates to a pixel, the inverse of [`Screen::to_normalized`]
+    pub fn to_pixel(&self, x: i32, y: i32, virtual_desktop: bool) -> (i32, i32) {
+        self.area(virtual_desktop).to_pixel(x, y)
+    }
+
+    fn area(&self, virtual_desktop: bool) -> Rect {
+        if virtual_desktop {
+            self.virtual_desktop()
+        } else {
+            self.primary()
+        }
+    }
+}
+
+impl MouseStroke {
+    /// A stroke placing the pointer on a pixel of any monitor
+    pub fn absolute_to_pixel(screen: &Screen, x: i32, y: i32) -> Self {
+        let (x, y) = screen.to_normalized(x, y, true);
+        MouseStroke::new(MOUSE_MOVE_ABSOLUTE | MOUSE_VIRTUAL_DESKTOP, 0, 0, x, y, 0)
+    }
+
+    /// A stroke placing the pointer on a pixel of the primary monitor
+    pub fn absolute_to_primary_pixel(screen: &Screen, x: i32, y: i32) -> Self {
+        let (x, y) = screen.to_normalized(x, y, false);
+        MouseStroke::new(MOUSE_MOVE_ABSOLUTE, 0, 0, x, y, 0)
+    }
+
+    /// The pixel an absolute stroke places the pointer on; `None` for relative strokes
+    pub fn pixel(&self, screen: &Screen) -> Option<(i32, i32)> {
+        (self.flags & MOUSE_MOVE_ABSOLUTE != 0)
+            .then(|| screen.to_pixel(self.x, self.y, self.flags & MOUSE_VIRTUAL_DESKTOP != 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1920x1080 primary with a 1280x1024 monitor to its left, lower by 200 pixels
+    fn dual() -> Screen {
+        Screen::new([
+            Rect::new(-1280, 200, 1280, 1024),
+            Rect::new(0, 0, 1920, 1080),
+        ])
+    }
+
+    #[test]
+    fn test_virtual_desktop_bounds() {
+        let screen = dual();
+        assert_eq!(screen.primary(), Rect::new(0, 0, 1920, 1080));
+        assert_eq!(screen.virtual_desktop(), Rect::new(-1280, 0, 3200, 1224));
+        assert_eq!(screen.monitor_at(-1, 300), Some(screen.monitors()[0]));
+        assert_eq!(screen.monitor_at(-1, 100), None);
+    }
+
+    #[test]
+    fn test_round_trip_every_pixel() {
+        let screen = dual();
+        let desktop = screen.virtual_desktop();
+        for x in desktop.left..desktop.right() {
+            let (nx, _) = screen.to_normalized(x, 0, true);
+            assert_eq!(screen.to_pixel(nx, 0, true).0, x);
+        }
+        for y in 0..1080 {
+            let (_, ny) = screen.to_normalized(0, y, false);
+            assert_eq!(screen.to_pixel(0, ny, false).1, y);
+        }
+    }
+
+    #[test]
+    fn test_normalized_corners() {
+        let screen = dual();
+        assert_eq!(screen.to_normalized(-1280, 0, true), (0, 0));
+        assert_eq!(screen.to_normalized(0, 0, false), (0, 0));
+        assert_eq!(screen.to_normalized(0, 0, true), (26215, 0));
+        // Off-screen pixels clamp to the edge
+        assert_eq!(
+            screen.to_pixel(ABSOLUTE_MAX, ABSOLUTE_MAX, true),
+            (1919, 1223)
+        );
+        assert_eq!(
+            screen.to_normalized(5000, -50, true),
+            screen.to_normalized(1919, 0, true)
+        );
+    }
+
+    #[test]
+    fn test_stroke_constructors() {
+        let screen = dual();
+        let stroke = MouseStroke::absolute_to_pixel(&screen, -640, 712);
+        assert_eq!(stroke.flags, MOUSE_MOVE_ABSOLUTE | MOUSE_VIRTUAL_DESKTOP);
+        assert_eq!(stroke.pixel(&screen), Some((-640, 712)));
+
+        let stroke = MouseStroke::absolute_to_primary_pixel(&screen, 960, 540);
+        assert_eq!((stroke.x, stroke.y), (32768, 32768));
+        assert_eq!(stroke.pixel(&screen), Some((960, 540)));
+
+        assert_eq!(MouseStroke::default().pixel(&screen), None);
+    }
+}
@@ -0,0 +1,808 @@
+//! A line-oriented language for writing and editing macros.
+//!
+//! ```text
+//! # Copy the selection and paste it twice, lower down
+//! down LCtrl
+//! tap C
+//! up LCtrl
+//! wait 40ms
+//! move rel 10 -5
+//! click left
+//! repeat 2 {
+//!     tap V
+//! }
+//! wheel -120
+//! type "done\n"
+//! ```
+//!
+//! Each line holds one command, and `#` starts a comment that runs to the end of the line.
+//! Keys go by the names of [`Key::from_name`] and buttons by those of
+//! [`MouseButton::from_name`]. The commands are:
+//!
+//! | Command | Effect |
+//! |---|---|
+//! | `down KEY`, `up KEY`, `tap KEY` | press, release, or press and release a key |
+//! | `press BUTTON`, `release BUTTON`, `click BUTTON` | the same for a mouse button |
+//! | `wait 40ms`, `wait 2s` | pause |
+//! | `move rel X Y` | move the pointer by X and Y counts |
+//! | `move abs X Y` | place the pointer at normalized coordinates across all monitors |
+//! | `wheel N`, `hwheel N` | turn the wheel by N, 120 per notch |
+//! | `repeat N { ... }` | run the enclosed commands N times; `}` goes on its own line |
+//! | `type "text"` | type text on a US QWERTY layout; `\"`, `\\`, `\n` and `\t` are escapes |
+//!
+//! [`Script`] parses with errors pointing at a line and column, prints back to the same text
+//! modulo comments and spacing, and compiles to a [`Recording`] that a
+//! [`Player`](crate::record::Player) can play, as long as its repeats unroll to no more than
+//! [`MAX_COMPILED`] steps.
+
+use crate::record::{Recorded, Recording};
+use crate::text::{Chord, UsQwerty};
+use crate::{
+    Event, Key, MOUSE_HWHEEL, MOUSE_MOVE_ABSOLUTE, MOUSE_MOVE_RELATIVE, MOUSE_VIRTUAL_DESKTOP,
+    MOUSE_WHEEL, MouseButton, MouseStroke, Stroke,
+};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// One command of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Down(Key),
+    Up(Key),
+    Tap(Key),
+    Press(MouseButton),
+    Release(MouseButton),
+    Click(MouseButton),
+    Wait(Duration),
+    MoveRelative(i32, i32),
+    MoveAbsolute(i32, i32),
+    Wheel(i16),
+    HWheel(i16),
+    Repeat(u32, Vec<Command>),
+    Type(String),
+}
+
+/// A parsed macro
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    pub commands: Vec<Command>,
+}
+
+/// Where and why a script failed to parse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column, in characters
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// How many steps a script may compile to
+///
+/// Every stroke is a step, and so is every other command, so that a repeat of waits is bounded
+/// too. Nested repeats multiply: `repeat 1000 { repeat 1000 { tap A } }` takes two million.
+pub const MAX_COMPILED: usize = 1_000_000;
+
+/// A script whose repeats unroll to more than [`MAX_COMPILED`] steps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooLong {
+    /// The steps the script would take, saturated at `usize::MAX`
+    pub steps: usize,
+}
+
+impl Display for TooLong {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "script compiles to {} steps, more than the {MAX_COMPILED} allowed",
+            self.steps
+        )
+    }
+}
+
+impl Error for TooLong {}
+
+/// A word or quoted string on a line
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    column: usize,
+    quoted: bool,
+}
+
+fn tokenize(line: &str, line_no: usize) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().enumerate().peekable();
+    while let Some(&(index, c)) = chars.peek() {
+        let column = index + 1;
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    None => {
+                        return Err(ParseError::new(line_no, column, "unterminated string"));
+                    }
+                    Some((_, '"')) => break,
+                    Some((escape, '\\')) => match chars.next() {
+                        Some((_, '"')) => text.push('"'),
+                        Some((_, '\\')) => text.push('\\'),
+                        Some((_, 'n')) => text.push('\n'),
+                        Some((_, 't')) => text.push('\t'),
+                        _ => {
+                            return Err(ParseError::new(line_no, escape + 1, "unknown escape"));
+                        }
+                    },
+                    Some((_, c)) => text.push(c),
+                }
+            }
+            tokens.push(Token {
+                text,
+                column,
+                quoted: true,
+            });
+        } else {
+            let mut text = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_whitespace() || c == '"' || c == '#' {
+                    break;
+                }
+                text.push(c);
+                chars.next();
+            }
+            tokens.push(Token {
+                text,
+                column,
+                quoted: false,
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses lines into nested blocks
+struct Parser {
+    lines: Vec<(usize, Vec<Token>)>,
+    next: usize,
+}
+
+impl Parser {
+    /// Parse commands until the end of the input, or of the block opened at `open`
+    fn block(&mut self, open: Option<(usize, usize)>) -> Result<Vec<Command>, ParseError> {
+        let mut commands = Vec::new();
+        while let Some((line, tokens)) = self.lines.get(self.next).cloned() {
+            self.next += 1;
+            if tokens[0].text == "}" && !tokens[0].quoted {
+                if open.is_none() {
+                    return Err(ParseError::new(line, tokens[0].column, "unmatched `}`"));
+                }
+                if let Some(extra) = tokens.get(1) {
+                    return Err(ParseError::new(line, extra.column, "expected end of line"));
+                }
+                return Ok(commands);
+            }
+            commands.push(self.command(line, &tokens)?);
+        }
+        match open {
+            Some((line, column)) => Err(ParseError::new(line, column, "unclosed `{`")),
+            None => Ok(commands),
+        }
+    }
+
+    fn command(&mut self, line: usize, tokens: &[Token]) -> Result<Command, ParseError> {
+        let args = Args { line, tokens };
+        let name = &tokens[0];
+        let command = match name.text.as_str() {
+            "down" => Command::Down(args.key(1)?),
+            "up" => Command::Up(args.key(1)?),
+            "tap" => Command::Tap(args.key(1)?),
+            "press" => Command::Press(args.button(1)?),
+            "release" => Command::Release(args.button(1)?),
+            "click" => Command::Click(args.button(1)?),
+            "wait" => Command::Wait(args.duration(1)?),
+            "wheel" => Command::Wheel(args.number(1)?),
+            "hwheel" => Command::HWheel(args.number(1)?),
+            "move" => {
+                let mode = args.word(1, "`rel` or `abs`")?;
+                let (x, y) = (args.number(2)?, args.number(3)?);
+                args.end(4)?;
+                return match mode.text.as_str() {
+                    "rel" => Ok(Command::MoveRelative(x, y)),
+                    "abs" => Ok(Command::MoveAbsolute(x, y)),
+                    _ => Err(args.error(1, "expected `rel` or `abs`")),
+                };
+            }
+            "repeat" => {
+                let count = args.number(1)?;
+                let brace = args.word(2, "`{`")?;
+                if brace.text != "{" {
+                    return Err(args.error(2, "expected `{`"));
+                }
+                args.end(3)?;
+                let body = self.block(Some((line, brace.column)))?;
+                return Ok(Command::Repeat(count, body));
+            }
+            "type" => {
+                let text = args.get(1, "a quoted string")?;
+                if !text.quoted {
+                    return Err(args.error(1, "expected a quoted string"));
+                }
+                // The column of each character, skipping the opening quote
+                if let Some((offset, c)) = text
+                    .text
+                    .chars()
+                    .enumerate()
+                    .find(|(_, c)| UsQwerty::chord(*c).is_none())
+                {
+                    return Err(ParseError::new(
+                        line,
+                        text.column + 1 + offset,
+                        format!("cannot type {c:?}"),
+                    ));
+                }
+                Command::Type(text.text.clone())
+            }
+            _ => {
+                return Err(ParseError::new(
+                    line,
+                    name.column,
+                    format!("unknown command `{}`", name.text),
+                ));
+            }
+        };
+        args.end(2)?;
+        Ok(command)
+    }
+}
+
+/// The tokens of one command
+struct Args<'a> {
+    line: usize,
+    tokens: &'a [Token],
+}
+
+impl Args<'_> {
+    /// An error at the token at `index`, or just past the end of the line
+    fn error(&self, index: usize, message: impl Into<String>) -> ParseError {
+        let column = match self.tokens.get(index) {
+            Some(token) => token.column,
+            None => {
+                let last = self.tokens.last().expect("commands have a name");
+                last.column + last.text.chars().count() + if last.quoted { 2 } else { 0 }
+            }
+        };
+        ParseError::new(self.line, column, message)
+    }
+
+    fn get(&self, index: usize, expected: &str) -> Result<&Token, ParseError> {
+        self.tokens
+            .get(index)
+            .ok_or_else(|| self.error(index, format!("expected {expected}")))
+    }
+
+    fn word(&self, index: usize, expected: &str) -> Result<&Token, ParseError> {
+        let token = self.get(index, expected)?;
+        if token.quoted {
+            return Err(self.error(index, format!("expected {expected}")));
+        }
+        Ok(token)
+    }
+
+    fn end(&self, index: usize) -> Result<(), ParseError> {
+        match self.tokens.get(index) {
+            Some(_) => Err(self.error(index, "expected end of line")),
+            None => Ok(()),
+        }
+    }
+
+    fn key(&self, index: usize) -> Result<Key, ParseError> {
+        let token = self.word(index, "a key")?;
+        Key::from_name(&token.text)
+            .ok_or_else(|| self.error(index, format!("unknown key `{}`", token.text)))
+    }
+
+    fn button(&self, index: usize) -> Result<MouseButton, ParseError> {
+        let token = self.word(index, "a mouse button")?;
+        MouseButton::from_name(&token.text)
+            .ok_or_else(|| self.error(index, format!("unknown mouse button `{}`", token.text)))
+    }
+
+    fn number<T: FromStr>(&self, index: usize) -> Result<T, ParseError> {
+        let token = self.word(index, "a number")?;
+        token
+            .text
+            .parse()
+            .map_err(|_| self.error(index, format!("invalid number `{}`", token.text)))
+    }
+
+    fn duration(&self, index: usize) -> Result<Duration, ParseError> {
+        let token = self.word(index, "a duration")?;
+        let text = &token.text;
+        let parsed = if let Some(millis) = text.strip_suffix("ms") {
+            millis.parse().ok().map(Duration::from_millis)
+        } else if let Some(secs) = text.strip_suffix('s') {
+            secs.parse().ok().map(Duration::from_secs)
+        } else {
+            None
+        };
+        parsed.ok_or_else(|| {
+            self.error(
+                index,
+                format!("invalid duration `{text}`; expected e.g. `40ms` or `2s`"),
+            )
+        })
+    }
+}
+
+impl FromStr for Script {
+    type Err = ParseError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut lines = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let tokens = tokenize(line, index + 1)?;
+            if !tokens.is_empty() {
+                lines.push((index + 1, tokens));
+            }
+        }
+        let mut parser = Parser { lines, next: 0 };
+        Ok(Script {
+            commands: parser.block(None)?,
+        })
+    }
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        source.parse()
+    }
+
+    /// The strokes of this script, keys going to `keyboard` and mouse strokes to `mouse`
+    pub fn compile(&self, keyboard: usize, mouse: usize) -> Result<Recording, TooLong> {
+        let steps = steps(&self.commands);
+        if steps > MAX_COMPILED {
+            return Err(TooLong { steps });
+        }
+        let mut compiler = Compiler {
+            keyboard,
+            mouse,
+            at: Duration::ZERO,
+            events: Vec::new(),
+        };
+        compiler.commands(&self.commands);
+        Ok(Recording {
+            duration: compiler.at,
+            events: compiler.events,
+            ..Recording::default()
+        })
+    }
+
+    /// A script reproducing a recording, with waits for the time between events
+    ///
+    /// Event times are rounded to whole milliseconds, which is all a script can express, before
+    /// the waits are taken between them, so rounding never adds up. Device indices are not
+    /// kept; [`Script::compile`] sends everything to one keyboard and one mouse. A down
+    /// immediately followed by the matching up becomes a tap or a click.
+    pub fn from_recording(recording: &Recording) -> Self {
+        let round = |at: Duration| Duration::from_millis(((at.as_micros() + 500) / 1000) as u64);
+        let mut commands = Vec::new();
+        let mut at = Duration::ZERO;
+        let mut events = recording.events.iter().peekable();
+        while let Some(recorded) = events.next() {
+            if round(recorded.at) > at {
+                commands.push(Command::Wait(round(recorded.at) - at));
+                at = round(recorded.at);
+            }
+            let next = events.peek().filter(|next| next.at == recorded.at);
+            match recorded.event.stroke {
+                Stroke::Key(stroke) => {
+                    let key = stroke.key();
+                    if stroke.is_up() {
+                        commands.push(Command::Up(key));
+                    } else if next.is_some_and(|n| n.event.key() == Some(&key.up())) {
+                        events.next();
+                        commands.push(Command::Tap(key));
+                    } else {
+                        commands.push(Command::Down(key));
+                    }
+                }
+                Stroke::Mouse(stroke) => {
+                    let next = next.and_then(|n| n.event.mouse().copied());
+                    if mouse_commands(stroke, next, &mut commands) {
+                        events.next();
+                    }
+                }
+            }
+        }
+        if round(recording.duration) > at {
+            commands.push(Command::Wait(round(recording.duration) - at));
+        }
+        Script { commands }
+    }
+}
+
+/// The commands for one mouse stroke; returns true if `next` was merged into a click
+fn mouse_commands(
+    stroke: MouseStroke,
+    next: Option<MouseStroke>,
+    commands: &mut Vec<Command>,
+) -> bool {
+    if stroke.flags & MOUSE_MOVE_ABSOLUTE != 0 {
+        commands.push(Command::MoveAbsolute(stroke.x, stroke.y));
+    } else if stroke.x != 0 || stroke.y != 0 {
+        commands.push(Command::MoveRelative(stroke.x, stroke.y));
+    }
+    if stroke.state & MOUSE_WHEEL != 0 {
+        commands.push(Command::Wheel(stroke.rolling));
+    }
+    if stroke.state & MOUSE_HWHEEL != 0 {
+        commands.push(Command::HWheel(stroke.rolling));
+    }
+
+    let mut merged = false;
+    for button in MouseButton::ALL {
+        if stroke.is_down(button) {
+            if next.is_some_and(|n| n == button.up()) && stroke.state == button.down_flag() {
+                merged = true;
+                commands.push(Command::Click(button));
+            } else {
+                commands.push(Command::Press(button));
+            }
+        }
+        if stroke.is_up(button) {
+            commands.push(Command::Release(button));
+        }
+    }
+    merged
+}
+
+/// The steps `commands` compile to; see [`MAX_COMPILED`]
+fn steps(commands: &[Command]) -> usize {
+    commands
+        .iter()
+        .map(|command| match command {
+            Command::Tap(_) | Command::Click(_) => 2,
+            Command::Repeat(count, body) => (*count as usize).saturating_mul(steps(body).max(1)),
+            Command::Type(text) => text
+                .chars()
+                .filter_map(UsQwerty::chord)
+                .map(|chord| chord.strokes().len())
+                .sum::<usize>()
+                .max(1),
+            _ => 1,
+        })
+        .fold(0, usize::saturating_add)
+}
+
+/// Accumulates timed events
+struct Compiler {
+    keyboard: usize,
+    mouse: usize,
+    at: Duration,
+    events: Vec<Recorded>,
+}
+
+impl Compiler {
+    fn push(&mut self, device: usize, stroke: impl Into<Stroke>) {
+        self.events.push(Recorded {
+            at: self.at,
+            event: Event::new(device, stroke),
+        });
+    }
+
+    fn commands(&mut self, commands: &[Command]) {
+        for command in commands {
+            self.command(command);
+        }
+    }
+
+    fn command(&mut self, command: &Command) {
+        let (keyboard, mouse) = (self.keyboard, self.mouse);
+        match command {
+            Command::Down(key) => self.push(keyboard, key.down()),
+            Command::Up(key) => self.push(keyboard, key.up()),
+            Command::Tap(key) => {
+                self.push(keyboard, key.down());
+                self.push(keyboard, key.up());
+            }
+            Command::Press(button) => self.push(mouse, button.down()),
+            Command::Release(button) => self.push(mouse, button.up()),
+            Command::Click(button) => {
+                self.push(mouse, button.down());
+                self.push(mouse, button.up());
+            }
+            Command::Wait(duration) => self.at += *duration,
+            Command::MoveRelative(x, y) => {
+                self.push(
+                    mouse,
+                    MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, *x, *y, 0),
+                );
+            }
+            Command::MoveAbsolute(x, y) => {
+                let flags = MOUSE_MOVE_ABSOLUTE | MOUSE_VIRTUAL_DESKTOP;
+                self.push(mouse, MouseStroke::new(flags, 0, 0, *x, *y, 0));
+            }
+            Command::Wheel(delta) => self.push(mouse, MouseStroke::wheel(*delta)),
+            Command::HWheel(delta) => self.push(mouse, MouseStroke::hwheel(*delta)),
+            Command::Repeat(count, body) => {
+                for _ in 0..*count {
+                    self.commands(body);
+                }
+            }
+            Command::Type(text) => {
+                let strokes = text
+                    .chars()
+                    .filter_map(UsQwerty::chord)
+                    .flat_map(Chord::strokes);
+                for stroke in strokes {
+                    self.push(keyboard, stroke);
+                }
+            }
+        }
+    }
+}
+
+fn write_block(f: &mut Formatter<'_>, commands: &[Command], depth: usize) -> std::fmt::Result {
+    let indent = "    ".repeat(depth);
+    for command in commands {
+        f.write_str(&indent)?;
+        match command {
+            Command::Down(key) => writeln!(f, "down {key}")?,
+            Command::Up(key) => writeln!(f, "up {key}")?,
+            Command::Tap(key) => writeln!(f, "tap {key}")?,
+            Command::Press(button) => writeln!(f, "press {button}")?,
+            Command::Release(button) => writeln!(f, "release {button}")?,
+            Command::Click(button) => writeln!(f, "click {button}")?,
+            Command::Wait(duration) => {
+                let millis = duration.as_millis();
+                if millis > 0 && millis % 1000 == 0 {
+                    writeln!(f, "wait {}s", millis / 1000)?
+                } else {
+                    writeln!(f, "wait {millis}ms")?
+                }
+            }
+            Command::MoveRelative(x, y) => writeln!(f, "move rel {x} {y}")?,
+            Command::MoveAbsolute(x, y) => writeln!(f, "move abs {x} {y}")?,
+            Command::Wheel(delta) => writeln!(f, "wheel {delta}")?,
+            Command::HWheel(delta) => writeln!(f, "hwheel {delta}")?,
+            Command::Repeat(count, body) => {
+                writeln!(f, "repeat {count} {{")?;
+                write_block(f, body, depth + 1)?;
+                writeln!(f, "{indent}}}")?
+            }
+            Command::Type(text) => {
+                f.write_str("type \"")?;
+                for c in text.chars() {
+                    match c {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\t' => f.write_str("\\t")?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                f.write_str("\"\n")?
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Display for Script {
+    /// One command per line, with blocks indented by four spaces
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write_block(f, &self.commands, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MAX_KEYBOARD;
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    const SAMPLE: &str = r#"
+# Copy, then paste twice lower down
+down LCtrl
+tap C   # copy
+up LCtrl
+wait 40ms
+move rel 10 -5
+click left
+repeat 2 {
+    down ctrl
+    tap v
+    up CTRL
+    repeat 1 {
+        wait 1s
+    }
+}
+wheel -120
+hwheel 240
+move abs 0 65535
+press x1
+release x1
+type "say \"hi\"\n"
+"#;
+
+    #[test]
+    fn test_parse() {
+        let script = Script::parse(SAMPLE).unwrap();
+        assert_eq!(script.commands.len(), 13);
+        assert_eq!(script.commands[0], Command::Down(Key::LEFT_CTRL));
+        assert_eq!(script.commands[4], Command::MoveRelative(10, -5));
+        assert_eq!(
+            script.commands[6],
+            Command::Repeat(
+                2,
+                vec![
+                    Command::Down(Key::LEFT_CTRL),
+                    Command::Tap(Key::V),
+                    Command::Up(Key::LEFT_CTRL),
+                    Command::Repeat(1, vec![Command::Wait(ms(1000))]),
+                ]
+            )
+        );
+        assert_eq!(script.commands[12], Command::Type("say \"hi\"\n".into()));
+    }
+
+    #[test]
+    fn test_format_round_trips() {
+        let script = Script::parse(SAMPLE).unwrap();
+        let formatted = script.to_string();
+        assert!(formatted.starts_with("down LCtrl\ntap C\nup LCtrl\nwait 40ms\n"));
+        assert!(formatted.contains("repeat 2 {\n    down LCtrl\n"));
+        assert!(formatted.contains("        wait 1s\n    }\n}\n"));
+        assert!(formatted.ends_with("type \"say \\\"hi\\\"\\n\"\n"));
+        assert_eq!(Script::parse(&formatted).unwrap(), script);
+        assert_eq!(Script::parse(&formatted).unwrap().to_string(), formatted);
+    }
+
+    #[test]
+    fn test_errors_point_at_line_and_column() {
+        let error = |source: &str| Script::parse(source).unwrap_err();
+        assert_eq!(
+            error("tap C\n  jump"),
+            ParseError::new(2, 3, "unknown command `jump`")
+        );
+        assert_eq!(
+            error("tap Nope"),
+            ParseError::new(1, 5, "unknown key `Nope`")
+        );
+        assert_eq!(error("tap"), ParseError::new(1, 4, "expected a key"));
+        assert_eq!(
+            error("tap A B"),
+            ParseError::new(1, 7, "expected end of line")
+        );
+        assert_eq!(
+            error("move up 1 2"),
+            ParseError::new(1, 6, "expected `rel` or `abs`")
+        );
+        assert_eq!(
+            error("wheel 1e3"),
+            ParseError::new(1, 7, "invalid number `1e3`")
+        );
+        assert_eq!(
+            error("wait 40"),
+            ParseError::new(1, 6, "invalid duration `40`; expected e.g. `40ms` or `2s`")
+        );
+        assert_eq!(
+            error("repeat 2 {\ntap A"),
+            ParseError::new(1, 10, "unclosed `{`")
+        );
+        assert_eq!(error("tap A\n}"), ParseError::new(2, 1, "unmatched `}`"));
+        assert_eq!(
+            error("type \"abc"),
+            ParseError::new(1, 6, "unterminated string")
+        );
+        assert_eq!(
+            error("type \"ab€\""),
+            ParseError::new(1, 9, "cannot type '€'")
+        );
+        assert_eq!(
+            error("type abc"),
+            ParseError::new(1, 6, "expected a quoted string")
+        );
+        assert_eq!(error("tap").to_string(), "1:4: expected a key");
+    }
+
+    #[test]
+    fn test_compile_timing() {
+        let script =
+            Script::parse("tap A\nwait 40ms\nrepeat 2 {\n click left\n wait 10ms\n}\ntype \"B\"")
+                .unwrap();
+        let recording = script.compile(1, MOUSE).unwrap();
+        let events: Vec<_> = recording.events.iter().map(|r| (r.at, r.event)).collect();
+        assert_eq!(
+            events,
+            vec![
+                (ms(0), Event::new(1, Key::A.down())),
+                (ms(0), Event::new(1, Key::A.up())),
+                (ms(40), Event::new(MOUSE, MouseButton::Left.down())),
+                (ms(40), Event::new(MOUSE, MouseButton::Left.up())),
+                (ms(50), Event::new(MOUSE, MouseButton::Left.down())),
+                (ms(50), Event::new(MOUSE, MouseButton::Left.up())),
+                (ms(60), Event::new(1, Key::LEFT_SHIFT.down())),
+                (ms(60), Event::new(1, Key::B.down())),
+                (ms(60), Event::new(1, Key::B.up())),
+                (ms(60), Event::new(1, Key::LEFT_SHIFT.up())),
+            ]
+        );
+        assert_eq!(recording.duration, ms(60));
+    }
+
+    #[test]
+    fn test_compile_limit() {
+        let script = Script::parse("repeat 1000 {\n repeat 500 {\n  tap A\n }\n}").unwrap();
+        assert_eq!(script.compile(0, MOUSE).unwrap().events.len(), MAX_COMPILED);
+
+        // Waits count as well, or this would take ages to compile
+        let script = Script::parse("repeat 4000000000 {\n repeat 4000000000 {\n  wait 1ms\n }\n}");
+        let error = script.unwrap().compile(0, MOUSE).unwrap_err();
+        assert!(error.steps > MAX_COMPILED);
+    }
+
+    #[test]
+    fn test_from_recording() {
+        let source = "down LShift\ntap A\nup LShift\nwait 25ms\nmove rel 3 4\nclick right\nwheel 120\npress left\nwait 1s\nrelease left\n";
+        let script = Script::parse(source).unwrap();
+        let recording = script.compile(0, MOUSE).unwrap();
+        let restored = Script::from_recording(&recording);
+        assert_eq!(restored.to_string(), source);
+
+        // Recorded times aren't whole milliseconds
+        let at = |micros| Duration::from_micros(micros);
+        let recording = Recording {
+            events: vec![
+                Recorded {
+                    at: at(1_400),
+                    event: Event::new(0, Key::A.down()),
+                },
+                Recorded {
+                    at: at(1_700),
+                    event: Event::new(0, Key::A.up()),
+                },
+                Recorded {
+                    at: at(4_600),
+                    event: Event::new(0, Key::B.down()),
+                },
+            ],
+            duration: at(10_200),
+            ..Recording::default()
+        };
+        let script = Script::from_recording(&recording);
+        let source = "wait 1ms\ndown A\nwait 1ms\nup A\nwait 3ms\ndown B\nwait 5ms\n";
+        assert_eq!(script.to_string(), source);
+        assert_eq!(Script::parse(source).unwrap(), script);
+    }
+}
@@ -0,0 +1,603 @@
+//! The key sequence syntax of AutoHotkey's `Send` and KeePass auto-type.
+//!
+//! ```text
+//! ^c{Tab 3}+{End}{Enter}
+//! ```
+//!
+//! Plain characters are typed as text. `^`, `+`, `!` and `#` hold Ctrl, Shift, Alt and the
+//! Windows key while the next key or character is sent. Braces name a key, with an optional
+//! repeat count or `down`/`up`:
+//!
+//! | Syntax | Effect |
+//! |---|---|
+//! | `{Enter}`, `{F5}`, `{0x54}` | tap a key, by an AutoHotkey name or any name of [`Key::from_name`] |
+//! | `{Tab 3}`, `{a 3}` | tap a key or type a character three times |
+//! | `{Shift down}`, `{Shift up}` | press or release a key |
+//! | `{^}`, `{{}`, `{}}` | type a character that is otherwise special |
+//! | `{Raw}`, `{Text}` | type the rest of the string as is |
+//!
+//! A single character in braces is always a character, so `{a}` types `a` and `{A}` types
+//! `A`; only `down` and `up` treat it as the key of that name.
+
+use crate::text::{Layout, Typist, UntypeableChar};
+use crate::{Key, KeyStroke};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Write};
+use std::str::FromStr;
+
+/// Characters that have to be braced to be typed
+const SPECIAL: [char; 6] = ['^', '+', '!', '#', '{', '}'];
+
+/// AutoHotkey's key names that [`Key::from_name`] doesn't know
+const AHK_NAMES: [(&str, Key); 25] = [
+    ("BS", Key::BACKSPACE),
+    ("AppsKey", Key::CONTEXT_MENU),
+    ("LControl", Key::LEFT_CTRL),
+    ("RControl", Key::RIGHT_CTRL),
+    ("Numpad0", Key::NUMPAD_0),
+    ("Numpad1", Key::NUMPAD_1),
+    ("Numpad2", Key::NUMPAD_2),
+    ("Numpad3", Key::NUMPAD_3),
+    ("Numpad4", Key::NUMPAD_4),
+    ("Numpad5", Key::NUMPAD_5),
+    ("Numpad6", Key::NUMPAD_6),
+    ("Numpad7", Key::NUMPAD_7),
+    ("Numpad8", Key::NUMPAD_8),
+    ("Numpad9", Key::NUMPAD_9),
+    ("NumpadDot", Key::NUMPAD_DECIMAL),
+    ("NumpadEnter", Key::NUMPAD_ENTER),
+    ("NumpadAdd", Key::NUMPAD_ADD),
+    ("NumpadSub", Key::NUMPAD_SUBTRACT),
+    ("NumpadMult", Key::NUMPAD_MULTIPLY),
+    ("NumpadDiv", Key::NUMPAD_DIVIDE),
+    ("Volume_Mute", Key::VOLUME_MUTE),
+    ("Volume_Up", Key::VOLUME_UP),
+    ("Volume_Down", Key::VOLUME_DOWN),
+    ("Media_Play_Pause", Key::MEDIA_PLAY_PAUSE),
+    ("Media_Next", Key::MEDIA_NEXT),
+];
+
+/// Look a key up by an AutoHotkey name or any name [`Key::from_name`] accepts
+pub fn key_from_name(name: &str) -> Option<Key> {
+    AHK_NAMES
+        .iter()
+        .find(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+        .map(|(_, key)| *key)
+        .or_else(|| Key::from_name(name))
+}
+
+/// A modifier prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Modifier {
+    /// `^`
+    Ctrl,
+    /// `+`
+    Shift,
+    /// `!`
+    Alt,
+    /// `#`
+    Win,
+}
+
+impl Modifier {
+    pub const ALL: [Modifier; 4] = [
+        Modifier::Ctrl,
+        Modifier::Shift,
+        Modifier::Alt,
+        Modifier::Win,
+    ];
+
+    pub fn prefix(self) -> char {
+        match self {
+            Modifier::Ctrl => '^',
+            Modifier::Shift => '+',
+            Modifier::Alt => '!',
+            Modifier::Win => '#',
+        }
+    }
+
+    pub fn from_prefix(prefix: char) -> Option<Modifier> {
+        Self::ALL
+            .into_iter()
+            .find(|modifier| modifier.prefix() == prefix)
+    }
+
+    /// The key held for this modifier, the left-hand one
+    pub fn key(self) -> Key {
+        match self {
+            Modifier::Ctrl => Key::LEFT_CTRL,
+            Modifier::Shift => Key::LEFT_SHIFT,
+            Modifier::Alt => Key::LEFT_ALT,
+            Modifier::Win => Key::LEFT_WIN,
+        }
+    }
+
+    /// The modifier a key holds, on either side of the keyboard
+    pub fn from_key(key: Key) -> Option<Modifier> {
+        match key {
+            Key::LEFT_CTRL | Key::RIGHT_CTRL => Some(Modifier::Ctrl),
+            Key::LEFT_SHIFT | Key::RIGHT_SHIFT => Some(Modifier::Shift),
+            Key::LEFT_ALT | Key::RIGHT_ALT => Some(Modifier::Alt),
+            Key::LEFT_WIN | Key::RIGHT_WIN => Some(Modifier::Win),
+            _ => None,
+        }
+    }
+}
+
+/// What a tap sends: a key, or a character typed with the layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Key(Key),
+    Char(char),
+}
+
+/// One step of a key sequence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendItem {
+    /// Send `target` `count` times while holding `modifiers`, pressed in order
+    Tap {
+        modifiers: Vec<Modifier>,
+        target: Target,
+        count: u32,
+    },
+    Down(Key),
+    Up(Key),
+}
+
+/// A parse error, located by column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// 1-based column, in characters
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(index: usize, message: impl Into<String>) -> Self {
+        Self {
+            column: index + 1,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// A parsed key sequence
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SendKeys {
+    pub items: Vec<SendItem>,
+}
+
+impl SendKeys {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut items = Vec::new();
+        let mut modifiers: Vec<Modifier> = Vec::new();
+        let mut modifiers_at = 0;
+        let mut at = 0;
+        while at < chars.len() {
+            let c = chars[at];
+            if let Some(modifier) = Modifier::from_prefix(c) {
+                if modifiers.contains(&modifier) {
+                    let message = format!("`{c}` ({modifier:?}) given twice");
+                    return Err(ParseError::new(at, message));
+                }
+                if modifiers.is_empty() {
+                    modifiers_at = at;
+                }
+                modifiers.push(modifier);
+                at += 1;
+                continue;
+            }
+
+            let (target, count) = match c {
+                '{' => {
+                    let Some(close) = (at + 2..chars.len()).find(|i| chars[*i] == '}') else {
+                        return Err(ParseError::new(at, "unclosed `{`"));
+                    };
+                    let braced = Self::braced(&chars, at + 1, close)?;
+                    at = close + 1;
+                    match braced {
+                        Braced::Tap(target, count) => (target, count),
+                        Braced::Down(_) | Braced::Up(_) if !modifiers.is_empty() => {
+                            let message = "modifiers can't apply to `down` or `up`";
+                            return Err(ParseError::new(modifiers_at, message));
+                        }
+                        Braced::Down(key) => {
+                            items.push(SendItem::Down(key));
+                            continue;
+                        }
+                        Braced::Up(key) => {
+                            items.push(SendItem::Up(key));
+                            continue;
+                        }
+                        Braced::Raw if !modifiers.is_empty() => {
+                            let message = "modifiers can't apply to `{Raw}` or `{Text}`";
+                            return Err(ParseError::new(modifiers_at, message));
+                        }
+                        Braced::Raw => {
+                            items.extend(chars[at..].iter().map(|c| SendItem::Tap {
+                                modifiers: Vec::new(),
+                                target: Target::Char(*c),
+                                count: 1,
+                            }));
+                            break;
+                        }
+                    }
+                }
+                '}' => return Err(ParseError::new(at, "unmatched `}`")),
+                c => {
+                    at += 1;
+                    (Target::Char(c), 1)
+                }
+            };
+            items.push(SendItem::Tap {
+                modifiers: std::mem::take(&mut modifiers),
+                target,
+                count,
+            });
+        }
+        if !modifiers.is_empty() {
+            let message = "modifier with nothing after it to apply to";
+            return Err(ParseError::new(modifiers_at, message));
+        }
+        Ok(Self { items })
+    }
+
+    /// Parse the contents of braces, `chars[start..end]`; the first character is always
+    /// taken as is so that `{{}` and `{}}` work
+    fn braced(chars: &[char], start: usize, end: usize) -> Result<Braced, ParseError> {
+        let name_end = match chars[start + 1..end].first() {
+            None | Some(' ') => start + 1,
+            Some(_) => (start + 1..end).find(|i| chars[*i] == ' ').unwrap_or(end),
+        };
+        let name: String = chars[start..name_end].iter().collect();
+        let argument_at = (name_end..end).find(|i| chars[*i] != ' ').unwrap_or(end);
+        let argument: String = chars[argument_at..end].iter().collect();
+        let single = (name_end == start + 1).then_some(chars[start]);
+
+        if argument.is_empty() {
+            if let Some(c) = single {
+                return Ok(Braced::Tap(Target::Char(c), 1));
+            }
+            if name.eq_ignore_ascii_case("Raw") || name.eq_ignore_ascii_case("Text") {
+                return Ok(Braced::Raw);
+            }
+        }
+        let key = || {
+            key_from_name(&name)
+                .ok_or_else(|| ParseError::new(start, format!("unknown key `{name}`")))
+        };
+        if argument.eq_ignore_ascii_case("down") {
+            return Ok(Braced::Down(key()?));
+        }
+        if argument.eq_ignore_ascii_case("up") {
+            return Ok(Braced::Up(key()?));
+        }
+
+        let count = match argument.as_str() {
+            "" => 1,
+            argument => match argument.parse::<u32>() {
+                Ok(0) => return Err(ParseError::new(argument_at, "count must be at least 1")),
+                Ok(count) => count,
+                Err(_) => {
+                    let message = format!("expected a count, `down` or `up`, not `{argument}`");
+                    return Err(ParseError::new(argument_at, message));
+                }
+            },
+        };
+        match single {
+            Some(c) => Ok(Braced::Tap(Target::Char(c), count)),
+            None => Ok(Braced::Tap(Target::Key(key()?), count)),
+        }
+    }
+
+    /// The strokes of this sequence, typing characters with `typist`
+    ///
+    /// The index of an [`UntypeableChar`] is that of its item. As in [`Typist::type_text`],
+    /// `\r\n` presses Enter once.
+    pub fn compile<L: Layout>(&self, typist: &Typist<L>) -> Result<Vec<KeyStroke>, UntypeableChar> {
+        // The character an item types once, without modifiers
+        let plain_char = |item: Option<&SendItem>| match item {
+            Some(SendItem::Tap {
+                modifiers,
+                target: Target::Char(c),
+                count: 1,
+            }) if modifiers.is_empty() => Some(*c),
+            _ => None,
+        };
+        let mut strokes = Vec::new();
+        for (index, item) in self.items.iter().enumerate() {
+            if plain_char(Some(item)) == Some('\r')
+                && plain_char(self.items.get(index + 1)) == Some('\n')
+            {
+                continue;
+            }
+            match item {
+                SendItem::Tap {
+                    modifiers,
+                    target,
+                    count,
+                } => {
+                    let tap = match target {
+                        Target::Key(key) => key.tap().to_vec(),
+                        Target::Char(c) => typist
+                            .type_text(c.encode_utf8(&mut [0; 4]))
+                            .map_err(|_| UntypeableChar { char: *c, index })?
+                            .strokes(),
+                    };
+                    strokes.extend(modifiers.iter().map(|modifier| modifier.key().down()));
+                    for _ in 0..*count {
+                        strokes.extend(&tap);
+                    }
+                    strokes.extend(modifiers.iter().rev().map(|modifier| modifier.key().up()));
+                }
+                SendItem::Down(key) => strokes.push(key.down()),
+                SendItem::Up(key) => strokes.push(key.up()),
+            }
+        }
+        Ok(strokes)
+    }
+
+    /// The strokes of this sequence on a US QWERTY layout
+    pub fn strokes(&self) -> Result<Vec<KeyStroke>, UntypeableChar> {
+        self.compile(&Typist::default())
+    }
+}
+
+/// What a pair of braces holds
+enum Braced {
+    Tap(Target, u32),
+    Down(Key),
+    Up(Key),
+    Raw,
+}
+
+impl FromStr for SendKeys {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+/// A key's name for braces; keys named by a single character go by scancode unless `down`
+/// or `up` follows, as `{a}` types a character
+fn braced_key_name(key: Key, alone: bool) -> String {
+    match key.name() {
+        Some(name) if !alone || name.chars().count() > 1 => name.to_string(),
+        _ => key.hex_name(),
+    }
+}
+
+impl Display for SendKeys {
+    /// The canonical form: key names as [`Key::name`] gives them and special characters braced
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for item in &self.items {
+            match item {
+                SendItem::Tap {
+                    modifiers,
+                    target,
+                    count,
+                } => {
+                    for modifier in modifiers {
+                        f.write_char(modifier.prefix())?;
+                    }
+                    let target = match target {
+                        Target::Key(key) => braced_key_name(*key, true),
+                        Target::Char(c) if *count == 1 && !SPECIAL.contains(c) => {
+                            f.write_char(*c)?;
+                            continue;
+                        }
+                        Target::Char(c) => c.to_string(),
+                    };
+                    match count {
+                        1 => write!(f, "{{{target}}}")?,
+                        count => write!(f, "{{{target} {count}}}")?,
+                    }
+                }
+                SendItem::Down(key) => write!(f, "{{{} down}}", braced_key_name(*key, false))?,
+                SendItem::Up(key) => write!(f, "{{{} up}}", braced_key_name(*key, false))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn tap(modifiers: &[Modifier], target: Target, count: u32) -> SendItem {
+        SendItem::Tap {
+            modifiers: modifiers.to_vec(),
+            target,
+            count,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let keys = SendKeys::parse("^c{Tab 3}+{End}{Enter}").unwrap();
+        assert_eq!(
+            keys.items,
+            vec![
+                tap(&[Modifier::Ctrl], Target::Char('c'), 1),
+                tap(&[], Target::Key(Key::TAB), 3),
+                tap(&[Modifier::Shift], Target::Key(Key::END), 1),
+                tap(&[], Target::Key(Key::ENTER), 1),
+            ]
+        );
+
+        let keys = SendKeys::parse("{Shift Down}{a 2}{A}{{}{}}{} 2}!#{BS}{Numpad7}{a up}").unwrap();
+        assert_eq!(
+            keys.items,
+            vec![
+                SendItem::Down(Key::LEFT_SHIFT),
+                tap(&[], Target::Char('a'), 2),
+                tap(&[], Target::Char('A'), 1),
+                tap(&[], Target::Char('{'), 1),
+                tap(&[], Target::Char('}'), 1),
+                tap(&[], Target::Char('}'), 2),
+                tap(
+                    &[Modifier::Alt, Modifier::Win],
+                    Target::Key(Key::BACKSPACE),
+                    1
+                ),
+                tap(&[], Target::Key(Key::NUMPAD_7), 1),
+                SendItem::Up(Key::A),
+            ]
+        );
+
+        let keys = SendKeys::parse("a{Raw}^{x}").unwrap();
+        let text: String = keys
+            .items
+            .iter()
+            .map(|item| match item {
+                SendItem::Tap {
+                    target: Target::Char(c),
+                    ..
+                } => *c,
+                _ => panic!("expected a character"),
+            })
+            .collect();
+        assert_eq!(text, "a^{x}");
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases = [
+            ("ab{Tab", 3, "unclosed `{`"),
+            ("ab}", 3, "unmatched `}`"),
+            ("x{Foo 2}", 3, "unknown key `Foo`"),
+            ("{Tab  3x}", 7, "expected a count, `down` or `up`, not `3x`"),
+            ("{Tab 0}", 6, "count must be at least 1"),
+            ("a^+^b", 4, "`^` (Ctrl) given twice"),
+            (
+                "a^!{Alt down}",
+                2,
+                "modifiers can't apply to `down` or `up`",
+            ),
+            ("^{Raw}", 1, "modifiers can't apply to `{Raw}` or `{Text}`"),
+            ("ab+", 3, "modifier with nothing after it to apply to"),
+        ];
+        for (text, column, message) in cases {
+            let error = SendKeys::parse(text).unwrap_err();
+            assert_eq!(
+                error,
+                ParseError {
+                    column,
+                    message: message.to_string()
+                },
+                "{text}"
+            );
+        }
+        assert_eq!(
+            SendKeys::parse("{").unwrap_err().to_string(),
+            "column 1: unclosed `{`"
+        );
+    }
+
+    #[test]
+    fn test_compile() {
+        let strokes = SendKeys::parse("^+{Esc}{Tab 2}+a")
+            .unwrap()
+            .strokes()
+            .unwrap();
+        let mut expected = vec![Key::LEFT_CTRL.down(), Key::LEFT_SHIFT.down()];
+        expected.extend(Key::ESCAPE.tap());
+        expected.extend([Key::LEFT_SHIFT.up(), Key::LEFT_CTRL.up()]);
+        expected.extend(Key::TAB.tap());
+        expected.extend(Key::TAB.tap());
+        expected.extend([Key::LEFT_SHIFT.down()]);
+        expected.extend(Key::A.tap());
+        expected.extend([Key::LEFT_SHIFT.up()]);
+        assert_eq!(strokes, expected);
+
+        // The typist presses Shift itself for capitals
+        let strokes = SendKeys::parse("{A 2}").unwrap().strokes().unwrap();
+        assert_eq!(strokes.len(), 8);
+
+        // A line break is one Enter, whichever way it is written
+        for text in ["a\r\nb", "a\nb"] {
+            let strokes = SendKeys::parse(text).unwrap().strokes().unwrap();
+            assert_eq!(
+                strokes,
+                [Key::A.tap(), Key::ENTER.tap(), Key::B.tap()].concat()
+            );
+        }
+
+        let typist = Typist::default().with_fallback(crate::text::Fallback::Fail);
+        let error = SendKeys::parse("ab€")
+            .unwrap()
+            .compile(&typist)
+            .unwrap_err();
+        assert_eq!(
+            error,
+            UntypeableChar {
+                char: '€',
+                index: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let keys =
+            SendKeys::parse("^c{tab 3}+{end}{enter}{Numpad7}{{}{^}x{a down}{0x1E}{. 2}").unwrap();
+        assert_eq!(
+            keys.to_string(),
+            "^c{Tab 3}+{End}{Enter}{Num7}{{}{^}x{A down}{0x1E}{. 2}"
+        );
+    }
+
+    fn items() -> impl Strategy<Value = SendItem> {
+        let keys = prop::sample::select(vec![
+            Key::A,
+            Key::DIGIT_1,
+            Key::TAB,
+            Key::ENTER,
+            Key::SPACE,
+            Key::F5,
+            Key::NUMPAD_DECIMAL,
+            Key::ARROW_LEFT,
+            Key::LEFT_SHIFT,
+            Key::RIGHT_WIN,
+            Key::with_prefix(0x7E, crate::KEY_E0),
+        ]);
+        let chars = prop::sample::select(vec![
+            'a', 'Z', '7', ' ', '^', '+', '!', '#', '{', '}', '\n', 'é', '€', '~',
+        ]);
+        let target = prop_oneof![
+            keys.clone().prop_map(Target::Key),
+            chars.prop_map(Target::Char),
+        ];
+        let modifiers = prop::sample::subsequence(Modifier::ALL.to_vec(), 0..=4).prop_shuffle();
+        prop_oneof![
+            (modifiers, target, 1u32..4).prop_map(|(modifiers, target, count)| SendItem::Tap {
+                modifiers,
+                target,
+                count
+            }),
+            keys.clone().prop_map(SendItem::Down),
+            keys.prop_map(SendItem::Up),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(items in prop::collection::vec(items(), 0..20)) {
+            let keys = SendKeys { items };
+            let text = keys.to_string();
+            prop_assert_eq!(SendKeys::parse(&text), Ok(keys), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! Pointer sensitivity and acceleration.
+//!
+//! [`Sensitivity`] rescales relative mouse movement per device. Each stroke's movement is
+//! multiplied by a constant sensitivity, a per-axis scale and a gain taken from a [`Curve`]
+//! of the stroke's speed. Speed is the length of the stroke's movement vector in counts,
+//! which at a fixed polling rate is proportional to counts per millisecond, so the result
+//! depends on the stroke stream alone and not on timing.
+//!
+//! Scaled movement is fractional; the fraction is carried over per device, so slow
+//! movement at low sensitivity still moves the pointer instead of rounding to nothing.
+
+use crate::{Event, MOUSE_MOVE_ABSOLUTE, MouseStroke, PerDevice, Processor, Remainder, Stroke};
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Gain as a function of speed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Curve {
+    /// Constant gain of 1
+    Linear,
+    /// `1 + (acceleration * (speed - offset))^(exponent - 1)`, limited to `cap`
+    ///
+    /// With `exponent` 2 this is the classic linear acceleration of Quake and similar games.
+    /// An `exponent` of 1 or less doesn't accelerate; the gain stays 1.
+    Classic {
+        acceleration: f64,
+        exponent: f64,
+        offset: f64,
+        cap: Option<f64>,
+    },
+    /// `(scale * speed)^exponent`, kept between `floor` and `cap`
+    Power {
+        scale: f64,
+        exponent: f64,
+        floor: f64,
+        cap: Option<f64>,
+    },
+    /// Logistic ramp from `min_gain` to `max_gain`, centered on `midpoint` speed
+    Sigmoid {
+        min_gain: f64,
+        max_gain: f64,
+        midpoint: f64,
+        rate: f64,
+    },
+    /// Piecewise-linear gain through `(speed, gain)` points sorted by speed; constant beyond
+    /// the first and last point
+    Table(Vec<(f64, f64)>),
+}
+
+impl Curve {
+    /// The gain for a movement of the given speed
+    pub fn gain(&self, speed: f64) -> f64 {
+        match self {
+            Curve::Linear => 1.0,
+            Curve::Classic {
+                acceleration,
+                exponent,
+                offset,
+                cap,
+            } => {
+                if *exponent <= 1.0 {
+                    return 1.0;
+                }
+                let excess = (acceleration * (speed - offset)).max(0.0);
+                let gain = 1.0 + excess.powf(exponent - 1.0);
+                cap.map_or(gain, |cap| gain.min(cap))
+            }
+            Curve::Power {
+                scale,
+                exponent,
+                floor,
+                cap,
+            } => {
+                let gain = (scale * speed).powf(*exponent).max(*floor);
+                cap.map_or(gain, |cap| gain.min(cap))
+            }
+            Curve::Sigmoid {
+                min_gain,
+                max_gain,
+                midpoint,
+                rate,
+            } => min_gain + (max_gain - min_gain) / (1.0 + (-rate * (speed - midpoint)).exp()),
+            Curve::Table(points) => interpolate(points, speed),
+        }
+    }
+}
+
+fn interpolate(points: &[(f64, f64)], speed: f64) -> f64 {
+    let Some(&(first_speed, first_gain)) = points.first() else {
+        return 1.0;
+    };
+    if speed <= first_speed {
+        return first_gain;
+    }
+    for pair in points.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if speed <= x1 {
+            if x1 == x0 {
+                return y1;
+            }
+            return y0 + (y1 - y0) * (speed - x0) / (x1 - x0);
+        }
+    }
+    points[points.len() - 1].1
+}
+
+/// Pointer transform for one device
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointerSettings {
+    pub sensitivity: f64,
+    pub scale_x: f64,
+    pub scale_y: f64,
+    pub curve: Curve,
+}
+
+impl Default for PointerSettings {
+    /// Leaves movement unchanged
+    fn default() -> Self {
+        Self::linear(1.0)
+    }
+}
+
+impl PointerSettings {
+    /// Constant sensitivity without acceleration
+    pub fn linear(sensitivity: f64) -> Self {
+        Self {
+            sensitivity,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            curve: Curve::Linear,
+        }
+    }
+
+    pub fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Scale the axes separately, on top of the sensitivity
+    pub fn with_scale(mut self, scale_x: f64, scale_y: f64) -> Self {
+        self.scale_x = scale_x;
+        self.scale_y = scale_y;
+        self
+    }
+
+    /// Fractional output movement for an input movement
+    pub fn apply(&self, x: i32, y: i32) -> (f64, f64) {
+        let (x, y) = (f64::from(x), f64::from(y));
+        let factor = self.sensitivity * self.curve.gain(x.hypot(y));
+        (x * factor * self.scale_x, y * factor * self.scale_y)
+    }
+}
+
+/// Applies sensitivity and acceleration to relative mouse movement
+#[derive(Debug, Clone)]
+pub struct Sensitivity {
+    settings: PerDevice<PointerSettings>,
+    remainders: HashMap<usize, (Remainder, Remainder)>,
+}
+
+impl Sensitivity {
+    pub fn new(settings: PointerSettings) -> Self {
+        Self::per_device(PerDevice::new(settings))
+    }
+
+    /// Use different settings for particular devices
+    pub fn per_device(settings: PerDevice<PointerSettings>) -> Self {
+        Self {
+            settings,
+            remainders: HashMap::new(),
+        }
+    }
+
+    /// Transform one stroke from `device`
+    ///
+    /// Absolute movement is left alone. Returns `None` when the stroke only carried movement
+    /// and all of it was absorbed into the remainder.
+    pub fn transform(&mut self, device: usize, stroke: MouseStroke) -> Option<MouseStroke> {
+        if stroke.flags & MOUSE_MOVE_ABSOLUTE != 0 || (stroke.x == 0 && stroke.y == 0) {
+            return Some(stroke);
+        }
+
+        let (x, y) = self.settings.get(device).apply(stroke.x, stroke.y);
+        let (remainder_x, remainder_y) = self.remainders.entry(device).or_default();
+
+        let mut transformed = stroke;
+        transformed.x = remainder_x.take(x);
+        transformed.y = remainder_y.take(y);
+        (!transformed.is_empty()).then_some(transformed)
+    }
+}
+
+impl Processor for Sensitivity {
+    fn process(&mut self, event: Event, _now: Instant, out: &mut Vec<Event>) {
+        match event.stroke {
+            Stroke::Mouse(stroke) => {
+                if let Some(stroke) = self.transform(event.device, stroke) {
+                    out.push(Event::new(event.device, stroke));
+                }
+            }
+            Stroke::Key(_) => out.push(event),
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.settings.attach(device, hardware_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_KEYBOARD, MOUSE_LEFT_BUTTON_DOWN, MOUSE_MOVE_RELATIVE};
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    fn moves(deltas: &[(i32, i32)]) -> Vec<MouseStroke> {
+        deltas
+            .iter()
+            .map(|&(x, y)| MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, x, y, 0))
+            .collect()
+    }
+
+    fn transform_all(sensitivity: &mut Sensitivity, strokes: &[MouseStroke]) -> Vec<(i32, i32)> {
+        strokes
+            .iter()
+            .filter_map(|stroke| sensitivity.transform(MOUSE, *stroke))
+            .map(|stroke| (stroke.x, stroke.y))
+            .collect()
+    }
+
+    #[test]
+    fn test_linear_golden() {
+        let mut sensitivity = Sensitivity::new(PointerSettings::linear(0.5));
+        let out = transform_all(
+            &mut sensitivity,
+            &moves(&[(1, 0), (1, 0), (3, -3), (1, 1), (-4, 0)]),
+        );
+        assert_eq!(out, vec![(1, 0), (1, -1), (1, 0), (-2, 0)]);
+    }
+
+    #[test]
+    fn test_axis_scale_golden() {
+        let mut sensitivity = Sensitivity::new(PointerSettings::linear(1.0).with_scale(2.0, 0.25));
+        let out = transform_all(&mut sensitivity, &moves(&[(1, 1), (1, 1), (1, 1), (1, 1)]));
+        assert_eq!(out, vec![(2, 0), (2, 0), (2, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn test_classic_golden() {
+        let curve = Curve::Classic {
+            acceleration: 0.1,
+            exponent: 2.0,
+            offset: 2.0,
+            cap: Some(2.0),
+        };
+        let mut sensitivity = Sensitivity::new(PointerSettings::linear(1.0).with_curve(curve));
+        // gains: 1.0, 1.0, 1.3, 2.0 (capped)
+        let out = transform_all(&mut sensitivity, &moves(&[(1, 0), (2, 0), (5, 0), (30, 0)]));
+        assert_eq!(out, vec![(1, 0), (2, 0), (6, 0), (60, 0)]);
+
+        // Without the special case, 0^0 would double the gain even at rest
+        for exponent in [1.0, 0.5] {
+            let curve = Curve::Classic {
+                acceleration: 0.1,
+                exponent,
+                offset: 2.0,
+                cap: None,
+            };
+            assert_eq!(curve.gain(1.0), 1.0);
+            assert_eq!(curve.gain(30.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn test_power_and_sigmoid_gain() {
+        let power = Curve::Power {
+            scale: 0.5,
+            exponent: 0.5,
+            floor: 1.0,
+            cap: Some(3.0),
+        };
+        assert_eq!(power.gain(1.0), 1.0);
+        assert_eq!(power.gain(8.0), 2.0);
+        assert_eq!(power.gain(100.0), 3.0);
+
+        let sigmoid = Curve::Sigmoid {
+            min_gain: 1.0,
+            max_gain: 3.0,
+            midpoint: 10.0,
+            rate: 1.0,
+        };
+        assert_eq!(sigmoid.gain(10.0), 2.0);
+        assert!(sigmoid.gain(0.0) < 1.001);
+        assert!(sigmoid.gain(20.0) > 2.999);
+    }
+
+    #[test]
+    fn test_table_golden() {
+        let curve = Curve::Table(vec![(0.0, 0.5), (10.0, 1.0), (20.0, 3.0)]);
+        assert_eq!(curve.gain(-1.0), 0.5);
+        assert_eq!(curve.gain(5.0), 0.75);
+        assert_eq!(curve.gain(15.0), 2.0);
+        assert_eq!(curve.gain(50.0), 3.0);
+
+        let mut sensitivity = Sensitivity::new(PointerSettings::linear(1.0).with_curve(curve));
+        // 2.8, 2.8 and -30.0, with the fraction carried over
+        let out = transform_all(&mut sensitivity, &moves(&[(0, 4), (0, 4), (0, -15)]));
+        assert_eq!(out, vec![(0, 2), (0, 3), (0, -29)]);
+    }
+
+    #[test]
+    fn test_buttons_and_absolute_untouched() {
+        let mut sensitivity = Sensitivity::new(PointerSettings::linear(0.1));
+        let click = MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_LEFT_BUTTON_DOWN, 0, 1, 0, 0);
+        let clicked = sensitivity.transform(MOUSE, click).unwrap();
+        assert_eq!((clicked.state, clicked.x), (MOUSE_LEFT_BUTTON_DOWN, 0));
+
+        let absolute = MouseStroke::new(MOUSE_MOVE_ABSOLUTE, 0, 0, 30000, 20000, 0);
+        assert_eq!(sensitivity.transform(MOUSE, absolute), Some(absolute));
+    }
+
+    #[test]
+    fn test_settings_and_remainders_per_device() {
+        let settings = PerDevice::new(PointerSettings::linear(0.5))
+            .with("PID_0002", PointerSettings::linear(2.0));
+        let mut sensitivity = Sensitivity::per_device(settings);
+        sensitivity.attach(MOUSE + 1, r"HID\VID_0001&PID_0002");
+
+        let mut out = Vec::new();
+        let now = Instant::now();
+        for device in [MOUSE, MOUSE + 1, MOUSE] {
+            let stroke = MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, 1, 0, 0);
+            sensitivity.process(Event::new(device, stroke), now, &mut out);
+        }
+        let moved: Vec<_> = out
+            .iter()
+            .map(|e| (e.device, e.mouse().unwrap().x))
+            .collect();
+        assert_eq!(moved, vec![(MOUSE + 1, 2), (MOUSE, 1)]);
+    }
+}
@@ -0,0 +1,465 @@
+//! Pointer smoothing and jitter suppression.
+//!
+//! [`Smoothing`] filters relative mouse movement per device with one of three [`Filter`]s: a
+//! moving average, a One-Euro filter, which smooths slow movement heavily and fast movement
+//! hardly at all, or a dead zone that ignores jitter within a small radius.
+//!
+//! The filters work on the device's accumulated position rather than on individual deltas,
+//! and each stroke moves the pointer to the nearest whole count of the filtered position, so
+//! rounding never accumulates. A filter lags behind the real position; once movement stops,
+//! the processor keeps feeding the filter on a timer until it catches up, and snaps to the real
+//! position after a timeout at the latest. Over time the pointer therefore moves exactly as far
+//! as the device did.
+//!
+//! Button and wheel bits pass through untouched; only the movement of a stroke changes.
+
+use crate::{
+    Event, MOUSE_MOVE_ABSOLUTE, MOUSE_MOVE_RELATIVE, MouseStroke, PerDevice, Processor, Stroke,
+};
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+/// How movement is smoothed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    /// Leave movement unchanged
+    None,
+    /// Average of the last `window` positions; lags by about half the window
+    MovingAverage { window: usize },
+    /// The One-Euro filter: a low-pass filter whose cutoff, in Hz, rises from `min_cutoff`
+    /// with speed, scaled by `beta`
+    ///
+    /// Lower `min_cutoff` removes more jitter at rest; higher `beta` removes more lag in fast
+    /// movement. See <https://gery.casiez.net/1euro/>.
+    OneEuro {
+        min_cutoff: f64,
+        beta: f64,
+        derivative_cutoff: f64,
+    },
+    /// Hold the pointer until the device moves more than `radius` counts away from it, then
+    /// drag it along at that distance
+    ///
+    /// Like the other filters, it snaps to the real position once the settle timeout passes
+    /// after the last movement, so jitter is held back rather than dropped: a burst of jitter
+    /// becomes a single stroke of its net offset, which is nothing if it returns to where it
+    /// started.
+    DeadZone { radius: f64 },
+}
+
+impl Filter {
+    pub fn moving_average(window: usize) -> Self {
+        Filter::MovingAverage {
+            window: window.max(1),
+        }
+    }
+
+    /// A One-Euro filter with a derivative cutoff of 1Hz
+    pub fn one_euro(min_cutoff: f64, beta: f64) -> Self {
+        Filter::OneEuro {
+            min_cutoff,
+            beta,
+            derivative_cutoff: 1.0,
+        }
+    }
+
+    pub fn dead_zone(radius: f64) -> Self {
+        Filter::DeadZone { radius }
+    }
+}
+
+/// Smoothing for one device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmoothingSettings {
+    pub filter: Filter,
+    /// How often the filter is fed while catching up after movement stops
+    pub settle_interval: Duration,
+    /// How long after the last movement the pointer snaps to the real position
+    pub settle_timeout: Duration,
+}
+
+impl Default for SmoothingSettings {
+    /// No smoothing
+    fn default() -> Self {
+        Self::new(Filter::None)
+    }
+}
+
+impl SmoothingSettings {
+    /// Catch up every 8ms, snapping after 100ms
+    pub fn new(filter: Filter) -> Self {
+        Self {
+            filter,
+            settle_interval: Duration::from_millis(8),
+            settle_timeout: Duration::from_millis(100),
+        }
+    }
+
+    pub fn with_settle_interval(mut self, interval: Duration) -> Self {
+        self.settle_interval = interval;
+        self
+    }
+
+    pub fn with_settle_timeout(mut self, timeout: Duration) -> Self {
+        self.settle_timeout = timeout;
+        self
+    }
+}
+
+/// One axis of a One-Euro filter
+#[derive(Debug, Clone, Copy, Default)]
+struct OneEuroAxis {
+    value: f64,
+    derivative: f64,
+}
+
+impl OneEuroAxis {
+    fn alpha(cutoff: f64, dt: f64) -> f64 {
+        let tau = 1.0 / (2.0 * PI * cutoff);
+        1.0 / (1.0 + tau / dt)
+    }
+
+    fn update(&mut self, x: f64, dt: f64, min_cutoff: f64, beta: f64, derivative_cutoff: f64) {
+        let derivative = (x - self.value) / dt;
+        self.derivative += (derivative - self.derivative) * Self::alpha(derivative_cutoff, dt);
+        let cutoff = min_cutoff + beta * self.derivative.abs();
+        self.value += (x - self.value) * Self::alpha(cutoff, dt);
+    }
+}
+
+/// Filter state of one device
+#[derive(Debug, Clone)]
+struct Track {
+    /// Accumulated real position
+    position: (i64, i64),
+    /// Filtered position
+    estimate: (f64, f64),
+    /// Movement sent so far
+    sent: (i64, i64),
+    history: VecDeque<(i64, i64)>,
+    euro: (OneEuroAxis, OneEuroAxis),
+    last_update: Instant,
+    last_input: Instant,
+    next_settle: Option<Instant>,
+}
+
+impl Track {
+    fn new(now: Instant) -> Self {
+        Self {
+            position: (0, 0),
+            estimate: (0.0, 0.0),
+            sent: (0, 0),
+            history: VecDeque::new(),
+            euro: Default::default(),
+            last_update: now,
+            last_input: now,
+            next_settle: None,
+        }
+    }
+
+    fn update(&mut self, filter: Filter, now: Instant) {
+        let (px, py) = (self.position.0 as f64, self.position.1 as f64);
+        self.estimate = match filter {
+            Filter::None => (px, py),
+            Filter::MovingAverage { window } => {
+                self.history.push_back(self.position);
+                while self.history.len() > window {
+                    self.history.pop_front();
+                }
+                let n = self.history.len() as f64;
+                let (sx, sy) = self
+                    .history
+                    .iter()
+                    .fold((0, 0), |(sx, sy), (x, y)| (sx + x, sy + y));
+                (sx as f64 / n, sy as f64 / n)
+            }
+            Filter::OneEuro {
+                min_cutoff,
+                beta,
+                derivative_cutoff,
+            } => {
+                let dt = (now - self.last_update)
+                    .max(Duration::from_millis(1))
+                    .as_secs_f64();
+                let (ex, ey) = &mut self.euro;
+                ex.update(px, dt, min_cutoff, beta, derivative_cutoff);
+                ey.update(py, dt, min_cutoff, beta, derivative_cutoff);
+                (ex.value, ey.value)
+            }
+            Filter::DeadZone { radius } => {
+                let (dx, dy) = (px - self.estimate.0, py - self.estimate.1);
+                let distance = dx.hypot(dy);
+                if distance > radius {
+                    let scale = radius / distance;
+                    (px - dx * scale, py - dy * scale)
+                } else {
+                    self.estimate
+                }
+            }
+        };
+        self.last_update = now;
+    }
+
+    fn snap(&mut self) {
+        self.estimate = (self.position.0 as f64, self.position.1 as f64);
+        self.history.clear();
+        self.euro.0.value = self.estimate.0;
+        self.euro.1.value = self.estimate.1;
+        self.euro.0.derivative = 0.0;
+        self.euro.1.derivative = 0.0;
+    }
+
+    fn is_settled(&self) -> bool {
+        self.estimate == (self.position.0 as f64, self.position.1 as f64)
+    }
+
+    /// Movement still to send to reach the filtered position
+    fn take(&mut self) -> (i32, i32) {
+        let target = (
+            self.estimate.0.round() as i64,
+            self.estimate.1.round() as i64,
+        );
+        let delta = (target.0 - self.sent.0, target.1 - self.sent.1);
+        self.sent = target;
+        (delta.0 as i32, delta.1 as i32)
+    }
+}
+
+/// Smooths relative mouse movement
+#[derive(Debug, Clone)]
+pub struct Smoothing {
+    settings: PerDevice<SmoothingSettings>,
+    tracks: HashMap<usize, Track>,
+}
+
+impl Smoothing {
+    pub fn new(settings: SmoothingSettings) -> Self {
+        Self::per_device(PerDevice::new(settings))
+    }
+
+    /// Use different settings for particular devices
+    pub fn per_device(settings: PerDevice<SmoothingSettings>) -> Self {
+        Self {
+            settings,
+            tracks: HashMap::new(),
+        }
+    }
+
+    fn transform(&mut self, device: usize, stroke: MouseStroke, now: Instant) -> MouseStroke {
+        let settings = *self.settings.get(device);
+        if stroke.flags & MOUSE_MOVE_ABSOLUTE != 0
+            || (stroke.x == 0 && stroke.y == 0)
+            || settings.filter == Filter::None
+        {
+            return stroke;
+        }
+
+        let track = self.tracks.entry(device).or_insert_with(|| Track::new(now));
+        if track.next_settle.is_none() {
+            // Idle until now; don't let the gap count as slow movement
+            track.last_update = now;
+        }
+        track.position.0 += i64::from(stroke.x);
+        track.position.1 += i64::from(stroke.y);
+        track.last_input = now;
+        track.update(settings.filter, now);
+        track.next_settle = (!track.is_settled()).then(|| now + settings.settle_interval);
+
+        let (x, y) = track.take();
+        let mut smoothed = stroke;
+        smoothed.x = x;
+        smoothed.y = y;
+        smoothed
+    }
+}
+
+impl Processor for Smoothing {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        let Stroke::Mouse(stroke) = event.stroke else {
+            out.push(event);
+            return;
+        };
+        let smoothed = self.transform(event.device, stroke, now);
+        if smoothed == stroke || !smoothed.is_empty() {
+            out.push(Event::new(event.device, smoothed));
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.tracks.values().filter_map(|t| t.next_settle).min()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        for (&device, track) in &mut self.tracks {
+            if track.next_settle.is_none_or(|next| next > now) {
+                continue;
+            }
+            let settings = *self.settings.get(device);
+            track.update(settings.filter, now);
+
+            let (px, py) = (track.position.0 as f64, track.position.1 as f64);
+            let close = (track.estimate.0 - px).abs() < 0.5 && (track.estimate.1 - py).abs() < 0.5;
+            if close || now - track.last_input >= settings.settle_timeout {
+                track.snap();
+            }
+            track.next_settle = (!track.is_settled()).then(|| now + settings.settle_interval);
+
+            let (x, y) = track.take();
+            if x != 0 || y != 0 {
+                let stroke = MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, x, y, 0);
+                out.push(Event::new(device, stroke));
+            }
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.settings.attach(device, hardware_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_KEYBOARD, MOUSE_LEFT_BUTTON_DOWN, MOUSE_WHEEL};
+    use proptest::prelude::*;
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn motion(x: i32, y: i32) -> MouseStroke {
+        MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, x, y, 0)
+    }
+
+    /// Feed strokes `gap` apart, ticking at every deadline in between and until settled
+    fn run(smoothing: &mut Smoothing, strokes: &[MouseStroke], gap: Duration) -> Vec<Event> {
+        let t0 = Instant::now();
+        let mut out = Vec::new();
+        let mut now = t0;
+        for stroke in strokes {
+            now += gap;
+            while let Some(deadline) = smoothing.deadline().filter(|d| *d <= now) {
+                smoothing.tick(deadline, &mut out);
+            }
+            smoothing.process(Event::new(MOUSE, *stroke), now, &mut out);
+        }
+        while let Some(deadline) = smoothing.deadline() {
+            smoothing.tick(deadline, &mut out);
+        }
+        out
+    }
+
+    fn total(events: &[Event]) -> (i32, i32) {
+        events
+            .iter()
+            .filter_map(Event::mouse)
+            .fold((0, 0), |(x, y), s| (x + s.x, y + s.y))
+    }
+
+    #[test]
+    fn test_moving_average_lags_then_catches_up() {
+        let mut smoothing = Smoothing::new(SmoothingSettings::new(Filter::moving_average(4)));
+        let out = run(&mut smoothing, &[motion(8, 0), motion(8, 0)], ms(4));
+        let xs: Vec<_> = out.iter().map(|e| e.mouse().unwrap().x).collect();
+        // Positions 8 and 16 average to 8 and 12, then settling feeds 16 until the average
+        // reaches 13.3, 14 and finally 16
+        assert_eq!(xs, vec![8, 4, 1, 1, 2]);
+        assert_eq!(total(&out), (16, 0));
+    }
+
+    #[test]
+    fn test_dead_zone_suppresses_jitter() {
+        let settings = SmoothingSettings::new(Filter::dead_zone(2.0));
+        let mut smoothing = Smoothing::new(settings);
+        let jitter = [
+            motion(1, 0),
+            motion(-1, 1),
+            motion(0, -1),
+            motion(1, 1),
+            motion(-1, -1),
+        ];
+        let t0 = Instant::now();
+        let mut out = Vec::new();
+        for (i, stroke) in jitter.iter().enumerate() {
+            smoothing.process(Event::new(MOUSE, *stroke), t0 + ms(i as u64), &mut out);
+        }
+        assert!(out.is_empty());
+
+        smoothing.process(Event::new(MOUSE, motion(10, 0)), t0 + ms(10), &mut out);
+        assert_eq!(total(&out), (8, 0));
+
+        // Jitter that doesn't return to where it started is held back until the settle
+        // timeout, then sent as one stroke of its net offset
+        while let Some(deadline) = smoothing.deadline() {
+            smoothing.tick(deadline, &mut out);
+        }
+        assert_eq!(total(&out), (10, 0));
+        out.clear();
+        for (i, stroke) in jitter[..4].iter().enumerate() {
+            smoothing.process(
+                Event::new(MOUSE, *stroke),
+                t0 + ms(200 + i as u64),
+                &mut out,
+            );
+        }
+        assert!(out.is_empty());
+        while let Some(deadline) = smoothing.deadline() {
+            smoothing.tick(deadline, &mut out);
+        }
+        assert_eq!(out, vec![Event::new(MOUSE, motion(1, 1))]);
+    }
+
+    #[test]
+    fn test_one_euro_smooths_slow_movement_more() {
+        let settings = SmoothingSettings::new(Filter::one_euro(1.0, 0.05));
+        let first_step = |step: i32| {
+            let mut smoothing = Smoothing::new(settings);
+            let t0 = Instant::now();
+            let mut out = Vec::new();
+            for i in 0..5 {
+                smoothing.process(Event::new(MOUSE, motion(step, 0)), t0 + ms(i * 8), &mut out);
+            }
+            f64::from(total(&out).0) / f64::from(step * 5)
+        };
+        assert!(first_step(1) < first_step(50));
+    }
+
+    #[test]
+    fn test_buttons_and_wheel_pass_through() {
+        let mut smoothing = Smoothing::new(SmoothingSettings::new(Filter::moving_average(8)));
+        let mut stroke = MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_LEFT_BUTTON_DOWN, 0, 1, 0, 0);
+        let out = run(&mut smoothing, &[stroke], ms(1));
+        stroke.x = 1;
+        assert_eq!(out[0], Event::new(MOUSE, stroke));
+
+        let wheel = MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_WHEEL, 120, 0, 0, 0);
+        let out = run(&mut smoothing, &[wheel], ms(1));
+        assert_eq!(out, vec![Event::new(MOUSE, wheel)]);
+    }
+
+    fn filters() -> impl Strategy<Value = Filter> {
+        prop_oneof![
+            (1usize..16).prop_map(Filter::moving_average),
+            (0.1f64..10.0, 0.0f64..1.0).prop_map(|(cutoff, beta)| Filter::one_euro(cutoff, beta)),
+            (0.0f64..10.0).prop_map(Filter::dead_zone),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_no_drift(
+            filter in filters(),
+            moves in prop::collection::vec((-50i32..50, -50i32..50), 1..100),
+            gap in 1u64..20,
+        ) {
+            let mut smoothing = Smoothing::new(SmoothingSettings::new(filter));
+            let strokes: Vec<_> = moves.iter().map(|&(x, y)| motion(x, y)).collect();
+            let out = run(&mut smoothing, &strokes, ms(gap));
+            let expected = moves.iter().fold((0, 0), |(x, y), (dx, dy)| (x + dx, y + dy));
+            prop_assert_eq!(total(&out), expected);
+            prop_assert!(out.iter().all(|e| e.device == MOUSE));
+        }
+    }
+}
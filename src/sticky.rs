@@ -0,0 +1,599 @@
+//! One-shot (sticky) modifiers and caps-word mode.
+//!
+//! [`StickyModifiers`] lets a modifier be tapped instead of held: tapping Shift leaves it
+//! logically pressed until the next non-modifier key goes down, and tapping it a second time
+//! locks it until it is tapped again. Holding a modifier while pressing other keys still works
+//! as an ordinary chord.
+//!
+//! [`CapsWord`] shifts letters until a key that cannot be part of a word is pressed, which is
+//! handy for typing `SCREAMING_SNAKE_CASE` identifiers without holding Shift or toggling
+//! Caps Lock.
+
+use crate::{Event, Key, KeyStroke, MODIFIERS, Processor, Stroke};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// Logical state of a sticky modifier that is not physically held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Latch {
+    /// Behaves like a plain modifier
+    Off,
+    /// Tapped once; released after the next key goes down
+    OneShot { since: Instant },
+    /// Tapped twice; released by the next tap
+    Locked,
+}
+
+#[derive(Debug, Clone)]
+struct Modifier {
+    key: Key,
+    held: bool,
+    /// Another key went down while this one was physically held
+    chorded: bool,
+    latch: Latch,
+    /// The withheld key up, sent once the latch is released
+    release: Option<Event>,
+}
+
+impl Modifier {
+    fn new(key: Key) -> Self {
+        Self {
+            key,
+            held: false,
+            chorded: false,
+            latch: Latch::Off,
+            release: None,
+        }
+    }
+
+    fn unlatch(&mut self, out: &mut Vec<Event>) {
+        self.latch = Latch::Off;
+        if let Some(release) = self.release.take() {
+            out.push(release);
+        }
+    }
+}
+
+/// Turns modifier taps into one-shot or locked modifiers
+#[derive(Debug, Clone)]
+pub struct StickyModifiers {
+    modifiers: Vec<Modifier>,
+    timeout: Option<Duration>,
+}
+
+impl Default for StickyModifiers {
+    /// Every Shift, Ctrl, Alt and Windows key is sticky and one-shots never expire
+    fn default() -> Self {
+        Self::new(MODIFIERS)
+    }
+}
+
+impl StickyModifiers {
+    /// Make the given keys sticky; other keys, including other modifiers, pass through
+    pub fn new(keys: impl IntoIterator<Item = Key>) -> Self {
+        Self {
+            modifiers: keys.into_iter().map(Modifier::new).collect(),
+            timeout: None,
+        }
+    }
+
+    /// Release a one-shot modifier if no key is pressed within `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Whether `key` is waiting to apply to the next key press
+    pub fn is_one_shot(&self, key: Key) -> bool {
+        self.modifier(key)
+            .is_some_and(|m| matches!(m.latch, Latch::OneShot { .. }))
+    }
+
+    /// Whether `key` is locked down
+    pub fn is_locked(&self, key: Key) -> bool {
+        self.modifier(key).is_some_and(|m| m.latch == Latch::Locked)
+    }
+
+    /// Release every latched modifier
+    pub fn clear(&mut self, out: &mut Vec<Event>) {
+        for modifier in &mut self.modifiers {
+            modifier.unlatch(out);
+        }
+    }
+
+    fn modifier(&self, key: Key) -> Option<&Modifier> {
+        self.modifiers.iter().find(|m| m.key == key)
+    }
+
+    fn process_modifier(
+        &mut self,
+        index: usize,
+        event: Event,
+        stroke: &KeyStroke,
+        now: Instant,
+        out: &mut Vec<Event>,
+    ) {
+        let modifier = &mut self.modifiers[index];
+        if stroke.is_down() {
+            if !modifier.held {
+                modifier.held = true;
+                modifier.chorded = false;
+            }
+            // While latched the key is already logically down, so the press and any
+            // autorepeat are redundant
+            if modifier.latch == Latch::Off {
+                out.push(event);
+            }
+            return;
+        }
+
+        modifier.held = false;
+        if modifier.chorded {
+            modifier.latch = Latch::Off;
+            modifier.release = None;
+            out.push(event);
+            return;
+        }
+        match modifier.latch {
+            Latch::Off => {
+                modifier.latch = Latch::OneShot { since: now };
+                modifier.release = Some(event);
+            }
+            Latch::OneShot { .. } => {
+                modifier.latch = Latch::Locked;
+                modifier.release = Some(event);
+            }
+            Latch::Locked => {
+                modifier.release = None;
+                modifier.latch = Latch::Off;
+                out.push(event);
+            }
+        }
+    }
+
+    fn consume_one_shots(&mut self, out: &mut Vec<Event>) {
+        for modifier in &mut self.modifiers {
+            if modifier.held {
+                modifier.chorded = true;
+            } else if matches!(modifier.latch, Latch::OneShot { .. }) {
+                modifier.unlatch(out);
+            }
+        }
+    }
+}
+
+impl Processor for StickyModifiers {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        match event.stroke {
+            Stroke::Key(stroke) => {
+                let key = stroke.key();
+                if let Some(index) = self.modifiers.iter().position(|m| m.key == key) {
+                    self.process_modifier(index, event, &stroke, now, out);
+                } else {
+                    out.push(event);
+                    if stroke.is_down() && !key.is_modifier() {
+                        self.consume_one_shots(out);
+                    }
+                }
+            }
+            Stroke::Mouse(stroke) => {
+                out.push(event);
+                if stroke.has_button_down() {
+                    self.consume_one_shots(out);
+                }
+            }
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        let timeout = self.timeout?;
+        self.modifiers
+            .iter()
+            .filter_map(|m| match m.latch {
+                Latch::OneShot { since } if !m.held => Some(since + timeout),
+                _ => None,
+            })
+            .min()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        let Some(timeout) = self.timeout else {
+            return;
+        };
+        for modifier in &mut self.modifiers {
+            if let Latch::OneShot { since } = modifier.latch
+                && !modifier.held
+                && since + timeout <= now
+            {
+                modifier.unlatch(out);
+            }
+        }
+    }
+}
+
+/// Shifts letters until a non-word key is pressed
+#[derive(Debug, Clone)]
+pub struct CapsWord {
+    toggle: Option<Key>,
+    shifted: HashSet<Key>,
+    continuing: HashSet<Key>,
+    idle_timeout: Option<Duration>,
+    active: bool,
+    last_activity: Option<Instant>,
+    /// Modifiers currently physically held
+    held: HashSet<Key>,
+}
+
+impl Default for CapsWord {
+    /// Toggled with Caps Lock, using US QWERTY letter positions, ending after five idle seconds
+    fn default() -> Self {
+        Self::new(Some(Key::CAPS_LOCK))
+    }
+}
+
+impl CapsWord {
+    /// Scancodes of the letter keys on a US QWERTY keyboard
+    pub const LETTERS: [Key; 26] = [
+        Key::Q,
+        Key::W,
+        Key::E,
+        Key::R,
+        Key::T,
+        Key::Y,
+        Key::U,
+        Key::I,
+        Key::O,
+        Key::P,
+        Key::A,
+        Key::S,
+        Key::D,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::Z,
+        Key::X,
+        Key::C,
+        Key::V,
+        Key::B,
+        Key::N,
+        Key::M,
+    ];
+
+    /// Create a caps-word processor activated by `toggle`, or only through
+    /// [`activate`](CapsWord::activate) when `None`
+    ///
+    /// Letters and `-` are shifted; digits, Backspace and Delete keep the word going.
+    pub fn new(toggle: Option<Key>) -> Self {
+        let mut shifted: HashSet<Key> = Self::LETTERS.into_iter().collect();
+        shifted.insert(Key::MINUS);
+        let continuing = [
+            Key::DIGIT_1,
+            Key::DIGIT_2,
+            Key::DIGIT_3,
+            Key::DIGIT_4,
+            Key::DIGIT_5,
+            Key::DIGIT_6,
+            Key::DIGIT_7,
+            Key::DIGIT_8,
+            Key::DIGIT_9,
+            Key::DIGIT_0,
+            Key::BACKSPACE,
+            Key::DELETE,
+        ]
+        .into_iter()
+        .collect();
+        Self {
+            toggle,
+            shifted,
+            continuing,
+            idle_timeout: Some(Duration::from_secs(5)),
+            active: false,
+            last_activity: None,
+            held: HashSet::new(),
+        }
+    }
+
+    /// Replace the set of keys that get shifted while active
+    pub fn with_shifted(mut self, keys: impl IntoIterator<Item = Key>) -> Self {
+        self.shifted = keys.into_iter().collect();
+        self
+    }
+
+    /// Replace the set of keys that are passed through unshifted without ending the word
+    pub fn with_continuing(mut self, keys: impl IntoIterator<Item = Key>) -> Self {
+        self.continuing = keys.into_iter().collect();
+        self
+    }
+
+    /// End the word after this long without a key press; `None` waits indefinitely
+    pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn activate(&mut self, now: Instant) {
+        self.active = true;
+        self.last_activity = Some(now);
+    }
+
+    pub fn deactivate(&mut self) {
+        self.active = false;
+        self.last_activity = None;
+    }
+
+    fn shift_held(&self) -> bool {
+        self.held.contains(&Key::LEFT_SHIFT) || self.held.contains(&Key::RIGHT_SHIFT)
+    }
+
+    fn other_modifier_held(&self) -> bool {
+        self.held
+            .iter()
+            .any(|&key| key != Key::LEFT_SHIFT && key != Key::RIGHT_SHIFT)
+    }
+}
+
+impl Processor for CapsWord {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        let Stroke::Key(stroke) = event.stroke else {
+            out.push(event);
+            return;
+        };
+        let key = stroke.key();
+
+        if Some(key) == self.toggle {
+            if stroke.is_down() {
+                if self.active {
+                    self.deactivate();
+                } else {
+                    self.activate(now);
+                }
+            }
+            return;
+        }
+
+        if key.is_modifier() {
+            if stroke.is_down() {
+                self.held.insert(key);
+            } else {
+                self.held.remove(&key);
+            }
+            out.push(event);
+            return;
+        }
+
+        if !self.active || stroke.is_up() {
+            out.push(event);
+            return;
+        }
+
+        if self.other_modifier_held()
+            || !(self.shifted.contains(&key) || self.continuing.contains(&key))
+        {
+            self.deactivate();
+            out.push(event);
+            return;
+        }
+
+        self.last_activity = Some(now);
+        if self.shifted.contains(&key) && !self.shift_held() {
+            out.push(Event::new(event.device, Key::LEFT_SHIFT.down()));
+            out.push(event);
+            out.push(Event::new(event.device, Key::LEFT_SHIFT.up()));
+        } else {
+            out.push(event);
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        if !self.active {
+            return None;
+        }
+        Some(self.last_activity? + self.idle_timeout?)
+    }
+
+    fn tick(&mut self, now: Instant, _out: &mut Vec<Event>) {
+        if self.deadline().is_some_and(|deadline| deadline <= now) {
+            self.deactivate();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MouseButton;
+
+    fn feed(processor: &mut impl Processor, strokes: &[KeyStroke], now: Instant) -> Vec<KeyStroke> {
+        let mut out = Vec::new();
+        for stroke in strokes {
+            processor.process(Event::new(0, *stroke), now, &mut out);
+        }
+        out.iter()
+            .filter_map(|event| event.key().copied())
+            .collect()
+    }
+
+    #[test]
+    fn test_one_shot_applies_to_next_key_only() {
+        let now = Instant::now();
+        let mut sticky = StickyModifiers::default();
+
+        let out = feed(
+            &mut sticky,
+            &[Key::LEFT_SHIFT.down(), Key::LEFT_SHIFT.up()],
+            now,
+        );
+        assert_eq!(out, vec![Key::LEFT_SHIFT.down()]);
+        assert!(sticky.is_one_shot(Key::LEFT_SHIFT));
+
+        let out = feed(
+            &mut sticky,
+            &[Key::A.down(), Key::A.up(), Key::B.down()],
+            now,
+        );
+        assert_eq!(
+            out,
+            vec![
+                Key::A.down(),
+                Key::LEFT_SHIFT.up(),
+                Key::A.up(),
+                Key::B.down()
+            ]
+        );
+        assert!(!sticky.is_one_shot(Key::LEFT_SHIFT));
+    }
+
+    #[test]
+    fn test_double_tap_locks_until_next_tap() {
+        let now = Instant::now();
+        let mut sticky = StickyModifiers::default();
+        let tap = Key::LEFT_CTRL.tap();
+
+        let out = feed(&mut sticky, &[tap[0], tap[1], tap[0], tap[1]], now);
+        assert_eq!(out, vec![Key::LEFT_CTRL.down()]);
+        assert!(sticky.is_locked(Key::LEFT_CTRL));
+
+        let out = feed(
+            &mut sticky,
+            &[Key::A.down(), Key::A.up(), Key::B.down(), Key::B.up()],
+            now,
+        );
+        assert_eq!(
+            out,
+            vec![Key::A.down(), Key::A.up(), Key::B.down(), Key::B.up()]
+        );
+
+        let out = feed(&mut sticky, &tap, now);
+        assert_eq!(out, vec![Key::LEFT_CTRL.up()]);
+        assert!(!sticky.is_locked(Key::LEFT_CTRL));
+    }
+
+    #[test]
+    fn test_held_modifier_is_a_plain_chord() {
+        let now = Instant::now();
+        let mut sticky = StickyModifiers::default();
+        let strokes = [
+            Key::LEFT_SHIFT.down(),
+            Key::LEFT_SHIFT.down(),
+            Key::A.down(),
+            Key::A.up(),
+            Key::LEFT_SHIFT.up(),
+        ];
+
+        assert_eq!(feed(&mut sticky, &strokes, now), strokes);
+        assert!(!sticky.is_one_shot(Key::LEFT_SHIFT));
+    }
+
+    #[test]
+    fn test_one_shot_combines_with_held_modifier() {
+        let now = Instant::now();
+        let mut sticky = StickyModifiers::new([Key::LEFT_SHIFT]);
+        let out = feed(
+            &mut sticky,
+            &[
+                Key::LEFT_SHIFT.down(),
+                Key::LEFT_SHIFT.up(),
+                Key::LEFT_CTRL.down(),
+                Key::A.down(),
+                Key::A.up(),
+                Key::LEFT_CTRL.up(),
+            ],
+            now,
+        );
+        assert_eq!(
+            out,
+            vec![
+                Key::LEFT_SHIFT.down(),
+                Key::LEFT_CTRL.down(),
+                Key::A.down(),
+                Key::LEFT_SHIFT.up(),
+                Key::A.up(),
+                Key::LEFT_CTRL.up(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_one_shot_consumed_by_click_and_timeout() {
+        let now = Instant::now();
+        let mut sticky = StickyModifiers::default().with_timeout(Duration::from_secs(1));
+        feed(&mut sticky, &Key::LEFT_CTRL.tap(), now);
+
+        let mut out = Vec::new();
+        let click = Event::new(crate::MAX_KEYBOARD, MouseButton::Left.down());
+        sticky.process(click, now, &mut out);
+        assert_eq!(out, vec![click, Event::new(0, Key::LEFT_CTRL.up())]);
+
+        feed(&mut sticky, &Key::LEFT_ALT.tap(), now);
+        assert_eq!(sticky.deadline(), Some(now + Duration::from_secs(1)));
+        let mut out = Vec::new();
+        sticky.tick(now + Duration::from_secs(1), &mut out);
+        assert_eq!(out, vec![Event::new(0, Key::LEFT_ALT.up())]);
+        assert_eq!(sticky.deadline(), None);
+    }
+
+    #[test]
+    fn test_caps_word_shifts_letters_until_non_word_key() {
+        let now = Instant::now();
+        let mut caps = CapsWord::default();
+
+        let out = feed(&mut caps, &Key::CAPS_LOCK.tap(), now);
+        assert!(out.is_empty());
+        assert!(caps.is_active());
+
+        let out = feed(
+            &mut caps,
+            &[Key::A.down(), Key::A.up(), Key::DIGIT_1.down()],
+            now,
+        );
+        assert_eq!(
+            out,
+            vec![
+                Key::LEFT_SHIFT.down(),
+                Key::A.down(),
+                Key::LEFT_SHIFT.up(),
+                Key::A.up(),
+                Key::DIGIT_1.down(),
+            ]
+        );
+        assert!(caps.is_active());
+
+        let out = feed(&mut caps, &[Key::SPACE.down(), Key::B.down()], now);
+        assert_eq!(out, vec![Key::SPACE.down(), Key::B.down()]);
+        assert!(!caps.is_active());
+    }
+
+    #[test]
+    fn test_caps_word_respects_held_modifiers() {
+        let now = Instant::now();
+        let mut caps = CapsWord::new(None);
+        caps.activate(now);
+
+        let out = feed(&mut caps, &[Key::RIGHT_SHIFT.down(), Key::A.down()], now);
+        assert_eq!(out, vec![Key::RIGHT_SHIFT.down(), Key::A.down()]);
+        feed(&mut caps, &[Key::RIGHT_SHIFT.up()], now);
+
+        let out = feed(&mut caps, &[Key::LEFT_CTRL.down(), Key::C.down()], now);
+        assert_eq!(out, vec![Key::LEFT_CTRL.down(), Key::C.down()]);
+        assert!(!caps.is_active());
+    }
+
+    #[test]
+    fn test_caps_word_idle_timeout() {
+        let now = Instant::now();
+        let mut caps = CapsWord::new(None).with_idle_timeout(Some(Duration::from_secs(2)));
+        caps.activate(now);
+        feed(&mut caps, &[Key::A.down()], now + Duration::from_secs(1));
+        assert_eq!(caps.deadline(), Some(now + Duration::from_secs(3)));
+
+        caps.tick(now + Duration::from_secs(3), &mut Vec::new());
+        assert!(!caps.is_active());
+    }
+}
@@ -0,0 +1,326 @@
+//! Absolute positioning from relative movement, as on a drawing tablet.
+//!
+//! [`Tablet`] integrates a mouse's relative movement into a position inside an input area
+//! measured in counts, clamped to its edges, and maps that area onto a rectangle of the screen.
+//! Every movement is sent as an absolute stroke, so the same spot in the input area always
+//! lands on the same pixel, wherever the pointer was before.
+//!
+//! With the aspect lock the input area is cropped, keeping its center, to the target's aspect
+//! ratio, so circles stay round. Smoothing eases the output position toward the integrated one
+//! with an exponential moving average; once the pen stops, the processor keeps easing on a timer
+//! and snaps to the integrated position after a timeout at the latest.
+
+use crate::screen::{Rect, Screen};
+use crate::{
+    Event, MOUSE_MOVE_ABSOLUTE, MOUSE_VIRTUAL_DESKTOP, MouseStroke, PerDevice, Processor, Stroke,
+};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How often the output eases toward the position after movement stops
+const SETTLE_INTERVAL: Duration = Duration::from_millis(8);
+/// How long after the last movement the output snaps to the position
+const SETTLE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How an input area maps onto the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TabletArea {
+    /// Input area size in counts
+    pub width: f64,
+    pub height: f64,
+    /// Screen rectangle the input area maps onto, in pixels
+    pub target: Rect,
+    pub aspect_lock: bool,
+    /// Weight of the previous output position, from 0 for none to just below 1
+    pub smoothing: f64,
+}
+
+impl TabletArea {
+    /// Map an input area onto `target`, with the aspect lock and without smoothing
+    pub fn new(width: f64, height: f64, target: Rect) -> Self {
+        Self {
+            width,
+            height,
+            target,
+            aspect_lock: true,
+            smoothing: 0.0,
+        }
+    }
+
+    pub fn with_aspect_lock(mut self, aspect_lock: bool) -> Self {
+        self.aspect_lock = aspect_lock;
+        self
+    }
+
+    pub fn with_smoothing(mut self, smoothing: f64) -> Self {
+        self.smoothing = smoothing.clamp(0.0, 0.99);
+        self
+    }
+
+    /// The part of the input area that is mapped: `(left, top, width, height)` in counts
+    pub fn active_area(&self) -> (f64, f64, f64, f64) {
+        if !self.aspect_lock || self.target.width <= 0 || self.target.height <= 0 {
+            return (0.0, 0.0, self.width, self.height);
+        }
+        let aspect = f64::from(self.target.width) / f64::from(self.target.height);
+        if self.width / self.height > aspect {
+            let width = self.height * aspect;
+            ((self.width - width) / 2.0, 0.0, width, self.height)
+        } else {
+            let height = self.width / aspect;
+            (0.0, (self.height - height) / 2.0, self.width, height)
+        }
+    }
+
+    /// The pixel a position in the input area maps to, clamped to the target
+    pub fn map(&self, x: f64, y: f64) -> (f64, f64) {
+        let (left, top, width, height) = self.active_area();
+        let target = self.target;
+        let fx = ((x - left) / width).clamp(0.0, 1.0);
+        let fy = ((y - top) / height).clamp(0.0, 1.0);
+        (
+            f64::from(target.left) + fx * f64::from(target.width - 1),
+            f64::from(target.top) + fy * f64::from(target.height - 1),
+        )
+    }
+}
+
+/// Where one device is in its input area and on screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pen {
+    position: (f64, f64),
+    output: Option<(f64, f64)>,
+    /// When to next ease toward the position, and when to snap to it, while the output lags
+    settle: Option<(Instant, Instant)>,
+}
+
+impl Pen {
+    /// Step the output toward the pixel the position maps to, and return it
+    fn ease(&mut self, area: &TabletArea) -> (f64, f64) {
+        let mapped = area.map(self.position.0, self.position.1);
+        let output = match self.output {
+            Some((ox, oy)) => (
+                ox + (mapped.0 - ox) * (1.0 - area.smoothing),
+                oy + (mapped.1 - oy) * (1.0 - area.smoothing),
+            ),
+            None => mapped,
+        };
+        self.output = Some(output);
+        output
+    }
+
+    /// Whether the output is within half a pixel of where the position maps to
+    fn is_settled(&self, area: &TabletArea) -> bool {
+        let mapped = area.map(self.position.0, self.position.1);
+        self.output
+            .is_none_or(|(ox, oy)| (ox - mapped.0).abs() < 0.5 && (oy - mapped.1).abs() < 0.5)
+    }
+}
+
+/// Turns relative mouse movement into absolute positioning within a screen area
+#[derive(Debug, Clone)]
+pub struct Tablet {
+    screen: Screen,
+    areas: PerDevice<Option<TabletArea>>,
+    pens: HashMap<usize, Pen>,
+}
+
+impl Tablet {
+    /// Map every mouse through the same area
+    pub fn new(screen: Screen, area: TabletArea) -> Self {
+        Self::per_device(screen, PerDevice::new(Some(area)))
+    }
+
+    /// Use different areas for particular devices; devices without one pass through
+    pub fn per_device(screen: Screen, areas: PerDevice<Option<TabletArea>>) -> Self {
+        Self {
+            screen,
+            areas,
+            pens: HashMap::new(),
+        }
+    }
+
+    /// The position of `device` in its input area, starting at the center
+    pub fn position(&self, device: usize) -> Option<(f64, f64)> {
+        match self.pens.get(&device) {
+            Some(pen) => Some(pen.position),
+            None => self
+                .areas
+                .get(device)
+                .map(|area| (area.width / 2.0, area.height / 2.0)),
+        }
+    }
+
+    /// Move `device` by a relative movement; returns the pixel the pointer should be on
+    pub fn advance(&mut self, device: usize, x: i32, y: i32) -> Option<(i32, i32)> {
+        let area = (*self.areas.get(device))?;
+        let pen = self.pens.entry(device).or_insert(Pen {
+            position: (area.width / 2.0, area.height / 2.0),
+            output: None,
+            settle: None,
+        });
+
+        // Clamp to the active area so moving back out of an edge responds at once
+        let (left, top, width, height) = area.active_area();
+        pen.position = (
+            (pen.position.0 + f64::from(x)).clamp(left, left + width),
+            (pen.position.1 + f64::from(y)).clamp(top, top + height),
+        );
+
+        let output = pen.ease(&area);
+        Some((output.0.round() as i32, output.1.round() as i32))
+    }
+
+    fn transform(&mut self, device: usize, stroke: MouseStroke, now: Instant) -> MouseStroke {
+        if stroke.flags & MOUSE_MOVE_ABSOLUTE != 0 || (stroke.x == 0 && stroke.y == 0) {
+            return stroke;
+        }
+        let Some((px, py)) = self.advance(device, stroke.x, stroke.y) else {
+            return stroke;
+        };
+        if let (Some(area), Some(pen)) = (self.areas.get(device), self.pens.get_mut(&device)) {
+            pen.settle =
+                (!pen.is_settled(area)).then(|| (now + SETTLE_INTERVAL, now + SETTLE_TIMEOUT));
+        }
+        let mut absolute = stroke;
+        absolute.flags |= MOUSE_MOVE_ABSOLUTE | MOUSE_VIRTUAL_DESKTOP;
+        (absolute.x, absolute.y) = self.screen.to_normalized(px, py, true);
+        absolute
+    }
+}
+
+impl Processor for Tablet {
+    fn process(&mut self, event: Event, now: Instant, out: &mut Vec<Event>) {
+        match event.stroke {
+            Stroke::Mouse(stroke) => {
+                out.push(Event::new(
+                    event.device,
+                    self.transform(event.device, stroke, now),
+                ));
+            }
+            Stroke::Key(_) => out.push(event),
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.pens
+            .values()
+            .filter_map(|pen| pen.settle.map(|(next, _)| next))
+            .min()
+    }
+
+    fn tick(&mut self, now: Instant, out: &mut Vec<Event>) {
+        for (&device, pen) in &mut self.pens {
+            let Some((_, snap)) = pen.settle.filter(|(next, _)| *next <= now) else {
+                continue;
+            };
+            let Some(area) = self.areas.get(device) else {
+                pen.settle = None;
+                continue;
+            };
+            let before = pen
+                .output
+                .map(|(x, y)| (x.round() as i32, y.round() as i32));
+            pen.ease(area);
+            if now >= snap || pen.is_settled(area) {
+                pen.output = Some(area.map(pen.position.0, pen.position.1));
+                pen.settle = None;
+            } else {
+                pen.settle = Some((now + SETTLE_INTERVAL, snap));
+            }
+
+            let (x, y) = pen.output.unwrap();
+            let pixel = (x.round() as i32, y.round() as i32);
+            if before != Some(pixel) {
+                let (x, y) = self.screen.to_normalized(pixel.0, pixel.1, true);
+                let flags = MOUSE_MOVE_ABSOLUTE | MOUSE_VIRTUAL_DESKTOP;
+                out.push(Event::new(device, MouseStroke::new(flags, 0, 0, x, y, 0)));
+            }
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.areas.attach(device, hardware_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_KEYBOARD, MOUSE_LEFT_BUTTON_DOWN, MOUSE_MOVE_RELATIVE};
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    #[test]
+    fn test_aspect_lock_crops_input_area() {
+        let area = TabletArea::new(2000.0, 2000.0, Rect::new(0, 0, 1600, 900));
+        assert_eq!(area.active_area(), (0.0, 437.5, 2000.0, 1125.0));
+        assert_eq!(area.map(0.0, 437.5), (0.0, 0.0));
+        assert_eq!(area.map(2000.0, 2000.0), (1599.0, 899.0));
+
+        let area = area.with_aspect_lock(false);
+        assert_eq!(area.active_area(), (0.0, 0.0, 2000.0, 2000.0));
+        assert_eq!(area.map(1000.0, 1000.0), (799.5, 449.5));
+    }
+
+    #[test]
+    fn test_integration_clamps_to_area() {
+        let target = Rect::new(100, 100, 1001, 1001);
+        let mut tablet = Tablet::new(
+            Screen::single(1920, 1080),
+            TabletArea::new(1000.0, 1000.0, target),
+        );
+        assert_eq!(tablet.position(MOUSE), Some((500.0, 500.0)));
+        assert_eq!(tablet.advance(MOUSE, 100, -50), Some((700, 550)));
+        assert_eq!(tablet.advance(MOUSE, 5000, 0), Some((1100, 550)));
+        // Back out of the edge immediately
+        assert_eq!(tablet.advance(MOUSE, -10, 0), Some((1090, 550)));
+        assert_eq!(tablet.position(MOUSE), Some((990.0, 450.0)));
+    }
+
+    #[test]
+    fn test_smoothing_eases_toward_position() {
+        let screen = Screen::single(1920, 1080);
+        let area = TabletArea::new(1000.0, 1000.0, Rect::new(0, 0, 1001, 1001)).with_smoothing(0.5);
+        let mut tablet = Tablet::new(screen.clone(), area);
+        let t0 = Instant::now();
+        let mut out = Vec::new();
+        let motion = |x| Event::new(MOUSE, MouseStroke::new(MOUSE_MOVE_RELATIVE, 0, 0, x, 0, 0));
+        tablet.process(motion(1), t0, &mut out);
+        tablet.process(motion(399), t0, &mut out);
+
+        // The pen stopped; the pointer keeps easing and then settles where the pen is
+        while let Some(deadline) = tablet.deadline() {
+            tablet.tick(deadline, &mut out);
+        }
+        let xs: Vec<_> = out
+            .iter()
+            .map(|event| event.mouse().unwrap().pixel(&screen).unwrap().0)
+            .collect();
+        assert_eq!(xs[..4], [501, 701, 800, 850]);
+        assert_eq!(xs.last(), Some(&900));
+        assert!(xs.len() < 14, "{xs:?}");
+    }
+
+    #[test]
+    fn test_strokes_become_absolute() {
+        let screen = Screen::single(1920, 1080);
+        let area = TabletArea::new(1920.0, 1080.0, screen.primary());
+        let mut tablet = Tablet::per_device(
+            screen.clone(),
+            PerDevice::new(None).with("PID_0002", Some(area)),
+        );
+        tablet.attach(MOUSE + 1, r"HID\VID_0001&PID_0002");
+
+        let mut out = Vec::new();
+        let stroke = MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_LEFT_BUTTON_DOWN, 0, 10, 0, 0);
+        tablet.process(Event::new(MOUSE, stroke), Instant::now(), &mut out);
+        tablet.process(Event::new(MOUSE + 1, stroke), Instant::now(), &mut out);
+        assert_eq!(out[0], Event::new(MOUSE, stroke));
+
+        let absolute = out[1].mouse().unwrap();
+        assert_eq!(absolute.flags, MOUSE_MOVE_ABSOLUTE | MOUSE_VIRTUAL_DESKTOP);
+        assert_eq!(absolute.state, MOUSE_LEFT_BUTTON_DOWN);
+        assert_eq!(absolute.pixel(&screen), Some((969, 540)));
+    }
+}
@@ -0,0 +1,625 @@
+//! Typing text as keystrokes.
+//!
+//! The driver only knows scancodes, so typing text means knowing which key, with which
+//! modifiers, produces each character. A [`Layout`] answers that; [`UsQwerty`] is built in and
+//! other layouts implement the trait. [`Typist`] turns text into strokes with a layout, falls
+//! back to a configurable [`Fallback`] for characters the layout can't produce, and paces the
+//! result.
+//!
+//! Conversion is pure: [`Typist::type_text`] returns the strokes, grouped per character, and
+//! sending them is left to [`Typed::play`] or `KeyboardDevice::send_typed`.
+
+use crate::{Key, KeyStroke};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Modifiers held while pressing a key
+///
+/// AltGr is Ctrl and Alt together, as Windows treats it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers {
+        shift: false,
+        ctrl: false,
+        alt: false,
+    };
+    pub const SHIFT: Modifiers = Modifiers {
+        shift: true,
+        ctrl: false,
+        alt: false,
+    };
+    pub const ALTGR: Modifiers = Modifiers {
+        shift: false,
+        ctrl: true,
+        alt: true,
+    };
+    pub const SHIFT_ALTGR: Modifiers = Modifiers {
+        shift: true,
+        ctrl: true,
+        alt: true,
+    };
+
+    pub fn is_altgr(self) -> bool {
+        self.ctrl && self.alt
+    }
+
+    /// The keys to hold, in the order they are pressed
+    ///
+    /// AltGr is pressed as Left Ctrl with Right Alt, which every layout reads as AltGr.
+    pub fn keys(self) -> Vec<Key> {
+        let mut keys = Vec::new();
+        if self.shift {
+            keys.push(Key::LEFT_SHIFT);
+        }
+        if self.ctrl {
+            keys.push(Key::LEFT_CTRL);
+        }
+        if self.is_altgr() {
+            keys.push(Key::RIGHT_ALT);
+        } else if self.alt {
+            keys.push(Key::LEFT_ALT);
+        }
+        keys
+    }
+}
+
+/// A key pressed with modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub key: Key,
+    pub modifiers: Modifiers,
+}
+
+impl Chord {
+    pub const fn new(key: Key, modifiers: Modifiers) -> Self {
+        Self { key, modifiers }
+    }
+
+    /// Press the modifiers, tap the key and release the modifiers in reverse
+    pub fn strokes(self) -> Vec<KeyStroke> {
+        let modifiers = self.modifiers.keys();
+        let mut strokes: Vec<_> = modifiers.iter().map(|key| key.down()).collect();
+        strokes.extend(self.key.tap());
+        strokes.extend(modifiers.iter().rev().map(|key| key.up()));
+        strokes
+    }
+}
+
+/// What a key produces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Char(char),
+    /// A dead key: nothing yet, but it combines with the next character
+    Dead(char),
+    /// Several characters at once
+    Text(String),
+}
+
+/// Which keys produce which characters, in both directions
+pub trait Layout {
+    /// The chords typing `c`, in order, or `None` if the layout can't produce it
+    ///
+    /// Most characters take one chord; characters composed with a dead key take two. The
+    /// chords assume Caps Lock is off.
+    fn chords(&self, c: char) -> Option<Vec<Chord>>;
+
+    /// What pressing `key` with `modifiers` produces
+    fn output(&self, key: Key, modifiers: Modifiers, caps_lock: bool) -> Option<Output>;
+
+    /// The character a dead key and the following character combine into
+    fn compose(&self, dead: char, base: char) -> Option<char> {
+        let _ = (dead, base);
+        None
+    }
+}
+
+impl<L: Layout + ?Sized> Layout for &L {
+    fn chords(&self, c: char) -> Option<Vec<Chord>> {
+        (**self).chords(c)
+    }
+
+    fn output(&self, key: Key, modifiers: Modifiers, caps_lock: bool) -> Option<Output> {
+        (**self).output(key, modifiers, caps_lock)
+    }
+
+    fn compose(&self, dead: char, base: char) -> Option<char> {
+        (**self).compose(dead, base)
+    }
+}
+
+/// The unshifted and shifted characters of the US QWERTY layout
+const US_QWERTY: [(Key, char, char); 48] = [
+    (Key::BACKQUOTE, '`', '~'),
+    (Key::DIGIT_1, '1', '!'),
+    (Key::DIGIT_2, '2', '@'),
+    (Key::DIGIT_3, '3', '#'),
+    (Key::DIGIT_4, '4', '$'),
+    (Key::DIGIT_5, '5', '%'),
+    (Key::DIGIT_6, '6', '^'),
+    (Key::DIGIT_7, '7', '&'),
+    (Key::DIGIT_8, '8', '*'),
+    (Key::DIGIT_9, '9', '('),
+    (Key::DIGIT_0, '0', ')'),
+    (Key::MINUS, '-', '_'),
+    (Key::EQUAL, '=', '+'),
+    (Key::Q, 'q', 'Q'),
+    (Key::W, 'w', 'W'),
+    (Key::E, 'e', 'E'),
+    (Key::R, 'r', 'R'),
+    (Key::T, 't', 'T'),
+    (Key::Y, 'y', 'Y'),
+    (Key::U, 'u', 'U'),
+    (Key::I, 'i', 'I'),
+    (Key::O, 'o', 'O'),
+    (Key::P, 'p', 'P'),
+    (Key::BRACKET_LEFT, '[', '{'),
+    (Key::BRACKET_RIGHT, ']', '}'),
+    (Key::BACKSLASH, '\\', '|'),
+    (Key::A, 'a', 'A'),
+    (Key::S, 's', 'S'),
+    (Key::D, 'd', 'D'),
+    (Key::F, 'f', 'F'),
+    (Key::G, 'g', 'G'),
+    (Key::H, 'h', 'H'),
+    (Key::J, 'j', 'J'),
+    (Key::K, 'k', 'K'),
+    (Key::L, 'l', 'L'),
+    (Key::SEMICOLON, ';', ':'),
+    (Key::QUOTE, '\'', '"'),
+    (Key::Z, 'z', 'Z'),
+    (Key::X, 'x', 'X'),
+    (Key::C, 'c', 'C'),
+    (Key::V, 'v', 'V'),
+    (Key::B, 'b', 'B'),
+    (Key::N, 'n', 'N'),
+    (Key::M, 'm', 'M'),
+    (Key::COMMA, ',', '<'),
+    (Key::PERIOD, '.', '>'),
+    (Key::SLASH, '/', '?'),
+    (Key::SPACE, ' ', ' '),
+];
+
+/// The US QWERTY layout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsQwerty;
+
+impl UsQwerty {
+    /// The chord typing `c`
+    pub fn chord(c: char) -> Option<Chord> {
+        match c {
+            '\n' => Some(Chord::new(Key::ENTER, Modifiers::NONE)),
+            '\t' => Some(Chord::new(Key::TAB, Modifiers::NONE)),
+            _ => US_QWERTY.iter().find_map(|&(key, plain, shifted)| {
+                if c == plain {
+                    Some(Chord::new(key, Modifiers::NONE))
+                } else if c == shifted {
+                    Some(Chord::new(key, Modifiers::SHIFT))
+                } else {
+                    None
+                }
+            }),
+        }
+    }
+
+    /// The character a key types, with or without Shift
+    pub fn char(key: Key, shift: bool) -> Option<char> {
+        match key {
+            Key::ENTER => Some('\n'),
+            Key::TAB => Some('\t'),
+            _ => US_QWERTY
+                .iter()
+                .find(|(candidate, _, _)| *candidate == key)
+                .map(|&(_, plain, shifted)| if shift { shifted } else { plain }),
+        }
+    }
+}
+
+impl Layout for UsQwerty {
+    fn chords(&self, c: char) -> Option<Vec<Chord>> {
+        Self::chord(c).map(|chord| vec![chord])
+    }
+
+    fn output(&self, key: Key, modifiers: Modifiers, caps_lock: bool) -> Option<Output> {
+        if modifiers.ctrl || modifiers.alt {
+            return None;
+        }
+        let plain = Self::char(key, false)?;
+        let shift = modifiers.shift ^ (caps_lock && plain.is_ascii_alphabetic());
+        Self::char(key, shift).map(Output::Char)
+    }
+}
+
+/// What to do with characters the layout can't type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Fallback {
+    /// Fail with [`UntypeableChar`]
+    Fail,
+    /// Leave the character out
+    Skip,
+    /// Hold Alt and type `0` and the decimal code point on the numpad
+    ///
+    /// Code points up to 255 give the Windows-1252 character in most applications; larger
+    /// ones give the Unicode character only in applications that accept them, such as those
+    /// built on rich edit controls. Characters Windows-1252 puts in 128 to 159, such as `€`,
+    /// are typed with their Windows-1252 code instead, which works everywhere.
+    #[default]
+    AltNumpad,
+    /// Hold Alt and type numpad `+` and the hexadecimal code point
+    ///
+    /// Works in every application, but only once `EnableHexNumpad` is set in the registry
+    /// under `HKEY_CURRENT_USER\Control Panel\Input Method`.
+    AltNumpadHex,
+}
+
+/// A character that neither the layout nor the fallback can type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UntypeableChar {
+    pub char: char,
+    /// Index of the character in the text, in characters
+    pub index: usize,
+}
+
+impl Display for UntypeableChar {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cannot type {:?} at character {} with this layout",
+            self.char, self.index
+        )
+    }
+}
+
+impl Error for UntypeableChar {}
+
+const NUMPAD_DIGITS: [Key; 10] = [
+    Key::NUMPAD_0,
+    Key::NUMPAD_1,
+    Key::NUMPAD_2,
+    Key::NUMPAD_3,
+    Key::NUMPAD_4,
+    Key::NUMPAD_5,
+    Key::NUMPAD_6,
+    Key::NUMPAD_7,
+    Key::NUMPAD_8,
+    Key::NUMPAD_9,
+];
+
+/// Windows-1252 codes 128 to 159, where it differs from Latin-1; the five unassigned codes
+/// stand for themselves
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// The character of a Windows-1252 code, as typed with Alt and a leading `0` on the numpad
+pub(crate) fn from_windows_1252(code: u8) -> char {
+    match code {
+        128..=159 => WINDOWS_1252[usize::from(code - 128)],
+        code => char::from(code),
+    }
+}
+
+/// The Windows-1252 code of a character, if it has one
+fn to_windows_1252(c: char) -> Option<u8> {
+    match WINDOWS_1252.iter().position(|&w| w == c) {
+        Some(index) => Some(128 + index as u8),
+        None => u8::try_from(c)
+            .ok()
+            .filter(|code| !(128..=159).contains(code)),
+    }
+}
+
+/// Alt held around taps of `keys`
+fn with_alt(keys: impl IntoIterator<Item = Key>) -> Vec<KeyStroke> {
+    let mut strokes = vec![Key::LEFT_ALT.down()];
+    strokes.extend(keys.into_iter().flat_map(Key::tap));
+    strokes.push(Key::LEFT_ALT.up());
+    strokes
+}
+
+impl Fallback {
+    fn strokes(self, c: char) -> Option<Vec<KeyStroke>> {
+        let code = u32::from(c);
+        match self {
+            Fallback::Fail => None,
+            Fallback::Skip => Some(Vec::new()),
+            Fallback::AltNumpad => {
+                let code = to_windows_1252(c).map_or(code, u32::from);
+                let digits = format!("0{code}");
+                Some(with_alt(
+                    digits.bytes().map(|d| NUMPAD_DIGITS[usize::from(d - b'0')]),
+                ))
+            }
+            Fallback::AltNumpadHex => {
+                let digits = format!("{code:x}");
+                let keys = digits.chars().map(|d| match d.to_digit(10) {
+                    Some(digit) => NUMPAD_DIGITS[digit as usize],
+                    None => {
+                        UsQwerty::chord(d)
+                            .expect("hex letters are on the layout")
+                            .key
+                    }
+                });
+                Some(with_alt(std::iter::once(Key::NUMPAD_ADD).chain(keys)))
+            }
+        }
+    }
+}
+
+/// Strokes typing a text, one group per character
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Typed {
+    pub chunks: Vec<Vec<KeyStroke>>,
+    /// Pause between characters
+    pub pacing: Duration,
+}
+
+impl Typed {
+    /// All strokes in order
+    pub fn strokes(&self) -> Vec<KeyStroke> {
+        self.chunks.concat()
+    }
+
+    /// How long sending takes with pacing
+    pub fn duration(&self) -> Duration {
+        self.pacing * self.chunks.len().saturating_sub(1) as u32
+    }
+
+    /// Send each character's strokes, pausing between characters
+    ///
+    /// Stops at the first error from `send`.
+    pub fn play<E>(&self, mut send: impl FnMut(&[KeyStroke]) -> Result<(), E>) -> Result<(), E> {
+        let start = Instant::now();
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let due = start + self.pacing * i as u32;
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+            send(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// Turns text into keystrokes for a layout
+#[derive(Debug, Clone)]
+pub struct Typist<L = UsQwerty> {
+    layout: L,
+    fallback: Fallback,
+    pacing: Duration,
+}
+
+impl Default for Typist {
+    /// US QWERTY
+    fn default() -> Self {
+        Self::new(UsQwerty)
+    }
+}
+
+impl<L: Layout> Typist<L> {
+    /// Type with `layout`, falling back to Alt+numpad codes, without pauses
+    pub fn new(layout: L) -> Self {
+        Self {
+            layout,
+            fallback: Fallback::default(),
+            pacing: Duration::ZERO,
+        }
+    }
+
+    pub fn with_fallback(mut self, fallback: Fallback) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Pause between characters
+    pub fn with_pacing(mut self, pacing: Duration) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    pub fn layout(&self) -> &L {
+        &self.layout
+    }
+
+    /// The strokes typing `text`; `\r\n` and `\n` both press Enter
+    pub fn type_text(&self, text: &str) -> Result<Typed, UntypeableChar> {
+        let mut chunks = Vec::new();
+        let mut chars = text.chars().enumerate().peekable();
+        while let Some((index, c)) = chars.next() {
+            if c == '\r' && chars.peek().is_some_and(|(_, next)| *next == '\n') {
+                continue;
+            }
+            let strokes = match self.layout.chords(c) {
+                Some(chords) => chords.into_iter().flat_map(Chord::strokes).collect(),
+                None => self
+                    .fallback
+                    .strokes(c)
+                    .ok_or(UntypeableChar { char: c, index })?,
+            };
+            if !strokes.is_empty() {
+                chunks.push(strokes);
+            }
+        }
+        Ok(Typed {
+            chunks,
+            pacing: self.pacing,
+        })
+    }
+}
+
+#[cfg(windows)]
+impl crate::KeyboardDevice {
+    /// Send typed text to this keyboard, pausing between characters
+    pub fn send_typed(&mut self, typed: &Typed) -> crate::Result<()> {
+        typed.play(|strokes| self.send(strokes).map(drop))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taps(keys: &[Key]) -> Vec<KeyStroke> {
+        keys.iter().flat_map(|key| key.tap()).collect()
+    }
+
+    #[test]
+    fn test_hello_world() {
+        let typed = Typist::default().type_text("Hi, all!\r\n").unwrap();
+        assert_eq!(typed.chunks.len(), 9);
+        assert_eq!(
+            typed.chunks[0],
+            vec![
+                Key::LEFT_SHIFT.down(),
+                Key::H.down(),
+                Key::H.up(),
+                Key::LEFT_SHIFT.up()
+            ]
+        );
+        assert_eq!(typed.chunks[1], Key::I.tap());
+        assert_eq!(typed.chunks[2], Key::COMMA.tap());
+        assert_eq!(typed.chunks[7][1], Key::DIGIT_1.down());
+        assert_eq!(typed.chunks[8], Key::ENTER.tap());
+        assert_eq!(typed.strokes().len(), 4 + 2 * 6 + 4 + 2);
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        let typist = Typist::default();
+        let typed = typist.type_text("é").unwrap();
+        let mut expected = vec![Key::LEFT_ALT.down()];
+        expected.extend(taps(&[
+            Key::NUMPAD_0,
+            Key::NUMPAD_2,
+            Key::NUMPAD_3,
+            Key::NUMPAD_3,
+        ]));
+        expected.push(Key::LEFT_ALT.up());
+        assert_eq!(typed.strokes(), expected);
+
+        let typed = typist
+            .clone()
+            .with_fallback(Fallback::AltNumpadHex)
+            .type_text("€")
+            .unwrap();
+        let mut expected = vec![Key::LEFT_ALT.down()];
+        expected.extend(taps(&[
+            Key::NUMPAD_ADD,
+            Key::NUMPAD_2,
+            Key::NUMPAD_0,
+            Key::A,
+            Key::C,
+        ]));
+        expected.push(Key::LEFT_ALT.up());
+        assert_eq!(typed.strokes(), expected);
+
+        // The euro sign has a Windows-1252 code
+        let typed = typist.type_text("€").unwrap();
+        let mut expected = vec![Key::LEFT_ALT.down()];
+        expected.extend(taps(&[
+            Key::NUMPAD_0,
+            Key::NUMPAD_1,
+            Key::NUMPAD_2,
+            Key::NUMPAD_8,
+        ]));
+        expected.push(Key::LEFT_ALT.up());
+        assert_eq!(typed.strokes(), expected);
+
+        let skipped = typist.clone().with_fallback(Fallback::Skip);
+        assert_eq!(skipped.type_text("a€b").unwrap().chunks.len(), 2);
+
+        let strict = typist.with_fallback(Fallback::Fail);
+        assert_eq!(
+            strict.type_text("ab€").unwrap_err(),
+            UntypeableChar {
+                char: '€',
+                index: 2
+            }
+        );
+    }
+
+    /// A layout typing `ä` with AltGr and `ê` with a dead circumflex
+    struct Custom;
+
+    impl Layout for Custom {
+        fn chords(&self, c: char) -> Option<Vec<Chord>> {
+            match c {
+                'ä' => Some(vec![Chord::new(Key::Q, Modifiers::ALTGR)]),
+                'ê' => Some(vec![
+                    Chord::new(Key::DIGIT_6, Modifiers::SHIFT),
+                    Chord::new(Key::E, Modifiers::NONE),
+                ]),
+                _ => UsQwerty.chords(c),
+            }
+        }
+
+        fn output(&self, key: Key, modifiers: Modifiers, caps_lock: bool) -> Option<Output> {
+            UsQwerty.output(key, modifiers, caps_lock)
+        }
+    }
+
+    #[test]
+    fn test_pluggable_layout() {
+        let typed = Typist::new(Custom).type_text("äê").unwrap();
+        assert_eq!(
+            typed.chunks[0],
+            vec![
+                Key::LEFT_CTRL.down(),
+                Key::RIGHT_ALT.down(),
+                Key::Q.down(),
+                Key::Q.up(),
+                Key::RIGHT_ALT.up(),
+                Key::LEFT_CTRL.up(),
+            ]
+        );
+        assert_eq!(typed.chunks[1].len(), 6);
+        assert_eq!(&typed.chunks[1][4..], &Key::E.tap());
+    }
+
+    #[test]
+    fn test_us_output() {
+        assert_eq!(
+            UsQwerty.output(Key::A, Modifiers::NONE, false),
+            Some(Output::Char('a'))
+        );
+        assert_eq!(
+            UsQwerty.output(Key::A, Modifiers::SHIFT, true),
+            Some(Output::Char('a'))
+        );
+        assert_eq!(
+            UsQwerty.output(Key::DIGIT_1, Modifiers::NONE, true),
+            Some(Output::Char('1'))
+        );
+        assert_eq!(UsQwerty.output(Key::A, Modifiers::ALTGR, false), None);
+    }
+
+    #[test]
+    fn test_pacing() {
+        let typed = Typist::default()
+            .with_pacing(Duration::from_millis(2))
+            .type_text("abc")
+            .unwrap();
+        assert_eq!(typed.duration(), Duration::from_millis(4));
+
+        let start = Instant::now();
+        let mut sent = Vec::new();
+        typed
+            .play(|strokes| {
+                sent.push((start.elapsed(), strokes.to_vec()));
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(sent.len(), 3);
+        assert!(sent[2].0 >= Duration::from_millis(4));
+        assert_eq!(sent[1].1, Key::B.tap());
+    }
+}
@@ -0,0 +1,462 @@
+//! Mouse wheel transformations.
+//!
+//! A wheel stroke sets [`MOUSE_WHEEL`] or [`MOUSE_HWHEEL`] in [`MouseStroke::state`] and carries
+//! its delta in [`MouseStroke::rolling`]; 120 is one notch, positive is up or right. High
+//! resolution wheels report fractions of a notch, so scaled deltas are accumulated per device
+//! and axis rather than rounded stroke by stroke.
+//!
+//! [`Wheel`] inverts and scales wheel movement per device, turns Shift+wheel into horizontal
+//! scrolling, and can replace wheel notches with key taps.
+
+use crate::remap::ActiveDevices;
+use crate::{
+    Event, Key, MOUSE_HWHEEL, MOUSE_MOVE_RELATIVE, MOUSE_WHEEL, MouseButton, MouseState,
+    MouseStroke, PerDevice, Processor, Remainder, Stroke,
+};
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+/// The wheel delta of one notch
+pub const WHEEL_DELTA: i16 = 120;
+
+/// Which wheel a stroke scrolls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WheelAxis {
+    Vertical,
+    Horizontal,
+}
+
+impl WheelAxis {
+    /// The state bit reporting movement of this wheel
+    pub const fn flag(self) -> MouseState {
+        match self {
+            WheelAxis::Vertical => MOUSE_WHEEL,
+            WheelAxis::Horizontal => MOUSE_HWHEEL,
+        }
+    }
+}
+
+impl MouseStroke {
+    /// A stroke scrolling the vertical wheel; positive is up
+    pub fn wheel(delta: i16) -> Self {
+        MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_WHEEL, delta, 0, 0, 0)
+    }
+
+    /// A stroke scrolling the horizontal wheel; positive is right
+    pub fn hwheel(delta: i16) -> Self {
+        MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_HWHEEL, delta, 0, 0, 0)
+    }
+
+    /// The wheel this stroke scrolls and by how much
+    pub fn wheel_delta(&self) -> Option<(WheelAxis, i16)> {
+        if self.state & MOUSE_WHEEL != 0 {
+            Some((WheelAxis::Vertical, self.rolling))
+        } else if self.state & MOUSE_HWHEEL != 0 {
+            Some((WheelAxis::Horizontal, self.rolling))
+        } else {
+            None
+        }
+    }
+
+    /// Replace the wheel movement of this stroke, setting and clearing the wheel bits to match
+    pub fn set_wheel(&mut self, wheel: Option<(WheelAxis, i16)>) {
+        self.state &= !(MOUSE_WHEEL | MOUSE_HWHEEL);
+        self.rolling = 0;
+        if let Some((axis, delta)) = wheel
+            && delta != 0
+        {
+            self.state |= axis.flag();
+            self.rolling = delta;
+        }
+    }
+}
+
+/// Wheel transform for one device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WheelSettings {
+    pub invert_vertical: bool,
+    pub invert_horizontal: bool,
+    pub scale_vertical: f64,
+    pub scale_horizontal: f64,
+}
+
+impl Default for WheelSettings {
+    /// Leaves wheel movement unchanged
+    fn default() -> Self {
+        Self {
+            invert_vertical: false,
+            invert_horizontal: false,
+            scale_vertical: 1.0,
+            scale_horizontal: 1.0,
+        }
+    }
+}
+
+impl WheelSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Natural scrolling: content follows the finger, as on a touchpad
+    pub fn natural() -> Self {
+        Self::new().with_invert(true, true)
+    }
+
+    pub fn with_invert(mut self, vertical: bool, horizontal: bool) -> Self {
+        self.invert_vertical = vertical;
+        self.invert_horizontal = horizontal;
+        self
+    }
+
+    pub fn with_scale(mut self, vertical: f64, horizontal: f64) -> Self {
+        self.scale_vertical = vertical;
+        self.scale_horizontal = horizontal;
+        self
+    }
+
+    /// Fractional output delta for an input delta
+    pub fn apply(&self, axis: WheelAxis, delta: i16) -> f64 {
+        let (invert, scale) = match axis {
+            WheelAxis::Vertical => (self.invert_vertical, self.scale_vertical),
+            WheelAxis::Horizontal => (self.invert_horizontal, self.scale_horizontal),
+        };
+        let delta = f64::from(delta) * scale;
+        if invert { -delta } else { delta }
+    }
+}
+
+/// A direction of wheel movement, for remapping to keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WheelDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl WheelDirection {
+    fn of(axis: WheelAxis, delta: i32) -> Self {
+        match (axis, delta > 0) {
+            (WheelAxis::Vertical, true) => WheelDirection::Up,
+            (WheelAxis::Vertical, false) => WheelDirection::Down,
+            (WheelAxis::Horizontal, true) => WheelDirection::Right,
+            (WheelAxis::Horizontal, false) => WheelDirection::Left,
+        }
+    }
+}
+
+/// Inverts, scales and redirects mouse wheel movement
+#[derive(Debug, Clone)]
+pub struct Wheel {
+    settings: PerDevice<WheelSettings>,
+    shift_horizontal: bool,
+    keys: HashMap<WheelDirection, Key>,
+    remainders: HashMap<(usize, WheelAxis), Remainder>,
+    /// Wheel movement toward the next key tap, per device and axis
+    notches: HashMap<(usize, WheelAxis), i32>,
+    shifts: HashSet<Key>,
+    /// Shift presses held back while Shift may only be turning the wheel, with their keyboard
+    withheld_shifts: Vec<(usize, Key)>,
+    /// Whether the wheel turned while the withheld Shift was held
+    shift_scrolled: bool,
+    active: ActiveDevices,
+}
+
+impl Wheel {
+    pub fn new(settings: WheelSettings) -> Self {
+        Self::per_device(PerDevice::new(settings))
+    }
+
+    /// Use different settings for particular devices
+    pub fn per_device(settings: PerDevice<WheelSettings>) -> Self {
+        Self {
+            settings,
+            shift_horizontal: false,
+            keys: HashMap::new(),
+            remainders: HashMap::new(),
+            notches: HashMap::new(),
+            shifts: HashSet::new(),
+            withheld_shifts: Vec::new(),
+            shift_scrolled: false,
+            active: ActiveDevices::default(),
+        }
+    }
+
+    /// Scroll horizontally while Shift is held
+    ///
+    /// Scrolling down scrolls right, as most applications do for Shift+wheel themselves. Shift
+    /// itself is withheld, so applications see a plain horizontal scroll, until another key or
+    /// a mouse button goes down; a Shift tapped without scrolling is sent as it was.
+    pub fn with_shift_horizontal(mut self, enabled: bool) -> Self {
+        self.shift_horizontal = enabled;
+        self
+    }
+
+    /// Replace wheel movement in a direction with a tap of `key` per notch
+    ///
+    /// Taps are sent to the most recently active keyboard. The direction is the one after
+    /// inversion and the Shift conversion.
+    pub fn with_key(mut self, direction: WheelDirection, key: Key) -> Self {
+        self.keys.insert(direction, key);
+        self
+    }
+
+    fn transform(&mut self, device: usize, mut stroke: MouseStroke, out: &mut Vec<Event>) {
+        let Some((axis, delta)) = stroke.wheel_delta() else {
+            out.push(Event::new(device, stroke));
+            return;
+        };
+
+        let mut scaled = self.settings.get(device).apply(axis, delta);
+        let mut axis = axis;
+        if self.shift_horizontal && axis == WheelAxis::Vertical && !self.shifts.is_empty() {
+            axis = WheelAxis::Horizontal;
+            scaled = -scaled;
+            self.shift_scrolled = true;
+        }
+        let delta = self
+            .remainders
+            .entry((device, axis))
+            .or_default()
+            .take(scaled);
+
+        if delta != 0 && self.keys.contains_key(&WheelDirection::of(axis, delta)) {
+            stroke.set_wheel(None);
+            self.tap_notches(device, axis, delta, out);
+        } else {
+            let delta = delta.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+            stroke.set_wheel(Some((axis, delta)));
+        }
+
+        if !stroke.is_empty() {
+            out.push(Event::new(device, stroke));
+        }
+    }
+
+    /// Send the Shift presses held back, since Shift is now used for more than the wheel
+    fn replay_shifts(&mut self, out: &mut Vec<Event>) {
+        for (device, key) in self.withheld_shifts.drain(..) {
+            out.push(Event::new(device, key.down()));
+        }
+    }
+
+    fn tap_notches(&mut self, device: usize, axis: WheelAxis, delta: i32, out: &mut Vec<Event>) {
+        let notches = self.notches.entry((device, axis)).or_default();
+        if notches.signum() != delta.signum() {
+            *notches = 0;
+        }
+        *notches += delta;
+
+        let key = self.keys[&WheelDirection::of(axis, delta)];
+        let notch = i32::from(WHEEL_DELTA);
+        while notches.abs() >= notch {
+            *notches -= notch * notches.signum();
+            out.extend(
+                key.tap()
+                    .map(|stroke| Event::new(self.active.keyboard, stroke)),
+            );
+        }
+    }
+}
+
+impl Processor for Wheel {
+    fn process(&mut self, event: Event, _now: Instant, out: &mut Vec<Event>) {
+        self.active.observe(event.device);
+
+        match event.stroke {
+            Stroke::Mouse(stroke) => {
+                if MouseButton::ALL
+                    .into_iter()
+                    .any(|button| stroke.is_down(button))
+                {
+                    self.replay_shifts(out);
+                }
+                self.transform(event.device, stroke, out)
+            }
+            Stroke::Key(stroke) => {
+                let key = stroke.key();
+                if key != Key::LEFT_SHIFT && key != Key::RIGHT_SHIFT {
+                    if !stroke.is_up() {
+                        self.replay_shifts(out);
+                    }
+                    out.push(event);
+                    return;
+                }
+                if stroke.is_up() {
+                    self.shifts.remove(&key);
+                    let withheld = self.withheld_shifts.iter().position(|(_, k)| *k == key);
+                    match withheld {
+                        Some(index) => {
+                            let (device, _) = self.withheld_shifts.remove(index);
+                            if !self.shift_scrolled {
+                                out.push(Event::new(device, key.down()));
+                                out.push(event);
+                            }
+                        }
+                        None => out.push(event),
+                    }
+                    if self.shifts.is_empty() {
+                        self.shift_scrolled = false;
+                    }
+                } else {
+                    let new = self.shifts.insert(key);
+                    if self.withheld_shifts.iter().any(|(_, k)| *k == key) {
+                        // Autorepeat of a withheld press
+                    } else if self.shift_horizontal && new && self.shifts.len() == 1 {
+                        self.withheld_shifts.push((event.device, key));
+                    } else {
+                        out.push(event);
+                    }
+                }
+            }
+        }
+    }
+
+    fn attach(&mut self, device: usize, hardware_id: &str) {
+        self.settings.attach(device, hardware_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MAX_KEYBOARD, MOUSE_LEFT_BUTTON_DOWN};
+
+    const MOUSE: usize = MAX_KEYBOARD;
+
+    fn feed(wheel: &mut Wheel, events: &[Event]) -> Vec<Event> {
+        let now = Instant::now();
+        let mut out = Vec::new();
+        for event in events {
+            wheel.process(*event, now, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_natural_scrolling_per_device() {
+        let settings =
+            PerDevice::new(WheelSettings::new()).with("PID_0002", WheelSettings::natural());
+        let mut wheel = Wheel::per_device(settings);
+        wheel.attach(MOUSE + 1, r"HID\VID_0001&PID_0002");
+
+        let out = feed(
+            &mut wheel,
+            &[
+                Event::new(MOUSE, MouseStroke::wheel(120)),
+                Event::new(MOUSE + 1, MouseStroke::wheel(120)),
+                Event::new(MOUSE + 1, MouseStroke::hwheel(-240)),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseStroke::wheel(120)),
+                Event::new(MOUSE + 1, MouseStroke::wheel(-120)),
+                Event::new(MOUSE + 1, MouseStroke::hwheel(240)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hi_res_scaling_accumulates() {
+        let mut wheel = Wheel::new(WheelSettings::new().with_scale(0.25, 1.0));
+        let out = feed(&mut wheel, &[Event::new(MOUSE, MouseStroke::wheel(10)); 4]);
+        let deltas: Vec<_> = out.iter().map(|e| e.mouse().unwrap().rolling).collect();
+        assert_eq!(deltas, vec![2, 3, 2, 3]);
+    }
+
+    #[test]
+    fn test_shift_wheel_scrolls_horizontally() {
+        let mut wheel = Wheel::new(WheelSettings::new()).with_shift_horizontal(true);
+        let out = feed(
+            &mut wheel,
+            &[
+                Event::new(0, Key::LEFT_SHIFT.down()),
+                Event::new(MOUSE, MouseStroke::wheel(-120)),
+                Event::new(0, Key::LEFT_SHIFT.up()),
+                Event::new(MOUSE, MouseStroke::wheel(-120)),
+            ],
+        );
+        // Shift is withheld, so only the horizontal scroll is seen
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseStroke::hwheel(120)),
+                Event::new(MOUSE, MouseStroke::wheel(-120)),
+            ]
+        );
+
+        // Shift comes through when it is used for anything else
+        let out = feed(
+            &mut wheel,
+            &[
+                Event::new(0, Key::RIGHT_SHIFT.down()),
+                Event::new(0, Key::RIGHT_SHIFT.down()),
+                Event::new(MOUSE, MouseStroke::wheel(-120)),
+                Event::new(0, Key::A.down()),
+                Event::new(0, Key::A.up()),
+                Event::new(0, Key::RIGHT_SHIFT.down()),
+                Event::new(MOUSE, MouseStroke::wheel(-120)),
+                Event::new(0, Key::RIGHT_SHIFT.up()),
+                Event::new(0, Key::LEFT_SHIFT.down()),
+                Event::new(0, Key::LEFT_SHIFT.up()),
+                Event::new(0, Key::LEFT_SHIFT.down()),
+                Event::new(MOUSE, MouseButton::Left.down()),
+                Event::new(0, Key::LEFT_SHIFT.up()),
+            ],
+        );
+        assert_eq!(
+            out,
+            vec![
+                Event::new(MOUSE, MouseStroke::hwheel(120)),
+                Event::new(0, Key::RIGHT_SHIFT.down()),
+                Event::new(0, Key::A.down()),
+                Event::new(0, Key::A.up()),
+                Event::new(0, Key::RIGHT_SHIFT.down()),
+                Event::new(MOUSE, MouseStroke::hwheel(120)),
+                Event::new(0, Key::RIGHT_SHIFT.up()),
+                Event::new(0, Key::LEFT_SHIFT.down()),
+                Event::new(0, Key::LEFT_SHIFT.up()),
+                Event::new(0, Key::LEFT_SHIFT.down()),
+                Event::new(MOUSE, MouseButton::Left.down()),
+                Event::new(0, Key::LEFT_SHIFT.up()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wheel_to_keys() {
+        let mut wheel = Wheel::new(WheelSettings::new())
+            .with_key(WheelDirection::Up, Key::VOLUME_UP)
+            .with_key(WheelDirection::Down, Key::VOLUME_DOWN);
+        let mut stroke = MouseStroke::wheel(60);
+        stroke.state |= MOUSE_LEFT_BUTTON_DOWN;
+        let out = feed(
+            &mut wheel,
+            &[
+                Event::new(2, Key::A.up()),
+                Event::new(MOUSE, stroke),
+                Event::new(MOUSE, MouseStroke::wheel(60)),
+                Event::new(MOUSE, MouseStroke::wheel(-240)),
+            ],
+        );
+        let [up_down, up_up] = Key::VOLUME_UP.tap();
+        let [down_down, down_up] = Key::VOLUME_DOWN.tap();
+        assert_eq!(
+            out[1..],
+            [
+                Event::new(
+                    MOUSE,
+                    MouseStroke::new(MOUSE_MOVE_RELATIVE, MOUSE_LEFT_BUTTON_DOWN, 0, 0, 0, 0)
+                ),
+                Event::new(2, up_down),
+                Event::new(2, up_up),
+                Event::new(2, down_down),
+                Event::new(2, down_up),
+                Event::new(2, down_down),
+                Event::new(2, down_up),
+            ]
+        );
+    }
+}